@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use crate::config::PowScheme;
+use crate::crypto::{self, HashAlgo};
+use crate::storage::{IpHash, Scope, Seed, Task, TaskId, UaHash};
+
+const SAMPLE_DURATION: Duration = Duration::from_secs(2);
+
+/// 基准测试进程内的求解速度（pow.hash_algo 对应的算法），并按难度 1..=10 估算期望求解时间。
+/// 期望尝试次数为 2^bits（bits = difficulty * 4），多 worker 时按线性加速折算
+pub fn run(workers: u32, hash_algo: &str) -> anyhow::Result<()> {
+    let workers = workers.max(1);
+    let algo = HashAlgo::from_name(hash_algo)
+        .ok_or_else(|| anyhow::anyhow!("invalid --hash-algo {hash_algo}: expected sha256 or sha3-256"))?;
+    let task = Task {
+        task_id: TaskId::from(crypto::generate_random_id()?),
+        seed: Seed(crypto::generate_random_seed()?),
+        // 不可能满足的难度，保证采样期间一直在哈希
+        bits: 256,
+        exp: 0,
+        scope: Scope("calibrate".to_string()),
+        ua_hash: UaHash(crypto::compute_ua_hash("cowcat-rs calibrate")),
+        ip_hash: IpHash(String::new()),
+        issued_at_ms: 0,
+        chain_length: 1,
+        captcha_answer: None,
+        commitment_nonce: None,
+    };
+
+    println!("sampling {} hash rate for {}s ...", algo.name(), SAMPLE_DURATION.as_secs());
+    let started = Instant::now();
+    let outcome = crypto::solve_pow(&PowScheme::default(), algo, &task, started + SAMPLE_DURATION);
+    if outcome.nonce.is_some() {
+        anyhow::bail!("calibration task was unexpectedly solved");
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let rate = outcome.attempts as f64 / elapsed.max(f64::EPSILON);
+
+    println!("hash rate: {} (single core)", format_rate(rate));
+    println!("workers:   {workers}");
+    println!();
+    println!("{:<10} {:>6} {:>16} {:>14}", "difficulty", "bits", "expected hashes", "expected time");
+    for difficulty in 1..=10u32 {
+        let bits = difficulty * 4;
+        let expected = 2f64.powi(bits as i32);
+        let seconds = expected / (rate * workers as f64);
+        println!(
+            "{:<10} {:>6} {:>16} {:>14}",
+            difficulty,
+            bits,
+            format!("{expected:.0}"),
+            format_duration(seconds)
+        );
+    }
+    println!();
+    println!("note: browsers (wasm/js) are typically several times slower than native code");
+    Ok(())
+}
+
+fn format_rate(rate: f64) -> String {
+    if rate >= 1_000_000.0 {
+        format!("{:.2} MH/s", rate / 1_000_000.0)
+    } else if rate >= 1_000.0 {
+        format!("{:.2} kH/s", rate / 1_000.0)
+    } else {
+        format!("{rate:.0} H/s")
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("{:.0}ms", seconds * 1000.0)
+    } else if seconds < 120.0 {
+        format!("{seconds:.1}s")
+    } else if seconds < 7200.0 {
+        format!("{:.1}min", seconds / 60.0)
+    } else if seconds < 172_800.0 {
+        format!("{:.1}h", seconds / 3600.0)
+    } else {
+        format!("{:.1}d", seconds / 86_400.0)
+    }
+}
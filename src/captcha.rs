@@ -0,0 +1,137 @@
+use std::fmt::Write as _;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+type Glyph = &'static [&'static [(u8, u8)]];
+
+/// 笔画字体：每个字形是若干折线，坐标落在 4×6 的网格内。
+/// 只画题目用到的字符，不嵌入真正的字体文件
+const GLYPHS: &[(char, Glyph)] = &[
+    ('0', &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)]]),
+    ('1', &[&[(1, 1), (2, 0), (2, 6)], &[(1, 6), (3, 6)]]),
+    ('2', &[&[(0, 0), (4, 0), (4, 3), (0, 3), (0, 6), (4, 6)]]),
+    ('3', &[&[(0, 0), (4, 0), (4, 6), (0, 6)], &[(1, 3), (4, 3)]]),
+    ('4', &[&[(0, 0), (0, 3), (4, 3)], &[(3, 0), (3, 6)]]),
+    ('5', &[&[(4, 0), (0, 0), (0, 3), (4, 3), (4, 6), (0, 6)]]),
+    ('6', &[&[(4, 0), (0, 0), (0, 6), (4, 6), (4, 3), (0, 3)]]),
+    ('7', &[&[(0, 0), (4, 0), (1, 6)]]),
+    ('8', &[&[(0, 0), (4, 0), (4, 6), (0, 6), (0, 0)], &[(0, 3), (4, 3)]]),
+    ('9', &[&[(4, 3), (0, 3), (0, 0), (4, 0), (4, 6), (0, 6)]]),
+    ('+', &[&[(0, 3), (4, 3)], &[(2, 1), (2, 5)]]),
+    ('-', &[&[(0, 3), (4, 3)]]),
+    ('=', &[&[(0, 2), (4, 2)], &[(0, 4), (4, 4)]]),
+    ('?', &[&[(0, 1), (0, 0), (4, 0), (4, 3), (2, 3), (2, 4)], &[(2, 5), (2, 6)]]),
+];
+
+/// 网格单位对应的像素
+const UNIT: i32 = 6;
+const GLYPH_ADVANCE: i32 = 5 * UNIT + 4;
+const MARGIN: i32 = 14;
+const HEIGHT: i32 = 6 * UNIT + 2 * MARGIN;
+const NOISE_LINES: usize = 5;
+
+pub struct Captcha {
+    /// 期望的答案（十进制整数）
+    pub answer: String,
+    /// 内联到页面的 SVG
+    pub svg: String,
+}
+
+/// 两位数加减一位数的算术题，以笔画字体渲染并加抖动、旋转与干扰线。
+/// 强度只够挡住直接读取页面文本的脚本，定位是 PoW 跑不动时的兜底，而非独立的防线
+pub fn generate() -> anyhow::Result<Captcha> {
+    let mut rng = Noise::new()?;
+    let a = 10 + rng.below(40) as i32;
+    let b = 1 + rng.below(9) as i32;
+    let (op, answer) = if rng.below(2) == 0 { ('+', a + b) } else { ('-', a - b) };
+    let question = format!("{a}{op}{b}=?");
+
+    let width = question.chars().count() as i32 * GLYPH_ADVANCE + 2 * MARGIN;
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{HEIGHT}" viewBox="0 0 {width} {HEIGHT}" role="img" aria-label="captcha">"#
+    );
+    let _ = write!(svg, r##"<rect width="{width}" height="{HEIGHT}" fill="#f4f1ea"/>"##);
+    for _ in 0..NOISE_LINES {
+        let (x1, y1) = (rng.below(width as u32) as i32, rng.below(HEIGHT as u32) as i32);
+        let (x2, y2) = (rng.below(width as u32) as i32, rng.below(HEIGHT as u32) as i32);
+        let _ = write!(
+            svg,
+            r##"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="#9a8f7d" stroke-width="1.5"/>"##
+        );
+    }
+    for (idx, ch) in question.chars().enumerate() {
+        let Some((_, strokes)) = GLYPHS.iter().find(|(glyph, _)| *glyph == ch) else {
+            continue;
+        };
+        let origin_x = MARGIN + idx as i32 * GLYPH_ADVANCE;
+        let origin_y = MARGIN + rng.below(9) as i32 - 4;
+        let angle = rng.below(31) as i32 - 15;
+        let (cx, cy) = (origin_x + 2 * UNIT, origin_y + 3 * UNIT);
+        let _ = write!(svg, r#"<g transform="rotate({angle} {cx} {cy})">"#);
+        for stroke in strokes.iter() {
+            let mut points = String::new();
+            for (x, y) in stroke.iter() {
+                let px = origin_x + *x as i32 * UNIT + rng.below(5) as i32 - 2;
+                let py = origin_y + *y as i32 * UNIT + rng.below(5) as i32 - 2;
+                let _ = write!(points, "{px},{py} ");
+            }
+            let _ = write!(
+                svg,
+                r##"<polyline points="{}" fill="none" stroke="#2b2620" stroke-width="3" stroke-linecap="round" stroke-linejoin="round"/>"##,
+                points.trim_end()
+            );
+        }
+        svg.push_str("</g>");
+    }
+    svg.push_str("</svg>");
+
+    Ok(Captcha {
+        answer: answer.to_string(),
+        svg,
+    })
+}
+
+/// 一次取够随机字节，渲染过程按需消耗；用完后回绕，只影响抖动的观感
+struct Noise {
+    bytes: [u8; 512],
+    pos: usize,
+}
+
+impl Noise {
+    fn new() -> anyhow::Result<Self> {
+        let mut bytes = [0u8; 512];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("failed to generate captcha randomness"))?;
+        Ok(Self { bytes, pos: 0 })
+    }
+
+    /// [0, bound)；bound 不超过 65536
+    fn below(&mut self, bound: u32) -> u32 {
+        let hi = self.bytes[self.pos % self.bytes.len()] as u32;
+        let lo = self.bytes[(self.pos + 1) % self.bytes.len()] as u32;
+        self.pos += 2;
+        ((hi << 8) | lo) % bound.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_answer_is_in_range_and_not_in_the_markup() {
+        for _ in 0..50 {
+            let captcha = generate().unwrap();
+            let answer: i32 = captcha.answer.parse().unwrap();
+            // 10..=49 加减 1..=9
+            assert!((1..=58).contains(&answer), "{answer}");
+            assert!(captcha.svg.starts_with("<svg") && captcha.svg.ends_with("</svg>"));
+            // 题目只以折线出现，页面里没有可直接读取的文本
+            assert!(!captcha.svg.contains("<text"));
+            assert!(!captcha.svg.contains("=?"));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, Method, Request, Uri};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+const QUEUE_CAPACITY: usize = 1024;
+/// 单个事件的投递上限：接收端挂起时不让队列一直卡在同一个事件上
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeEvent {
+    pub ip: String,
+    pub path: String,
+    pub user_agent: String,
+    pub difficulty: i32,
+    pub timestamp: i64,
+}
+
+/// 挑战下发事件的异步上报：队列满时直接丢弃，绝不阻塞请求路径
+pub struct ChallengeWebhook {
+    tx: mpsc::Sender<ChallengeEvent>,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl ChallengeWebhook {
+    pub fn start(endpoint: &str) -> anyhow::Result<std::sync::Arc<Self>> {
+        Self::start_with_timeout(endpoint, DELIVERY_TIMEOUT)
+    }
+
+    fn start_with_timeout(endpoint: &str, timeout: Duration) -> anyhow::Result<std::sync::Arc<Self>> {
+        let uri = endpoint
+            .parse::<Uri>()
+            .map_err(|err| anyhow::anyhow!("invalid pow.challenge_webhook: {err}"))?;
+        let (tx, mut rx) = mpsc::channel::<ChallengeEvent>(QUEUE_CAPACITY);
+        let webhook = std::sync::Arc::new(Self {
+            tx,
+            dropped: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+
+        let client: Client<HttpConnector, Body> =
+            Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+        let worker = webhook.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                worker.deliver(&client, &uri, &event, timeout).await;
+            }
+        });
+
+        tracing::info!(endpoint, "challenge webhook enabled");
+        Ok(webhook)
+    }
+
+    pub fn emit(&self, event: ChallengeEvent) {
+        if self.tx.try_send(event).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::debug!(dropped, "challenge webhook queue full, event dropped");
+        }
+    }
+
+    /// 队列满而丢弃的事件数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 投递失败（连接错误、非 2xx 或超时）的事件数
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    async fn deliver(&self, client: &Client<HttpConnector, Body>, uri: &Uri, event: &ChallengeEvent, timeout: Duration) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to encode challenge webhook event");
+                return;
+            }
+        };
+        let req = match Request::builder()
+            .method(Method::POST)
+            .uri(uri.clone())
+            .header(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(Body::from(body))
+        {
+            Ok(req) => req,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to build challenge webhook request");
+                return;
+            }
+        };
+
+        match tokio::time::timeout(timeout, client.request(req)).await {
+            Ok(Ok(resp)) if resp.status().is_success() => {}
+            Ok(Ok(resp)) => {
+                let failed = self.failed.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(status = %resp.status(), failed, "challenge webhook rejected event");
+            }
+            Ok(Err(err)) => {
+                let failed = self.failed.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(error = %err, failed, "challenge webhook delivery failed");
+            }
+            Err(_) => {
+                let failed = self.failed.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(timeout_ms = timeout.as_millis() as u64, failed, "challenge webhook delivery timed out");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    /// 本地桩接收端：把收到的 JSON 转发到返回的通道，并按 status 回应
+    async fn stub_endpoint(status: StatusCode, delay: Duration) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = axum::Router::new().fallback(move |body: axum::body::Bytes| {
+            let tx = tx.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                let _ = tx.send(serde_json::from_slice(&body).unwrap());
+                status
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}/events"), rx)
+    }
+
+    fn event(path: &str) -> ChallengeEvent {
+        ChallengeEvent {
+            ip: "203.0.113.7".to_string(),
+            path: path.to_string(),
+            user_agent: "test-agent".to_string(),
+            difficulty: 4,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    async fn wait_for_failed(webhook: &ChallengeWebhook, expected: u64) {
+        for _ in 0..100 {
+            if webhook.failed() >= expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(webhook.failed(), expected);
+    }
+
+    #[tokio::test]
+    async fn events_are_posted_as_json() {
+        let (endpoint, mut received) = stub_endpoint(StatusCode::NO_CONTENT, Duration::ZERO).await;
+        let webhook = ChallengeWebhook::start(&endpoint).unwrap();
+        webhook.emit(event("/login"));
+        let body = tokio::time::timeout(Duration::from_secs(2), received.recv()).await.unwrap().unwrap();
+        assert_eq!(body["path"], "/login");
+        assert_eq!(body["ip"], "203.0.113.7");
+        assert_eq!(body["difficulty"], 4);
+        assert_eq!(body["timestamp"], 1_700_000_000);
+        assert_eq!((webhook.dropped(), webhook.failed()), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn rejected_or_unreachable_deliveries_are_counted() {
+        let (endpoint, mut received) = stub_endpoint(StatusCode::INTERNAL_SERVER_ERROR, Duration::ZERO).await;
+        let webhook = ChallengeWebhook::start(&endpoint).unwrap();
+        webhook.emit(event("/a"));
+        webhook.emit(event("/b"));
+        assert!(received.recv().await.is_some());
+        wait_for_failed(&webhook, 2).await;
+
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let webhook = ChallengeWebhook::start(&format!("http://{closed}/events")).unwrap();
+        webhook.emit(event("/a"));
+        wait_for_failed(&webhook, 1).await;
+        assert_eq!(webhook.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn hanging_endpoint_times_out_and_the_full_queue_drops() {
+        let (endpoint, _received) = stub_endpoint(StatusCode::OK, Duration::from_secs(30)).await;
+        let webhook = ChallengeWebhook::start_with_timeout(&endpoint, Duration::from_millis(100)).unwrap();
+        webhook.emit(event("/slow"));
+        wait_for_failed(&webhook, 1).await;
+
+        // 投递卡住时队列很快填满，之后的事件直接丢弃而不阻塞 emit
+        for idx in 0..QUEUE_CAPACITY + 50 {
+            webhook.emit(event(&format!("/burst/{idx}")));
+        }
+        assert!(webhook.dropped() >= 49, "{}", webhook.dropped());
+    }
+}
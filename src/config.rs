@@ -1,50 +1,37 @@
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fs;
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::crypto::HashAlgo;
 use crate::rules::RuleAction;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
     pub server: ServerConfig,
     pub pow: PowConfig,
     pub proxy: ProxyConfig,
     pub rules: RulesConfig,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            server: ServerConfig::default(),
-            pow: PowConfig::default(),
-            proxy: ProxyConfig::default(),
-            rules: RulesConfig::default(),
-        }
-    }
+    pub admin: AdminConfig,
+    pub favicon: FaviconConfig,
+    pub storage: StorageConfig,
 }
 
 impl Config {
-    pub fn load(path: &str) -> anyhow::Result<Self> {
-        let raw = match fs::read_to_string(path) {
-            Ok(data) => data,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                tracing::warn!(path, "config not found, using defaults");
-                String::new()
-            }
-            Err(err) => {
-                return Err(anyhow::anyhow!("failed to read config {path}: {err}"));
-            }
-        };
-        let mut cfg: Config = if raw.trim().is_empty() {
-            Config::default()
-        } else {
-            toml::from_str(&raw)
-                .map_err(|err| anyhow::anyhow!("failed to parse config {path}: {err}"))?
+    /// 按顺序读取并合并多个配置文件：表逐字段深度合并，标量与数组整体覆盖
+    pub fn load(paths: &[String]) -> anyhow::Result<Self> {
+        let merged = read_merged_toml(paths)?;
+        let mut cfg: Config = match merged {
+            Some(value) => value
+                .try_into()
+                .map_err(|err| anyhow::anyhow!("failed to parse config {}: {err}", paths.join(", ")))?,
+            None => Config::default(),
         };
 
-        cfg.load_external_rules(path)?;
+        cfg.load_external_rules(primary_config_path(paths))?;
 
         cfg.apply_defaults();
         cfg.apply_env()?;
@@ -119,6 +106,13 @@ impl Config {
             }
         }
 
+        if let Ok(v) = env::var("COWCAT_SERVER_ADMIN_LISTEN") {
+            let trimmed = v.trim().to_string();
+            if !trimmed.is_empty() {
+                self.server.admin_listen = Some(trimmed);
+            }
+        }
+
         // Pow config
         if let Ok(v) = env::var("COWCAT_POW_DIFFICULTY") {
             let trimmed = v.trim();
@@ -208,17 +202,157 @@ impl Config {
         if self.pow.workers < 1 || self.pow.workers > 8 {
             anyhow::bail!("pow.workers must be within 1..=8");
         }
-        let worker = self.pow.worker_type.as_str();
-        if worker != "wasm" && worker != "native" {
+        if !is_valid_worker_type(&self.pow.worker_type) {
             anyhow::bail!("pow.worker_type must be wasm or native");
         }
+        if HashAlgo::from_name(&self.pow.hash_algo).is_none() {
+            anyhow::bail!("pow.hash_algo must be sha256 or sha3-256");
+        }
+        if self.pow.dev_mode && !cfg!(debug_assertions) && !dev_mode_allowed_in_release() {
+            anyhow::bail!(
+                "pow.dev_mode is insecure and refused in release builds; set COWCAT_ALLOW_INSECURE_DEV_MODE=true to force it"
+            );
+        }
+        if let Some(admin_listen) = &self.server.admin_listen {
+            let addr: std::net::SocketAddr = admin_listen
+                .parse()
+                .map_err(|err| anyhow::anyhow!("invalid server.admin_listen {admin_listen}: {err}"))?;
+            if self.server.listen.parse::<std::net::SocketAddr>().is_ok_and(|listen| listen == addr) {
+                anyhow::bail!("server.admin_listen must differ from server.listen");
+            }
+        }
+        let mut kids = HashSet::new();
+        for key in &self.pow.cookie_keys {
+            let kid = key.kid.trim();
+            if kid.is_empty() || key.secret.trim().is_empty() {
+                anyhow::bail!("pow.cookie_keys entries need a non-empty kid and secret");
+            }
+            // "v1" 在 cookie_key_usage 中代表 primary 密钥
+            if kid == "v1" {
+                anyhow::bail!("pow.cookie_keys kid v1 is reserved for the salt-derived primary key");
+            }
+            if !kids.insert(kid) {
+                anyhow::bail!("pow.cookie_keys has duplicate kid {kid}");
+            }
+        }
+        let active_kid = self.pow.active_cookie_kid.trim();
+        if !active_kid.is_empty() && !kids.contains(active_kid) {
+            anyhow::bail!("pow.active_cookie_kid {active_kid} is not listed in pow.cookie_keys");
+        }
+        for entry in &self.admin.tokens {
+            if entry.id.trim().is_empty() || entry.token.trim().len() < 16 {
+                anyhow::bail!("admin.tokens entries need a non-empty id and a token of at least 16 characters");
+            }
+        }
+        if self.pow.bind_fingerprint && self.server.tls.is_none() {
+            anyhow::bail!("pow.bind_fingerprint needs server.tls: the fingerprint comes from the local tls handshake");
+        }
+        if self.proxy.strict_host && self.proxy.allowed_hosts.is_empty() && self.proxy.host_rule.is_empty() {
+            anyhow::bail!("proxy.strict_host needs proxy.allowed_hosts or proxy.host_rule, otherwise every request is rejected");
+        }
+        for &status in &self.favicon.cache_statuses {
+            if !(200..=599).contains(&status) || status == 304 {
+                anyhow::bail!("favicon.cache_statuses must be final statuses within 200..=599 (except 304): {status}");
+            }
+        }
+        if let Some(fallback) = &self.pow.wasm_fallback {
+            if fallback.difficulty < 1 || fallback.difficulty > 10 {
+                anyhow::bail!("pow.wasm_fallback.difficulty must be within 1..=10");
+            }
+        }
+        if self.pow.chain_length < 1 || self.pow.chain_length > MAX_CHAIN_LENGTH {
+            anyhow::bail!("pow.chain_length must be within 1..={MAX_CHAIN_LENGTH}");
+        }
+        if let Some(cors) = &self.pow.cors {
+            if cors.allowed_origins.is_empty() {
+                anyhow::bail!("pow.cors needs at least one entry in allowed_origins");
+            }
+            if cors.allow_credentials && cors.allowed_origins.iter().any(|origin| origin == "*") {
+                anyhow::bail!("pow.cors.allow_credentials cannot be combined with the \"*\" origin");
+            }
+        }
+        for entry in &self.pow.path_difficulty {
+            if !entry.prefix.starts_with('/') {
+                anyhow::bail!("pow.path_difficulty prefix must start with '/': {}", entry.prefix);
+            }
+        }
+        if !self.pow.scheme.fields.contains(&PowField::Seed) {
+            anyhow::bail!("pow.scheme.fields must include seed, otherwise solutions are reusable across tasks");
+        }
+        if !CHALLENGE_STATUSES.contains(&self.pow.challenge_status) {
+            anyhow::bail!("pow.challenge_status must be one of {:?}", CHALLENGE_STATUSES);
+        }
+        let asset_base = self.pow.asset_base_url.trim();
+        if !asset_base.is_empty() {
+            let uri = asset_base
+                .parse::<http::Uri>()
+                .map_err(|err| anyhow::anyhow!("invalid pow.asset_base_url: {err}"))?;
+            let scheme_ok = matches!(uri.scheme_str(), Some("https") | Some("http"));
+            let has_query = uri.path_and_query().and_then(|pq| pq.query()).is_some();
+            if !scheme_ok || uri.authority().is_none() || has_query || asset_base.contains('"') {
+                anyhow::bail!("pow.asset_base_url must be an absolute http(s) url without query");
+            }
+        }
+        let remote_url = self.pow.page.remote_url.trim();
+        if !remote_url.is_empty() && !remote_url.starts_with("http://") {
+            anyhow::bail!("pow.page.remote_url must be an http:// url");
+        }
+        if let Some(reputation) = &self.rules.reputation {
+            if !reputation.endpoint.trim().starts_with("http://") {
+                anyhow::bail!("rules.reputation.endpoint must be an http:// url");
+            }
+            if matches!(reputation.action, RuleAction::Allow) {
+                anyhow::bail!("rules.reputation.action must be block or challenge");
+            }
+            if !reputation.threshold.is_finite() {
+                anyhow::bail!("rules.reputation.threshold must be a finite number");
+            }
+        }
+        let webhook = self.pow.challenge_webhook.trim();
+        if !webhook.is_empty() && !webhook.starts_with("http://") {
+            anyhow::bail!("pow.challenge_webhook must be an http:// url");
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.cert_path.trim().is_empty() || tls.key_path.trim().is_empty() {
+                anyhow::bail!("server.tls needs both cert_path and key_path");
+            }
+        }
         Ok(())
     }
 
+    /// Cookie 是否带 Secure 属性：自行终结 TLS 时总是 https，不受 pow.secure 影响
+    pub fn secure_cookies(&self) -> bool {
+        self.pow.secure || self.server.tls.is_some()
+    }
+
+    /// 完整加载流程之后的生效配置，序列化为 TOML；salt / 令牌等敏感值被替换
+    pub fn dump_toml(&self) -> anyhow::Result<String> {
+        let mut redacted = self.clone();
+        redact(&mut redacted.pow.salt);
+        redact(&mut redacted.pow.debug_token);
+        // redis_url 带凭据时整体隐去
+        if redacted.storage.redis_url.contains('@') {
+            redact(&mut redacted.storage.redis_url);
+        }
+        for key in &mut redacted.pow.cookie_keys {
+            redact(&mut key.secret);
+        }
+        for entry in &mut redacted.admin.tokens {
+            redact(&mut entry.token);
+        }
+        toml::to_string_pretty(&redacted).map_err(|err| anyhow::anyhow!("failed to serialize config: {err}"))
+    }
+
     pub fn print_config(&self) {
         tracing::info!("SERVER: {:?}", self.server);
         tracing::info!("POW: {:?}", self.pow);
+        if self.pow.dev_mode {
+            tracing::warn!("POW: dev_mode enabled, challenges are issued with difficulty 0 (INSECURE)");
+        }
         tracing::info!("PROXY: {:?}", self.proxy);
+        if !self.admin.tokens.is_empty() {
+            tracing::info!("ADMIN: {} token(s) configured", self.admin.tokens.len());
+        }
         if self.rules.enabled {
             tracing::info!(
                 "RULES: {}/{} rules (enabled/total), default_action: {:?}, allow_wellknown: {}",
@@ -231,34 +365,328 @@ impl Config {
                 tracing::info!("RULES: loaded from external file: {}", f);
             }
         }
+        if self.rules.block_suspicious_paths {
+            tracing::info!("RULES: suspicious path detection enabled, action: {:?}", self.rules.suspicious_path_action);
+        }
+    }
+}
+
+/// pow.chain_length 的上限
+pub const MAX_CHAIN_LENGTH: u32 = 8;
+
+/// 允许用于挑战页的状态码
+pub const CHALLENGE_STATUSES: &[u16] = &[200, 403, 429, 503];
+
+fn redact(value: &mut String) {
+    if !value.is_empty() {
+        *value = "<redacted>".to_string();
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+pub fn is_valid_worker_type(value: &str) -> bool {
+    value == "wasm" || value == "native"
+}
+
+/// 外部 rules_file 相对于第一个配置文件所在目录解析
+pub fn primary_config_path(paths: &[String]) -> &str {
+    paths.first().map(String::as_str).unwrap_or("config.toml")
+}
+
+pub fn read_merged_toml(paths: &[String]) -> anyhow::Result<Option<toml::Value>> {
+    let mut merged: Option<toml::Value> = None;
+    for path in paths {
+        let raw = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!(path = %path, "config not found, skipping");
+                continue;
+            }
+            Err(err) => {
+                return Err(anyhow::anyhow!("failed to read config {path}: {err}"));
+            }
+        };
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let value: toml::Value = toml::from_str(&raw)
+            .map_err(|err| anyhow::anyhow!("failed to parse config {path}: {err}"))?;
+        match merged.as_mut() {
+            Some(base) => merge_toml(base, value),
+            None => merged = Some(value),
+        }
+    }
+    Ok(merged)
+}
+
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base_table), toml::Value::Table(overlay_table)) => {
+            for (key, value) in overlay_table {
+                match base_table.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base_table.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn dev_mode_allowed_in_release() -> bool {
+    env::var("COWCAT_ALLOW_INSECURE_DEV_MODE")
+        .map(|v| v.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ServerConfig {
     pub listen: String,
+    /// 可选的内部监听地址；设置后健康检查与 admin 接口只在此端口提供，公网端口不再暴露 admin
+    pub admin_listen: Option<String>,
+    /// 可选的 OpenTelemetry 链路导出；未配置时不做任何事
+    pub otel: Option<OtelConfig>,
+    /// 单个请求允许的请求头数量上限，超出返回 431；0 表示不限制
+    pub max_header_count: usize,
+    /// 全部请求头（名 + 值）的字节数上限，超出返回 431；0 表示不限制
+    pub max_header_bytes: usize,
+    /// 入站 HTTP/1 连接是否允许 keep-alive；关闭后每个请求结束即断开
+    pub http1_keepalive: bool,
+    /// 读取完整请求头的时限（毫秒），包括 keep-alive 连接上等待下一个请求；0 表示不限制
+    pub header_read_timeout_ms: u64,
+    /// 不做动态压缩的响应类型（按前缀匹配 Content-Type），默认排除已压缩/难以压缩的二进制
+    pub no_compress_types: Vec<String>,
+    /// 公网监听的每个连接以 PROXY protocol v1/v2 头部开始（如 HAProxy send-proxy），
+    /// 以其中的客户端地址取代套接字对端；头部缺失或格式错误时关闭连接
+    pub proxy_protocol: bool,
+    /// drain 模式下代替挑战返回的 503 所带的 Retry-After 秒数
+    pub drain_retry_after_secs: u64,
+    /// 公网监听直接终结 HTTPS；未配置时为明文 HTTP，由前置代理负责 TLS
+    pub tls: Option<TlsConfig>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             listen: "0.0.0.0:8080".to_string(),
+            admin_listen: None,
+            otel: None,
+            max_header_count: 0,
+            max_header_bytes: 0,
+            http1_keepalive: true,
+            header_read_timeout_ms: 10_000,
+            no_compress_types: vec![
+                "application/octet-stream".to_string(),
+                "application/wasm".to_string(),
+            ],
+            proxy_protocol: false,
+            drain_retry_after_secs: 30,
+            tls: None,
+        }
+    }
+}
+
+/// server.tls：PEM 格式的证书链与私钥（PKCS#8、PKCS#1 或 SEC1），启动时加载
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OtelConfig {
+    /// OTLP gRPC 接收端，例如 http://127.0.0.1:4317
+    pub endpoint: String,
+    pub service_name: String,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:4317".to_string(),
+            service_name: "cowcat-rs".to_string(),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct PowConfig {
     pub difficulty: i32,
     pub cookie_expire_hours: i64,
     pub salt: String,
+    /// 具名的 Cookie 签名密钥，用于分阶段轮换；salt 派生的密钥始终作为 primary 校验 v1 Cookie
+    pub cookie_keys: Vec<CookieKey>,
+    /// 签发 Cookie 使用的 kid（须在 cookie_keys 中）；为空时仍用 primary 签发 v1 Cookie
+    pub active_cookie_kid: String,
     pub workers: i32,
     pub worker_type: String,
+    /// PoW 哈希算法：sha256 或 sha3-256；非默认值经任务帧 TLV 0x0d 告知客户端
+    pub hash_algo: String,
     pub ip_policy: IpPolicy,
+    /// 可信反向代理的 CIDR 列表：只有直连对端在其中时才采信 X-Real-IP / X-Forwarded-For，
+    /// 否则使用套接字对端地址；为空时信任所有对端
+    pub trusted_proxies: Vec<String>,
     pub test_mode: bool,
+    pub dev_mode: bool,
     pub secure: bool,
+    pub challenge_webhook: String,
+    pub debug_token: String,
+    pub self_test: bool,
+    pub failure_ban: Option<FailureBanConfig>,
+    pub min_solve_ms: u64,
+    pub min_solve_enforce: bool,
+    /// 链式挑战长度：第 i+1 个任务的 seed 由第 i 个的解派生，客户端只能顺序求解；1 表示单个挑战
+    pub chain_length: u32,
+    /// 轻量的路径前缀 → 难度映射，最长前缀优先；规则引擎仍可在此基础上调整
+    pub path_difficulty: Vec<PathDifficulty>,
+    pub service_worker_mode: ServiceWorkerMode,
+    /// 携带其中任一 Cookie（非空）的请求直接放行；仅检查存在性，属于安全上的取舍
+    pub skip_if_cookie: Vec<String>,
+    /// 挑战页脚本/wasm 的外部 CDN 基础 URL；为空时由本机 /__cowcatwaf/assets 提供
+    pub asset_base_url: String,
+    /// 挑战页的 HTTP 状态码，取值见 CHALLENGE_STATUSES
+    pub challenge_status: u16,
+    /// verify 成功响应中附带本次求解的 bits
+    pub verify_response_bits: bool,
+    /// 下发的任务附带 HMAC-SHA256 签名（TLV_SIG），verify 请求必须原样带回；
+    /// 关闭时不下发也不校验，旧版页面照常工作
+    pub signed_tasks: bool,
+    /// 每次下发任务时生成只存服务端的随机数，任务帧携带 HMAC(task_id, 随机数) 承诺（TLV_COMMITMENT），
+    /// verify 请求必须原样带回，把求解绑定到这一次下发的页面
+    pub bind_commitment: bool,
+    /// 通行 Cookie 绑定签发时连接的 TLS 指纹（协商的 cipher suite 与 ClientHello 能力列表的哈希），
+    /// 换了 TLS 栈的客户端拿着同一 Cookie 会重新挑战；需要 server.tls
+    pub bind_fingerprint: bool,
+    /// 允许不支持 Cookie 的客户端用 Authorization: Bearer / X-Cowcat-Token 携带通行令牌；
+    /// verify 响应同时在 X-Cowcat-Token 头（Accept JSON 时还在 JSON 响应体）中返回令牌
+    pub accept_bearer_token: bool,
+    /// 可选的预生成任务池，摊薄高频下发时的随机数开销
+    pub task_pool: Option<TaskPoolConfig>,
+    /// 客户端 wasm worker 不可用时，允许经 /__cowcatwaf/fallback 换取 native worker 的低难度任务
+    pub wasm_fallback: Option<WasmFallbackConfig>,
+    /// PoW 跑不动（无 JS、辅助技术）时改用 /__cowcatwaf/captcha 的算术验证码换取同样的 Cookie
+    pub captcha_fallback: Option<CaptchaFallbackConfig>,
+    /// 新访客的第一个需要挑战的请求直接放行并种下一次性的宽限 Cookie，之后仍无通行 Cookie 的请求照常挑战
+    pub first_request_grace: Option<FirstRequestGraceConfig>,
+    /// redirect 的最大字节数（去除控制字符后），超出时改为 "/"；0 为不限制
+    pub max_redirect_len: usize,
+    /// 每个 IP 每分钟最多下发的挑战数（令牌桶，允许同样大小的突发），超出返回 429；0 为不限制
+    pub challenge_rate_per_min: u32,
+    /// /verify 的按 IP 与全局限流，超出返回 429
+    pub verify_rate_limit: Option<VerifyRateLimitConfig>,
+    /// 挑战接口的 CORS，供不同源的 SPA 调用；未配置时仅同源
+    pub cors: Option<CorsConfig>,
+    /// 按路径、请求头、IP 累加风险分并映射为基础难度，取代 path_difficulty / difficulty
+    pub risk_score: Option<RiskScoreConfig>,
+    /// 哈希原像的构造方式，必须与 worker 一致；默认即内置 worker 使用的 v1
+    pub scheme: PowScheme,
+    pub page: PageConfig,
+}
+
+/// salt 与 debug_token 不随启动日志（print_config）输出
+impl std::fmt::Debug for PowConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 完整解构：新增字段时编译器会提醒在这里补上
+        let Self {
+            difficulty,
+            cookie_expire_hours,
+            salt,
+            cookie_keys,
+            active_cookie_kid,
+            workers,
+            worker_type,
+            hash_algo,
+            ip_policy,
+            trusted_proxies,
+            test_mode,
+            dev_mode,
+            secure,
+            challenge_webhook,
+            debug_token,
+            self_test,
+            failure_ban,
+            min_solve_ms,
+            min_solve_enforce,
+            chain_length,
+            path_difficulty,
+            service_worker_mode,
+            skip_if_cookie,
+            asset_base_url,
+            challenge_status,
+            verify_response_bits,
+            signed_tasks,
+            bind_commitment,
+            bind_fingerprint,
+            accept_bearer_token,
+            task_pool,
+            wasm_fallback,
+            captcha_fallback,
+            first_request_grace,
+            max_redirect_len,
+            challenge_rate_per_min,
+            verify_rate_limit,
+            cors,
+            risk_score,
+            scheme,
+            page,
+        } = self;
+        f.debug_struct("PowConfig")
+            .field("difficulty", difficulty)
+            .field("cookie_expire_hours", cookie_expire_hours)
+            .field("salt", &redacted_str(salt))
+            .field("cookie_keys", cookie_keys)
+            .field("active_cookie_kid", active_cookie_kid)
+            .field("workers", workers)
+            .field("worker_type", worker_type)
+            .field("hash_algo", hash_algo)
+            .field("ip_policy", ip_policy)
+            .field("trusted_proxies", trusted_proxies)
+            .field("test_mode", test_mode)
+            .field("dev_mode", dev_mode)
+            .field("secure", secure)
+            .field("challenge_webhook", challenge_webhook)
+            .field("debug_token", &redacted_str(debug_token))
+            .field("self_test", self_test)
+            .field("failure_ban", failure_ban)
+            .field("min_solve_ms", min_solve_ms)
+            .field("min_solve_enforce", min_solve_enforce)
+            .field("chain_length", chain_length)
+            .field("path_difficulty", path_difficulty)
+            .field("service_worker_mode", service_worker_mode)
+            .field("skip_if_cookie", skip_if_cookie)
+            .field("asset_base_url", asset_base_url)
+            .field("challenge_status", challenge_status)
+            .field("verify_response_bits", verify_response_bits)
+            .field("signed_tasks", signed_tasks)
+            .field("bind_commitment", bind_commitment)
+            .field("bind_fingerprint", bind_fingerprint)
+            .field("accept_bearer_token", accept_bearer_token)
+            .field("task_pool", task_pool)
+            .field("wasm_fallback", wasm_fallback)
+            .field("captcha_fallback", captcha_fallback)
+            .field("first_request_grace", first_request_grace)
+            .field("max_redirect_len", max_redirect_len)
+            .field("challenge_rate_per_min", challenge_rate_per_min)
+            .field("verify_rate_limit", verify_rate_limit)
+            .field("cors", cors)
+            .field("risk_score", risk_score)
+            .field("scheme", scheme)
+            .field("page", page)
+            .finish()
+    }
+}
+
+/// 未配置时原样显示空串，便于看出是否设置
+fn redacted_str(value: &str) -> &str {
+    if value.is_empty() {
+        ""
+    } else {
+        "<redacted>"
+    }
 }
 
 impl Default for PowConfig {
@@ -267,57 +695,551 @@ impl Default for PowConfig {
             difficulty: 3,
             cookie_expire_hours: 24,
             salt: String::new(),
+            cookie_keys: Vec::new(),
+            active_cookie_kid: String::new(),
             workers: 4,
             worker_type: "wasm".to_string(),
+            hash_algo: "sha256".to_string(),
             ip_policy: IpPolicy::None,
+            trusted_proxies: Vec::new(),
             test_mode: false,
+            dev_mode: false,
             secure: true,
+            challenge_webhook: String::new(),
+            debug_token: String::new(),
+            self_test: false,
+            failure_ban: None,
+            min_solve_ms: 0,
+            min_solve_enforce: false,
+            chain_length: 1,
+            path_difficulty: Vec::new(),
+            service_worker_mode: ServiceWorkerMode::default(),
+            skip_if_cookie: Vec::new(),
+            asset_base_url: String::new(),
+            challenge_status: 403,
+            verify_response_bits: false,
+            signed_tasks: false,
+            bind_commitment: false,
+            bind_fingerprint: false,
+            accept_bearer_token: false,
+            task_pool: None,
+            wasm_fallback: None,
+            captcha_fallback: None,
+            first_request_grace: None,
+            max_redirect_len: 2048,
+            challenge_rate_per_min: 0,
+            verify_rate_limit: None,
+            cors: None,
+            risk_score: None,
+            scheme: PowScheme::default(),
+            page: PageConfig::default(),
+        }
+    }
+}
+
+impl PowConfig {
+    /// path_difficulty 中最长匹配前缀的难度；无匹配时回落到全局 difficulty
+    pub fn base_difficulty(&self, path: &str) -> i32 {
+        self.path_difficulty
+            .iter()
+            .filter(|entry| path.starts_with(entry.prefix.as_str()))
+            .max_by_key(|entry| entry.prefix.len())
+            .map(|entry| crate::rules::clamp_difficulty(entry.difficulty))
+            .unwrap_or(self.difficulty)
+    }
+
+    /// 已在 validate 中校验过，无法识别时不会走到这里
+    pub fn hash_algo(&self) -> HashAlgo {
+        HashAlgo::from_name(&self.hash_algo).unwrap_or_default()
+    }
+}
+
+/// Service Worker 脚本请求（绕过 PoW）的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceWorkerMode {
+    /// 转发给上游（上游没有该脚本时注册会失败）
+    #[default]
+    Proxy,
+    /// 直接返回一个空的 Service Worker 脚本，让注册无害地成功
+    Empty,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PathDifficulty {
+    pub prefix: String,
+    pub difficulty: i32,
+}
+
+/// 原像中可出现的字段；nonce 总是最后一个，不在此列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowField {
+    Version,
+    Seed,
+    Exp,
+    Bits,
+    Scope,
+    UaHash,
+}
+
+/// 原像 = fields 依次以 divider 连接，再接 divider + nonce
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PowScheme {
+    /// Version 字段写入原像的取值
+    pub version: String,
+    pub divider: String,
+    pub fields: Vec<PowField>,
+}
+
+impl Default for PowScheme {
+    /// v1：v1|seed|exp|bits|scope|ua_hash|nonce
+    fn default() -> Self {
+        Self {
+            version: "v1".to_string(),
+            divider: "|".to_string(),
+            fields: vec![
+                PowField::Version,
+                PowField::Seed,
+                PowField::Exp,
+                PowField::Bits,
+                PowField::Scope,
+                PowField::UaHash,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TaskPoolConfig {
+    pub size: usize,
+    pub refill_interval_ms: u64,
+}
+
+impl Default for TaskPoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            refill_interval_ms: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RiskScoreConfig {
+    pub path: Vec<RiskPathWeight>,
+    pub header: Vec<RiskHeaderWeight>,
+    pub ip: Vec<RiskIpWeight>,
+    /// 分数 → 难度的阶梯，取 min_score 不超过总分的最高一档
+    pub curve: Vec<RiskStep>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RiskPathWeight {
+    pub prefix: String,
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RiskHeaderWeight {
+    #[serde(flatten)]
+    pub matcher: HeaderMatch,
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RiskIpWeight {
+    pub cidr: Vec<String>,
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RiskStep {
+    pub min_score: u32,
+    pub difficulty: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// 允许的 Origin 列表，如 "https://app.example.com"；"*" 表示任意来源（不可与 allow_credentials 同用）
+    pub allowed_origins: Vec<String>,
+    /// 允许携带凭据，跨域 verify 下发的 Cookie 才会被浏览器保存
+    pub allow_credentials: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VerifyRateLimitConfig {
+    /// 每个 IP 在 window_secs 内最多的 verify 次数，0 表示不按 IP 限制
+    pub per_ip: u32,
+    /// 所有客户端合计在 window_secs 内最多的 verify 次数，0 表示不限制
+    pub global: u32,
+    pub window_secs: u64,
+}
+
+impl Default for VerifyRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: 30,
+            global: 0,
+            window_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WasmFallbackConfig {
+    /// 降级任务的难度，native worker 走 WebCrypto，明显慢于 wasm
+    pub difficulty: i32,
+    /// 每个 IP 在 window_secs 内最多获得的降级任务数
+    pub max_per_ip: u32,
+    pub window_secs: u64,
+}
+
+impl Default for WasmFallbackConfig {
+    fn default() -> Self {
+        Self {
+            difficulty: 2,
+            max_per_ip: 3,
+            window_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FirstRequestGraceConfig {
+    /// 宽限 Cookie 的有效期，同时也是同一 IP 两次宽限之间的最短间隔（防止丢弃 Cookie 的客户端反复领取）
+    pub window_secs: u64,
+}
+
+impl Default for FirstRequestGraceConfig {
+    fn default() -> Self {
+        Self { window_secs: 3600 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CaptchaFallbackConfig {
+    /// 每个 IP 在 window_secs 内最多获得的验证码数（答错后换的新题也计入）
+    pub max_per_ip: u32,
+    pub window_secs: u64,
+}
+
+impl Default for CaptchaFallbackConfig {
+    fn default() -> Self {
+        Self {
+            max_per_ip: 10,
+            window_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FailureBanConfig {
+    pub threshold: u32,
+    pub base_ttl_secs: u64,
+}
+
+impl Default for FailureBanConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            base_ttl_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PageConfig {
+    pub assets_dir: String,
+    pub theme: PageTheme,
+    /// 挑战页响应附带 Link: rel=preload，让浏览器并行拉取脚本与 wasm
+    pub preload: bool,
+    /// 把页面脚本、worker 脚本与 wasm（data URL）全部内联进挑战页，页面不再请求任何静态资源
+    pub inline_all: bool,
+    /// 启动时从该 http:// 地址拉取挑战页 HTML（须含必需占位符），失败时回落到内嵌页面
+    pub remote_url: String,
+    pub remote_timeout_ms: u64,
+    /// 大于 0 时按此间隔重新拉取，失败保留当前页面
+    pub remote_refresh_secs: u64,
+}
+
+impl Default for PageConfig {
+    fn default() -> Self {
+        Self {
+            assets_dir: String::new(),
+            theme: PageTheme::default(),
+            preload: true,
+            inline_all: false,
+            remote_url: String::new(),
+            remote_timeout_ms: 5000,
+            remote_refresh_secs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageTheme {
+    /// 跟随浏览器 prefers-color-scheme
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl PageTheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PageTheme::Auto => "auto",
+            PageTheme::Light => "light",
+            PageTheme::Dark => "dark",
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub tokens: Vec<AdminToken>,
+}
+
+/// 挑战任务的存储后端
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// backend = "redis" 时的连接地址，如 redis://:password@127.0.0.1:6379/0
+    pub redis_url: String,
+    /// 任务 key 的前缀，多个部署共用一个 Redis 时用它隔离
+    pub redis_key_prefix: String,
+    /// 连接与单条命令的超时（毫秒）
+    pub redis_timeout_ms: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            redis_url: "redis://127.0.0.1:6379".to_string(),
+            redis_key_prefix: "cowcat:task:".to_string(),
+            redis_timeout_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// 进程内 HashMap，重启即丢失，不能跨实例共享
+    #[default]
+    Memory,
+    /// Redis（>= 6.2，需要 GETDEL），重启不丢失，可供多个实例共享
+    Redis,
+}
+
+/// `/favicon.ico` 回源缓存
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FaviconConfig {
+    /// 启动后立即预取一次 favicon，首个请求不必等待回源
+    pub warm_on_start: bool,
+    /// 可缓存的上游状态码；为空时缓存任意 2xx
+    pub cache_statuses: Vec<u16>,
+    /// 非 2xx（如 404）缓存条目的有效期，通常应短于 2xx 的 1 小时
+    pub error_ttl_secs: u64,
+}
+
+impl Default for FaviconConfig {
+    fn default() -> Self {
+        Self {
+            warm_on_start: false,
+            cache_statuses: Vec::new(),
+            error_ttl_secs: 300,
+        }
+    }
+}
+
+/// 2xx favicon 的缓存有效期
+const FAVICON_TTL: Duration = Duration::from_secs(3600);
+
+impl FaviconConfig {
+    /// 上游响应可缓存时返回其有效期
+    pub fn cache_ttl(&self, status: http::StatusCode) -> Option<Duration> {
+        let cacheable = if self.cache_statuses.is_empty() {
+            status.is_success()
+        } else {
+            self.cache_statuses.contains(&status.as_u16())
+        };
+        if !cacheable {
+            return None;
+        }
+        Some(if status.is_success() {
+            FAVICON_TTL
+        } else {
+            Duration::from_secs(self.error_ttl_secs)
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CookieKey {
+    pub kid: String,
+    pub secret: String,
+}
+
+impl std::fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieKey")
+            .field("kid", &self.kid)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct AdminToken {
+    pub id: String,
+    pub token: String,
+}
+
+impl std::fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminToken")
+            .field("id", &self.id)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct ProxyConfig {
     pub target: String,
+    /// 多个等价的默认上游，非空时取代 target，按轮询分摊请求；连接失败时换下一个重试
+    pub targets: Vec<String>,
     pub host_rule: Vec<ProxyHostRule>,
+    /// 洪峰期间合并相同的可缓存 GET，默认关闭
+    pub micro_cache: MicroCacheConfig,
+    /// 连接上游的超时（毫秒），不可达的上游尽快失败返回 504，而不是等系统默认的几十秒；0 表示不限制
+    pub connect_timeout_ms: u64,
+    /// 从发出请求到收到上游响应头的总超时（毫秒），超时返回 504；不限制响应体的传输时间；0 表示不限制
+    pub request_timeout_ms: u64,
+    /// 向上游发送 X-Forwarded-Port（取 server.listen 的端口），上游已有该头时保留原值
+    pub emit_forwarded_port: bool,
+    /// 开启后 Host 既不匹配 host_rule 也不在 allowed_hosts 中的请求直接返回 421，不再回落到默认上游
+    pub strict_host: bool,
+    /// strict_host 下允许使用默认上游 target 的 Host 列表
+    pub allowed_hosts: Vec<String>,
+    /// 对所有上游响应（含 micro_cache 命中）增删响应头；挑战页等本服务生成的响应不受影响
+    pub response_headers: ResponseHeadersConfig,
 }
 
 impl Default for ProxyConfig {
     fn default() -> Self {
         Self {
             target: "http://127.0.0.1:1234".to_string(),
+            targets: Vec::new(),
             host_rule: Vec::new(),
+            micro_cache: MicroCacheConfig::default(),
+            connect_timeout_ms: 3_000,
+            request_timeout_ms: 30_000,
+            emit_forwarded_port: false,
+            strict_host: false,
+            allowed_hosts: Vec::new(),
+            response_headers: ResponseHeadersConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ResponseHeadersConfig {
+    /// 头名 → 值，覆盖上游的同名头
+    pub add: BTreeMap<String, String>,
+    /// 先于 add 执行，头名不区分大小写
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MicroCacheConfig {
+    pub enabled: bool,
+    pub ttl_secs: u64,
+    /// 全部缓存响应体的字节数上限
+    pub max_bytes: usize,
+}
+
+impl Default for MicroCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 2,
+            max_bytes: 64 * 1024 * 1024,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProxyHostRule {
     pub host: String,
     pub target: String,
+    pub page: Option<HostPageConfig>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+/// 按 host 覆盖挑战页品牌：html/css 为文件路径，image1/image2 为 webp 图片路径
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HostPageConfig {
+    pub html: Option<String>,
+    pub css: Option<String>,
+    pub image1: Option<String>,
+    pub image2: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum IpPolicy {
+    #[default]
     None,
     Enable,
     Strict,
 }
 
-impl Default for IpPolicy {
-    fn default() -> Self {
-        IpPolicy::None
-    }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnInvalid {
+    #[default]
+    Fail,
+    Skip,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct RulesConfig {
     pub enabled: bool,
     pub default_action: RuleAction,
     pub allow_wellknown: bool,
+    /// 内置启发式：路径穿越、空字节、过长 UTF-8 编码；独立于 enabled 和用户规则
+    pub block_suspicious_paths: bool,
+    /// 命中后的动作，只允许 block / challenge
+    pub suspicious_path_action: RuleAction,
+    /// 蜜罐路径（解码后精确匹配）：正常访客不会访问，命中即按 honeypot_ban_secs 封禁该 IP
+    pub honeypot_paths: Vec<String>,
+    pub honeypot_ban_secs: u64,
+    /// 外部 IP 信誉查询：分数达到 threshold 的 IP 在下发挑战前被封禁或加难度；启动时读取
+    pub reputation: Option<ReputationConfig>,
+    /// 单条规则无效（CIDR、header 等写错）时：fail 拒绝整套规则，skip 记录并丢弃该条
+    pub on_invalid: OnInvalid,
     pub rules_file: Option<String>,
     pub rule: Vec<RuleConfig>,
 }
@@ -328,12 +1250,53 @@ impl Default for RulesConfig {
             enabled: false,
             default_action: RuleAction::Challenge,
             allow_wellknown: true,
+            block_suspicious_paths: false,
+            suspicious_path_action: RuleAction::Block,
+            honeypot_paths: Vec::new(),
+            honeypot_ban_secs: 3600,
+            reputation: None,
+            on_invalid: OnInvalid::Fail,
             rules_file: None,
             rule: Vec::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReputationConfig {
+    /// GET 查询地址，`{ip}` 替换为客户端 IP；不含 `{ip}` 时追加 `ip=` 查询参数。响应为 `{"score": <number>}`
+    pub endpoint: String,
+    /// 分数不低于该值即视为恶意 IP
+    pub threshold: f64,
+    /// 命中后的动作，只允许 block / challenge
+    pub action: RuleAction,
+    /// action = challenge 时在原难度上增加的位数
+    pub difficulty_delta: i32,
+    /// 单次查询超时，超时按查询失败处理（放行，不改变判定）
+    pub timeout_ms: u64,
+    pub cache_ttl_secs: u64,
+    /// 查询失败的缓存时长，避免查询服务故障时每个请求都等待超时
+    pub error_ttl_secs: u64,
+    /// 缓存条目上限，满时不缓存新 IP（过期条目每分钟清理一次）
+    pub cache_max_entries: usize,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            threshold: 50.0,
+            action: RuleAction::Block,
+            difficulty_delta: 2,
+            timeout_ms: 300,
+            cache_ttl_secs: 600,
+            error_ttl_secs: 30,
+            cache_max_entries: 100_000,
+        }
+    }
+}
+
 impl RulesConfig {
     pub fn get_rule_len(&self) -> usize {
         self.rule.len()
@@ -344,17 +1307,29 @@ impl RulesConfig {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct RuleConfig {
     pub name: Option<String>,
     pub enabled: bool,
     pub action: RuleAction,
     pub difficulty_delta: Option<i32>,
+    /// 取代基础难度（pow.difficulty / path_difficulty）的绝对难度，与 difficulty_delta 同时设置时优先
+    pub difficulty_absolute: Option<i32>,
+    pub worker_type: Option<String>,
     pub path_prefix: Option<String>,
     pub path_exact: Option<String>,
+    /// 匹配解码一次后 path 的正则（未锚定，需要整段匹配时写 ^...$）
+    pub path_regex: Option<String>,
+    pub path_raw_prefix: Option<String>,
+    pub path_raw_contains: Option<String>,
     pub header: Option<HeaderMatch>,
     pub ip_cidr: Option<Vec<String>>,
+    pub accept_language: Option<Vec<String>>,
+    /// 生效时段 "HH:MM-HH:MM"（左闭右开），起点晚于终点表示跨午夜，如 "22:00-06:00"
+    pub active_hours: Option<String>,
+    /// active_hours 使用的时区，固定偏移 "+08:00" / "-05:30" 或 "UTC"（默认）
+    pub timezone: Option<String>,
 }
 
 impl Default for RuleConfig {
@@ -364,17 +1339,130 @@ impl Default for RuleConfig {
             enabled: true,
             action: RuleAction::Challenge,
             difficulty_delta: None,
+            difficulty_absolute: None,
+            worker_type: None,
             path_prefix: None,
             path_exact: None,
+            path_regex: None,
+            path_raw_prefix: None,
+            path_raw_contains: None,
             header: None,
             ip_cidr: None,
+            accept_language: None,
+            active_hours: None,
+            timezone: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HeaderMatch {
     pub name: String,
     pub equals: Option<String>,
     pub contains: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 写入临时目录下的配置文件，文件名带测试名避免并行测试互相覆盖
+    fn write_config(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("cowcat-config-test-{}-{name}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn pow_debug_output_redacts_secrets() {
+        let mut pow = PowConfig::default();
+        let printed = format!("{pow:?}");
+        assert!(printed.contains(r#"debug_token: """#), "{printed}");
+
+        pow.salt = "salt-secret".to_string();
+        pow.debug_token = "debug-secret".to_string();
+        pow.cookie_keys = vec![CookieKey { kid: "k1".to_string(), secret: "cookie-secret".to_string() }];
+        let printed = format!("{pow:?}");
+        for secret in ["salt-secret", "debug-secret", "cookie-secret"] {
+            assert!(!printed.contains(secret), "{secret} leaked: {printed}");
+        }
+        assert!(printed.contains(r#"debug_token: "<redacted>""#), "{printed}");
+        assert!(printed.contains("difficulty: 3"), "{printed}");
+    }
+
+    #[test]
+    fn later_file_overrides_scalars() {
+        let base = write_config("scalar-base", "[pow]\ndifficulty = 3\nworkers = 2\n");
+        let over = write_config("scalar-over", "[pow]\ndifficulty = 6\n");
+
+        let cfg = Config::load(&[base, over]).unwrap();
+        assert_eq!(cfg.pow.difficulty, 6);
+        assert_eq!(cfg.pow.workers, 2);
+    }
+
+    #[test]
+    fn tables_merge_field_by_field() {
+        let base = write_config(
+            "table-base",
+            "[server]\nlisten = \"127.0.0.1:9000\"\n[proxy]\ntarget = \"http://10.0.0.1:80\"\nallowed_hosts = [\"a.example\", \"b.example\"]\n",
+        );
+        let over = write_config("table-over", "[proxy]\nallowed_hosts = [\"c.example\"]\n");
+
+        let cfg = Config::load(&[base, over]).unwrap();
+        assert_eq!(cfg.server.listen, "127.0.0.1:9000");
+        assert_eq!(cfg.proxy.target, "http://10.0.0.1:80");
+        // 数组整体替换，不做拼接
+        assert_eq!(cfg.proxy.allowed_hosts, vec!["c.example".to_string()]);
+    }
+
+    #[test]
+    fn missing_files_are_skipped() {
+        let base = write_config("missing-base", "[pow]\ndifficulty = 5\n");
+        let missing = std::env::temp_dir()
+            .join("cowcat-config-test-does-not-exist.toml")
+            .to_string_lossy()
+            .into_owned();
+
+        let cfg = Config::load(&[base, missing]).unwrap();
+        assert_eq!(cfg.pow.difficulty, 5);
+    }
+
+    #[test]
+    fn dump_reflects_merged_values_and_redacts_secrets() {
+        let path = write_config(
+            "dump",
+            "[server]\nlisten = \"127.0.0.1:9100\"\n[pow]\ndifficulty = 7\nsalt = \"s3cret-salt\"\ndebug_token = \"dbg\"\n[storage]\nredis_url = \"redis://user:pw@cache:6379\"\n",
+        );
+        let dumped = Config::load(&[path]).unwrap().dump_toml().unwrap();
+
+        let value: toml::Value = toml::from_str(&dumped).unwrap();
+        assert_eq!(value["server"]["listen"].as_str(), Some("127.0.0.1:9100"));
+        assert_eq!(value["pow"]["difficulty"].as_integer(), Some(7));
+        assert_eq!(value["pow"]["salt"].as_str(), Some("<redacted>"));
+        assert_eq!(value["pow"]["debug_token"].as_str(), Some("<redacted>"));
+        assert_eq!(value["storage"]["redis_url"].as_str(), Some("<redacted>"));
+        assert!(!dumped.contains("s3cret-salt"));
+        assert!(!dumped.contains("pw@cache"));
+    }
+
+    #[test]
+    fn dump_is_loadable_and_stable() {
+        let path = write_config("dump-stable", "[pow]\ndifficulty = 4\n[proxy]\nallowed_hosts = [\"a.example\"]\n");
+        let first = Config::load(&[path]).unwrap().dump_toml().unwrap();
+        let reloaded = write_config("dump-stable-reloaded", &first);
+        let second = Config::load(&[reloaded]).unwrap().dump_toml().unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn bind_fingerprint_needs_local_tls() {
+        let plain = write_config("fp-plain", "[pow]\nbind_fingerprint = true\n");
+        let err = Config::load(&[plain]).err().unwrap().to_string();
+        assert!(err.contains("pow.bind_fingerprint needs server.tls"), "{err}");
+
+        let mut config = Config::default();
+        config.pow.bind_fingerprint = true;
+        config.server.tls = Some(TlsConfig { cert_path: "cert.pem".to_string(), key_path: "key.pem".to_string() });
+        assert!(config.validate().is_ok());
+    }
+}
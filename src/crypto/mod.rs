@@ -1,5 +1,6 @@
 mod pow;
 mod token;
+mod verifier;
 
 use std::net::IpAddr;
 
@@ -11,10 +12,12 @@ use ring::rand::{SecureRandom, SystemRandom};
 use base64::Engine;
 
 use crate::config::IpPolicy;
+use crate::ip_source::ip::TrustedProxies;
 use crate::protocol::http::HeaderMapExt;
 
-pub use pow::verify_pow;
-pub use token::{generate_cookie, verify_cookie};
+pub use pow::{pow_preimage, solve_pow, verify_pow, verify_pow_chain, HashAlgo};
+pub use token::{generate_cookie, verify_cookie, CookieKeyring};
+pub use verifier::{PowVerifier, Verifier};
 
 pub fn compute_ua_hash(user_agent: &str) -> String {
     let digest = ring::digest::digest(&ring::digest::SHA256, user_agent.as_bytes());
@@ -29,6 +32,14 @@ pub fn compute_ip_hash(ip: &str) -> String {
     base64::engine::general_purpose::URL_SAFE.encode(&digest.as_ref()[..8])
 }
 
+/// 常量时间比较，用于校验调试令牌等秘密值
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 pub fn generate_random_id() -> anyhow::Result<String> {
     let rng = SystemRandom::new();
     let mut buf = vec![0u8; 16];
@@ -44,9 +55,15 @@ pub fn generate_random_seed() -> anyhow::Result<String> {
     Ok(base64::engine::general_purpose::URL_SAFE.encode(buf))
 }
 
-pub fn extract_client_ip(headers: &HeaderMap, extensions: &Extensions, policy: IpPolicy) -> String {
+pub fn extract_client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    policy: IpPolicy,
+    trusted: &TrustedProxies,
+) -> String {
     match policy {
         IpPolicy::None => String::new(),
+        IpPolicy::Enable if !trusted.trusts_peer(extensions) => remote_ip(extensions).unwrap_or_default(),
         IpPolicy::Enable => {
             if let Some(ip) = headers.get_ip(header::HeaderName::from_static("x-real-ip")) {
                 return ip;
@@ -66,10 +83,37 @@ fn remote_ip(extensions: &Extensions) -> Option<String> {
 }
 
 pub fn parse_ip(ip: &str) -> Option<IpAddr> {
-    let trimmed = ip.trim();
-    if trimmed.is_empty() {
-        None
-    } else {
-        trimmed.parse::<IpAddr>().ok()
+    crate::ip_source::ip::parse_forwarded_ip(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_untrusted_peer() -> (HeaderMap, Extensions, TrustedProxies) {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo("198.51.100.1:4000".parse::<std::net::SocketAddr>().unwrap()));
+        let trusted = TrustedProxies::from_config(&["10.0.0.0/8".to_string()]).unwrap();
+        (headers, extensions, trusted)
+    }
+
+    #[test]
+    fn untrusted_peer_cannot_spoof_the_bound_ip() {
+        let (headers, extensions, trusted) = from_untrusted_peer();
+        assert_eq!(extract_client_ip(&headers, &extensions, IpPolicy::Enable, &trusted), "198.51.100.1");
+        assert_eq!(extract_client_ip(&headers, &extensions, IpPolicy::Strict, &trusted), "198.51.100.1");
+        assert_eq!(extract_client_ip(&headers, &extensions, IpPolicy::None, &trusted), "");
+    }
+
+    #[test]
+    fn trusted_peer_forwards_the_client_ip() {
+        let (headers, _, trusted) = from_untrusted_peer();
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo("10.9.8.7:4000".parse::<std::net::SocketAddr>().unwrap()));
+        assert_eq!(extract_client_ip(&headers, &extensions, IpPolicy::Enable, &trusted), "203.0.113.7");
+        // Strict 始终只看直连对端
+        assert_eq!(extract_client_ip(&headers, &extensions, IpPolicy::Strict, &trusted), "10.9.8.7");
     }
 }
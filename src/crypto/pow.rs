@@ -1,29 +1,178 @@
+use std::time::Instant;
+
 use ring::digest::{Context, SHA256};
+use sha3::{Digest, Sha3_256};
 
+use crate::config::{PowField, PowScheme};
 use crate::storage::Task;
 
-const POW_VERSION: &str = "v1";
-const POW_DIVIDER: &str = "|";
+/// 每批尝试次数，批与批之间检查一次截止时间
+const SOLVE_BATCH: u32 = 4096;
+
+/// verify_pow_any 单批最多接受的 nonce 数，超出整批拒绝，避免一个请求触发大量哈希
+pub const MAX_BATCH_NONCES: usize = 64;
+
+/// pow.hash_algo：对原像求哈希的算法，前导零判定与原像格式不随算法变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Sha3_256,
+}
+
+impl HashAlgo {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "sha256" => Some(HashAlgo::Sha256),
+            "sha3-256" => Some(HashAlgo::Sha3_256),
+            _ => None,
+        }
+    }
+
+    /// 配置与任务帧（TLV 0x0d）中使用的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Sha3_256 => "sha3-256",
+        }
+    }
+}
+
+/// 已写入原像固定前缀的哈希状态
+#[derive(Clone)]
+enum PowHasher {
+    Sha256(Context),
+    Sha3(Sha3_256),
+}
+
+impl PowHasher {
+    fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Sha256 => PowHasher::Sha256(Context::new(&SHA256)),
+            HashAlgo::Sha3_256 => PowHasher::Sha3(Sha3_256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            PowHasher::Sha256(ctx) => ctx.update(data),
+            PowHasher::Sha3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> [u8; 32] {
+        match self {
+            PowHasher::Sha256(ctx) => ctx.finish().as_ref().try_into().expect("sha256 摘要为 32 字节"),
+            PowHasher::Sha3(hasher) => hasher.finalize().into(),
+        }
+    }
+
+    fn leading_zero_bits(self) -> i32 {
+        count_leading_zero_bits(&self.finish())
+    }
+}
+
+pub fn verify_pow(scheme: &PowScheme, algo: HashAlgo, task: &Task, nonce: &str) -> bool {
+    verify_pow_any(scheme, algo, task, &[nonce]).is_some()
+}
+
+/// 链式挑战：nonces 为逗号分隔的各环解，第 i+1 环的 seed 由第 i 环的 seed 与解派生，
+/// 环数必须与任务的 chain_length 一致
+pub fn verify_pow_chain(scheme: &PowScheme, algo: HashAlgo, task: &Task, nonces: &str) -> bool {
+    let nonces: Vec<&str> = nonces.split(',').collect();
+    if nonces.len() != task.chain_length.max(1) as usize {
+        return false;
+    }
+    let mut step = task.clone();
+    for nonce in nonces {
+        if !verify_pow(scheme, algo, &step, nonce) {
+            return false;
+        }
+        step.seed.0 = chain_seed(&step.seed.0, nonce);
+    }
+    true
+}
 
-pub fn verify_pow(task: &Task, nonce: &str) -> bool {
-    let mut ctx = Context::new(&SHA256);
-    append_digest_field(&mut ctx, POW_VERSION, false);
-    append_digest_field(&mut ctx, task.seed.0.as_str(), false);
-    append_digest_field(&mut ctx, task.exp.to_string().as_str(), false);
-    append_digest_field(&mut ctx, task.bits.to_string().as_str(), false);
-    append_digest_field(&mut ctx, task.scope.0.as_str(), false);
-    append_digest_field(&mut ctx, task.ua_hash.0.as_str(), false);
-    append_digest_field(&mut ctx, nonce, true);
-    let digest = ctx.finish();
-    let leading = count_leading_zero_bits(&digest.as_ref());
-    leading >= task.bits as i32
+/// 下一环的 seed；客户端（catpaw.js）按同样规则拼接
+pub fn chain_seed(seed: &str, nonce: &str) -> String {
+    format!("{seed}.{nonce}")
+}
+
+/// 批量校验：固定前缀只哈希一次，每个 nonce 克隆前缀状态再追加，返回第一个满足难度的 nonce；
+/// 空批次或超过 MAX_BATCH_NONCES 时返回 None
+pub fn verify_pow_any<'a>(scheme: &PowScheme, algo: HashAlgo, task: &Task, nonces: &[&'a str]) -> Option<&'a str> {
+    if nonces.len() > MAX_BATCH_NONCES {
+        return None;
+    }
+    let prefix = prefix_hasher(scheme, algo, task);
+    nonces.iter().copied().find(|nonce| {
+        let mut hasher = prefix.clone();
+        hasher.update(nonce.as_bytes());
+        hasher.leading_zero_bits() >= task.bits as i32
+    })
 }
 
-fn append_digest_field(ctx: &mut Context, param: &str, is_last: bool) {
-    ctx.update(param.as_bytes());
-    if !is_last {
-        ctx.update(POW_DIVIDER.as_bytes());
+pub struct SolveOutcome {
+    pub nonce: Option<String>,
+    pub attempts: u64,
+}
+
+/// 进程内求解：与 WASM/JS worker 一致，按十进制 u32 递增尝试 nonce。
+/// 超过截止时间仍未找到时 nonce 为 None，attempts 可用于估算哈希速率
+pub fn solve_pow(scheme: &PowScheme, algo: HashAlgo, task: &Task, deadline: Instant) -> SolveOutcome {
+    let prefix = prefix_hasher(scheme, algo, task);
+    let mut attempts = 0u64;
+    let mut nonce = 0u32;
+    loop {
+        for _ in 0..SOLVE_BATCH {
+            let candidate = nonce.to_string();
+            let mut hasher = prefix.clone();
+            hasher.update(candidate.as_bytes());
+            attempts += 1;
+            if hasher.leading_zero_bits() >= task.bits as i32 {
+                return SolveOutcome { nonce: Some(candidate), attempts };
+            }
+            nonce = match nonce.checked_add(1) {
+                Some(next) => next,
+                None => return SolveOutcome { nonce: None, attempts },
+            };
+        }
+        if Instant::now() >= deadline {
+            return SolveOutcome { nonce: None, attempts };
+        }
+    }
+}
+
+/// 完整的哈希原像：按 scheme 依次写入各字段，每个字段后跟分隔符，最后追加 nonce。
+/// 这是与 worker 之间的契约，自定义 worker 可以用它比对自己拼出的字节；
+/// hash_algo(原像) 的前导零位数不少于 task.bits 即为有效解
+pub fn pow_preimage(scheme: &PowScheme, task: &Task, nonce: &str) -> Vec<u8> {
+    let mut preimage = preimage_prefix(scheme, task);
+    preimage.extend_from_slice(nonce.as_bytes());
+    preimage
+}
+
+fn preimage_prefix(scheme: &PowScheme, task: &Task) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    for field in &scheme.fields {
+        match field {
+            PowField::Version => prefix.extend_from_slice(scheme.version.as_bytes()),
+            PowField::Seed => prefix.extend_from_slice(task.seed.0.as_bytes()),
+            PowField::Exp => prefix.extend_from_slice(task.exp.to_string().as_bytes()),
+            PowField::Bits => prefix.extend_from_slice(task.bits.to_string().as_bytes()),
+            PowField::Scope => prefix.extend_from_slice(task.scope.0.as_bytes()),
+            PowField::UaHash => prefix.extend_from_slice(task.ua_hash.0.as_bytes()),
+        }
+        prefix.extend_from_slice(scheme.divider.as_bytes());
     }
+    prefix
+}
+
+/// 原像中 nonce 之前的固定部分只哈希一次，逐个 nonce 克隆后再追加
+fn prefix_hasher(scheme: &PowScheme, algo: HashAlgo, task: &Task) -> PowHasher {
+    let mut hasher = PowHasher::new(algo);
+    hasher.update(&preimage_prefix(scheme, task));
+    hasher
 }
 
 fn count_leading_zero_bits(hash: &[u8]) -> i32 {
@@ -43,3 +192,205 @@ fn count_leading_zero_bits(hash: &[u8]) -> i32 {
     }
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{IpHash, Scope, Seed, TaskId, UaHash};
+
+    fn task(bits: u32) -> Task {
+        Task {
+            task_id: TaskId::from("task"),
+            seed: Seed("seed".to_string()),
+            bits,
+            exp: 1_700_000_000,
+            scope: Scope("example.com".to_string()),
+            ua_hash: UaHash("ua".to_string()),
+            ip_hash: IpHash("ip".to_string()),
+            issued_at_ms: 0,
+            chain_length: 1,
+            captcha_answer: None,
+            commitment_nonce: None,
+        }
+    }
+
+    fn solve(scheme: &PowScheme, task: &Task) -> String {
+        let deadline = Instant::now() + std::time::Duration::from_secs(10);
+        solve_pow(scheme, HashAlgo::Sha256, task, deadline).nonce.unwrap()
+    }
+
+    /// 从 0 起第一个不满足难度的 nonce
+    fn first_invalid(scheme: &PowScheme, task: &Task) -> String {
+        (0u32..)
+            .map(|n| n.to_string())
+            .find(|nonce| !verify_pow(scheme, HashAlgo::Sha256, task, nonce))
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_pow_any_returns_the_matching_nonce() {
+        let scheme = PowScheme::default();
+        let task = task(8);
+        let good = solve(&scheme, &task);
+        let bad = first_invalid(&scheme, &task);
+        assert_eq!(verify_pow_any(&scheme, HashAlgo::Sha256, &task, &[&bad, &good]), Some(good.as_str()));
+        assert_eq!(verify_pow_any(&scheme, HashAlgo::Sha256, &task, &[&bad]), None);
+    }
+
+    /// 按 catpaw.js 的方式逐环求解：每一环用上一环的 seed 与解派生新 seed
+    fn solve_chain(scheme: &PowScheme, task: &Task) -> Vec<String> {
+        let mut step = task.clone();
+        (0..task.chain_length)
+            .map(|_| {
+                let nonce = solve(scheme, &step);
+                step.seed.0 = chain_seed(&step.seed.0, &nonce);
+                nonce
+            })
+            .collect()
+    }
+
+    #[test]
+    fn solved_chains_verify() {
+        let scheme = PowScheme::default();
+        for chain_length in [1, 2, 3] {
+            let task = Task { chain_length, ..task(12) };
+            let nonces = solve_chain(&scheme, &task);
+            assert!(verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &nonces.join(",")), "{chain_length}");
+        }
+        assert_eq!(chain_seed("seed", "42"), "seed.42");
+    }
+
+    #[test]
+    fn malformed_chains_are_rejected() {
+        let scheme = PowScheme::default();
+        let task = Task { chain_length: 3, ..task(12) };
+        let nonces = solve_chain(&scheme, &task);
+
+        let mut reordered = nonces.clone();
+        reordered.swap(1, 2);
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &reordered.join(",")));
+        reordered = nonces.clone();
+        reordered.swap(0, 1);
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &reordered.join(",")));
+
+        // 环数必须与 chain_length 一致：少一环、多一环、空串都不行
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &nonces[..2].join(",")));
+        let extra = format!("{},{}", nonces.join(","), nonces[2]);
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &extra));
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, ""));
+
+        // 每一环都对原始 seed 求解（未派生）只有第一环有效
+        let undelegated = solve(&scheme, &Task { chain_length: 1, ..task.clone() });
+        let mut second = task.clone();
+        second.seed.0 = chain_seed(&task.seed.0, &nonces[0]);
+        assert!(verify_pow(&scheme, HashAlgo::Sha256, &task, &undelegated));
+        assert!(!verify_pow(&scheme, HashAlgo::Sha256, &second, &undelegated));
+        let forged = format!("{},{},{}", nonces[0], undelegated, nonces[2]);
+        assert!(!verify_pow_chain(&scheme, HashAlgo::Sha256, &task, &forged));
+    }
+
+    #[test]
+    fn verify_pow_any_rejects_empty_and_oversize_batches() {
+        let scheme = PowScheme::default();
+        let task = task(8);
+        let good = solve(&scheme, &task);
+        assert_eq!(verify_pow_any(&scheme, HashAlgo::Sha256, &task, &[]), None);
+
+        let mut batch = vec!["x"; MAX_BATCH_NONCES];
+        batch.push(&good);
+        assert_eq!(verify_pow_any(&scheme, HashAlgo::Sha256, &task, &batch), None);
+        batch.remove(0);
+        assert_eq!(verify_pow_any(&scheme, HashAlgo::Sha256, &task, &batch), Some(good.as_str()));
+    }
+
+    #[test]
+    fn preimage_follows_the_scheme() {
+        let task = task(8);
+        assert_eq!(pow_preimage(&PowScheme::default(), &task, "42"), b"v1|seed|1700000000|8|example.com|ua|42");
+        let scheme = PowScheme {
+            version: "fork".to_string(),
+            divider: ":".to_string(),
+            fields: vec![PowField::Seed, PowField::Version],
+        };
+        assert_eq!(pow_preimage(&scheme, &task, "42"), b"seed:fork:42");
+    }
+
+    #[test]
+    fn changing_the_scheme_changes_the_accepted_nonce() {
+        let task = task(8);
+        let v1 = PowScheme::default();
+        let custom_divider = PowScheme { divider: ":".to_string(), ..PowScheme::default() };
+        let reordered = PowScheme {
+            fields: vec![PowField::Seed, PowField::Version, PowField::Exp, PowField::Bits, PowField::Scope, PowField::UaHash],
+            ..PowScheme::default()
+        };
+
+        let v1_nonce = solve(&v1, &task);
+        for scheme in [&custom_divider, &reordered] {
+            let nonce = solve(scheme, &task);
+            assert_ne!(nonce, v1_nonce);
+            assert!(!verify_pow(scheme, HashAlgo::Sha256, &task, &v1_nonce));
+            assert!(!verify_pow(&v1, HashAlgo::Sha256, &task, &nonce));
+        }
+    }
+
+    /// 固定原像的已知摘要，防止前缀缓存或算法分派悄悄改变哈希输入
+    #[test]
+    fn known_preimages_hash_to_known_digests() {
+        let scheme = PowScheme::default();
+        let task = task(8);
+        let cases = [
+            (HashAlgo::Sha256, "401", "0031e1638be262d8981a884f5252edbfe115ae497d48d52f69fc2a424fbe7fa1", 10),
+            (HashAlgo::Sha3_256, "944", "0018d61b14c41f8d97b65ae4a268722f973f6f3b70945ed011bbbfd373a4a1e2", 11),
+        ];
+        for (algo, nonce, digest, zero_bits) in cases {
+            let mut hasher = prefix_hasher(&scheme, algo, &task);
+            hasher.update(nonce.as_bytes());
+            assert_eq!(hex::encode(hasher.clone().finish()), digest, "{}", algo.name());
+            assert_eq!(hasher.leading_zero_bits(), zero_bits);
+
+            let mut whole = PowHasher::new(algo);
+            whole.update(&pow_preimage(&scheme, &task, nonce));
+            assert_eq!(hex::encode(whole.finish()), digest, "{}", algo.name());
+
+            assert!(verify_pow(&scheme, algo, &task, nonce));
+        }
+    }
+
+    /// FIPS 202 的 SHA3-256 测试向量：确认用的是 SHA-3 而不是填充不同的原始 Keccak-256
+    #[test]
+    fn sha3_matches_the_fips_202_vectors() {
+        for (input, digest) in [
+            (&b""[..], "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"),
+            (&b"abc"[..], "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+        ] {
+            let mut hasher = PowHasher::new(HashAlgo::Sha3_256);
+            hasher.update(input);
+            assert_eq!(hex::encode(hasher.finish()), digest);
+        }
+    }
+
+    #[test]
+    fn sha3_solutions_round_trip_and_do_not_cross_algorithms() {
+        let scheme = PowScheme::default();
+        let task = task(10);
+        let deadline = Instant::now() + std::time::Duration::from_secs(10);
+        let nonce = solve_pow(&scheme, HashAlgo::Sha3_256, &task, deadline).nonce.unwrap();
+        assert!(verify_pow(&scheme, HashAlgo::Sha3_256, &task, &nonce));
+        // 固定原像下 944 只满足 sha3 的 8 位难度，401 只满足 sha256 的
+        let fixed = self::task(8);
+        assert!(verify_pow(&scheme, HashAlgo::Sha3_256, &fixed, "944"));
+        assert!(!verify_pow(&scheme, HashAlgo::Sha256, &fixed, "944"));
+        assert!(verify_pow(&scheme, HashAlgo::Sha256, &fixed, "401"));
+        assert!(!verify_pow(&scheme, HashAlgo::Sha3_256, &fixed, "401"));
+    }
+
+    #[test]
+    fn hash_algo_names_round_trip() {
+        for algo in [HashAlgo::Sha256, HashAlgo::Sha3_256] {
+            assert_eq!(HashAlgo::from_name(algo.name()), Some(algo));
+        }
+        assert_eq!(HashAlgo::from_name(" SHA3-256 "), Some(HashAlgo::Sha3_256));
+        assert_eq!(HashAlgo::from_name("keccak256"), None);
+    }
+}
@@ -1,3 +1,6 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use base64::Engine;
 use ring::hmac;
 use serde::{Deserialize, Serialize};
@@ -13,27 +16,97 @@ pub struct TokenPayload {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub ip: Option<String>,
     pub nonce: String,
+    /// 签名密钥的 id，仅 v2 携带；v1 一律由 primary 密钥签名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// pow.bind_fingerprint：签发时连接的 TLS 指纹
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fp: Option<String>,
+}
+
+struct CookieKey {
+    secret: String,
+    /// 校验通过的次数，供运维判断旧密钥是否还有 Cookie 在用
+    verified: AtomicU64,
+}
+
+impl CookieKey {
+    fn new(secret: String) -> Self {
+        Self { secret, verified: AtomicU64::new(0) }
+    }
+}
+
+/// Cookie 签名密钥集合。primary 由 pow.salt 派生，签发与校验 v1（无 kid）Cookie；
+/// pow.cookie_keys 按 kid 索引，配置了 active_cookie_kid 时签发携带该 kid 的 v2 Cookie，
+/// 校验时按 kid 直接取密钥，不逐个尝试
+pub struct CookieKeyring {
+    primary: CookieKey,
+    keys: HashMap<String, CookieKey>,
+    active_kid: Option<String>,
+}
+
+impl CookieKeyring {
+    pub fn new(primary: String, keys: HashMap<String, String>, active_kid: Option<String>) -> Self {
+        Self {
+            primary: CookieKey::new(primary),
+            keys: keys.into_iter().map(|(kid, secret)| (kid, CookieKey::new(secret))).collect(),
+            active_kid,
+        }
+    }
+
+    /// 签发用的 (kid, 密钥)；未配置 active kid 时为 primary
+    fn signing_key(&self) -> (Option<&str>, &CookieKey) {
+        match self.active_kid.as_deref().and_then(|kid| self.keys.get_key_value(kid)) {
+            Some((kid, key)) => (Some(kid.as_str()), key),
+            None => (None, &self.primary),
+        }
+    }
+
+    fn key_for(&self, payload: &TokenPayload) -> Option<&CookieKey> {
+        match (payload.v.as_str(), payload.kid.as_deref()) {
+            ("v1", None) => Some(&self.primary),
+            ("v2", Some(kid)) => self.keys.get(kid),
+            _ => None,
+        }
+    }
+
+    /// 各密钥至今校验通过的 Cookie 数，primary 记为 "v1"
+    pub fn usage(&self) -> BTreeMap<String, u64> {
+        let mut usage: BTreeMap<String, u64> = self
+            .keys
+            .iter()
+            .map(|(kid, key)| (kid.clone(), key.verified.load(Ordering::Relaxed)))
+            .collect();
+        usage.insert("v1".to_string(), self.primary.verified.load(Ordering::Relaxed));
+        usage
+    }
 }
 
+/// fingerprint 为空时不绑定连接指纹
+#[allow(clippy::too_many_arguments)]
 pub fn generate_cookie(
-    secret: &str,
+    keyring: &CookieKeyring,
     bits: i32,
     scope: &str,
     ua_hash: &str,
     ip_hash: &str,
+    fingerprint: &str,
     nonce: &str,
     duration_seconds: i64,
 ) -> String {
     let exp = OffsetDateTime::now_utc().unix_timestamp() + duration_seconds;
     let ip_value = if ip_hash.is_empty() { None } else { Some(ip_hash.to_string()) };
+    let (kid, key) = keyring.signing_key();
     let payload = TokenPayload {
-        v: "v1".to_string(),
+        v: if kid.is_some() { "v2" } else { "v1" }.to_string(),
         exp,
         bits,
         scope: scope.to_string(),
         ua: ua_hash.to_string(),
         ip: ip_value,
         nonce: nonce.to_string(),
+        kid: kid.map(str::to_string),
+        fp: (!fingerprint.is_empty()).then(|| fingerprint.to_string()),
     };
 
     let payload_json = match serde_json::to_vec(&payload) {
@@ -42,22 +115,16 @@ pub fn generate_cookie(
     };
 
     let payload_b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(payload_json);
-    let sig = sign(secret, payload_b64.as_bytes());
+    let sig = sign(&key.secret, payload_b64.as_bytes());
     format!("{payload_b64}.{sig}")
 }
 
-pub fn verify_cookie(secret: &str, token: &str) -> Option<TokenPayload> {
+/// 先解出 payload 取得版本与 kid，再用对应密钥验签；未知 kid 直接拒绝
+pub fn verify_cookie(keyring: &CookieKeyring, token: &str) -> Option<TokenPayload> {
     let token = token.trim().trim_matches('"');
     let (payload_b64_raw, sig_raw) = split_token(token)?;
     let payload_b64 = payload_b64_raw.trim_end_matches('=');
     let sig = sig_raw.trim_end_matches('=');
-    let expected = sign(secret, payload_b64.as_bytes());
-    tracing::debug!("expected: {}", expected);
-    tracing::debug!("sig: {}", sig);
-    if sig != expected {
-        tracing::debug!("pow cookie signature mismatch");
-        return None;
-    }
     let payload_json = match base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload_b64) {
         Ok(data) => data,
         Err(err) => {
@@ -72,8 +139,15 @@ pub fn verify_cookie(secret: &str, token: &str) -> Option<TokenPayload> {
             return None;
         }
     };
-    if payload.v != "v1" {
-        tracing::debug!("pow cookie version mismatch");
+    let Some(key) = keyring.key_for(&payload) else {
+        tracing::debug!(version = %payload.v, kid = ?payload.kid, "pow cookie version or kid unknown");
+        return None;
+    };
+    let expected = sign(&key.secret, payload_b64.as_bytes());
+    tracing::debug!("expected: {}", expected);
+    tracing::debug!("sig: {}", sig);
+    if sig != expected {
+        tracing::debug!("pow cookie signature mismatch");
         return None;
     }
     if payload.exp < OffsetDateTime::now_utc().unix_timestamp() {
@@ -85,13 +159,12 @@ pub fn verify_cookie(secret: &str, token: &str) -> Option<TokenPayload> {
         tracing::debug!("pow cookie nonce is empty");
         return None;
     }
+    key.verified.fetch_add(1, Ordering::Relaxed);
     Some(payload)
 }
 
 fn split_token(token: &str) -> Option<(&str, &str)> {
-    let mut iter = token.splitn(2, '.');
-    let payload = iter.next()?;
-    let sig = iter.next()?;
+    let (payload, sig) = token.split_once('.')?;
     if payload.is_empty() || sig.is_empty() {
         None
     } else {
@@ -104,3 +177,73 @@ fn sign(secret: &str, message: &[u8]) -> String {
     let tag = hmac::sign(&key, message);
     base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(tag.as_ref())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(kids: &[&str], active: Option<&str>) -> CookieKeyring {
+        let keys = kids.iter().map(|kid| (kid.to_string(), format!("secret-{kid}"))).collect();
+        CookieKeyring::new("primary".to_string(), keys, active.map(str::to_string))
+    }
+
+    fn issue(keyring: &CookieKeyring) -> String {
+        generate_cookie(keyring, 4, "example.com", "ua", "", "", "42", 3600)
+    }
+
+    /// 用给定密钥重新签一份改过的 payload
+    fn forge(payload: &TokenPayload, secret: &str) -> String {
+        let payload_b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload).unwrap());
+        let sig = sign(secret, payload_b64.as_bytes());
+        format!("{payload_b64}.{sig}")
+    }
+
+    #[test]
+    fn v1_cookie_is_signed_by_primary_and_survives_a_kid_rollout() {
+        let cookie = issue(&keyring(&[], None));
+        let payload = verify_cookie(&keyring(&[], None), &cookie).unwrap();
+        assert_eq!((payload.v.as_str(), payload.kid), ("v1", None));
+
+        let rolled_out = keyring(&["k1", "k2"], Some("k2"));
+        assert!(verify_cookie(&rolled_out, &cookie).is_some());
+        assert_eq!(rolled_out.usage()["v1"], 1);
+        assert_eq!(rolled_out.usage()["k2"], 0);
+    }
+
+    #[test]
+    fn v2_cookie_is_looked_up_by_kid() {
+        let cookie = issue(&keyring(&["k1", "k2"], Some("k1")));
+        let payload = verify_cookie(&keyring(&["k1"], None), &cookie).unwrap();
+        assert_eq!((payload.v.as_str(), payload.kid.as_deref()), ("v2", Some("k1")));
+
+        // 激活 k2 后 k1 仍在列表中即可校验；从列表移除后立即失效
+        let staged = keyring(&["k1", "k2"], Some("k2"));
+        assert!(verify_cookie(&staged, &cookie).is_some());
+        assert_eq!(staged.usage()["k1"], 1);
+        assert!(verify_cookie(&keyring(&["k2"], Some("k2")), &cookie).is_none());
+        assert!(verify_cookie(&keyring(&[], None), &cookie).is_none());
+    }
+
+    #[test]
+    fn kid_and_version_must_agree_with_the_signing_key() {
+        let ring = keyring(&["k1", "k2"], Some("k1"));
+        let cookie = issue(&ring);
+        let mut payload = verify_cookie(&ring, &cookie).unwrap();
+
+        // 改 kid 而不重签：k2 的密钥验不过 k1 的签名
+        let (_, sig) = cookie.split_once('.').unwrap();
+        payload.kid = Some("k2".to_string());
+        let forged = forge(&payload, "secret-k1");
+        let relabelled = format!("{}.{sig}", forged.split_once('.').unwrap().0);
+        assert!(verify_cookie(&ring, &relabelled).is_none());
+
+        // 版本与 kid 不匹配的组合一律拒绝，即使签名正确
+        payload.kid = None;
+        assert!(verify_cookie(&ring, &forge(&payload, "primary")).is_none());
+        payload.v = "v1".to_string();
+        payload.kid = Some("k1".to_string());
+        assert!(verify_cookie(&ring, &forge(&payload, "secret-k1")).is_none());
+        payload.kid = None;
+        assert!(verify_cookie(&ring, &forge(&payload, "primary")).is_some());
+    }
+}
@@ -0,0 +1,35 @@
+use super::HashAlgo;
+use crate::config::PowScheme;
+use crate::handlers::message::MSG_INVALID_PROOF_OF_WORK;
+use crate::protocol::frame::BinaryVerifyRequest;
+use crate::storage::{ConsumeError, Task};
+
+/// 挑战类型的扩展点：pow_verify 在 consume_if 的闭包里先做与挑战类型无关的绑定校验
+/// （UA、IP），再调用这里的 verify，最后做求解耗时检查。闭包在任务存储的锁内同步执行，
+/// 因此实现必须是同步且快速的；需要远程校验（如 Turnstile）的实现应在取任务之前完成网络调用，
+/// 这里只比对结果。返回 ValidationFailed 时任务同样被消耗
+pub trait Verifier: Send + Sync {
+    fn verify(&self, task: &Task, request: &BinaryVerifyRequest) -> Result<(), ConsumeError>;
+}
+
+/// 默认实现：按 pow.scheme 与 pow.hash_algo 校验前导零，链式任务逐环校验
+pub struct PowVerifier {
+    scheme: PowScheme,
+    algo: HashAlgo,
+}
+
+impl PowVerifier {
+    pub fn new(scheme: PowScheme, algo: HashAlgo) -> Self {
+        Self { scheme, algo }
+    }
+}
+
+impl Verifier for PowVerifier {
+    fn verify(&self, task: &Task, request: &BinaryVerifyRequest) -> Result<(), ConsumeError> {
+        if super::verify_pow_chain(&self.scheme, self.algo, task, &request.nonce) {
+            Ok(())
+        } else {
+            Err(ConsumeError::ValidationFailed(MSG_INVALID_PROOF_OF_WORK))
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::state::AppState;
+
+/// 切换 drain 状态，状态变化时记录日志；返回切换前的状态。
+/// drain 期间持有效 Cookie 的请求照常放行，需要挑战的请求返回 503，便于下线前排空实例
+pub fn set_draining(state: &AppState, draining: bool, trigger: &str) -> bool {
+    let previous = state.draining.swap(draining, Ordering::Relaxed);
+    if previous != draining {
+        if draining {
+            tracing::warn!(trigger, "drain mode entered: no new challenges will be issued");
+        } else {
+            tracing::warn!(trigger, "drain mode left: challenges are issued again");
+        }
+    }
+    previous
+}
+
+/// 收到 SIGUSR1 时进入 drain；信号只进不出，退出 drain 需经管理接口
+#[cfg(unix)]
+pub fn start_signal_listener(state: Arc<AppState>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to install SIGUSR1 handler, drain is only available via the admin endpoint");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            set_draining(&state, true, "sigusr1");
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn start_signal_listener(_state: Arc<AppState>) {}
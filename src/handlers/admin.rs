@@ -0,0 +1,114 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::json;
+use time::OffsetDateTime;
+
+use crate::crypto::constant_time_eq;
+use crate::protocol::http::HeaderMapExt;
+use crate::state::AppState;
+
+pub async fn pow_status(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    if let Err(status) = authorize(&state, &headers) {
+        return status.into_response();
+    }
+    Json(json!({
+        "pow_enabled": state.pow_enabled.load(Ordering::Relaxed),
+        "draining": state.draining.load(Ordering::Relaxed),
+        "pending_tasks": state.task_store.len().await,
+        // 各签名密钥自启动以来校验通过的 Cookie 数，归零的旧 kid 可以安全下线
+        "cookie_key_usage": state.cookie_keys.usage(),
+        "verify_rate_limited": state.verify_limiter.as_ref().map(|limiter| {
+            let (per_ip, global) = limiter.rejected();
+            json!({ "per_ip": per_ip, "global": global })
+        }),
+    }))
+    .into_response()
+}
+
+/// Prometheus 文本格式的计数器；与 admin 接口同一监听，但不要求令牌，便于抓取
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        state.metrics.render(
+            state.pow_enabled.load(Ordering::Relaxed),
+            state.draining.load(Ordering::Relaxed),
+            state.challenge_webhook.as_deref(),
+            state.failure_bans.as_deref(),
+        ),
+    )
+}
+
+pub async fn pow_enable(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    set_pow_enabled(&state, &headers, true)
+}
+
+pub async fn pow_disable(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    set_pow_enabled(&state, &headers, false)
+}
+
+fn set_pow_enabled(state: &AppState, headers: &HeaderMap, enabled: bool) -> axum::response::Response {
+    let token_id = match authorize(state, headers) {
+        Ok(id) => id,
+        Err(status) => return status.into_response(),
+    };
+    let previous = state.pow_enabled.swap(enabled, Ordering::Relaxed);
+    tracing::warn!(
+        token_id = %token_id,
+        previous,
+        pow_enabled = enabled,
+        at = OffsetDateTime::now_utc().unix_timestamp(),
+        "pow kill-switch toggled via admin endpoint"
+    );
+    Json(json!({ "pow_enabled": enabled, "previous": previous })).into_response()
+}
+
+pub async fn drain_enable(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    set_draining(&state, &headers, true)
+}
+
+pub async fn drain_disable(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    set_draining(&state, &headers, false)
+}
+
+fn set_draining(state: &AppState, headers: &HeaderMap, draining: bool) -> axum::response::Response {
+    let token_id = match authorize(state, headers) {
+        Ok(id) => id,
+        Err(status) => return status.into_response(),
+    };
+    let previous = crate::drain::set_draining(state, draining, &format!("admin token {token_id}"));
+    Json(json!({ "draining": draining, "previous": previous })).into_response()
+}
+
+/// 校验 Authorization: Bearer <token>，返回匹配令牌的 id 用于审计日志。
+/// 未配置任何管理令牌时，管理接口整体视为不存在
+pub fn authorize(state: &AppState, headers: &HeaderMap) -> Result<String, StatusCode> {
+    if state.config.admin.tokens.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let presented = headers
+        .get_str(header::AUTHORIZATION)
+        .and_then(|value| value.trim().strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+    if presented.is_empty() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let mut matched = None;
+    for entry in &state.config.admin.tokens {
+        if constant_time_eq(presented.as_bytes(), entry.token.as_bytes()) {
+            matched = Some(entry.id.clone());
+        }
+    }
+    match matched {
+        Some(id) => Ok(id),
+        None => {
+            tracing::warn!("admin request rejected: invalid token");
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
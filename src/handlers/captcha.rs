@@ -0,0 +1,402 @@
+use std::sync::Arc;
+
+use axum::extract::{Form, Query, State};
+use axum::http::{header, Extensions, HeaderMap, Response, StatusCode};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use crate::config::IpPolicy;
+use crate::crypto::{compute_ip_hash, compute_ua_hash, generate_cookie};
+use crate::handlers::message::*;
+use crate::handlers::pow::{build_task, cookie_fingerprint, headers_user_agent, pow_set_cookie, sanitize_redirect, POW_PREFIX};
+use crate::ip_source::ip::resolve_request_ip;
+use crate::metrics::{ChallengeSource, VerifyFailure};
+use crate::state::AppState;
+use crate::storage::{ConsumeError, Task, TaskId};
+use crate::crypto;
+
+/// 写入 Cookie 的 nonce 字段，区分验证码换取的通行凭证
+const CAPTCHA_COOKIE_NONCE: &str = "captcha";
+
+#[derive(Debug, Deserialize)]
+pub struct CaptchaQuery {
+    /// 挑战页任务的 task_id：尚未消耗的 PoW 任务当场作废换题，已在 /verify 失败的凭票据换题
+    task_id: Option<String>,
+    redirect: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CaptchaForm {
+    task_id: String,
+    answer: String,
+    #[serde(default)]
+    redirect: String,
+}
+
+/// GET /captcha：下发一道验证码，答案随任务存进 task store。
+/// 只对挑战失败过（或信誉被标记）的客户端出题，否则 403
+pub async fn captcha_page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CaptchaQuery>,
+    headers: HeaderMap,
+    extensions: Extensions,
+) -> Response<axum::body::Body> {
+    if state.captcha_page.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if !captcha_allowed(&state, &headers, &extensions, query.task_id.as_deref()).await {
+        let (client_ip, _) = resolve_request_ip(&headers, &extensions, &state.trusted_proxies);
+        tracing::info!(client_ip = %client_ip, "{}", MSG_CAPTCHA_NOT_ALLOWED);
+        return (StatusCode::FORBIDDEN, MSG_CAPTCHA_NOT_ALLOWED).into_response();
+    }
+    let redirect = sanitize_redirect(query.redirect.as_deref().unwrap_or("/"), state.config.pow.max_redirect_len);
+    issue_captcha(&state, &headers, &extensions, &redirect, None, StatusCode::OK).await
+}
+
+/// 出题前的入场条件，依次检查：
+/// 1. task_id 是 /verify 失败后登记的票据，且来自同一 IP；
+/// 2. task_id 是尚未消耗的 PoW 任务，UA/IP 与请求一致，当场消耗掉；
+/// 3. 客户端 IP 的信誉分达到阈值
+async fn captcha_allowed(state: &AppState, headers: &HeaderMap, extensions: &Extensions, task_id: Option<&str>) -> bool {
+    let (client_ip, _) = resolve_request_ip(headers, extensions, &state.trusted_proxies);
+    if let Some(task_id) = task_id.filter(|id| !id.is_empty()) {
+        if let Some(tickets) = &state.captcha_tickets {
+            if tickets.redeem(task_id, &client_ip).await {
+                return true;
+            }
+        }
+        let ua_hash = compute_ua_hash(headers_user_agent(headers));
+        let ip_hash = if state.config.pow.ip_policy != IpPolicy::None {
+            compute_ip_hash(&crypto::extract_client_ip(headers, extensions, state.config.pow.ip_policy, &state.trusted_proxies))
+        } else {
+            String::new()
+        };
+        let consumed = state.task_store.consume_if(task_id, Box::new(|task: &Task| {
+            let same_client = task.captcha_answer.is_none()
+                && task.ua_hash.0 == ua_hash
+                && (state.config.pow.ip_policy == IpPolicy::None || task.ip_hash.0 == ip_hash);
+            if same_client {
+                Ok(())
+            } else {
+                Err(ConsumeError::ValidationFailed(MSG_INVALID_REQUEST))
+            }
+        })).await;
+        if consumed.is_ok() {
+            return true;
+        }
+    }
+    match &state.reputation {
+        Some(reputation) => reputation.is_flagged(&client_ip, &state.metrics).await,
+        None => false,
+    }
+}
+
+/// POST /captcha：核对答案，通过后签发与 PoW 相同的通行 Cookie 并跳回原地址；
+/// 任务一经提交即被消耗，答错时换一道新题
+pub async fn captcha_verify(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    extensions: Extensions,
+    Form(form): Form<CaptchaForm>,
+) -> Response<axum::body::Body> {
+    if state.captcha_page.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let redirect = sanitize_redirect(&form.redirect, state.config.pow.max_redirect_len);
+    let redirect = redirect.as_str();
+    let answer = form.answer.trim();
+
+    let ua_hash = compute_ua_hash(headers_user_agent(&headers));
+    let ip_hash = if state.config.pow.ip_policy != IpPolicy::None {
+        compute_ip_hash(&crypto::extract_client_ip(&headers, &extensions, state.config.pow.ip_policy, &state.trusted_proxies))
+    } else {
+        String::new()
+    };
+
+    let task = match state.task_store.consume_if(&form.task_id, Box::new(|task: &Task| {
+        match task.captcha_answer.as_deref() {
+            None => return Err(ConsumeError::ValidationFailed(MSG_INVALID_REQUEST)),
+            Some(expected) if expected != answer => return Err(ConsumeError::ValidationFailed(MSG_CAPTCHA_MISMATCH)),
+            Some(_) => {}
+        }
+        if task.ua_hash.0 != ua_hash {
+            tracing::warn!(task_id = %task.task_id.short_id(), "{}", MSG_USER_AGENT_MISMATCH);
+            return Err(ConsumeError::ValidationFailed(MSG_USER_AGENT_MISMATCH));
+        }
+        if state.config.pow.ip_policy != IpPolicy::None && task.ip_hash.0 != ip_hash {
+            tracing::warn!(task_id = %task.task_id.short_id(), "{}", MSG_IP_ADDRESS_MISMATCH);
+            return Err(ConsumeError::ValidationFailed(MSG_IP_ADDRESS_MISMATCH));
+        }
+        Ok(())
+    })).await {
+        Ok(task) => task,
+        Err(ConsumeError::NotFound) => {
+            state.metrics.verify_failed(VerifyFailure::NotFound);
+            tracing::warn!(task_id = %TaskId::from(form.task_id.as_str()).short_id(), "{}", MSG_TASK_NOT_FOUND_OR_EXPIRED);
+            // 编造的 task_id 不能换来新题，否则就绕过了 GET 的入场检查
+            return (StatusCode::BAD_REQUEST, MSG_TASK_NOT_FOUND_OR_EXPIRED).into_response();
+        }
+        Err(ConsumeError::Expired) => {
+            state.metrics.verify_failed(VerifyFailure::Expired);
+            tracing::warn!(task_id = %TaskId::from(form.task_id.as_str()).short_id(), "{}", MSG_TASK_EXPIRED);
+            return issue_captcha(&state, &headers, &extensions, redirect, Some("验证码已过期，请重新输入"), StatusCode::BAD_REQUEST).await;
+        }
+        Err(ConsumeError::Unavailable) => {
+            state.metrics.verify_failed(VerifyFailure::StoreUnavailable);
+            return (StatusCode::SERVICE_UNAVAILABLE, MSG_TASK_STORE_UNAVAILABLE).into_response();
+        }
+        Err(ConsumeError::ValidationFailed(msg)) => {
+            state.metrics.verify_failed(VerifyFailure::from_message(msg));
+            let (client_ip, _) = resolve_request_ip(&headers, &extensions, &state.trusted_proxies);
+            tracing::info!(task_id = %TaskId::from(form.task_id.as_str()).short_id(), client_ip = %client_ip, "{}", msg);
+            if msg == MSG_INVALID_REQUEST {
+                return (StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST).into_response();
+            }
+            if msg == MSG_CAPTCHA_MISMATCH {
+                if let Some(bans) = &state.failure_bans {
+                    bans.record_failure(&client_ip);
+                }
+            }
+            return issue_captcha(&state, &headers, &extensions, redirect, Some("答案不正确，请重新输入"), StatusCode::BAD_REQUEST).await;
+        }
+    };
+
+    state.metrics.verify_succeeded();
+    let (client_ip, ip_source) = resolve_request_ip(&headers, &extensions, &state.trusted_proxies);
+    if let Some(bans) = &state.failure_bans {
+        bans.record_success(&client_ip);
+    }
+
+    let expire_seconds = state.config.pow.cookie_expire_hours * 3600;
+    let cookie_value = generate_cookie(
+        &state.cookie_keys,
+        task.bits as i32,
+        &task.scope.0,
+        &task.ua_hash.0,
+        &task.ip_hash.0,
+        cookie_fingerprint(&state, &extensions),
+        CAPTCHA_COOKIE_NONCE,
+        expire_seconds,
+    );
+    let location = if state.config.pow.test_mode {
+        format!("{}/ok-page?bits={}", POW_PREFIX, task.bits)
+    } else {
+        redirect.to_string()
+    };
+    tracing::info!(
+        task_id = %task.task_id.short_id(),
+        client_ip = %client_ip,
+        ip_source = %ip_source.get_string(),
+        user_agent = %headers_user_agent(&headers),
+        redirect = %location,
+        "{}",
+        MSG_CAPTCHA_VERIFIED
+    );
+
+    let mut response_headers = HeaderMap::new();
+    if let Ok(value) = header::HeaderValue::from_str(&pow_set_cookie(&state, cookie_value, expire_seconds)) {
+        response_headers.insert(header::SET_COOKIE, value);
+    }
+    if let Ok(value) = header::HeaderValue::from_str(&location) {
+        response_headers.insert(header::LOCATION, value);
+    }
+    response_headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+    (StatusCode::SEE_OTHER, response_headers).into_response()
+}
+
+async fn issue_captcha(
+    state: &AppState,
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    redirect: &str,
+    error: Option<&str>,
+    status: StatusCode,
+) -> Response<axum::body::Body> {
+    let (Some(template), Some(limiter)) = (&state.captcha_page, &state.captcha_limiter) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let (client_ip, _) = resolve_request_ip(headers, extensions, &state.trusted_proxies);
+    if !limiter.try_acquire(&client_ip).await {
+        tracing::info!(client_ip = %client_ip, "{}", MSG_CAPTCHA_RATE_LIMITED);
+        return (StatusCode::TOO_MANY_REQUESTS, MSG_CAPTCHA_RATE_LIMITED).into_response();
+    }
+
+    let generated = crate::captcha::generate().and_then(|captcha| {
+        let mut task = build_task(state, headers, extensions, 0)?;
+        // 不需要算力：bits 记为 0，答案代替 PoW 作为消费条件
+        task.bits = 0;
+        task.captcha_answer = Some(captcha.answer);
+        Ok((task, captcha.svg))
+    });
+    let (task, svg) = match generated {
+        Ok(pair) => pair,
+        Err(err) => {
+            tracing::error!(error = %err, "{}", MSG_FAILED_TO_GENERATE_TASK);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if let Err(err) = state.task_store.insert(task.clone()).await {
+        tracing::error!(error = %err, "{}", MSG_TASK_STORE_UNAVAILABLE);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    state.metrics.challenge_issued(ChallengeSource::Captcha);
+    tracing::info!(
+        task_id = %task.task_id.short_id(),
+        client_ip = %client_ip,
+        user_agent = %headers_user_agent(headers),
+        "{}",
+        MSG_CAPTCHA_ISSUED
+    );
+
+    let html = crate::static_files::render_captcha_page(template, &svg, &task.task_id.0, redirect, error);
+    (
+        status,
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        html,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CaptchaFallbackConfig, Config};
+    use axum::extract::ConnectInfo;
+    use http_body_util::BodyExt;
+    use std::net::SocketAddr;
+
+    async fn captcha_state(config: Config) -> Arc<AppState> {
+        let mut config = config;
+        config.pow.captcha_fallback = Some(CaptchaFallbackConfig::default());
+        Arc::new(AppState::new(config).await.unwrap())
+    }
+
+    fn client(ua: &str, ip: &str) -> (HeaderMap, Extensions) {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, header::HeaderValue::from_str(ua).unwrap());
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(format!("{ip}:40000").parse::<SocketAddr>().unwrap()));
+        (headers, extensions)
+    }
+
+    async fn get_captcha(state: &Arc<AppState>, task_id: Option<&str>, ua: &str, ip: &str) -> Response<axum::body::Body> {
+        let (headers, extensions) = client(ua, ip);
+        let query = CaptchaQuery { task_id: task_id.map(str::to_string), redirect: Some("/home".to_string()) };
+        captcha_page(State(state.clone()), Query(query), headers, extensions).await
+    }
+
+    async fn post_answer(state: &Arc<AppState>, task_id: &str, answer: &str, ua: &str, ip: &str) -> Response<axum::body::Body> {
+        let (headers, extensions) = client(ua, ip);
+        let form = CaptchaForm { task_id: task_id.to_string(), answer: answer.to_string(), redirect: "/home".to_string() };
+        captcha_verify(State(state.clone()), headers, extensions, Form(form)).await
+    }
+
+    /// 按 issue_captcha 的方式存入一道答案已知的验证码
+    async fn insert_captcha(state: &AppState, answer: &str, ua: &str, ip: &str) -> String {
+        let (headers, extensions) = client(ua, ip);
+        let mut task = build_task(state, &headers, &extensions, 0).unwrap();
+        task.bits = 0;
+        task.captcha_answer = Some(answer.to_string());
+        let task_id = task.task_id.0.to_string();
+        state.task_store.insert(task).await.unwrap();
+        task_id
+    }
+
+    async fn insert_pow_task(state: &AppState, ua: &str, ip: &str) -> String {
+        let (headers, extensions) = client(ua, ip);
+        let task = build_task(state, &headers, &extensions, 3).unwrap();
+        let task_id = task.task_id.0.to_string();
+        state.task_store.insert(task).await.unwrap();
+        task_id
+    }
+
+    async fn body_text(resp: Response<axum::body::Body>) -> String {
+        String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn captcha_page_requires_a_challenge_task_or_ticket() {
+        let state = captcha_state(Config::default()).await;
+        assert_eq!(get_captcha(&state, None, "ua", "203.0.113.7").await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(get_captcha(&state, Some("made-up"), "ua", "203.0.113.7").await.status(), StatusCode::FORBIDDEN);
+
+        // 未消耗的 PoW 任务：UA 不符不换题，且不因此作废
+        let live = insert_pow_task(&state, "ua", "203.0.113.7").await;
+        assert_eq!(get_captcha(&state, Some(&live), "other-ua", "203.0.113.7").await.status(), StatusCode::FORBIDDEN);
+        let live = insert_pow_task(&state, "ua", "203.0.113.7").await;
+        let resp = get_captcha(&state, Some(&live), "ua", "203.0.113.7").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(body_text(resp).await.contains("<svg"));
+        // 换过一次即被消耗
+        assert_eq!(get_captcha(&state, Some(&live), "ua", "203.0.113.7").await.status(), StatusCode::FORBIDDEN);
+
+        // /verify 失败后登记的票据：只对同一 IP 有效，用一次作废
+        let tickets = state.captcha_tickets.as_ref().unwrap();
+        tickets.grant("failed-task", "203.0.113.7").await;
+        assert_eq!(get_captcha(&state, Some("failed-task"), "ua", "198.51.100.1").await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(get_captcha(&state, Some("failed-task"), "ua", "203.0.113.7").await.status(), StatusCode::OK);
+        assert_eq!(get_captcha(&state, Some("failed-task"), "ua", "203.0.113.7").await.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn right_answer_sets_the_pass_cookie() {
+        let state = captcha_state(Config::default()).await;
+        let task_id = insert_captcha(&state, "42", "ua", "203.0.113.7").await;
+        let resp = post_answer(&state, &task_id, " 42 ", "ua", "203.0.113.7").await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers()[header::LOCATION], "/home");
+        assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with(crate::handlers::pow::POW_COOKIE_NAME));
+
+        // 任务已被取走，重放不再有效，也不会换来新题
+        let replay = post_answer(&state, &task_id, "42", "ua", "203.0.113.7").await;
+        assert_eq!(replay.status(), StatusCode::BAD_REQUEST);
+        assert!(replay.headers().get(header::SET_COOKIE).is_none());
+        assert!(!body_text(replay).await.contains("<svg"));
+    }
+
+    #[tokio::test]
+    async fn wrong_answer_consumes_the_task_and_issues_a_new_one() {
+        let state = captcha_state(Config::default()).await;
+        let task_id = insert_captcha(&state, "42", "ua", "203.0.113.7").await;
+        let resp = post_answer(&state, &task_id, "41", "ua", "203.0.113.7").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+        let html = body_text(resp).await;
+        assert!(html.contains("答案不正确"), "{html}");
+        assert!(html.contains("<svg"), "{html}");
+        assert!(!html.contains(&task_id));
+
+        let retry = post_answer(&state, &task_id, "42", "ua", "203.0.113.7").await;
+        assert!(retry.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn ua_or_ip_mismatch_is_rejected() {
+        let mut config = Config::default();
+        config.pow.ip_policy = IpPolicy::Enable;
+        let state = captcha_state(config).await;
+
+        let task_id = insert_captcha(&state, "42", "ua", "203.0.113.7").await;
+        let resp = post_answer(&state, &task_id, "42", "other-ua", "203.0.113.7").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+
+        let task_id = insert_captcha(&state, "42", "ua", "203.0.113.7").await;
+        let resp = post_answer(&state, &task_id, "42", "ua", "198.51.100.1").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn pow_task_cannot_be_answered_as_a_captcha() {
+        let state = captcha_state(Config::default()).await;
+        let task_id = insert_pow_task(&state, "ua", "203.0.113.7").await;
+        let resp = post_answer(&state, &task_id, "0", "ua", "203.0.113.7").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+        assert!(!body_text(resp).await.contains("<svg"));
+    }
+}
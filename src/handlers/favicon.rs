@@ -2,80 +2,316 @@ use std::sync::Arc;
 
 use axum::body::Body;
 use axum::extract::State;
-use axum::http::{Request, Response, StatusCode, Uri};
+use axum::http::uri::PathAndQuery;
+use axum::http::{header, HeaderMap, Method, Request, Response, StatusCode, Uri};
+use bytes::Bytes;
 use axum::response::IntoResponse;
 use http_body_util::BodyExt;
 
+use crate::proxy::error::ProxyError;
 use crate::proxy::forward::{build_target_uri, rewrite_headers};
 use crate::state::{AppState, FaviconCache};
 
 pub async fn favicon_handler(
     State(state): State<Arc<AppState>>,
-    mut req: Request<Body>,
+    req: Request<Body>,
 ) -> impl IntoResponse {
-    // 检查缓存
-    {
+    // 检查缓存：新鲜时直接应答；过期但带校验器时保留下来，用于向上游条件请求
+    let stale = {
         let cache = state.favicon_cache.read().await;
-        if let Some(cached) = cache.as_ref() {
-            if cached.is_valid() {
+        match cache.as_ref() {
+            Some(cached) if cached.is_valid() => {
                 tracing::debug!("returning cached favicon");
-                let mut response = Response::builder()
-                    .status(cached.status)
-                    .body(Body::from(cached.body.clone()))
-                    .unwrap();
-                *response.headers_mut() = cached.headers.clone();
-                return response;
+                return respond_cached(cached, req.headers());
             }
+            Some(cached) if cached.has_validators() => Some(cached.clone()),
+            _ => None,
+        }
+    };
+
+    let client_headers = req.headers().clone();
+    match fetch_favicon(&state, req, stale).await {
+        Ok(Fetched::Cached(cached)) => respond_cached(&cached, &client_headers),
+        Ok(Fetched::Passthrough { status, headers, body }) => build_response(status, headers, body),
+        Err(err) => {
+            tracing::warn!(error = %err, "favicon fetch failed");
+            err.into_response()
+        }
+    }
+}
+
+/// 启动时预取一次 favicon 填充缓存；失败只记 debug，之后按需回源照常工作
+pub async fn warm_favicon(state: Arc<AppState>) {
+    let req = match Request::builder()
+        .method(Method::GET)
+        .uri("/favicon.ico")
+        .body(Body::empty())
+    {
+        Ok(req) => req,
+        Err(err) => {
+            tracing::debug!(error = %err, "failed to build favicon warm request");
+            return;
         }
+    };
+    match fetch_favicon(&state, req, None).await {
+        Ok(Fetched::Cached(_)) => tracing::info!("favicon cache warmed"),
+        Ok(Fetched::Passthrough { status, .. }) => {
+            tracing::debug!(status = %status, "favicon warm fetch not cacheable")
+        }
+        Err(err) => tracing::debug!(error = %err, "favicon warm fetch failed"),
     }
+}
 
+enum Fetched {
+    /// 已写入缓存（新取得或经上游 304 续期）
+    Cached(FaviconCache),
+    /// 不可缓存的上游响应，原样转发
+    Passthrough {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    },
+}
+
+/// 日志由调用方决定级别：客户端请求记 warn，启动预热只记 debug
+enum FetchError {
+    Uri(axum::http::uri::InvalidUriParts),
+    Upstream(hyper_util::client::legacy::Error),
+    Body(hyper::Error),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Uri(err) => write!(f, "failed to build favicon upstream uri: {err}"),
+            FetchError::Upstream(err) => {
+                let proxy_err = ProxyError::from_client_error(err);
+                write!(f, "favicon proxy request failed ({}): {err}", proxy_err.reason())
+            }
+            FetchError::Body(err) => write!(f, "failed to read favicon body: {err}"),
+        }
+    }
+}
+
+impl IntoResponse for FetchError {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            FetchError::Upstream(err) => ProxyError::from_client_error(&err).into_response(),
+            FetchError::Uri(_) | FetchError::Body(_) => StatusCode::BAD_GATEWAY.into_response(),
+        }
+    }
+}
+
+/// 向上游取 /favicon.ico 并更新缓存；stale 为带校验器的过期缓存时发条件请求
+async fn fetch_favicon(
+    state: &AppState,
+    mut req: Request<Body>,
+    stale: Option<FaviconCache>,
+) -> Result<Fetched, FetchError> {
     // 构建目标 URI（固定为 /favicon.ico）
     let mut target_uri_parts = req.uri().clone().into_parts();
-    target_uri_parts.path_and_query = Some("/favicon.ico".parse().unwrap());
-    let target_uri = Uri::from_parts(target_uri_parts).unwrap();
-    *req.uri_mut() = build_target_uri(&state.proxy_target.uri, &target_uri);
-    rewrite_headers(req.headers_mut(), &state.proxy_target);
+    target_uri_parts.path_and_query = Some(PathAndQuery::from_static("/favicon.ico"));
+    let target_uri = Uri::from_parts(target_uri_parts).map_err(FetchError::Uri)?;
+    let upstream = state.upstreams.pick();
+    *req.uri_mut() = build_target_uri(&upstream.uri, &target_uri);
+    rewrite_headers(req.headers_mut(), upstream, state.forwarded_port.as_ref());
+    // 客户端的条件头不转发（上游 304 没有 body 可缓存），只用缓存自己的校验器做重新验证
+    req.headers_mut().remove(header::IF_NONE_MATCH);
+    req.headers_mut().remove(header::IF_MODIFIED_SINCE);
+    if let Some(stale) = &stale {
+        if let Some(etag) = stale.etag() {
+            req.headers_mut().insert(header::IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = stale.last_modified() {
+            req.headers_mut().insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
 
     // 请求上游
-    let resp = match state.proxy_client.request(req).await {
-        Ok(resp) => resp,
-        Err(err) => {
-            tracing::debug!(error = %err, "favicon proxy request failed");
-            return StatusCode::BAD_GATEWAY.into_response();
-        }
-    };
+    let resp = state.proxy_client.request(req).await.map_err(FetchError::Upstream)?;
 
     let status = resp.status();
+    if status == StatusCode::NOT_MODIFIED {
+        if let Some(mut refreshed) = stale {
+            refreshed.cached_at = std::time::Instant::now();
+            *state.favicon_cache.write().await = Some(refreshed.clone());
+            tracing::debug!("favicon revalidated with upstream");
+            return Ok(Fetched::Cached(refreshed));
+        }
+    }
     let (parts, body) = resp.into_parts();
 
     // 读取 body 到内存
-    let body_bytes = match body.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(err) => {
-            tracing::error!(error = %err, "failed to read favicon body");
-            return StatusCode::BAD_GATEWAY.into_response();
-        }
-    };
+    let body_bytes = body.collect().await.map_err(FetchError::Body)?.to_bytes();
 
-    // 如果状态码是 2xx，缓存响应
-    if status.is_success() {
+    // 状态码在 favicon.cache_statuses 内（默认任意 2xx）时缓存响应
+    if let Some(ttl) = state.config.favicon.cache_ttl(status) {
         let cache = FaviconCache {
             status,
-            headers: parts.headers.clone(),
-            body: body_bytes.clone(),
+            headers: parts.headers,
+            body: body_bytes,
             cached_at: std::time::Instant::now(),
+            ttl,
         };
-        *state.favicon_cache.write().await = Some(cache);
-        tracing::debug!("cached favicon response");
+        *state.favicon_cache.write().await = Some(cache.clone());
+        tracing::debug!(status = %status, ttl_secs = ttl.as_secs(), "cached favicon response");
+        return Ok(Fetched::Cached(cache));
     }
 
-    // 构建响应
-    let mut response = Response::builder()
-        .status(status)
-        .body(Body::from(body_bytes))
-        .unwrap();
-    *response.headers_mut() = parts.headers;
+    Ok(Fetched::Passthrough {
+        status,
+        headers: parts.headers,
+        body: body_bytes,
+    })
+}
+
+/// 客户端的 If-None-Match / If-Modified-Since 与缓存匹配时回 304，否则回完整缓存内容；
+/// 缓存的错误响应（如 404）不参与条件请求
+fn respond_cached(cached: &FaviconCache, request_headers: &HeaderMap) -> Response<Body> {
+    if !cached.status.is_success() || !is_not_modified(cached, request_headers) {
+        return build_response(cached.status, cached.headers.clone(), cached.body.clone());
+    }
+    let mut headers = HeaderMap::new();
+    for name in [header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL, header::EXPIRES, header::VARY] {
+        if let Some(value) = cached.headers.get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    build_response(StatusCode::NOT_MODIFIED, headers, Bytes::new())
+}
 
+/// If-None-Match 优先（弱比较）；没有时才看 If-Modified-Since，按原样回显的值做精确比较
+fn is_not_modified(cached: &FaviconCache, request_headers: &HeaderMap) -> bool {
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        let Some(etag) = cached.etag().and_then(|v| v.to_str().ok()) else {
+            return false;
+        };
+        let etag = etag.trim().trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(|candidate| candidate.trim())
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag);
+    }
+    match (request_headers.get(header::IF_MODIFIED_SINCE), cached.last_modified()) {
+        (Some(since), Some(last_modified)) => since == last_modified,
+        _ => false,
+    }
+}
+
+fn build_response(status: StatusCode, headers: HeaderMap, body: Bytes) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
     response
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn state(config: Config) -> Arc<AppState> {
+        Arc::new(AppState::new(config).await.unwrap())
+    }
+
+    /// 本地上游：/favicon.ico 按给定状态码与 ETag 应答，返回地址与命中次数
+    async fn stub_upstream(status: StatusCode, etag: Option<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            "/favicon.ico",
+            axum::routing::get(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let mut headers = HeaderMap::new();
+                    if let Some(etag) = etag {
+                        headers.insert(header::ETAG, header::HeaderValue::from_static(etag));
+                    }
+                    (status, headers, "icon-bytes")
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), hits)
+    }
+
+    fn favicon_request(if_none_match: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/favicon.ico");
+        if let Some(value) = if_none_match {
+            builder = builder.header(header::IF_NONE_MATCH, value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    async fn get_favicon(state: Arc<AppState>, req: Request<Body>) -> Response<Body> {
+        favicon_handler(State(state), req).await.into_response()
+    }
+
+    #[tokio::test]
+    async fn authority_form_uri_is_502_not_a_panic() {
+        // CONNECT 形式的 URI 只有 authority，替换 path 后缺 scheme，无法组装
+        let req = Request::builder().uri("example.com:443").body(Body::empty()).unwrap();
+        let resp = get_favicon(state(Config::default()).await, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn matching_etag_is_answered_with_304_from_cache() {
+        let (target, hits) = stub_upstream(StatusCode::OK, Some("\"icon-v1\"")).await;
+        let mut config = Config::default();
+        config.proxy.target = target;
+        let state = state(config).await;
+
+        let first = get_favicon(state.clone(), favicon_request(None)).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[header::ETAG], "\"icon-v1\"");
+
+        let conditional = get_favicon(state.clone(), favicon_request(Some("W/\"icon-v1\""))).await;
+        assert_eq!(conditional.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(conditional.headers()[header::ETAG], "\"icon-v1\"");
+        assert!(conditional.into_body().collect().await.unwrap().to_bytes().is_empty());
+
+        let stale_client = get_favicon(state, favicon_request(Some("\"icon-v0\""))).await;
+        assert_eq!(stale_client.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_refetched_by_default() {
+        let (target, hits) = stub_upstream(StatusCode::NOT_FOUND, None).await;
+        let mut config = Config::default();
+        config.proxy.target = target;
+        let state = state(config).await;
+
+        for _ in 0..2 {
+            let resp = get_favicon(state.clone(), favicon_request(None)).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(state.favicon_cache.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn not_found_is_cached_when_listed() {
+        let (target, hits) = stub_upstream(StatusCode::NOT_FOUND, None).await;
+        let mut config = Config::default();
+        config.proxy.target = target;
+        config.favicon.cache_statuses = vec![200, 404];
+        config.favicon.error_ttl_secs = 60;
+        let state = state(config).await;
+
+        for _ in 0..2 {
+            let resp = get_favicon(state.clone(), favicon_request(None)).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert_eq!(resp.into_body().collect().await.unwrap().to_bytes(), "icon-bytes");
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        let cached = state.favicon_cache.read().await;
+        assert_eq!(cached.as_ref().unwrap().ttl, std::time::Duration::from_secs(60));
+    }
+}
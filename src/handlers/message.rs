@@ -7,5 +7,19 @@ pub const MSG_TASK_EXPIRED: &str = "task expired";
 pub const MSG_USER_AGENT_MISMATCH: &str = "user agent mismatch";
 pub const MSG_IP_ADDRESS_MISMATCH: &str = "ip address mismatch";
 pub const MSG_INVALID_PROOF_OF_WORK: &str = "invalid proof of work";
+pub const MSG_SOLVED_TOO_FAST: &str = "proof of work solved too fast";
+pub const MSG_INVALID_TASK_SIGNATURE: &str = "invalid task signature";
+pub const MSG_INVALID_COMMITMENT: &str = "invalid challenge commitment";
 pub const MSG_FAILED_TO_GENERATE_TASK: &str = "failed to generate task";
-pub const MSG_FAILED_TO_ENCODE_TASK_RESPONSE_FRAME: &str = "failed to encode task response frame";
\ No newline at end of file
+pub const MSG_FAILED_TO_ENCODE_TASK_RESPONSE_FRAME: &str = "failed to encode task response frame";
+pub const MSG_FALLBACK_RATE_LIMITED: &str = "too many fallback requests";
+pub const MSG_WASM_FALLBACK_GRANTED: &str = "wasm fallback granted";
+pub const MSG_VERIFY_RATE_LIMITED: &str = "too many verify requests";
+pub const MSG_TASK_STORE_UNAVAILABLE: &str = "task store unavailable";
+pub const MSG_CAPTCHA_ISSUED: &str = "captcha issued";
+pub const MSG_CAPTCHA_VERIFIED: &str = "captcha verified";
+pub const MSG_CAPTCHA_MISMATCH: &str = "captcha answer mismatch";
+pub const MSG_CAPTCHA_RATE_LIMITED: &str = "too many captcha requests";
+pub const MSG_CAPTCHA_NOT_ALLOWED: &str = "captcha requires a failed challenge";
+pub const MSG_CHALLENGE_RATE_LIMITED: &str = "too many challenge requests";
+pub const MSG_DRAINING: &str = "instance is draining, retry later";
\ No newline at end of file
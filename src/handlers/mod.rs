@@ -1,3 +1,5 @@
+pub mod admin;
 pub mod pow;
 pub mod message;
 pub mod favicon;
+pub mod captcha;
@@ -1,30 +1,74 @@
+use std::fmt::Write as _;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::extract::{Query, State};
-use axum::http::{header, HeaderMap, Request, Response, StatusCode, Uri};
+use axum::http::{header, HeaderMap, Method, Request, Response, StatusCode, Uri};
 use axum::response::IntoResponse;
 use base64::Engine;
 use http_body_util::BodyExt;
 use serde::Deserialize;
 use time::OffsetDateTime;
 
+use crate::challenge_webhook::ChallengeEvent;
 use crate::config::IpPolicy;
 use crate::crypto::{compute_ip_hash, compute_ua_hash, generate_cookie};
 use crate::handlers::message::*;
+use crate::metrics::{ChallengeSource, VerifyFailure};
 use crate::protocol::frame::{
     decode_frame, decode_task_request, decode_verify_request, encode_error_frame,
-    encode_task_response, encode_verify_response, deobfuscate_frame, BinaryTaskResponse,
-    BinaryVerifyResponse, FRAME_TYPE_TASK_REQUEST, FRAME_TYPE_VERIFY_REQUEST, XOR_KEY,
+    encode_task_response, encode_verify_response, deobfuscate_frame, sign_task, task_commitment, verify_task_commitment, verify_task_signature, BinaryTaskResponse,
+    BinaryVerifyRequest, BinaryVerifyResponse, FRAME_TYPE_TASK_REQUEST, FRAME_TYPE_VERIFY_REQUEST, XOR_KEY,
 };
 use crate::protocol::http::HeaderMapExt;
 use crate::rules::clamp_difficulty;
-use crate::state::AppState;
+use crate::state::{AppState, UpstreamHealth};
+use crate::tls::TlsFingerprint;
+use crate::static_files::{TemplateAssets, TemplateSegment};
 use crate::storage::{ConsumeError, IpHash, Scope, Seed, Task, TaskId, UaHash};
 use crate::{crypto, protocol};
 use crate::ip_source::ip::resolve_request_ip;
 
 pub const POW_PREFIX: &str = "/__cowcatwaf";
+
+/// 按路径段边界匹配内部前缀：`/__cowcatwaf` 与 `/__cowcatwaf/...` 命中，`/__cowcatwafXYZ` 不命中。
+/// 区分大小写，与 axum 路由一致；`/__COWCATWAF/...` 不是内部路由，按普通请求过 gate
+pub fn is_pow_path(path: &str) -> bool {
+    path.strip_prefix(POW_PREFIX)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+/// 清理客户端带来的 redirect：去掉 CR/LF 等控制字符，避免经 Location 头或页面导航注入；
+/// 只接受站内路径，`https://…`、`//host`、`/\host` 一类会被浏览器当作跨站地址的，
+/// 以及清理后为空或超过 max_len 字节（0 为不限制）的，一律回落到 "/"
+pub fn sanitize_redirect(raw: &str, max_len: usize) -> String {
+    let cleaned: String = raw.chars().filter(|ch| !ch.is_control()).collect();
+    let same_origin = cleaned.starts_with('/') && !cleaned.starts_with("//") && !cleaned.starts_with("/\\");
+    if !same_origin || (max_len > 0 && cleaned.len() > max_len) {
+        return "/".to_string();
+    }
+    cleaned
+}
+
+/// 挑战页把 redirect 写进 `<script type="application/json">` 的字符串字面量：
+/// 引号、反斜杠与 `<`（防 `</script>` 提前闭合）等改写为 \uXXXX，JSON 解析后还原为原值
+fn escape_json_in_script(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for ch in raw.chars() {
+        match ch {
+            '"' | '\\' | '<' | '>' | '&' | '\'' | '\u{2028}' | '\u{2029}' => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
 pub const POW_COOKIE_NAME: &str = "cowcat.waf.token";
+/// pow.first_request_grace 种下的一次性宽限 Cookie
+pub const GRACE_COOKIE_NAME: &str = "cowcat.waf.grace";
+/// pow.accept_bearer_token 下携带通行令牌的请求/响应头
+pub const POW_TOKEN_HEADER: &str = "x-cowcat-token";
 
 #[derive(Debug, Deserialize)]
 pub struct ChallengeQuery {
@@ -36,8 +80,16 @@ pub async fn challenge_page(
     Query(query): Query<ChallengeQuery>,
     req: Request<axum::body::Body>,
 ) -> impl IntoResponse {
-    let redirect = query.redirect.unwrap_or_else(|| "/".to_string());
-    build_challenge_response(&state, req.headers(), req.extensions(), &redirect, state.config.pow.difficulty).await
+    let redirect = sanitize_redirect(query.redirect.as_deref().unwrap_or("/"), state.config.pow.max_redirect_len);
+    build_challenge_response(
+        &state,
+        req.headers(),
+        req.extensions(),
+        &redirect,
+        state.config.pow.difficulty,
+        &state.config.pow.worker_type,
+    )
+    .await
 }
 
 pub async fn pow_task(
@@ -47,18 +99,18 @@ pub async fn pow_task(
     let (parts, body) = req.into_parts();
     let body = match body.collect().await {
         Ok(collected) => collected.to_bytes(),
-        Err(_) => return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
+        Err(_) => return error_frame(&parts.headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
     };
     if !body.is_empty() {
         let (frame_type, payload) = match decode_frame(&body) {
             Ok(res) => res,
-            Err(_) => return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
+            Err(_) => return problem_response(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
         };
         if frame_type != FRAME_TYPE_TASK_REQUEST {
-            return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
+            return error_frame(&parts.headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
         }
         if decode_task_request(payload).is_err() {
-            return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
+            return error_frame(&parts.headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
         }
     }
 
@@ -66,7 +118,7 @@ pub async fn pow_task(
         Ok(task) => task,
         Err(err) => {
             tracing::error!(error = %err, "{}", MSG_FAILED_TO_GENERATE_TASK);
-            return error_frame(StatusCode::INTERNAL_SERVER_ERROR, MSG_FAILED_TO_GENERATE_TASK);
+            return error_frame(&parts.headers, StatusCode::INTERNAL_SERVER_ERROR, MSG_FAILED_TO_GENERATE_TASK);
         }
     };
     tracing::debug!(
@@ -76,7 +128,11 @@ pub async fn pow_task(
         "{}",
         MSG_POW_TASK_CREATED
     );
-    state.task_store.insert(task.clone()).await;
+    if let Err(err) = state.task_store.insert(task.clone()).await {
+        tracing::error!(error = %err, "{}", MSG_TASK_STORE_UNAVAILABLE);
+        return error_frame(&parts.headers, StatusCode::SERVICE_UNAVAILABLE, MSG_TASK_STORE_UNAVAILABLE);
+    }
+    state.metrics.challenge_issued(ChallengeSource::Task);
 
     let resp = BinaryTaskResponse {
         task_id: task.task_id.0.to_string(),
@@ -88,6 +144,10 @@ pub async fn pow_task(
         ip_hash: task.ip_hash.0.clone(),
         workers: state.config.pow.workers,
         worker_type: state.config.pow.worker_type.clone(),
+        chain_length: task.chain_length.min(u8::MAX as u32) as u8,
+        hash_algo: state.config.pow.hash_algo().name().to_string(),
+        sig: state.task_sign_key.as_ref().map(|key| sign_task(key, &task)),
+        commitment: state.commitment_key.as_ref().and_then(|key| task_commitment(key, &task)),
     };
     let mut frame = protocol::frame::encode_frame(protocol::frame::FRAME_TYPE_TASK_RESPONSE, encode_task_response(resp));
     deobfuscate_frame(&mut frame, XOR_KEY);
@@ -97,37 +157,86 @@ pub async fn pow_task(
     (headers, frame).into_response()
 }
 
-pub async fn pow_verify(
+/// 页面报告 wasm worker 不可用时改发 native worker 的低难度任务。
+/// 按 IP 限流，避免被当成稳定的降难度捷径；未配置 pow.wasm_fallback 时接口不存在
+pub async fn pow_fallback(
     State(state): State<Arc<AppState>>,
     req: Request<axum::body::Body>,
 ) -> impl IntoResponse {
-    let (parts, body) = req.into_parts();
-    let body = match body.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(_) => return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
+    let (parts, _body) = req.into_parts();
+    let (Some(fallback), Some(limiter)) = (&state.config.pow.wasm_fallback, &state.fallback_limiter) else {
+        return StatusCode::NOT_FOUND.into_response();
     };
-    if body.is_empty() {
-        return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
+    let (client_ip, _) = resolve_request_ip(&parts.headers, &parts.extensions, &state.trusted_proxies);
+    if !limiter.try_acquire(&client_ip).await {
+        tracing::info!(client_ip = %client_ip, "wasm fallback refused: rate limited");
+        return error_frame(&parts.headers, StatusCode::TOO_MANY_REQUESTS, MSG_FALLBACK_RATE_LIMITED);
     }
 
-    let mut deobfuscated = body.to_vec();
-    deobfuscate_frame(&mut deobfuscated, XOR_KEY);
-    let (frame_type, payload) = match decode_frame(&deobfuscated) {
-        Ok(res) => res,
-        Err(_) => return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
+    let task = match build_task(&state, &parts.headers, &parts.extensions, fallback.difficulty) {
+        Ok(task) => task,
+        Err(err) => {
+            tracing::error!(error = %err, "{}", MSG_FAILED_TO_GENERATE_TASK);
+            return error_frame(&parts.headers, StatusCode::INTERNAL_SERVER_ERROR, MSG_FAILED_TO_GENERATE_TASK);
+        }
     };
-    if frame_type != FRAME_TYPE_VERIFY_REQUEST {
-        return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST);
+    let frame = match protocol::frame::encode_task_response_frame(
+        &task,
+        state.config.pow.workers,
+        "native",
+        state.config.pow.hash_algo().name(),
+        state.task_sign_key.as_ref(),
+        state.commitment_key.as_ref(),
+    ) {
+        Ok(frame) => frame,
+        Err(err) => {
+            tracing::error!(error = %err, "{}", MSG_FAILED_TO_ENCODE_TASK_RESPONSE_FRAME);
+            return error_frame(&parts.headers, StatusCode::INTERNAL_SERVER_ERROR, MSG_FAILED_TO_ENCODE_TASK_RESPONSE_FRAME);
+        }
+    };
+    if let Err(err) = state.task_store.insert(task.clone()).await {
+        tracing::error!(error = %err, "{}", MSG_TASK_STORE_UNAVAILABLE);
+        return error_frame(&parts.headers, StatusCode::SERVICE_UNAVAILABLE, MSG_TASK_STORE_UNAVAILABLE);
     }
+    state.metrics.challenge_issued(ChallengeSource::Fallback);
+    tracing::info!(
+        task_id = %task.task_id.short_id(),
+        client_ip = %client_ip,
+        bits = task.bits,
+        user_agent = %headers_user_agent(&parts.headers),
+        "{}",
+        MSG_WASM_FALLBACK_GRANTED
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/octet-stream"));
+    (headers, frame).into_response()
+}
 
-    let verify_req = match decode_verify_request(payload) {
+pub async fn pow_verify(
+    State(state): State<Arc<AppState>>,
+    req: Request<axum::body::Body>,
+) -> impl IntoResponse {
+    let (parts, body) = req.into_parts();
+    if let Some(limiter) = &state.verify_limiter {
+        let (client_ip, _) = resolve_request_ip(&parts.headers, &parts.extensions, &state.trusted_proxies);
+        if let Err(limit) = limiter.check(&client_ip).await {
+            tracing::debug!(client_ip = %client_ip, limit = ?limit, "{}", MSG_VERIFY_RATE_LIMITED);
+            state.metrics.verify_failed(VerifyFailure::RateLimited);
+            return error_frame(&parts.headers, StatusCode::TOO_MANY_REQUESTS, MSG_VERIFY_RATE_LIMITED);
+        }
+    }
+    let verify_req = match read_verify_request(&parts.headers, body).await {
         Ok(req) => req,
-        Err(_) => return error_frame(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST),
+        Err(response) => {
+            state.metrics.verify_failed(VerifyFailure::InvalidRequest);
+            return response;
+        }
     };
 
     let ua_hash = compute_ua_hash(headers_user_agent(&parts.headers));
     let ip_for_verify = if state.config.pow.ip_policy != IpPolicy::None {
-        crypto::extract_client_ip(&parts.headers, &parts.extensions, state.config.pow.ip_policy)
+        crypto::extract_client_ip(&parts.headers, &parts.extensions, state.config.pow.ip_policy, &state.trusted_proxies)
     } else {
         String::new()
     };
@@ -137,7 +246,30 @@ pub async fn pow_verify(
         String::new()
     };
 
-    let task = match state.task_store.consume_if(&verify_req.task_id, |task| {
+    let task = match state.task_store.consume_if(&verify_req.task_id, Box::new(|task: &Task| {
+        // 验证码任务只能经 /captcha 消费，不能用 PoW 结果兑换
+        if task.captcha_answer.is_some() {
+            return Err(ConsumeError::ValidationFailed(MSG_INVALID_REQUEST));
+        }
+        // 签名只证明任务字段出自本服务且与 task_id 对应；缺失或不符都按篡改处理
+        if let Some(key) = &state.task_sign_key {
+            let valid = verify_req.sig.as_deref().is_some_and(|sig| verify_task_signature(key, task, sig));
+            if !valid {
+                tracing::warn!(task_id = %task.task_id.short_id(), has_sig = verify_req.sig.is_some(), "{}", MSG_INVALID_TASK_SIGNATURE);
+                return Err(ConsumeError::ValidationFailed(MSG_INVALID_TASK_SIGNATURE));
+            }
+        }
+        // 承诺绑定到这一次下发：换一份页面或伪造的承诺都对不上任务里保存的随机数
+        if let Some(key) = &state.commitment_key {
+            let valid = verify_req
+                .commitment
+                .as_deref()
+                .is_some_and(|commitment| verify_task_commitment(key, task, commitment));
+            if !valid {
+                tracing::warn!(task_id = %task.task_id.short_id(), has_commitment = verify_req.commitment.is_some(), "{}", MSG_INVALID_COMMITMENT);
+                return Err(ConsumeError::ValidationFailed(MSG_INVALID_COMMITMENT));
+            }
+        }
         if task.ua_hash.0 != ua_hash {
             tracing::warn!(task_id = %task.task_id.short_id(), "{}", MSG_USER_AGENT_MISMATCH);
             return Err(ConsumeError::ValidationFailed(MSG_USER_AGENT_MISMATCH));
@@ -146,65 +278,84 @@ pub async fn pow_verify(
             tracing::warn!(task_id = %task.task_id.short_id(), "{}", MSG_IP_ADDRESS_MISMATCH);
             return Err(ConsumeError::ValidationFailed(MSG_IP_ADDRESS_MISMATCH));
         }
-        if !crypto::verify_pow(task, &verify_req.nonce) {
-            tracing::warn!(task_id = %task.task_id.short_id(), "{}", MSG_INVALID_PROOF_OF_WORK);
-            return Err(ConsumeError::ValidationFailed(MSG_INVALID_PROOF_OF_WORK));
+        if let Err(err) = state.verifier.verify(task, &verify_req) {
+            if let ConsumeError::ValidationFailed(msg) = &err {
+                tracing::warn!(task_id = %task.task_id.short_id(), "{}", msg);
+            }
+            return Err(err);
         }
+        check_min_solve_time(&state, task).map_err(ConsumeError::ValidationFailed)?;
         Ok(())
-    }).await {
+    })).await {
         Ok(task) => task,
         Err(ConsumeError::NotFound) => {
+            state.metrics.verify_failed(VerifyFailure::NotFound);
             tracing::warn!(task_id = %TaskId::from(verify_req.task_id.as_str()).short_id(), "{}", MSG_TASK_NOT_FOUND_OR_EXPIRED);
-            return error_frame(StatusCode::BAD_REQUEST, MSG_TASK_NOT_FOUND_OR_EXPIRED);
+            return error_frame(&parts.headers, StatusCode::BAD_REQUEST, MSG_TASK_NOT_FOUND_OR_EXPIRED);
         }
         Err(ConsumeError::Expired) => {
+            state.metrics.verify_failed(VerifyFailure::Expired);
             tracing::warn!(task_id = %TaskId::from(verify_req.task_id.as_str()).short_id(), "{}", MSG_TASK_EXPIRED);
-            return error_frame(StatusCode::BAD_REQUEST, MSG_TASK_EXPIRED);
+            grant_captcha_ticket(&state, &parts.headers, &parts.extensions, &verify_req.task_id).await;
+            return error_frame(&parts.headers, StatusCode::BAD_REQUEST, MSG_TASK_EXPIRED);
+        }
+        Err(ConsumeError::Unavailable) => {
+            state.metrics.verify_failed(VerifyFailure::StoreUnavailable);
+            return error_frame(&parts.headers, StatusCode::SERVICE_UNAVAILABLE, MSG_TASK_STORE_UNAVAILABLE);
         }
         Err(ConsumeError::ValidationFailed(msg)) => {
-            return error_frame(StatusCode::BAD_REQUEST, msg);
+            state.metrics.verify_failed(VerifyFailure::from_message(msg));
+            if msg == MSG_INVALID_PROOF_OF_WORK {
+                if let Some(bans) = &state.failure_bans {
+                    let (client_ip, _) = resolve_request_ip(&parts.headers, &parts.extensions, &state.trusted_proxies);
+                    bans.record_failure(&client_ip);
+                }
+            }
+            // MSG_INVALID_REQUEST 对应提交到这里的验证码任务，不算 PoW 失败
+            if msg != MSG_INVALID_REQUEST {
+                grant_captcha_ticket(&state, &parts.headers, &parts.extensions, &verify_req.task_id).await;
+            }
+            return error_frame(&parts.headers, StatusCode::BAD_REQUEST, msg);
         }
     };
 
+    state.metrics.verify_succeeded();
+    if let Some(bans) = &state.failure_bans {
+        let (client_ip, _) = resolve_request_ip(&parts.headers, &parts.extensions, &state.trusted_proxies);
+        bans.record_success(&client_ip);
+    }
+
     let expire_seconds = state.config.pow.cookie_expire_hours * 3600;
     let cookie_value = generate_cookie(
-        &state.server_secret,
+        &state.cookie_keys,
         task.bits as i32,
         &task.scope.0,
         &task.ua_hash.0,
         &task.ip_hash.0,
+        cookie_fingerprint(&state, &parts.extensions),
         &verify_req.nonce,
         expire_seconds,
     );
 
     let redirect = if state.config.pow.test_mode {
-        format!("{}/ok", POW_PREFIX)
-    } else if verify_req.redirect.is_empty() {
-        "/".to_string()
+        // 优先用客户端上报的计算耗时，缺失时用服务端从下发到验证的时间
+        let elapsed_ms = compute_time_ms(&parts.uri)
+            .unwrap_or_else(|| (unix_millis(OffsetDateTime::now_utc()) - task.issued_at_ms).max(0) as u64);
+        format!("{}/ok-page?bits={}&elapsed_ms={}", POW_PREFIX, task.bits, elapsed_ms)
     } else {
-        verify_req.redirect.clone()
+        sanitize_redirect(&verify_req.redirect, state.config.pow.max_redirect_len)
     };
 
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/octet-stream"));
-    let set_cookie = if state.config.pow.secure {
-        cookie::Cookie::build((POW_COOKIE_NAME, cookie_value))
-            .path("/")
-            .http_only(true)
-            .secure(true)
-            .same_site(cookie::SameSite::None)
-            .max_age(time::Duration::seconds(expire_seconds))
-            .build()
-            .to_string()
-    } else {
-        cookie::Cookie::build((POW_COOKIE_NAME, cookie_value))
-            .path("/")
-            .http_only(true)
-            .max_age(time::Duration::seconds(expire_seconds))
-            .build()
-            .to_string()
-    };
-    
+    if state.config.pow.accept_bearer_token {
+        if let Ok(value) = header::HeaderValue::from_str(&cookie_value) {
+            headers.insert(header::HeaderName::from_static(POW_TOKEN_HEADER), value);
+        }
+    }
+    let token = state.config.pow.accept_bearer_token.then(|| cookie_value.clone());
+    let set_cookie = pow_set_cookie(&state, cookie_value, expire_seconds);
+
     if let Ok(value) = header::HeaderValue::from_str(&set_cookie) {
         headers.insert(header::SET_COOKIE, value);
     }
@@ -217,7 +368,7 @@ pub async fn pow_verify(
     // 提取并格式化计算时间
     let elapsed = extract_and_format_compute_time(&parts.uri);
 
-    let final_ip = resolve_request_ip(&parts.headers, &parts.extensions);
+    let final_ip = resolve_request_ip(&parts.headers, &parts.extensions, &state.trusted_proxies);
     
     // 根据是否有计算时间，使用不同的日志格式
     if let Some(time_str) = &elapsed {
@@ -246,19 +397,95 @@ pub async fn pow_verify(
             MSG_POW_VERIFIED
         );
     }
-    let resp = BinaryVerifyResponse { redirect };
+    let bits = state.config.pow.verify_response_bits.then_some(task.bits);
+    // 非浏览器客户端：Accept JSON 时以 JSON 返回令牌，省去解析二进制帧
+    if let Some(token) = token.filter(|_| accepts_json(&parts.headers)) {
+        headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+        let body = serde_json::json!({
+            "redirect": redirect,
+            "token": token,
+            "expires_in": expire_seconds,
+            "bits": bits,
+        });
+        return (headers, body.to_string()).into_response();
+    }
+    let resp = BinaryVerifyResponse { redirect, bits };
     let frame = protocol::frame::encode_frame(protocol::frame::FRAME_TYPE_VERIFY_RESPONSE, encode_verify_response(resp));
     (headers, frame).into_response()
 }
 
 
+/// pow.bind_fingerprint 时写入通行 Cookie 的连接指纹；未开启或连接不是本服务终结的 TLS 时为空。
+/// 已被 /verify 消耗却未通过的 PoW 任务登记为验证码票据（pow.captcha_fallback）
+async fn grant_captcha_ticket(state: &AppState, headers: &HeaderMap, extensions: &axum::http::Extensions, task_id: &str) {
+    if let Some(tickets) = &state.captcha_tickets {
+        let (client_ip, _) = resolve_request_ip(headers, extensions, &state.trusted_proxies);
+        tickets.grant(task_id, &client_ip).await;
+    }
+}
+
+/// 签发（/verify、/captcha）与 pow_gate 校验共用
+pub(crate) fn cookie_fingerprint<'a>(state: &AppState, extensions: &'a axum::http::Extensions) -> &'a str {
+    if !state.config.pow.bind_fingerprint {
+        return "";
+    }
+    extensions.get::<TlsFingerprint>().map(|fp| fp.0.as_str()).unwrap_or_default()
+}
+
+/// 通行 Cookie 的 Set-Cookie 值，/verify 与 /captcha 共用
+pub(crate) fn pow_set_cookie(state: &AppState, cookie_value: String, expire_seconds: i64) -> String {
+    if state.config.secure_cookies() {
+        cookie::Cookie::build((POW_COOKIE_NAME, cookie_value))
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(cookie::SameSite::None)
+            .max_age(time::Duration::seconds(expire_seconds))
+            .build()
+            .to_string()
+    } else {
+        cookie::Cookie::build((POW_COOKIE_NAME, cookie_value))
+            .path("/")
+            .http_only(true)
+            .max_age(time::Duration::seconds(expire_seconds))
+            .build()
+            .to_string()
+    }
+}
+
 pub async fn serve_asset(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     axum::extract::Path(path): axum::extract::Path<String>,
+    request_headers: HeaderMap,
 ) -> impl IntoResponse {
     let file_path = format!("assets/{}", path.trim_start_matches('/'));
-    let Some(bytes) = crate::static_files::get_asset(&file_path) else {
-        return StatusCode::NOT_FOUND.into_response();
+    // wasm 体积最大：每次加载都重新验证，但内容未变时只回 304，不重发响应体
+    let etag = file_path
+        .ends_with("catpaw.wasm")
+        .then(|| crate::static_files::get_asset_etag(&file_path))
+        .flatten();
+    if let Some(etag) = &etag {
+        if etag_matches(&request_headers, etag) {
+            let mut headers = HeaderMap::new();
+            if let Ok(value) = header::HeaderValue::from_str(etag) {
+                headers.insert(header::ETAG, value);
+            }
+            headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static(cache_control_for(&file_path)));
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+    }
+    let bytes = match crate::static_files::get_asset(&file_path) {
+        Some(bytes) => bytes,
+        None => {
+            let assets_dir = state.config.pow.page.assets_dir.trim();
+            if assets_dir.is_empty() {
+                return StatusCode::NOT_FOUND.into_response();
+            }
+            match crate::static_files::get_disk_asset(assets_dir, &path).await {
+                Some(bytes) => bytes,
+                None => return StatusCode::NOT_FOUND.into_response(),
+            }
+        }
     };
 
     let content_type = content_type_for(&file_path);
@@ -271,19 +498,151 @@ pub async fn serve_asset(
     if let Ok(value) = header::HeaderValue::from_str(cache_control) {
         headers.insert(header::CACHE_CONTROL, value);
     }
+    if let Some(value) = etag.and_then(|etag| header::HeaderValue::from_str(&etag).ok()) {
+        headers.insert(header::ETAG, value);
+    }
     (headers, bytes).into_response()
 }
 
+/// If-None-Match 弱比较：忽略 W/ 前缀，`*` 匹配任意
+fn etag_matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// 读取并解码 verify 请求体；失败时返回应直接回给客户端的响应
+async fn read_verify_request(
+    headers: &HeaderMap,
+    body: axum::body::Body,
+) -> Result<BinaryVerifyRequest, Response<axum::body::Body>> {
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(_) => return Err(error_frame(headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST)),
+    };
+    if body.is_empty() {
+        return Err(problem_response(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST));
+    }
+
+    let mut deobfuscated = body.to_vec();
+    deobfuscate_frame(&mut deobfuscated, XOR_KEY);
+    let (frame_type, payload) = match decode_frame(&deobfuscated) {
+        Ok(res) => res,
+        Err(_) => return Err(problem_response(StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST)),
+    };
+    if frame_type != FRAME_TYPE_VERIFY_REQUEST {
+        return Err(error_frame(headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST));
+    }
+
+    decode_verify_request(payload).map_err(|_| error_frame(headers, StatusCode::BAD_REQUEST, MSG_INVALID_REQUEST))
+}
+
 pub async fn health_ok() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
+/// /healthz 的探测结果在此时间内复用，频繁的存活探针不会压到上游
+const UPSTREAM_HEALTH_TTL: Duration = Duration::from_secs(2);
+const UPSTREAM_HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 向 proxy.target 发 HEAD，上游有任何响应（不论状态码）即 200，否则 503；结果缓存 UPSTREAM_HEALTH_TTL
+pub async fn healthz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut cached = state.upstream_health.lock().await;
+    let fresh = cached
+        .as_ref()
+        .is_some_and(|health| health.checked_at.elapsed() < UPSTREAM_HEALTH_TTL);
+    if !fresh {
+        let result = probe_upstream(&state).await;
+        if let Err(err) = &result {
+            tracing::warn!(error = %err, "upstream health check failed");
+        }
+        *cached = Some(UpstreamHealth {
+            checked_at: Instant::now(),
+            result,
+        });
+    }
+    let (status, body) = match cached.as_ref().map(|health| &health.result) {
+        Some(Ok(upstream_status)) => (
+            StatusCode::OK,
+            serde_json::json!({ "status": "ok", "upstream_status": upstream_status }),
+        ),
+        Some(Err(err)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "unavailable", "error": err }),
+        ),
+        None => (StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({ "status": "unavailable" })),
+    };
+    (
+        status,
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        body.to_string(),
+    )
+}
+
+/// 依次探测默认上游，任一可达即视为健康（代理会把连接失败转移到其他上游）；全部失败时返回最后一个原因
+async fn probe_upstream(state: &AppState) -> Result<u16, String> {
+    let mut result = Err(String::new());
+    for target in state.upstreams.targets() {
+        result = probe_target(state, target).await;
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+async fn probe_target(state: &AppState, target: &crate::state::ProxyTarget) -> Result<u16, String> {
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri(target.uri.clone())
+        .header(header::HOST, target.host_value.clone())
+        .body(axum::body::Body::empty())
+        .map_err(|err| err.to_string())?;
+    match tokio::time::timeout(UPSTREAM_HEALTH_TIMEOUT, state.proxy_client.request(req)).await {
+        Ok(Ok(resp)) => Ok(resp.status().as_u16()),
+        Ok(Err(err)) => Err(crate::proxy::error::ProxyError::from_client_error(&err).reason().to_string()),
+        Err(_) => Err(crate::proxy::error::ProxyError::Timeout.reason().to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkPageQuery {
+    pub bits: Option<u32>,
+    pub elapsed_ms: Option<u64>,
+}
+
+/// test_mode 下验证通过后的确认页；/ok 保持纯文本，供存活探针使用
+pub async fn ok_page(State(state): State<Arc<AppState>>, Query(query): Query<OkPageQuery>) -> impl IntoResponse {
+    let Some(template) = &state.ok_page else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let elapsed = query.elapsed_ms.map(format_compute_time);
+    let html = crate::static_files::render_ok_page(template, query.bits, elapsed.as_deref());
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        html,
+    )
+        .into_response()
+}
+
 pub async fn build_challenge_response(
     state: &AppState,
     headers: &HeaderMap,
     extensions: &axum::http::Extensions,
     redirect: &str,
     difficulty: i32,
+    worker_type: &str,
 ) -> Response<axum::body::Body> {
     let task = match build_task(state, headers, extensions, difficulty) {
         Ok(task) => task,
@@ -293,7 +652,14 @@ pub async fn build_challenge_response(
         }
     };
 
-    let task_frame = match protocol::frame::encode_task_response_frame(&task, state.config.pow.workers, &state.config.pow.worker_type) {
+    let task_frame = match protocol::frame::encode_task_response_frame(
+        &task,
+        state.config.pow.workers,
+        worker_type,
+        state.config.pow.hash_algo().name(),
+        state.task_sign_key.as_ref(),
+        state.commitment_key.as_ref(),
+    ) {
         Ok(frame) => frame,
         Err(err) => {
             tracing::error!(error = %err, "{}", MSG_FAILED_TO_ENCODE_TASK_RESPONSE_FRAME);
@@ -301,41 +667,86 @@ pub async fn build_challenge_response(
         }
     };
 
-    state.task_store.insert(task.clone()).await;
+    if let Err(err) = state.task_store.insert(task.clone()).await {
+        tracing::error!(error = %err, "{}", MSG_TASK_STORE_UNAVAILABLE);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    state.metrics.challenge_issued(ChallengeSource::Page);
+
+    if let Some(webhook) = &state.challenge_webhook {
+        webhook.emit(ChallengeEvent {
+            ip: resolve_request_ip(headers, extensions, &state.trusted_proxies).0,
+            path: redirect.to_string(),
+            user_agent: headers_user_agent(headers).to_string(),
+            difficulty,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+        });
+    }
 
     let task_b64 = base64::engine::general_purpose::STANDARD.encode(task_frame);
-    let rendered = render_template(
-        &state.template,
-        &task_b64,
-        redirect,
-        &state.cowcat_image1,
-        &state.cowcat_image2,
-    );
+    let assets = state.template_for(headers);
+    let rendered = render_template(&assets, &task_b64, &escape_json_in_script(redirect));
+    let rendered = if state.captcha_page.is_some() {
+        inject_captcha_entry(rendered, &task.task_id.0, redirect)
+    } else {
+        rendered
+    };
 
     let mut headers = HeaderMap::new();
     headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private, max-age=0, no-store, no-cache, must-revalidate"));
     headers.insert(header::PRAGMA, header::HeaderValue::from_static("no-cache"));
     headers.insert(header::EXPIRES, header::HeaderValue::from_static("0"));
     headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("text/html; charset=utf-8"));
+    if state.config.pow.page.preload && !state.config.pow.page.inline_all {
+        headers.insert(header::LINK, preload_link_header(&crate::static_files::asset_base(&state.config.pow)));
+    }
+
+    let status = StatusCode::from_u16(state.config.pow.challenge_status).unwrap_or(StatusCode::FORBIDDEN);
+    (status, headers, rendered).into_response()
+}
+
+/// worker 脚本带时间戳参数加载，预加载命中不了缓存，因此只预加载主脚本与 wasm；
+/// wasm 由 fetch() 以 cors 模式请求，preload 需带 crossorigin 才能复用
+fn preload_link_header(asset_base: &str) -> header::HeaderValue {
+    let value = format!(
+        "<{asset_base}/catpaw.min.js>; rel=preload; as=script, \
+         <{asset_base}/catpaw.wasm>; rel=preload; as=fetch; crossorigin"
+    );
+    header::HeaderValue::from_str(&value).unwrap_or_else(|_| header::HeaderValue::from_static(""))
+}
 
-    (StatusCode::FORBIDDEN, headers, rendered).into_response()
+/// pow.captcha_fallback：在 </body> 前放入验证码入口。脚本在 PoW 失败时取消 hidden，
+/// 未启用 JavaScript 的浏览器则经 <noscript> 直接看到入口。入口带上本页任务的 task_id，
+/// /captcha 凭它（或它在 /verify 失败后留下的票据）才肯出题
+fn inject_captcha_entry(mut html: String, task_id: &str, redirect: &str) -> String {
+    let href = format!(
+        "{}/captcha?task_id={}&amp;redirect={}",
+        POW_PREFIX,
+        percent_encoding::utf8_percent_encode(task_id, percent_encoding::NON_ALPHANUMERIC),
+        percent_encoding::utf8_percent_encode(redirect, percent_encoding::NON_ALPHANUMERIC)
+    );
+    let entry = format!(
+        r#"<p id="cowcat-captcha" class="s" hidden><a href="{href}">无法完成验证？改用验证码</a></p><noscript><p class="s"><a href="{href}">浏览器未启用 JavaScript，改用验证码</a></p></noscript>"#
+    );
+    let at = html.rfind("</body>").unwrap_or(html.len());
+    html.insert_str(at, &entry);
+    html
 }
 
-fn render_template(
-    template: &str,
-    task_data: &str,
-    redirect_url: &str,
-    cowcat_image1: &str,
-    cowcat_image2: &str,
-) -> String {
-    template
-        .replace("{{ TaskData }}", task_data)
-        .replace("{{ RedirectURL }}", redirect_url)
-        .replace("{{ CowcatImage1 }}", cowcat_image1)
-        .replace("{{ CowcatImage2 }}", cowcat_image2)
+/// 按加载时切分好的片段依次拼接，不再扫描模板；按每个占位符出现一次预估容量
+fn render_template(assets: &TemplateAssets, task_data: &str, redirect_url: &str) -> String {
+    let mut out = String::with_capacity(assets.literal_len + task_data.len() + redirect_url.len());
+    for segment in &assets.segments {
+        match segment {
+            TemplateSegment::Literal(text) => out.push_str(text),
+            TemplateSegment::TaskData => out.push_str(task_data),
+            TemplateSegment::RedirectUrl => out.push_str(redirect_url),
+        }
+    }
+    out
 }
 
-fn build_task(
+pub(crate) fn build_task(
     state: &AppState,
     headers: &HeaderMap,
     extensions: &axum::http::Extensions,
@@ -343,7 +754,7 @@ fn build_task(
 ) -> anyhow::Result<Task> {
     let ua_hash = compute_ua_hash(headers_user_agent(headers));
     let ip_for_verify = if state.config.pow.ip_policy != IpPolicy::None {
-        crypto::extract_client_ip(headers, extensions, state.config.pow.ip_policy)
+        crypto::extract_client_ip(headers, extensions, state.config.pow.ip_policy, &state.trusted_proxies)
     } else {
         String::new()
     };
@@ -353,10 +764,20 @@ fn build_task(
         String::new()
     };
 
-    let task_id = crypto::generate_random_id()?;
-    let seed = crypto::generate_random_seed()?;
-    let bits = (clamp_difficulty(difficulty) * 4) as u32;
-    let exp = OffsetDateTime::now_utc().unix_timestamp() + 120;
+    let (task_id, seed) = match state.task_pool.as_ref().and_then(|pool| pool.pop()) {
+        Some(pair) => pair,
+        None => (crypto::generate_random_id()?, crypto::generate_random_seed()?),
+    };
+    let bits = if state.config.pow.dev_mode {
+        tracing::debug!("dev_mode: issuing difficulty 0 task (insecure)");
+        0
+    } else {
+        (clamp_difficulty(difficulty) * 4) as u32
+    };
+    let now = OffsetDateTime::now_utc();
+    let chain_length = state.config.pow.chain_length.max(1);
+    // 每一环都给足单个任务的时限
+    let exp = now.unix_timestamp() + 120 * chain_length as i64;
     let scope = headers_host(headers).unwrap_or_else(|| "unknown".to_string());
 
     Ok(Task {
@@ -367,17 +788,83 @@ fn build_task(
         scope: Scope(scope),
         ua_hash: UaHash(ua_hash),
         ip_hash: IpHash(ip_hash),
+        issued_at_ms: unix_millis(now),
+        chain_length,
+        captcha_answer: None,
+        commitment_nonce: state.commitment_key.as_ref().map(|_| crypto::generate_random_id()).transpose()?,
     })
 }
 
-fn error_frame(status: StatusCode, message: &str) -> Response<axum::body::Body> {
+fn unix_millis(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// 求解耗时低于下限（按 worker 数折算，链式挑战按长度累加）时视为可疑：默认仅记录，enforce 时拒绝
+fn check_min_solve_time(state: &AppState, task: &Task) -> Result<(), &'static str> {
+    let floor = state.config.pow.min_solve_ms * task.chain_length.max(1) as u64;
+    if floor == 0 || task.bits == 0 {
+        return Ok(());
+    }
+    let workers = state.config.pow.workers.max(1) as u64;
+    let threshold_ms = (floor / workers) as i64;
+    let elapsed_ms = unix_millis(OffsetDateTime::now_utc()) - task.issued_at_ms;
+    if elapsed_ms >= threshold_ms {
+        return Ok(());
+    }
+    let enforce = state.config.pow.min_solve_enforce;
+    tracing::warn!(
+        task_id = %task.task_id.short_id(),
+        bits = task.bits,
+        elapsed_ms,
+        threshold_ms,
+        enforce,
+        "{}",
+        MSG_SOLVED_TOO_FAST
+    );
+    if enforce {
+        Err(MSG_SOLVED_TOO_FAST)
+    } else {
+        Ok(())
+    }
+}
+
+/// 协议错误：客户端声明接受 JSON 时回 RFC 7807 problem details，否则回二进制错误帧
+fn error_frame(request_headers: &HeaderMap, status: StatusCode, message: &str) -> Response<axum::body::Body> {
+    if accepts_json(request_headers) {
+        return problem_response(status, message);
+    }
     let frame = encode_error_frame(message);
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/octet-stream"));
     (status, headers, frame).into_response()
 }
 
-fn headers_user_agent(headers: &HeaderMap) -> &str {
+/// 根本不是合法帧（多半是人或配错的客户端）时直接回 problem details，二进制帧对他们毫无可读性
+fn problem_response(status: StatusCode, message: &str) -> Response<axum::body::Body> {
+    let body = serde_json::json!({
+        "type": "about:blank",
+        "title": status.canonical_reason().unwrap_or("Error"),
+        "status": status.as_u16(),
+        "detail": message,
+    });
+    (
+        status,
+        [(header::CONTENT_TYPE, header::HeaderValue::from_static("application/problem+json"))],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+fn accepts_json(headers: &HeaderMap) -> bool {
+    headers.get_all(header::ACCEPT).iter().filter_map(|v| v.to_str().ok()).any(|accept| {
+        accept.split(',').any(|media| {
+            let media = media.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+            media == "application/json" || media == "application/problem+json"
+        })
+    })
+}
+
+pub(crate) fn headers_user_agent(headers: &HeaderMap) -> &str {
     headers.get_str(header::USER_AGENT).unwrap_or_default()
 }
 
@@ -386,12 +873,16 @@ fn headers_host(headers: &HeaderMap) -> Option<String> {
 }
 
 fn extract_and_format_compute_time(uri: &Uri) -> Option<String> {
+    compute_time_ms(uri).map(format_compute_time)
+}
+
+fn compute_time_ms(uri: &Uri) -> Option<u64> {
     let query = uri.query()?;
     for pair in query.split('&') {
         if let Some((key, value)) = pair.split_once('=') {
             if key == "compute_time" {
                 if let Ok(ms) = value.parse::<u64>() {
-                    return Some(format_compute_time(ms));
+                    return Some(ms);
                 }
             }
         }
@@ -444,12 +935,668 @@ fn cache_control_for(path: &str) -> &'static str {
         || path.contains("catpaw.min.js")
         || path.contains("catpaw.worker.min.js")
         || path.contains("catpaw.html")
-        || path.contains("catpaw.wasm")
     {
         "private, max-age=0, no-store, no-cache, must-revalidate, post-check=0, pre-check=0"
+    } else if path.contains("catpaw.wasm") {
+        // 可以存但每次都要带 ETag 重新验证：更新后立即生效，未变化时只走 304
+        "private, no-cache"
     } else if path.contains("webp") {
         "public, max-age=86400"
     } else {
         "public, no-cache"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::storage::{TaskStoreBackend, TaskValidator};
+
+    async fn state(config: Config) -> Arc<AppState> {
+        Arc::new(AppState::new(config).await.unwrap())
+    }
+
+    async fn get_asset(state: Arc<AppState>, path: &str) -> Response<axum::body::Body> {
+        serve_asset(State(state), axum::extract::Path(path.to_string()), HeaderMap::new())
+            .await
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn missing_asset_is_404_with_and_without_assets_dir() {
+        assert_eq!(get_asset(state(Config::default()).await, "fonts/absent.woff2").await.status(), StatusCode::NOT_FOUND);
+
+        let dir = std::env::temp_dir().join(format!("cowcat-serve-asset-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("brand.woff2"), b"font-bytes").unwrap();
+        let mut config = Config::default();
+        config.pow.page.assets_dir = dir.to_str().unwrap().to_string();
+        let state = state(config).await;
+
+        assert_eq!(get_asset(state.clone(), "fonts/absent.woff2").await.status(), StatusCode::NOT_FOUND);
+        let resp = get_asset(state, "brand.woff2").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "font/woff2");
+    }
+
+    #[tokio::test]
+    async fn wasm_revalidates_with_304_on_matching_etag() {
+        let state = state(Config::default()).await;
+        let first = get_asset(state.clone(), "catpaw.wasm").await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[header::CACHE_CONTROL], "private, no-cache");
+        let etag = first.headers()[header::ETAG].clone();
+
+        let mut conditional = HeaderMap::new();
+        conditional.insert(header::IF_NONE_MATCH, etag.clone());
+        let resp = serve_asset(State(state.clone()), axum::extract::Path("catpaw.wasm".to_string()), conditional)
+            .await
+            .into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+        assert!(resp.into_body().collect().await.unwrap().to_bytes().is_empty());
+
+        let mut stale = HeaderMap::new();
+        stale.insert(header::IF_NONE_MATCH, header::HeaderValue::from_static("W/\"0000\""));
+        let resp = serve_asset(State(state), axum::extract::Path("catpaw.wasm".to_string()), stale)
+            .await
+            .into_response();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    async fn get_ok_page(config: Config, query: OkPageQuery) -> Response<axum::body::Body> {
+        ok_page(State(state(config).await), Query(query)).await.into_response()
+    }
+
+    #[tokio::test]
+    async fn ok_page_renders_html_in_test_mode() {
+        let mut config = Config::default();
+        config.pow.test_mode = true;
+        let resp = get_ok_page(config.clone(), OkPageQuery { bits: Some(12), elapsed_ms: Some(1500) }).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+        let html = String::from_utf8(body_bytes(resp).await.to_vec()).unwrap();
+        assert!(html.contains(r#"<div class="hsv">12</div>"#), "{html}");
+        assert!(html.contains(r#"<div class="hsv">1.50s</div>"#), "{html}");
+        assert!(!html.contains("{{"), "{html}");
+
+        let resp = get_ok_page(config, OkPageQuery { bits: None, elapsed_ms: None }).await;
+        let html = String::from_utf8(body_bytes(resp).await.to_vec()).unwrap();
+        assert_eq!(html.matches(r#"<div class="hsv">-</div>"#).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn ok_page_is_404_outside_test_mode_and_ok_stays_plain() {
+        let resp = get_ok_page(Config::default(), OkPageQuery { bits: Some(12), elapsed_ms: None }).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = health_ok().await.into_response();
+        assert_eq!(body_bytes(resp).await, "OK");
+    }
+
+    async fn challenge(config: Config) -> Response<axum::body::Body> {
+        let state = state(config).await;
+        build_challenge_response(&state, &HeaderMap::new(), &axum::http::Extensions::new(), "/", 1, "wasm").await
+    }
+
+    #[tokio::test]
+    async fn challenge_preloads_script_and_wasm() {
+        let resp = challenge(Config::default()).await;
+        assert_eq!(
+            resp.headers()[header::LINK],
+            "</__cowcatwaf/assets/catpaw.min.js>; rel=preload; as=script, \
+             </__cowcatwaf/assets/catpaw.wasm>; rel=preload; as=fetch; crossorigin"
+        );
+
+        let mut config = Config::default();
+        config.pow.asset_base_url = "https://cdn.example.com/cowcat/".to_string();
+        let link = challenge(config).await.headers()[header::LINK].to_str().unwrap().to_string();
+        assert!(link.starts_with("<https://cdn.example.com/cowcat/catpaw.min.js>; rel=preload"), "{link}");
+        assert!(link.contains("<https://cdn.example.com/cowcat/catpaw.wasm>; rel=preload; as=fetch"), "{link}");
+    }
+
+    #[tokio::test]
+    async fn no_preload_when_disabled_or_inlined() {
+        let mut config = Config::default();
+        config.pow.page.preload = false;
+        assert!(challenge(config).await.headers().get(header::LINK).is_none());
+
+        let mut config = Config::default();
+        config.pow.page.inline_all = true;
+        assert!(challenge(config).await.headers().get(header::LINK).is_none());
+    }
+
+    #[test]
+    fn pow_path_matches_on_segment_boundary() {
+        assert!(is_pow_path("/__cowcatwaf"));
+        assert!(is_pow_path("/__cowcatwaf/"));
+        assert!(is_pow_path("/__cowcatwaf/verify"));
+        assert!(!is_pow_path("/__cowcatwafXYZ"));
+        assert!(!is_pow_path("/__cowcatwaf.php"));
+        assert!(!is_pow_path("/__COWCATWAF/verify"));
+        assert!(!is_pow_path("/x/__cowcatwaf/verify"));
+        assert!(!is_pow_path("/"));
+    }
+
+    async fn post_task(accept: Option<&str>, body: Vec<u8>) -> Response<axum::body::Body> {
+        let mut builder = Request::builder().method(Method::POST).uri("/__cowcatwaf/task");
+        if let Some(accept) = accept {
+            builder = builder.header(header::ACCEPT, accept);
+        }
+        let req = builder.body(axum::body::Body::from(body)).unwrap();
+        pow_task(State(state(Config::default()).await), req).await.into_response()
+    }
+
+    async fn body_bytes(resp: Response<axum::body::Body>) -> bytes::Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn protocol_error_is_problem_json_when_accepted() {
+        // 合法的帧，但类型不对
+        let frame = protocol::frame::encode_frame(FRAME_TYPE_VERIFY_REQUEST, Vec::new());
+        let resp = post_task(Some("application/json, */*;q=0.1"), frame).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/problem+json");
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(resp).await).unwrap();
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["title"], "Bad Request");
+        assert_eq!(body["detail"], MSG_INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn protocol_error_is_binary_frame_otherwise() {
+        let frame = protocol::frame::encode_frame(FRAME_TYPE_VERIFY_REQUEST, Vec::new());
+        let resp = post_task(Some("*/*"), frame).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/octet-stream");
+        let body = body_bytes(resp).await;
+        let (frame_type, _) = decode_frame(&body).unwrap();
+        assert_eq!(frame_type, protocol::frame::FRAME_TYPE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn garbage_body_is_problem_json_regardless_of_accept() {
+        let resp = post_task(None, b"hello".to_vec()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/problem+json");
+    }
+
+    /// 桩任务存储：最多保存一个任务并记下每次 consume_if 的 task_id；unavailable 时模拟后端故障
+    #[derive(Default)]
+    struct MockTaskStore {
+        task: std::sync::Mutex<Option<Task>>,
+        consumed: std::sync::Mutex<Vec<String>>,
+        unavailable: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl TaskStoreBackend for MockTaskStore {
+        async fn insert(&self, task: Task) -> anyhow::Result<()> {
+            *self.task.lock().unwrap() = Some(task);
+            Ok(())
+        }
+
+        async fn consume_if(&self, task_id: &str, validate: TaskValidator<'_>) -> Result<Task, ConsumeError> {
+            self.consumed.lock().unwrap().push(task_id.to_string());
+            if self.unavailable {
+                return Err(ConsumeError::Unavailable);
+            }
+            let task = self
+                .task
+                .lock()
+                .unwrap()
+                .take_if(|task| &*task.task_id.0 == task_id)
+                .ok_or(ConsumeError::NotFound)?;
+            validate(&task)?;
+            Ok(task)
+        }
+
+        async fn len(&self) -> usize {
+            usize::from(self.task.lock().unwrap().is_some())
+        }
+
+        async fn clear(&self) {
+            self.task.lock().unwrap().take();
+        }
+    }
+
+    /// 难度 0 的任务：任意规范 nonce 都满足，UA 与 verify_request 发出的一致
+    fn mock_task(task_id: &str) -> Task {
+        Task {
+            task_id: TaskId::from(task_id),
+            seed: Seed("seed".to_string()),
+            bits: 0,
+            exp: OffsetDateTime::now_utc().unix_timestamp() + 300,
+            scope: Scope("example.com".to_string()),
+            ua_hash: UaHash(compute_ua_hash("test-agent")),
+            ip_hash: IpHash(String::new()),
+            issued_at_ms: 0,
+            chain_length: 1,
+            captcha_answer: None,
+            commitment_nonce: None,
+        }
+    }
+
+    async fn state_with_store(config: Config, store: Arc<MockTaskStore>) -> Arc<AppState> {
+        let mut state = AppState::new(config).await.unwrap();
+        state.task_store = store;
+        Arc::new(state)
+    }
+
+    /// 客户端发出的 verify 帧：按给定顺序写入 TLV，整帧用 XOR_KEY 混淆
+    fn verify_frame(fields: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for (tag, value) in fields {
+            payload.push(*tag);
+            payload.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            payload.extend_from_slice(value);
+        }
+        let mut frame = protocol::frame::encode_frame(FRAME_TYPE_VERIFY_REQUEST, payload);
+        deobfuscate_frame(&mut frame, XOR_KEY);
+        frame
+    }
+
+    async fn verify_request(state: Arc<AppState>, frame: Vec<u8>) -> Response<axum::body::Body> {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/__cowcatwaf/verify")
+            .header(header::USER_AGENT, "test-agent")
+            .body(axum::body::Body::from(frame))
+            .unwrap();
+        pow_verify(State(state), req).await.into_response()
+    }
+
+    fn solved(task_id: &str) -> Vec<u8> {
+        use protocol::frame::{TLV_NONCE, TLV_REDIRECT, TLV_TASK_ID};
+        verify_frame(&[(TLV_TASK_ID, task_id.as_bytes()), (TLV_NONCE, b"0"), (TLV_REDIRECT, b"/home")])
+    }
+
+    #[tokio::test]
+    async fn verify_consumes_from_the_injected_store() {
+        let store = Arc::new(MockTaskStore::default());
+        store.insert(mock_task("mock-task")).await.unwrap();
+        let state = state_with_store(Config::default(), store.clone()).await;
+
+        let resp = verify_request(state.clone(), solved("mock-task")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with(POW_COOKIE_NAME));
+        let (frame_type, _) = decode_frame(&body_bytes(resp).await).unwrap();
+        assert_eq!(frame_type, protocol::frame::FRAME_TYPE_VERIFY_RESPONSE);
+        assert_eq!(store.len().await, 0);
+
+        // 任务已被取走，同一解重放时存储报告 NotFound
+        let replay = verify_request(state, solved("mock-task")).await;
+        assert_eq!(replay.status(), StatusCode::BAD_REQUEST);
+        assert!(replay.headers().get(header::SET_COOKIE).is_none());
+        assert_eq!(*store.consumed.lock().unwrap(), ["mock-task", "mock-task"]);
+    }
+
+    #[tokio::test]
+    async fn captcha_task_cannot_be_redeemed_via_verify() {
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.captcha_fallback = Some(crate::config::CaptchaFallbackConfig::default());
+        let state = state_with_store(config, store.clone()).await;
+        store
+            .insert(Task { captcha_answer: Some("42".to_string()), ..mock_task("captcha-task") })
+            .await
+            .unwrap();
+
+        let resp = verify_request(state.clone(), solved("captcha-task")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().get(header::SET_COOKIE).is_none());
+        assert_eq!(store.len().await, 0);
+        // 验证码任务不算 PoW 失败，不留换题票据
+        let tickets = state.captcha_tickets.as_ref().unwrap();
+        assert!(!tickets.redeem("captcha-task", "").await);
+    }
+
+    #[tokio::test]
+    async fn failed_verify_leaves_a_captcha_ticket() {
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.captcha_fallback = Some(crate::config::CaptchaFallbackConfig::default());
+        let state = state_with_store(config, store.clone()).await;
+        store.insert(Task { bits: 64, ..mock_task("hard-task") }).await.unwrap();
+
+        let resp = verify_request(state.clone(), solved("hard-task")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let tickets = state.captcha_tickets.as_ref().unwrap();
+        assert!(tickets.redeem("hard-task", "").await);
+        // 不存在的 task_id 不留票据
+        let resp = verify_request(state.clone(), solved("made-up")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(!tickets.redeem("made-up", "").await);
+    }
+
+    #[test]
+    fn redirect_control_characters_are_stripped() {
+        assert_eq!(sanitize_redirect("/home\r\nSet-Cookie: a=b", 2048), "/homeSet-Cookie: a=b");
+        assert_eq!(sanitize_redirect("/a\tb\0c\u{7f}d\u{85}", 2048), "/abcd");
+        assert_eq!(sanitize_redirect("\r\n", 2048), "/");
+        assert_eq!(sanitize_redirect("", 2048), "/");
+        assert_eq!(sanitize_redirect("/路径?q=1", 2048), "/路径?q=1");
+    }
+
+    #[test]
+    fn oversized_redirect_falls_back_to_root() {
+        let at_limit = format!("/{}", "a".repeat(15));
+        assert_eq!(sanitize_redirect(&at_limit, 16), at_limit);
+        assert_eq!(sanitize_redirect(&format!("{at_limit}b"), 16), "/");
+        // 按清理后的字节数计：控制字符不占额度，多字节字符按 UTF-8 长度算
+        assert_eq!(sanitize_redirect(&format!("{at_limit}\r\n"), 16), at_limit);
+        assert_eq!(sanitize_redirect(&format!("/{}", "é".repeat(8)), 16), "/");
+        let long = format!("/{}", "a".repeat(100_000));
+        assert_eq!(sanitize_redirect(&long, 0), long);
+    }
+
+    #[test]
+    fn cross_origin_redirects_fall_back_to_root() {
+        for raw in [
+            "https://evil.example/",
+            "//evil.example",
+            "/\\evil.example",
+            "/\t/evil.example",
+            "evil.example",
+            "javascript:alert(1)",
+        ] {
+            assert_eq!(sanitize_redirect(raw, 2048), "/", "{raw:?}");
+        }
+        assert_eq!(sanitize_redirect("/a//b?next=//evil.example", 2048), "/a//b?next=//evil.example");
+    }
+
+    #[test]
+    fn redirect_is_escaped_for_the_json_script_block() {
+        let escaped = escape_json_in_script(r#"/x?q="</script><script>alert(1)</script>\"#);
+        assert_eq!(escaped, r"/x?q=\u0022\u003c/script\u003e\u003cscript\u003ealert(1)\u003c/script\u003e\u005c");
+        // 作为 JSON 字符串解析后还原为原值
+        let parsed: String = serde_json::from_str(&format!("\"{escaped}\"")).unwrap();
+        assert_eq!(parsed, r#"/x?q="</script><script>alert(1)</script>\"#);
+    }
+
+    async fn challenge_html(redirect: &str) -> String {
+        let query = ChallengeQuery { redirect: Some(redirect.to_string()) };
+        let req = Request::builder().uri("/__cowcatwaf/").body(axum::body::Body::empty()).unwrap();
+        let resp = challenge_page(State(state(Config::default()).await), Query(query), req).await.into_response();
+        String::from_utf8(body_bytes(resp).await.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn challenge_page_keeps_a_hostile_redirect_inside_the_json_string() {
+        let html = challenge_html(r#"/a"}</script><script>alert(1)</script>"#).await;
+        assert!(!html.contains("<script>alert(1)"), "{html}");
+        assert!(html.contains(r#""redirect": "/a\u0022}\u003c/script\u003e"#), "{html}");
+
+        let html = challenge_html("//evil.example/").await;
+        assert!(html.contains(r#""redirect": "/""#), "{html}");
+    }
+
+    fn response_redirect(frame: &[u8]) -> String {
+        let (_, payload) = decode_frame(frame).unwrap();
+        assert_eq!(payload[0], protocol::frame::TLV_REDIRECT);
+        let len = u16::from_be_bytes([payload[1], payload[2]]) as usize;
+        String::from_utf8(payload[3..3 + len].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn verify_sanitizes_the_returned_redirect() {
+        use protocol::frame::{TLV_NONCE, TLV_REDIRECT, TLV_TASK_ID};
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.max_redirect_len = 64;
+        let state = state_with_store(config, store.clone()).await;
+
+        for (raw, expected) in [
+            ("/home\r\nLocation: https://evil.example".to_string(), "/homeLocation: https://evil.example".to_string()),
+            ("https://evil.example/".to_string(), "/".to_string()),
+            ("//evil.example".to_string(), "/".to_string()),
+            (format!("/{}", "x".repeat(64)), "/".to_string()),
+        ] {
+            store.insert(mock_task("redirect-task")).await.unwrap();
+            let frame = verify_frame(&[(TLV_TASK_ID, b"redirect-task"), (TLV_NONCE, b"0"), (TLV_REDIRECT, raw.as_bytes())]);
+            let resp = verify_request(state.clone(), frame).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(response_redirect(&body_bytes(resp).await), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn unavailable_store_is_503() {
+        let store = Arc::new(MockTaskStore { unavailable: true, ..MockTaskStore::default() });
+        let state = state_with_store(Config::default(), store.clone()).await;
+        let resp = verify_request(state, solved("mock-task")).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(*store.consumed.lock().unwrap(), ["mock-task"]);
+    }
+
+    async fn verify_request_from(state: Arc<AppState>, frame: Vec<u8>, ip: &str) -> Response<axum::body::Body> {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri("/__cowcatwaf/verify")
+            .header(header::USER_AGENT, "test-agent")
+            .body(axum::body::Body::from(frame))
+            .unwrap();
+        req.extensions_mut()
+            .insert(axum::extract::ConnectInfo(format!("{ip}:40000").parse::<std::net::SocketAddr>().unwrap()));
+        pow_verify(State(state), req).await.into_response()
+    }
+
+    #[tokio::test]
+    async fn verify_flood_is_429_before_touching_the_store() {
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.verify_rate_limit = Some(crate::config::VerifyRateLimitConfig { per_ip: 2, global: 3, window_secs: 60 });
+        let state = state_with_store(config, store.clone()).await;
+
+        for _ in 0..2 {
+            let resp = verify_request_from(state.clone(), solved("probe"), "203.0.113.7").await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+        for _ in 0..3 {
+            let resp = verify_request_from(state.clone(), solved("probe"), "203.0.113.7").await;
+            assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert!(error_message(resp).await.contains(MSG_VERIFY_RATE_LIMITED));
+        }
+        // 单 IP 拒绝不占全局额度：还剩一次给其他客户端，之后全局限流
+        let resp = verify_request_from(state.clone(), solved("probe"), "198.51.100.1").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = verify_request_from(state.clone(), solved("probe"), "198.51.100.2").await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        assert_eq!(store.consumed.lock().unwrap().len(), 3);
+        assert_eq!(state.verify_limiter.as_ref().unwrap().rejected(), (3, 1));
+        assert!(state.metrics.render(true, false, None, None).contains("cowcat_verify_failed_total{reason=\"rate_limited\"} 4"));
+    }
+
+    /// 帧里带回的错误信息（error_frame 的 TLV_ERROR 为 UTF-8 文本）
+    async fn error_message(resp: Response<axum::body::Body>) -> String {
+        let body = body_bytes(resp).await;
+        let (frame_type, payload) = decode_frame(&body).unwrap();
+        assert_eq!(frame_type, protocol::frame::FRAME_TYPE_ERROR);
+        String::from_utf8_lossy(payload).to_string()
+    }
+
+    #[tokio::test]
+    async fn signed_tasks_reject_missing_or_tampered_signatures() {
+        use protocol::frame::{TLV_NONCE, TLV_REDIRECT, TLV_SIG, TLV_TASK_ID};
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.signed_tasks = true;
+        let state = state_with_store(config, store.clone()).await;
+        let key = state.task_sign_key.as_ref().unwrap();
+        let sig = sign_task(key, &mock_task("signed-task"));
+        let mut flipped = sig.clone();
+        flipped[7] ^= 0x80;
+        let other_task = sign_task(key, &mock_task("other-task"));
+
+        let base: [(u8, &[u8]); 3] = [(TLV_TASK_ID, b"signed-task"), (TLV_NONCE, b"0"), (TLV_REDIRECT, b"/")];
+        for bad in [None, Some(flipped.as_slice()), Some(&sig[..16]), Some(other_task.as_slice())] {
+            store.insert(mock_task("signed-task")).await.unwrap();
+            let mut fields = base.to_vec();
+            fields.extend(bad.map(|sig| (TLV_SIG, sig)));
+            let resp = verify_request(state.clone(), verify_frame(&fields)).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            assert!(resp.headers().get(header::SET_COOKIE).is_none());
+            assert!(error_message(resp).await.contains(MSG_INVALID_TASK_SIGNATURE));
+            // 签名不符同样取走任务，不能换个签名重试
+            assert_eq!(store.len().await, 0);
+        }
+
+        store.insert(mock_task("signed-task")).await.unwrap();
+        let mut fields = base.to_vec();
+        fields.push((TLV_SIG, &sig));
+        let resp = verify_request(state, verify_frame(&fields)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with(POW_COOKIE_NAME));
+    }
+
+    fn committed_task(page_load: &str) -> Task {
+        Task { commitment_nonce: Some(page_load.to_string()), ..mock_task("committed-task") }
+    }
+
+    #[tokio::test]
+    async fn bound_commitment_must_match_the_issuing_page_load() {
+        use protocol::frame::{TLV_COMMITMENT, TLV_NONCE, TLV_REDIRECT, TLV_TASK_ID};
+        let store = Arc::new(MockTaskStore::default());
+        let mut config = Config::default();
+        config.pow.bind_commitment = true;
+        let state = state_with_store(config, store.clone()).await;
+        let key = state.commitment_key.as_ref().unwrap();
+        let commitment = task_commitment(key, &committed_task("page-load-1")).unwrap();
+        // 同一 task_id、另一次页面加载的承诺
+        let other_load = task_commitment(key, &committed_task("page-load-2")).unwrap();
+        let forged = [0u8; 32];
+
+        let base: [(u8, &[u8]); 3] = [(TLV_TASK_ID, b"committed-task"), (TLV_NONCE, b"0"), (TLV_REDIRECT, b"/")];
+        for bad in [None, Some(&forged[..]), Some(other_load.as_slice()), Some(&commitment[..31])] {
+            store.insert(committed_task("page-load-1")).await.unwrap();
+            let mut fields = base.to_vec();
+            fields.extend(bad.map(|commitment| (TLV_COMMITMENT, commitment)));
+            let resp = verify_request(state.clone(), verify_frame(&fields)).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            assert!(error_message(resp).await.contains(MSG_INVALID_COMMITMENT));
+        }
+
+        // 任务没有随机数（开启前下发的）时，带什么承诺都不通过
+        store.insert(mock_task("committed-task")).await.unwrap();
+        let mut fields = base.to_vec();
+        fields.push((TLV_COMMITMENT, &commitment));
+        let resp = verify_request(state.clone(), verify_frame(&fields)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        store.insert(committed_task("page-load-1")).await.unwrap();
+        let resp = verify_request(state, verify_frame(&fields)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with(POW_COOKIE_NAME));
+    }
+
+    /// Set-Cookie 中通行 Cookie 携带的指纹
+    fn issued_fingerprint(resp: &Response<axum::body::Body>, state: &AppState) -> Option<String> {
+        let set_cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+        let value = cookie::Cookie::parse(set_cookie).unwrap().value().to_string();
+        crate::crypto::verify_cookie(&state.cookie_keys, &value).unwrap().fp
+    }
+
+    #[tokio::test]
+    async fn verify_stamps_the_connection_fingerprint_when_bound() {
+        for (bind, expected) in [(true, Some("fp-chrome")), (false, None)] {
+            let store = Arc::new(MockTaskStore::default());
+            store.insert(mock_task("mock-task")).await.unwrap();
+            let mut config = Config::default();
+            config.pow.bind_fingerprint = bind;
+            let state = state_with_store(config, store).await;
+
+            let mut req = Request::builder()
+                .method(Method::POST)
+                .uri("/__cowcatwaf/verify")
+                .header(header::USER_AGENT, "test-agent")
+                .body(axum::body::Body::from(solved("mock-task")))
+                .unwrap();
+            req.extensions_mut().insert(TlsFingerprint("fp-chrome".to_string()));
+            let resp = pow_verify(State(state.clone()), req).await.into_response();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(issued_fingerprint(&resp, &state).as_deref(), expected);
+        }
+    }
+
+    /// 单次渲染之前的实现：对整页依次 String::replace
+    fn replace_chain(template: &str, task_data: &str, redirect_url: &str, image1: &str, image2: &str) -> String {
+        template
+            .replace("{{ TaskData }}", task_data)
+            .replace("{{ RedirectURL }}", redirect_url)
+            .replace("{{ CowcatImage1 }}", image1)
+            .replace("{{ CowcatImage2 }}", image2)
+    }
+
+    /// 同一份内嵌模板按 (当前渲染, replace 链) 各渲染一次
+    async fn render_both_ways(pow: &crate::config::PowConfig, task_data: &str, redirect: &str) -> (String, String) {
+        let assets = crate::static_files::load_template_assets(pow).await.unwrap();
+        let template = crate::static_files::embedded_challenge_template(pow).unwrap();
+        (
+            render_template(&assets, task_data, redirect),
+            replace_chain(&template, task_data, redirect, &assets.cowcat_image1, &assets.cowcat_image2),
+        )
+    }
+
+    /// 与线上任务帧长度相当的 base64，字符集覆盖 + 与 /
+    fn sample_task_data() -> String {
+        let frame: Vec<u8> = (0..=255u8).cycle().step_by(7).take(240).collect();
+        base64::engine::general_purpose::STANDARD.encode(frame)
+    }
+
+    #[tokio::test]
+    async fn rendering_matches_the_replace_chain_on_the_real_template() {
+        let task_data = sample_task_data();
+        let redirects = [
+            "/",
+            "/search?q=%7B%7B",
+            "/a?x={{ Weird }}{{",
+            "/{{ TaskData",
+            "/{{ TaskData }}",
+            "/{{ RedirectURL }}{{ RedirectURL }}",
+            "/路径?q=}}{{ {{{{ }}",
+            "{{",
+        ];
+        for inline_all in [false, true] {
+            let mut pow = Config::default().pow;
+            pow.page.inline_all = inline_all;
+            for redirect in redirects {
+                let (rendered, expected) = render_both_ways(&pow, &task_data, redirect).await;
+                assert!(rendered == expected, "inline_all={inline_all} redirect={redirect:?}");
+                assert!(rendered.contains(&task_data));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn every_bundled_page_variant_renders_byte_identical_both_ways() {
+        use crate::config::PageTheme;
+        let task_data = sample_task_data();
+        for theme in [PageTheme::Auto, PageTheme::Light, PageTheme::Dark] {
+            for inline_all in [false, true] {
+                for asset_base_url in ["", "https://cdn.example.com/cowcat"] {
+                    let mut pow = Config::default().pow;
+                    pow.page.theme = theme;
+                    pow.page.inline_all = inline_all;
+                    pow.asset_base_url = asset_base_url.to_string();
+                    let (rendered, expected) = render_both_ways(&pow, &task_data, "/home?a=1&b=2").await;
+                    assert!(rendered.as_bytes() == expected.as_bytes(), "{theme:?} inline_all={inline_all} {asset_base_url}");
+                    assert!(!rendered.contains("{{ "), "placeholder left in {theme:?} inline_all={inline_all}");
+                }
+            }
+        }
+    }
+
+    /// 与 replace 链唯一有意的差别：填入的值里的图片占位符不再被展开
+    #[tokio::test]
+    async fn image_placeholders_inside_values_are_not_expanded() {
+        let pow = Config::default().pow;
+        let redirect = "/x?{{ CowcatImage1 }}&{{ CowcatImage2 }}";
+        let (rendered, chained) = render_both_ways(&pow, &sample_task_data(), redirect).await;
+        assert!(rendered.contains(redirect));
+        assert!(!chained.contains(redirect));
+        assert!(chained.len() > rendered.len() + 10_000);
+    }
+}
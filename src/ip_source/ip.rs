@@ -1,6 +1,9 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 use axum::extract::connect_info::ConnectInfo;
 use axum::http::{header, HeaderMap};
 use axum::http::Extensions;
+use ipnet::IpNet;
 
 use crate::protocol::http::HeaderMapExt;
 
@@ -21,7 +24,45 @@ impl IpSource {
     }
 }
 
-pub fn resolve_request_ip(headers: &HeaderMap, extensions: &Extensions) -> (String, IpSource) {
+/// pow.trusted_proxies：只有直连对端落在这些网段内时才采信 X-Real-IP / X-Forwarded-For；
+/// 为空时信任所有对端（与未配置时的行为一致）
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn from_config(values: &[String]) -> anyhow::Result<Self> {
+        let mut nets = Vec::new();
+        for raw in values {
+            let net: IpNet = raw
+                .trim()
+                .parse()
+                .map_err(|err| anyhow::anyhow!("invalid pow.trusted_proxies entry {raw}: {err}"))?;
+            nets.push(net);
+        }
+        Ok(Self { nets })
+    }
+
+    /// 直连对端是否可信；拿不到 ConnectInfo 时按不可信处理
+    pub fn trusts_peer(&self, extensions: &Extensions) -> bool {
+        if self.nets.is_empty() {
+            return true;
+        }
+        let Some(info) = extensions.get::<ConnectInfo<std::net::SocketAddr>>() else {
+            return false;
+        };
+        // 双栈监听时 IPv4 对端表现为 ::ffff:a.b.c.d
+        let peer = info.0.ip().to_canonical();
+        self.nets.iter().any(|net| net.contains(&peer))
+    }
+}
+
+pub fn resolve_request_ip(headers: &HeaderMap, extensions: &Extensions, trusted: &TrustedProxies) -> (String, IpSource) {
+    if !trusted.trusts_peer(extensions) {
+        let ip = remote_ip(extensions).unwrap_or_default();
+        return (ip, IpSource::ClientIp);
+    }
     if let Some(ip) = headers.get_ip(header::HeaderName::from_static("x-real-ip")) {
         return (ip, IpSource::XRealIp);
     }
@@ -32,7 +73,120 @@ pub fn resolve_request_ip(headers: &HeaderMap, extensions: &Extensions) -> (Stri
     (ip, IpSource::ClientIp)
 }
 
+/// 解析 X-Forwarded-For / X-Real-IP 里的单个地址：`1.2.3.4`、`1.2.3.4:80`、`::1`、`[::1]`、`[::1]:443`。
+/// 不带方括号的 IPv6 无法区分端口，只按完整地址解析；其余形式解析失败返回 None
+pub fn parse_forwarded_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    if let Some(rest) = value.strip_prefix('[') {
+        let (addr, tail) = rest.split_once(']')?;
+        if !tail.is_empty() && !tail.strip_prefix(':').is_some_and(is_port) {
+            return None;
+        }
+        return addr.parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    let (host, port) = value.rsplit_once(':')?;
+    if !is_port(port) {
+        return None;
+    }
+    host.parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+fn is_port(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) && value.parse::<u16>().is_ok()
+}
+
 fn remote_ip(extensions: &Extensions) -> Option<String> {
     let info = extensions.get::<ConnectInfo<std::net::SocketAddr>>()?;
     Some(info.0.ip().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(value: &str) -> Option<String> {
+        parse_forwarded_ip(value).map(|ip| ip.to_string())
+    }
+
+    #[test]
+    fn forwarded_ip_strips_brackets_and_ports() {
+        assert_eq!(parsed("1.2.3.4:80").as_deref(), Some("1.2.3.4"));
+        assert_eq!(parsed(" 1.2.3.4 ").as_deref(), Some("1.2.3.4"));
+        assert_eq!(parsed("[::1]:443").as_deref(), Some("::1"));
+        assert_eq!(parsed("[2001:db8::1]").as_deref(), Some("2001:db8::1"));
+        assert_eq!(parsed("::1").as_deref(), Some("::1"));
+        // 规范形式：前导零与大写被归一，CIDR 规则按同一形式比较
+        assert_eq!(parsed("2001:DB8:0::0001").as_deref(), Some("2001:db8::1"));
+    }
+
+    #[test]
+    fn malformed_forwarded_ip_is_none() {
+        for value in ["", "garbage", "garbage:99", "1.2.3.4:", "1.2.3.4:99999", "1.2.3.4:8a", "[::1]:99999", "[::1", "[1.2.3.4]", "[::1]x", "unknown"] {
+            assert_eq!(parsed(value), None, "{value:?}");
+        }
+    }
+
+    fn forwarded(value: &str) -> (String, String) {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo("10.0.0.9:5000".parse::<std::net::SocketAddr>().unwrap()));
+        let (ip, source) = resolve_request_ip(&headers, &extensions, &TrustedProxies::default());
+        (ip, source.get_string())
+    }
+
+    #[test]
+    fn forwarded_for_uses_the_first_hop_and_falls_back_on_garbage() {
+        assert_eq!(forwarded("[2001:db8::1]:443, 10.0.0.1"), ("2001:db8::1".to_string(), "x_forwarded_for".to_string()));
+        assert_eq!(forwarded("1.2.3.4:80,::1"), ("1.2.3.4".to_string(), "x_forwarded_for".to_string()));
+        assert_eq!(forwarded("garbage:99, 1.2.3.4"), ("10.0.0.9".to_string(), "client_ip".to_string()));
+    }
+
+    /// 直连对端 peer 发来、带伪造转发头的请求
+    fn spoofed_from(peer: Option<&str>) -> (HeaderMap, Extensions) {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+        headers.insert("x-real-ip", "203.0.113.8".parse().unwrap());
+        let mut extensions = Extensions::new();
+        if let Some(peer) = peer {
+            extensions.insert(ConnectInfo(peer.parse::<std::net::SocketAddr>().unwrap()));
+        }
+        (headers, extensions)
+    }
+
+    fn trusted(nets: &[&str]) -> TrustedProxies {
+        TrustedProxies::from_config(&nets.iter().map(|net| net.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn forwarded_headers_are_honoured_only_from_trusted_peers() {
+        let proxies = trusted(&["10.0.0.0/8", "2001:db8::/32"]);
+        for peer in ["10.1.2.3:4000", "[2001:db8::5]:4000", "[::ffff:10.1.2.3]:4000"] {
+            let (headers, extensions) = spoofed_from(Some(peer));
+            assert_eq!(resolve_request_ip(&headers, &extensions, &proxies).0, "203.0.113.8", "{peer}");
+        }
+
+        let (headers, extensions) = spoofed_from(Some("198.51.100.1:4000"));
+        let (ip, source) = resolve_request_ip(&headers, &extensions, &proxies);
+        assert_eq!((ip.as_str(), source.get_string().as_str()), ("198.51.100.1", "client_ip"));
+        // 拿不到对端地址时按不可信处理
+        let (headers, extensions) = spoofed_from(None);
+        assert_eq!(resolve_request_ip(&headers, &extensions, &proxies).0, "");
+    }
+
+    #[test]
+    fn every_peer_is_trusted_without_trusted_proxies() {
+        let (headers, extensions) = spoofed_from(Some("198.51.100.1:4000"));
+        assert_eq!(resolve_request_ip(&headers, &extensions, &TrustedProxies::default()).0, "203.0.113.8");
+    }
+
+    #[test]
+    fn invalid_trusted_proxy_is_a_config_error() {
+        let err = TrustedProxies::from_config(&["10.0.0.0/8".to_string(), "10.0.0.300/8".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("10.0.0.300/8"), "{err}");
+        assert!(TrustedProxies::from_config(&["10.0.0.1".to_string()]).is_err());
+    }
+}
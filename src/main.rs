@@ -1,30 +1,47 @@
+mod calibrate;
+mod captcha;
+mod challenge_webhook;
 mod config;
 mod crypto;
+mod drain;
 mod handlers;
 mod ip_source;
+mod metrics;
 mod middleware;
 mod protocol;
 mod proxy;
+mod proxy_protocol;
+mod reputation;
+mod risk;
 mod rules;
 mod rules_watcher;
+mod self_test;
+mod serve;
 mod state;
 mod static_files;
 mod storage;
+mod telemetry;
+mod tls;
+mod test_rule;
 
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::middleware::from_fn_with_state;
 use axum::routing::{get, post};
 use axum::Router;
-use clap::Parser;
-use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use clap::{Parser, Subcommand};
+use axum::http::{header, Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Version};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate};
 use tower_http::compression::CompressionLayer;
-use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
-use crate::config::Config;
-use crate::handlers::favicon::favicon_handler;
-use crate::handlers::pow::{challenge_page, health_ok, pow_task, pow_verify, serve_asset};
+use crate::config::{Config, CorsConfig};
+use crate::handlers::captcha::{captcha_page, captcha_verify};
+use crate::handlers::admin::{drain_disable, drain_enable, metrics, pow_disable, pow_enable, pow_status};
+use crate::handlers::favicon::{favicon_handler, warm_favicon};
+use crate::handlers::pow::{challenge_page, health_ok, healthz, ok_page, pow_fallback, pow_task, pow_verify, serve_asset, POW_TOKEN_HEADER};
 use crate::middleware::pow::pow_gate;
 use crate::proxy::forward::proxy_handler;
 use crate::state::AppState;
@@ -32,60 +49,442 @@ use crate::state::AppState;
 #[derive(Parser, Debug)]
 #[command(name = "cowcat-rs", version, about = "CowCat PoW shield (Rust)")]
 struct Args {
+    /// Config file; repeat to merge several files in order (later files override earlier ones)
     #[arg(long, default_value = "config.toml")]
-    config: String,
+    config: Vec<String>,
+
+    /// Load, merge, apply env overrides and validate the config, print it as TOML (secrets redacted), then exit
+    #[arg(long)]
+    dump_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Benchmark the in-process PoW grind and print expected solve time per difficulty
+    Calibrate {
+        /// Number of parallel workers to assume (matches pow.workers)
+        #[arg(long, default_value_t = 1)]
+        workers: u32,
+        /// Hash algorithm to benchmark (matches pow.hash_algo): sha256 or sha3-256
+        #[arg(long, default_value = "sha256")]
+        hash_algo: String,
+    },
+    /// Evaluate the configured rules against a synthetic request and print the decision
+    TestRule {
+        /// Request path, optionally with a query string
+        #[arg(long)]
+        path: String,
+        /// Request header as "Name: value"; repeat for several headers
+        #[arg(long = "header")]
+        headers: Vec<String>,
+        /// Client IP used for ip_cidr matching
+        #[arg(long)]
+        ip: Option<String>,
+        /// Evaluation time as a unix timestamp (seconds) for active_hours; defaults to now
+        #[arg(long)]
+        at: Option<i64>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    tracing_subscriber::fmt()
-        .json()
-        .with_env_filter(EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env_lossy()
-        )
-        .init();
+    match &args.command {
+        Some(Command::Calibrate { workers, hash_algo }) => return calibrate::run(*workers, hash_algo),
+        Some(Command::TestRule { path, headers, ip, at }) => {
+            let config = Config::load(&args.config)?;
+            return test_rule::run(&config, path, headers, ip.as_deref(), *at);
+        }
+        None => {}
+    }
+
+    if args.dump_config {
+        print!("{}", Config::load(&args.config)?.dump_toml()?);
+        return Ok(());
+    }
+
+    let mut telemetry = telemetry::init();
 
     let config = Config::load(&args.config)?;
     config.print_config();
+    telemetry.enable_otel(config.server.otel.as_ref())?;
+    if config.pow.self_test {
+        self_test::run(&config).await?;
+    }
+    // 证书问题在启动任何后台任务之前暴露
+    let tls = config.server.tls.as_ref().map(tls::load_acceptor).transpose()?;
     let state = Arc::new(AppState::new(config).await?);
 
     rules_watcher::start_rules_watcher(state.clone(), args.config.clone());
+    drain::start_signal_listener(state.clone());
+    static_files::start_remote_page_refresh(state.clone());
+    if state.config.favicon.warm_on_start {
+        tokio::spawn(warm_favicon(state.clone()));
+    }
+
+    let listen = state.config.server.listen.clone();
+    let app = public_app(state.clone())?;
+    // 配置了 admin_listen 时健康检查与 admin 接口只挂在内部监听上
+    let admin = state
+        .config
+        .server
+        .admin_listen
+        .clone()
+        .map(|admin_listen| (admin_listen, admin_app(state.clone())));
 
+    let addr: SocketAddr = listen
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid listen address: {err}"))?;
+
+    tracing::warn!(listen = %addr, tls = tls.is_some(), "cowcat-rs starting");
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let public = serve::serve(listener, app, &state.config.server, state.config.server.proxy_protocol, tls);
+    let served = match admin {
+        Some((admin_listen, admin_app)) => {
+            let admin_addr: SocketAddr = admin_listen
+                .parse()
+                .map_err(|err| anyhow::anyhow!("invalid admin listen address: {err}"))?;
+            tracing::warn!(admin_listen = %admin_addr, "admin listener starting");
+            let admin_listener = tokio::net::TcpListener::bind(admin_addr).await?;
+            let admin = serve::serve(admin_listener, admin_app, &state.config.server, false, None);
+            tokio::try_join!(public, admin).map(|_| ())
+        }
+        None => public.await,
+    };
+    telemetry.shutdown();
+    served?;
+
+    Ok(())
+}
+
+/// 公网监听的路由：挑战接口在 /__cowcatwaf 下，其余请求经 pow_gate 转发上游
+fn public_app(state: Arc<AppState>) -> anyhow::Result<Router> {
+    let no_compress_types: Arc<[String]> = state.config.server.no_compress_types.clone().into();
     let pow_routes = Router::new()
         .route("/", get(challenge_page))
-        .route("/ok", get(health_ok))
+        .route("/ok-page", get(ok_page))
         .route("/assets/{*path}", get(serve_asset))
         .route("/task", post(pow_task))
+        .route("/fallback", post(pow_fallback))
         .route("/verify", post(pow_verify))
-        .layer(
-            CompressionLayer::new()
-                .br(true)
-                .gzip(true)
-                .compress_when(
-                    DefaultPredicate::new()
-                        .and(NotForContentType::const_new("application/octet-stream")),
-                  //      .and(NotForContentType::const_new("application/wasm")),
-                ),
-        );
+        .route("/captcha", get(captcha_page).post(captcha_verify));
+    // CORS 只加在挑战接口上，不覆盖下面合并进来的 admin 接口
+    let pow_routes = match &state.config.pow.cors {
+        Some(cors) => pow_routes.layer(cors_layer(cors)?),
+        None => pow_routes,
+    };
+    let pow_routes = match &state.config.server.admin_listen {
+        Some(_) => pow_routes,
+        None => pow_routes.merge(admin_routes()),
+    };
+    let pow_routes = pow_routes.layer(compression_layer(no_compress_types));
 
-    let listen = state.config.server.listen.clone();
-    let app = Router::new()
+    Ok(Router::new()
         .route("/favicon.ico", get(favicon_handler))
         .nest("/__cowcatwaf", pow_routes)
         .fallback(proxy_handler)
         .layer(from_fn_with_state(state.clone(), pow_gate))
-        .with_state(state);
+        .with_state(state))
+}
 
-    let addr: SocketAddr = listen
-        .parse()
-        .map_err(|err| anyhow::anyhow!("invalid listen address: {err}"))?;
+/// server.admin_listen 上的路由：只有健康检查与 admin 接口，不经 pow_gate
+fn admin_app(state: Arc<AppState>) -> Router {
+    Router::new()
+        .nest("/__cowcatwaf", admin_routes())
+        .with_state(state)
+}
 
-    tracing::warn!(listen = %addr, "cowcat-rs starting");
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+/// pow.cors：跨域 SPA 调用 /task、/verify；允许凭据时浏览器才会接受 verify 下发的 Cookie
+fn cors_layer(cfg: &CorsConfig) -> anyhow::Result<CorsLayer> {
+    let origin = if cfg.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = cfg
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim())
+                    .map_err(|err| anyhow::anyhow!("invalid pow.cors origin {origin}: {err}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    Ok(CorsLayer::new()
+        .allow_origin(origin)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::CONTENT_TYPE, header::ACCEPT])
+        .expose_headers([HeaderName::from_static(POW_TOKEN_HEADER)])
+        .allow_credentials(cfg.allow_credentials)
+        .max_age(Duration::from_secs(600)))
+}
 
-    Ok(())
+/// 健康检查与 admin 接口；设置了 admin_listen 时只挂在内部监听上，否则并入公网的 /__cowcatwaf
+fn admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/ok", get(health_ok))
+        .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics))
+        .route("/admin/pow", get(pow_status))
+        .route("/admin/pow/enable", post(pow_enable))
+        .route("/admin/pow/disable", post(pow_disable))
+        .route("/admin/drain/enable", post(drain_enable))
+        .route("/admin/drain/disable", post(drain_disable))
+}
+
+fn compression_layer(no_compress_types: Arc<[String]>) -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new()
+        .br(true)
+        .gzip(true)
+        .compress_when(DefaultPredicate::new().and(
+            move |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+                !is_no_compress_type(&no_compress_types, headers)
+            },
+        ))
+}
+
+/// wasm、octet-stream 等二进制用 gzip/br 收益很小，攻击流量下白白消耗 CPU
+fn is_no_compress_type(types: &[String], headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let content_type = content_type.trim().to_ascii_lowercase();
+    types
+        .iter()
+        .any(|excluded| content_type.starts_with(&excluded.to_ascii_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn asset_encoding(path: &str) -> Option<HeaderValue> {
+        let state = Arc::new(AppState::new(Config::default()).await.unwrap());
+        let app = Router::new()
+            .route("/assets/{*path}", get(serve_asset))
+            .layer(compression_layer(state.config.server.no_compress_types.clone().into()))
+            .with_state(state);
+        let req = Request::builder()
+            .uri(path)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        resp.headers().get(header::CONTENT_ENCODING).cloned()
+    }
+
+    #[tokio::test]
+    async fn wasm_is_not_gzipped() {
+        assert_eq!(asset_encoding("/assets/catpaw.wasm").await, None);
+    }
+
+    #[tokio::test]
+    async fn script_is_still_gzipped() {
+        assert_eq!(asset_encoding("/assets/catpaw.min.js").await.unwrap(), "gzip");
+    }
+
+    /// proxy.target 指向一个已关闭的端口，确保测试不会碰到真实上游
+    fn closed_upstream_config() -> Config {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let mut config = Config::default();
+        config.proxy.target = format!("http://{addr}");
+        config
+    }
+
+    async fn get_status(app: Router, path: &str) -> StatusCode {
+        let req = Request::builder().uri(path).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn health_routes_are_public_without_admin_listen() {
+        let state = Arc::new(AppState::new(closed_upstream_config()).await.unwrap());
+        assert_eq!(get_status(public_app(state).unwrap(), "/__cowcatwaf/ok").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn admin_listen_moves_health_and_admin_routes_off_the_public_app() {
+        let mut config = closed_upstream_config();
+        config.server.admin_listen = Some("127.0.0.1:0".to_string());
+        let state = Arc::new(AppState::new(config).await.unwrap());
+
+        for path in ["/__cowcatwaf/ok", "/__cowcatwaf/healthz", "/__cowcatwaf/metrics"] {
+            assert_eq!(get_status(public_app(state.clone()).unwrap(), path).await, StatusCode::NOT_FOUND, "{path}");
+        }
+        assert_eq!(get_status(admin_app(state.clone()), "/__cowcatwaf/ok").await, StatusCode::OK);
+        assert_eq!(get_status(admin_app(state), "/__cowcatwaf/challenge").await, StatusCode::NOT_FOUND);
+    }
+
+    async fn healthz_json(app: Router) -> (StatusCode, serde_json::Value) {
+        let req = Request::builder().uri("/__cowcatwaf/healthz").body(Body::empty()).unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+        let status = resp.status();
+        let bytes = http_body_util::BodyExt::collect(resp.into_body()).await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn healthz_is_503_when_the_upstream_is_down() {
+        let state = Arc::new(AppState::new(closed_upstream_config()).await.unwrap());
+        let (status, body) = healthz_json(public_app(state).unwrap()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "unavailable");
+        assert!(body["error"].as_str().is_some_and(|err| !err.is_empty()), "{body}");
+    }
+
+    #[tokio::test]
+    async fn healthz_reports_the_upstream_status_and_caches_the_probe() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        // 上游有响应即健康，状态码只做展示
+        let upstream = Router::new().fallback(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            async { StatusCode::IM_A_TEAPOT }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let mut config = Config::default();
+        config.proxy.target = format!("http://{addr}");
+        config.server.admin_listen = Some("127.0.0.1:0".to_string());
+        let state = Arc::new(AppState::new(config).await.unwrap());
+        for _ in 0..2 {
+            let (status, body) = healthz_json(admin_app(state.clone())).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, serde_json::json!({ "status": "ok", "upstream_status": 418 }));
+        }
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+    }
+
+    fn cors_config(origins: &[&str]) -> Config {
+        let mut config = closed_upstream_config();
+        config.pow.cors = Some(CorsConfig {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            allow_credentials: true,
+        });
+        config
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/__cowcatwaf/verify")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_allows_listed_origins_with_credentials() {
+        let state = Arc::new(AppState::new(cors_config(&["https://app.example.com"])).await.unwrap());
+        let resp = public_app(state.clone()).unwrap().oneshot(preflight("https://app.example.com")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let resp = public_app(state).unwrap().oneshot(preflight("https://evil.example")).await.unwrap();
+        assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn credentialed_request_gets_cors_headers() {
+        let state = Arc::new(AppState::new(cors_config(&["https://app.example.com"])).await.unwrap());
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/__cowcatwaf/task")
+            .header(header::ORIGIN, "https://app.example.com")
+            .header(header::COOKIE, "session=abc")
+            .body(Body::from("not a frame"))
+            .unwrap();
+        let resp = public_app(state).unwrap().oneshot(req).await.unwrap();
+        // 即使请求本身被拒绝，浏览器也要靠这些头才能把错误交给页面
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let headers = resp.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://app.example.com");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_EXPOSE_HEADERS], POW_TOKEN_HEADER);
+    }
+
+    #[tokio::test]
+    async fn no_cors_headers_by_default() {
+        let state = Arc::new(AppState::new(closed_upstream_config()).await.unwrap());
+        let resp = public_app(state).unwrap().oneshot(preflight("https://app.example.com")).await.unwrap();
+        assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    async fn metrics_text(app: Router) -> String {
+        let req = Request::builder().uri("/__cowcatwaf/metrics").body(Body::empty()).unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain; version=0.0.4; charset=utf-8");
+        let bytes = http_body_util::BodyExt::collect(resp.into_body()).await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn metrics_endpoint_lists_every_counter() {
+        let state = Arc::new(AppState::new(closed_upstream_config()).await.unwrap());
+        let text = metrics_text(public_app(state).unwrap()).await;
+        for name in [
+            "cowcat_challenges_issued_total",
+            "cowcat_verify_succeeded_total",
+            "cowcat_verify_failed_total",
+            "cowcat_proxy_forwarded_total",
+            "cowcat_proxy_errors_total",
+        ] {
+            assert!(text.contains(&format!("# TYPE {name} counter\n")), "{name} missing:\n{text}");
+        }
+        for sample in [
+            "cowcat_challenges_issued_total{source=\"page\"} 0",
+            "cowcat_verify_succeeded_total 0",
+            "cowcat_verify_failed_total{reason=\"ua_mismatch\"} 0",
+            "cowcat_verify_failed_total{reason=\"ip_mismatch\"} 0",
+            "cowcat_verify_failed_total{reason=\"invalid_pow\"} 0",
+            "cowcat_verify_failed_total{reason=\"expired\"} 0",
+            "cowcat_verify_failed_total{reason=\"not_found\"} 0",
+            "cowcat_proxy_errors_total{reason=\"connection_refused\"} 0",
+            "cowcat_challenge_webhook_events_total{result=\"dropped\"} 0",
+            "cowcat_challenge_webhook_events_total{result=\"failed\"} 0",
+            "cowcat_banned_ips 0",
+            "cowcat_pow_enabled 1",
+            "cowcat_draining 0",
+        ] {
+            assert!(text.lines().any(|line| line == sample), "{sample} missing:\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_count_verify_failures_and_proxy_errors() {
+        let mut config = closed_upstream_config();
+        config.pow.difficulty = 0;
+        let state = Arc::new(AppState::new(config).await.unwrap());
+        let app = public_app(state).unwrap();
+
+        let verify = Request::builder().method(Method::POST).uri("/__cowcatwaf/verify").body(Body::from("garbage")).unwrap();
+        assert_eq!(app.clone().oneshot(verify).await.unwrap().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get_status(app.clone(), "/page").await, StatusCode::BAD_GATEWAY);
+
+        let text = metrics_text(app).await;
+        assert!(text.lines().any(|line| line == "cowcat_verify_failed_total{reason=\"invalid_request\"} 1"), "{text}");
+        assert!(text.lines().any(|line| line == "cowcat_proxy_errors_total{reason=\"connection_refused\"} 1"), "{text}");
+        assert!(text.lines().any(|line| line == "cowcat_proxy_forwarded_total 0"), "{text}");
+    }
 }
@@ -0,0 +1,333 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::challenge_webhook::ChallengeWebhook;
+use crate::handlers::message::*;
+use crate::proxy::error::ProxyError;
+use crate::storage::FailureBans;
+
+/// 挑战任务的下发来源
+#[derive(Debug, Clone, Copy)]
+pub enum ChallengeSource {
+    /// 内嵌在挑战页中的任务
+    Page,
+    /// POST /task
+    Task,
+    /// POST /fallback（pow.wasm_fallback）
+    Fallback,
+    /// GET /captcha（pow.captcha_fallback）
+    Captcha,
+}
+
+impl ChallengeSource {
+    const ALL: [ChallengeSource; 4] = [
+        ChallengeSource::Page,
+        ChallengeSource::Task,
+        ChallengeSource::Fallback,
+        ChallengeSource::Captcha,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ChallengeSource::Page => "page",
+            ChallengeSource::Task => "task",
+            ChallengeSource::Fallback => "fallback",
+            ChallengeSource::Captcha => "captcha",
+        }
+    }
+}
+
+/// /verify 失败的原因
+#[derive(Debug, Clone, Copy)]
+pub enum VerifyFailure {
+    UaMismatch,
+    IpMismatch,
+    InvalidPow,
+    TooFast,
+    Expired,
+    NotFound,
+    InvalidRequest,
+    RateLimited,
+    StoreUnavailable,
+    CaptchaMismatch,
+    BadSignature,
+    BadCommitment,
+}
+
+impl VerifyFailure {
+    const ALL: [VerifyFailure; 12] = [
+        VerifyFailure::UaMismatch,
+        VerifyFailure::IpMismatch,
+        VerifyFailure::InvalidPow,
+        VerifyFailure::TooFast,
+        VerifyFailure::Expired,
+        VerifyFailure::NotFound,
+        VerifyFailure::InvalidRequest,
+        VerifyFailure::RateLimited,
+        VerifyFailure::StoreUnavailable,
+        VerifyFailure::CaptchaMismatch,
+        VerifyFailure::BadSignature,
+        VerifyFailure::BadCommitment,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            VerifyFailure::UaMismatch => "ua_mismatch",
+            VerifyFailure::IpMismatch => "ip_mismatch",
+            VerifyFailure::InvalidPow => "invalid_pow",
+            VerifyFailure::TooFast => "too_fast",
+            VerifyFailure::Expired => "expired",
+            VerifyFailure::NotFound => "not_found",
+            VerifyFailure::InvalidRequest => "invalid_request",
+            VerifyFailure::RateLimited => "rate_limited",
+            VerifyFailure::StoreUnavailable => "store_unavailable",
+            VerifyFailure::CaptchaMismatch => "captcha_mismatch",
+            VerifyFailure::BadSignature => "bad_signature",
+            VerifyFailure::BadCommitment => "bad_commitment",
+        }
+    }
+
+    /// ConsumeError::ValidationFailed 携带的消息 → 原因；未知消息归为 invalid_request
+    pub fn from_message(msg: &str) -> Self {
+        match msg {
+            MSG_USER_AGENT_MISMATCH => VerifyFailure::UaMismatch,
+            MSG_IP_ADDRESS_MISMATCH => VerifyFailure::IpMismatch,
+            MSG_INVALID_PROOF_OF_WORK => VerifyFailure::InvalidPow,
+            MSG_SOLVED_TOO_FAST => VerifyFailure::TooFast,
+            MSG_CAPTCHA_MISMATCH => VerifyFailure::CaptchaMismatch,
+            MSG_INVALID_TASK_SIGNATURE => VerifyFailure::BadSignature,
+            MSG_INVALID_COMMITMENT => VerifyFailure::BadCommitment,
+            _ => VerifyFailure::InvalidRequest,
+        }
+    }
+}
+
+/// 挑战页响应的压缩编码；按编码分开统计，便于比较不同编码的压缩率
+#[derive(Debug, Clone, Copy)]
+pub enum CompressionEncoding {
+    Gzip,
+}
+
+impl CompressionEncoding {
+    const ALL: [CompressionEncoding; 1] = [CompressionEncoding::Gzip];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CompressionEncoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// rules.reputation 的一次查询结果
+#[derive(Debug, Clone, Copy)]
+pub enum ReputationLookup {
+    /// 命中缓存（含缓存的失败结果）
+    Hit,
+    /// 未命中缓存，向查询服务发起了请求
+    Miss,
+    /// 查询失败或超时，按无分数放行
+    Error,
+}
+
+impl ReputationLookup {
+    const ALL: [ReputationLookup; 3] = [ReputationLookup::Hit, ReputationLookup::Miss, ReputationLookup::Error];
+
+    fn label(self) -> &'static str {
+        match self {
+            ReputationLookup::Hit => "hit",
+            ReputationLookup::Miss => "miss",
+            ReputationLookup::Error => "error",
+        }
+    }
+}
+
+/// 压缩率（压缩后 / 原始）直方图的桶上界
+const RATIO_BUCKETS: [f64; 8] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.8, 1.0];
+/// 压缩率之和以百万分之一为单位累加，避免浮点原子操作
+const RATIO_SCALE: f64 = 1_000_000.0;
+
+#[derive(Default)]
+struct CompressionStats {
+    original_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+    /// 各桶独立计数，输出时再累加为 Prometheus 的累计桶；最后一格为超过 1.0（压缩后反而变大）
+    ratio_buckets: [AtomicU64; RATIO_BUCKETS.len() + 1],
+    ratio_sum_micros: AtomicU64,
+}
+
+/// 进程内计数器，由 /__cowcatwaf/metrics 以 Prometheus 文本格式输出；重启归零
+#[derive(Default)]
+pub struct Metrics {
+    challenges_issued: [AtomicU64; ChallengeSource::ALL.len()],
+    verify_succeeded: AtomicU64,
+    verify_failed: [AtomicU64; VerifyFailure::ALL.len()],
+    proxy_forwarded: AtomicU64,
+    proxy_errors: [AtomicU64; ProxyError::ALL.len()],
+    challenge_compression: [CompressionStats; CompressionEncoding::ALL.len()],
+    reputation_lookups: [AtomicU64; ReputationLookup::ALL.len()],
+    reputation_blocked: AtomicU64,
+    reputation_challenged: AtomicU64,
+    micro_cache_hits: AtomicU64,
+    micro_cache_misses: AtomicU64,
+    drain_rejected: AtomicU64,
+}
+
+impl Metrics {
+    pub fn challenge_issued(&self, source: ChallengeSource) {
+        self.challenges_issued[source as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn verify_succeeded(&self) {
+        self.verify_succeeded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn verify_failed(&self, reason: VerifyFailure) {
+        self.verify_failed[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 上游返回了响应（任意状态码）
+    pub fn proxy_forwarded(&self) {
+        self.proxy_forwarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn proxy_error(&self, err: ProxyError) {
+        self.proxy_errors[err as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 挑战页压缩一次：原始与压缩后的字节数
+    pub fn challenge_compressed(&self, encoding: CompressionEncoding, original: usize, compressed: usize) {
+        let stats = &self.challenge_compression[encoding as usize];
+        stats.original_bytes.fetch_add(original as u64, Ordering::Relaxed);
+        stats.compressed_bytes.fetch_add(compressed as u64, Ordering::Relaxed);
+        let ratio = compressed as f64 / original.max(1) as f64;
+        let bucket = RATIO_BUCKETS
+            .iter()
+            .position(|bound| ratio <= *bound)
+            .unwrap_or(RATIO_BUCKETS.len());
+        stats.ratio_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        stats.ratio_sum_micros.fetch_add((ratio * RATIO_SCALE) as u64, Ordering::Relaxed);
+    }
+
+    pub fn reputation_lookup(&self, result: ReputationLookup) {
+        self.reputation_lookups[result as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 信誉分达到阈值的请求：blocked 为直接拒绝，否则为加难度挑战
+    pub fn reputation_flagged(&self, blocked: bool) {
+        let counter = if blocked { &self.reputation_blocked } else { &self.reputation_challenged };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// proxy.micro_cache 查询：hit 含搭上别人回源的请求，miss 为实际发往上游的请求
+    pub fn micro_cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.micro_cache_hits } else { &self.micro_cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// drain 期间因需要挑战而被 503 拒绝的请求
+    pub fn drain_rejected(&self) {
+        self.drain_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// pow_enabled 与 draining 为当前的 PoW 开关与 drain 状态，作为 gauge 输出；webhook 与 bans 为其他组件持有的计数，
+    /// 在输出时读取，未配置时输出 0
+    pub fn render(&self, pow_enabled: bool, draining: bool, webhook: Option<&ChallengeWebhook>, bans: Option<&FailureBans>) -> String {
+        let mut out = String::new();
+        counter_header(&mut out, "cowcat_challenges_issued_total", "Challenge tasks issued, by source.");
+        for source in ChallengeSource::ALL {
+            sample(&mut out, "cowcat_challenges_issued_total", Some(("source", source.label())), &self.challenges_issued[source as usize]);
+        }
+        counter_header(&mut out, "cowcat_verify_succeeded_total", "Successful PoW verifications.");
+        sample(&mut out, "cowcat_verify_succeeded_total", None, &self.verify_succeeded);
+        counter_header(&mut out, "cowcat_verify_failed_total", "Failed PoW verifications, by reason.");
+        for reason in VerifyFailure::ALL {
+            sample(&mut out, "cowcat_verify_failed_total", Some(("reason", reason.label())), &self.verify_failed[reason as usize]);
+        }
+        counter_header(&mut out, "cowcat_proxy_forwarded_total", "Requests answered by the upstream.");
+        sample(&mut out, "cowcat_proxy_forwarded_total", None, &self.proxy_forwarded);
+        counter_header(&mut out, "cowcat_proxy_errors_total", "Upstream requests that failed, by reason.");
+        for err in ProxyError::ALL {
+            sample(&mut out, "cowcat_proxy_errors_total", Some(("reason", err.reason())), &self.proxy_errors[err as usize]);
+        }
+        self.render_compression(&mut out);
+        counter_header(&mut out, "cowcat_reputation_lookups_total", "IP reputation lookups, by cache result.");
+        for result in ReputationLookup::ALL {
+            sample(&mut out, "cowcat_reputation_lookups_total", Some(("result", result.label())), &self.reputation_lookups[result as usize]);
+        }
+        counter_header(&mut out, "cowcat_reputation_flagged_total", "Requests from IPs at or above the reputation threshold, by action.");
+        sample(&mut out, "cowcat_reputation_flagged_total", Some(("action", "block")), &self.reputation_blocked);
+        sample(&mut out, "cowcat_reputation_flagged_total", Some(("action", "challenge")), &self.reputation_challenged);
+        counter_header(&mut out, "cowcat_micro_cache_lookups_total", "Cacheable proxy requests, by micro cache result.");
+        sample(&mut out, "cowcat_micro_cache_lookups_total", Some(("result", "hit")), &self.micro_cache_hits);
+        sample(&mut out, "cowcat_micro_cache_lookups_total", Some(("result", "miss")), &self.micro_cache_misses);
+        counter_header(&mut out, "cowcat_challenge_webhook_events_total", "Challenge webhook events that were not delivered, by result.");
+        let (dropped, failed) = webhook.map_or((0, 0), |webhook| (webhook.dropped(), webhook.failed()));
+        let _ = writeln!(out, "cowcat_challenge_webhook_events_total{{result=\"dropped\"}} {dropped}");
+        let _ = writeln!(out, "cowcat_challenge_webhook_events_total{{result=\"failed\"}} {failed}");
+        let _ = writeln!(out, "# HELP cowcat_banned_ips IPs currently banned by failure_ban or honeypot paths.");
+        let _ = writeln!(out, "# TYPE cowcat_banned_ips gauge");
+        let _ = writeln!(out, "cowcat_banned_ips {}", bans.map_or(0, FailureBans::banned_count));
+        let _ = writeln!(out, "# HELP cowcat_pow_enabled Whether the PoW gate is enabled (1) or switched off via the admin endpoint (0).");
+        let _ = writeln!(out, "# TYPE cowcat_pow_enabled gauge");
+        let _ = writeln!(out, "cowcat_pow_enabled {}", u8::from(pow_enabled));
+        let _ = writeln!(out, "# HELP cowcat_draining Whether the instance is draining (1) and issues no new challenges.");
+        let _ = writeln!(out, "# TYPE cowcat_draining gauge");
+        let _ = writeln!(out, "cowcat_draining {}", u8::from(draining));
+        counter_header(&mut out, "cowcat_drain_rejected_total", "Requests answered with 503 instead of a challenge while draining.");
+        sample(&mut out, "cowcat_drain_rejected_total", None, &self.drain_rejected);
+        out
+    }
+
+    fn render_compression(&self, out: &mut String) {
+        counter_header(out, "cowcat_challenge_bytes_original_total", "Challenge page bytes before compression, by encoding.");
+        for encoding in CompressionEncoding::ALL {
+            let stats = &self.challenge_compression[encoding as usize];
+            sample(out, "cowcat_challenge_bytes_original_total", Some(("encoding", encoding.label())), &stats.original_bytes);
+        }
+        counter_header(out, "cowcat_challenge_bytes_compressed_total", "Challenge page bytes after compression, by encoding.");
+        for encoding in CompressionEncoding::ALL {
+            let stats = &self.challenge_compression[encoding as usize];
+            sample(out, "cowcat_challenge_bytes_compressed_total", Some(("encoding", encoding.label())), &stats.compressed_bytes);
+        }
+        counter_header(out, "cowcat_challenge_bytes_saved_total", "Challenge page bytes saved by compression, by encoding.");
+        for encoding in CompressionEncoding::ALL {
+            let stats = &self.challenge_compression[encoding as usize];
+            let saved = stats
+                .original_bytes
+                .load(Ordering::Relaxed)
+                .saturating_sub(stats.compressed_bytes.load(Ordering::Relaxed));
+            let _ = writeln!(out, "cowcat_challenge_bytes_saved_total{{encoding=\"{}\"}} {saved}", encoding.label());
+        }
+
+        let name = "cowcat_challenge_compression_ratio";
+        let _ = writeln!(out, "# HELP {name} Compressed / original size of challenge pages, by encoding.");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for encoding in CompressionEncoding::ALL {
+            let stats = &self.challenge_compression[encoding as usize];
+            let label = encoding.label();
+            let mut cumulative = 0;
+            for (index, bound) in RATIO_BUCKETS.iter().enumerate() {
+                cumulative += stats.ratio_buckets[index].load(Ordering::Relaxed);
+                let _ = writeln!(out, "{name}_bucket{{encoding=\"{label}\",le=\"{bound}\"}} {cumulative}");
+            }
+            cumulative += stats.ratio_buckets[RATIO_BUCKETS.len()].load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{encoding=\"{label}\",le=\"+Inf\"}} {cumulative}");
+            let sum = stats.ratio_sum_micros.load(Ordering::Relaxed) as f64 / RATIO_SCALE;
+            let _ = writeln!(out, "{name}_sum{{encoding=\"{label}\"}} {sum}");
+            let _ = writeln!(out, "{name}_count{{encoding=\"{label}\"}} {cumulative}");
+        }
+    }
+}
+
+fn counter_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+}
+
+fn sample(out: &mut String, name: &str, label: Option<(&str, &str)>, value: &AtomicU64) {
+    let value = value.load(Ordering::Relaxed);
+    let _ = match label {
+        Some((key, label)) => writeln!(out, "{name}{{{key}=\"{label}\"}} {value}"),
+        None => writeln!(out, "{name} {value}"),
+    };
+}
@@ -1,54 +1,372 @@
 use std::net::IpAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::body::Body;
 use axum::extract::{Request, State};
-use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use http_body_util::BodyExt;
 use std::io::Write;
+use tracing::Instrument;
 
-use crate::config::IpPolicy;
+use crate::config::{IpPolicy, ServiceWorkerMode};
 use crate::crypto::{compute_ip_hash, compute_ua_hash};
-use crate::handlers::pow::{build_challenge_response, POW_COOKIE_NAME, POW_PREFIX};
+use crate::handlers::message::{MSG_CHALLENGE_RATE_LIMITED, MSG_DRAINING};
+use crate::handlers::pow::{build_challenge_response, cookie_fingerprint, is_pow_path, sanitize_redirect, GRACE_COOKIE_NAME, POW_COOKIE_NAME, POW_TOKEN_HEADER};
 use crate::ip_source::ip::resolve_request_ip;
+use crate::metrics::CompressionEncoding;
+use crate::middleware::timing::{RequestTiming, TimingStage};
 use crate::protocol::http::HeaderMapExt;
+use crate::reputation::ReputationVerdict;
 use crate::rules::{RuleAction, RuleDecision};
 use crate::state::AppState;
 
 #[derive(Clone, Copy, Debug)]
 pub struct PowVerified;
 
+/// 请求 ID：沿用上游传入的 X-Request-Id，否则随机生成；同时作为 span 字段与响应头
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 为每个请求创建 span 并在整个中间件/处理器期间保持进入状态，
+/// 下游日志（pow_verify、proxy_handler 等）都会带上同一组上下文字段
 pub async fn pow_gate(
     State(state): State<Arc<AppState>>,
     mut req: Request,
     next: Next,
 ) -> Response {
+    if let Some(resp) = check_header_limits(&state, req.headers()) {
+        return resp;
+    }
+    let request_id = request_id_for(req.headers());
+    let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        client_ip = %client_ip,
+        status = tracing::field::Empty,
+    );
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    let timing = debug_token_matches(&state, req.headers()).then(RequestTiming::new);
+    if let Some(timing) = &timing {
+        req.extensions_mut().insert(timing.clone());
+    }
+
+    let mut resp = pow_gate_inner(state, req, next).instrument(span.clone()).await;
+    span.record("status", resp.status().as_u16());
+    if let Some(timing) = &timing {
+        timing.append_to(resp.headers_mut());
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    resp
+}
+
+/// 在解析 Cookie / UA 等之前拒绝超量请求头，避免 header bomb 消耗内存与解析时间
+fn check_header_limits(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+    let max_count = state.config.server.max_header_count;
+    let max_bytes = state.config.server.max_header_bytes;
+    let count = headers.len();
+    if max_count > 0 && count > max_count {
+        tracing::info!(count, max_count, "request rejected: too many headers");
+        return Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE.into_response());
+    }
+    if max_bytes > 0 {
+        let bytes: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        if bytes > max_bytes {
+            tracing::info!(bytes, max_bytes, "request rejected: headers too large");
+            return Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE.into_response());
+        }
+    }
+    None
+}
+
+fn request_id_for(headers: &HeaderMap) -> String {
+    if let Some(value) = headers.get_str(REQUEST_ID_HEADER) {
+        let value = value.trim();
+        if !value.is_empty() && value.len() <= 64 && value.bytes().all(|b| b.is_ascii_graphic()) {
+            return value.to_string();
+        }
+    }
+    crate::crypto::generate_random_id()
+        .map(|id| id[..16].to_string())
+        .unwrap_or_default()
+}
+
+/// pow_gate 对单个请求的判定结果；判定本身不构造响应，便于单独推演各分支
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GateDecision {
+    /// 放行到上游；verified 表示 PoW Cookie 校验通过（影响 host 路由）
+    PassThrough { verified: bool },
+    Block,
+    /// IP 在封禁期内（连续提交无效 PoW 或触发蜜罐）
+    Banned,
+    /// 命中 rules.honeypot_paths：封禁 ttl 并回应普通的 404
+    Honeypot { ttl: Duration },
+    /// 以空 Service Worker 脚本应答（service_worker_mode = empty）
+    EmptyServiceWorker,
+    /// worker_type 为 None 时使用 pow.worker_type
+    Challenge { difficulty: i32, worker_type: Option<String> },
+}
+
+async fn pow_gate_inner(state: Arc<AppState>, mut req: Request, next: Next) -> Response {
     tracing::debug!(method = %req.method(), path = %req.uri().path(), "pow gate check");
+    let banned = match &state.failure_bans {
+        Some(bans) => {
+            let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+            bans.is_banned(&client_ip)
+        }
+        None => false,
+    };
+
+    match decide(&state, &req, banned) {
+        GateDecision::PassThrough { verified } => {
+            if verified {
+                req.extensions_mut().insert(PowVerified);
+                strip_token_headers(&state, req.headers_mut());
+            }
+            next.run(req).await
+        }
+        GateDecision::Block | GateDecision::Banned => StatusCode::FORBIDDEN.into_response(),
+        GateDecision::Honeypot { ttl } => {
+            let (client_ip, ip_source) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+            tracing::warn!(
+                client_ip = %client_ip,
+                ip_source = %ip_source.get_string(),
+                path = %req.uri().path(),
+                user_agent = %req.headers().get_str(header::USER_AGENT).unwrap_or_default(),
+                ttl_secs = ttl.as_secs(),
+                "honeypot tripped, ip banned"
+            );
+            match &state.failure_bans {
+                Some(bans) => bans.ban(&client_ip, ttl),
+                None => tracing::warn!("honeypot ban skipped: ban table not created at startup"),
+            }
+            StatusCode::NOT_FOUND.into_response()
+        }
+        GateDecision::EmptyServiceWorker => empty_service_worker_response(),
+        GateDecision::Challenge {
+            difficulty,
+            worker_type,
+        } => {
+            // drain：已有会话照常放行（上面的 PassThrough），新访客不再下发挑战
+            if state.draining.load(Ordering::Relaxed) {
+                state.metrics.drain_rejected();
+                tracing::info!(path = %req.uri().path(), "{}", MSG_DRAINING);
+                return draining_response(state.config.server.drain_retry_after_secs);
+            }
+            let mut difficulty = difficulty;
+            let mut flagged = false;
+            if let Some(reputation) = &state.reputation {
+                let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+                match reputation.check(&client_ip, &state.metrics).await {
+                    Some(ReputationVerdict::Block) => {
+                        tracing::info!(client_ip = %client_ip, path = %req.uri().path(), "request blocked: bad ip reputation");
+                        return StatusCode::FORBIDDEN.into_response();
+                    }
+                    Some(ReputationVerdict::Raise(delta)) => {
+                        let raised = crate::rules::clamp_difficulty(difficulty.saturating_add(delta));
+                        tracing::info!(client_ip = %client_ip, base = difficulty, effective = raised, "bad ip reputation: difficulty raised");
+                        difficulty = raised;
+                        flagged = true;
+                    }
+                    None => {}
+                }
+            }
+            // 信誉不佳的 IP 不给宽限
+            let grace = if flagged {
+                None
+            } else {
+                grant_first_request_grace(&state, req.headers(), req.extensions(), req.uri()).await
+            };
+            if let Some(grace_cookie) = grace {
+                let mut resp = next.run(req).await;
+                if let Ok(value) = HeaderValue::from_str(&grace_cookie) {
+                    resp.headers_mut().append(header::SET_COOKIE, value);
+                }
+                return resp;
+            }
+            if let Some(limiter) = &state.challenge_limiter {
+                let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+                if let Err(retry_after) = limiter.try_acquire(&client_ip) {
+                    tracing::info!(client_ip = %client_ip, retry_after_ms = retry_after.as_millis() as u64, "{}", MSG_CHALLENGE_RATE_LIMITED);
+                    return challenge_rate_limited_response(retry_after);
+                }
+            }
+            let started = Instant::now();
+            let redirect = sanitize_redirect(redirect_target(&req), state.config.pow.max_redirect_len);
+            let resp = build_challenge_response(
+                &state,
+                req.headers(),
+                req.extensions(),
+                &redirect,
+                difficulty,
+                worker_type.as_deref().unwrap_or(&state.config.pow.worker_type),
+            )
+            .await;
+            let resp = maybe_gzip_challenge_response(&state, req.headers(), resp).await;
+            if let Some(timing) = req.extensions().get::<Arc<RequestTiming>>() {
+                timing.record(TimingStage::Challenge, started.elapsed());
+            }
+            resp
+        }
+    }
+}
+
+/// pow.first_request_grace：请求未带宽限 Cookie、且该 IP 本窗口内尚未领取过时放行一次，
+/// 返回要种下的 Set-Cookie。宽限 Cookie 只表示“已用过”，带着它的请求一律照常挑战
+async fn grant_first_request_grace(
+    state: &AppState,
+    headers: &HeaderMap,
+    extensions: &axum::http::Extensions,
+    uri: &Uri,
+) -> Option<String> {
+    let grace = state.config.pow.first_request_grace.as_ref()?;
+    let limiter = state.grace_limiter.as_ref()?;
+    if state.config.pow.test_mode || has_cookie(headers, GRACE_COOKIE_NAME) {
+        return None;
+    }
+    let (client_ip, ip_source) = resolve_request_ip(headers, extensions, &state.trusted_proxies);
+    // 拿不到 IP 时无法限制重复领取，不给宽限
+    if client_ip.is_empty() || !limiter.try_acquire(&client_ip).await {
+        return None;
+    }
+    tracing::info!(
+        client_ip = %client_ip,
+        ip_source = %ip_source.get_string(),
+        path = %uri.path(),
+        user_agent = %headers.get_str(header::USER_AGENT).unwrap_or_default(),
+        "first request grace granted"
+    );
+    let max_age = time::Duration::seconds(grace.window_secs as i64);
+    let cookie = cookie::Cookie::build((GRACE_COOKIE_NAME, "1")).path("/").http_only(true).max_age(max_age);
+    let cookie = if state.config.secure_cookies() {
+        cookie.secure(true).same_site(cookie::SameSite::None)
+    } else {
+        cookie
+    };
+    Some(cookie.build().to_string())
+}
+
+fn has_cookie(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|raw| cookie::Cookie::split_parse(raw).flatten().any(|cookie| cookie.name() == name))
+}
+
+/// 挑战下发超出 pow.challenge_rate_per_min：Retry-After 向上取整到秒
+fn challenge_rate_limited_response(retry_after: Duration) -> Response {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [
+            (header::RETRY_AFTER, secs.max(1).to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        MSG_CHALLENGE_RATE_LIMITED,
+    )
+        .into_response()
+}
+
+/// drain 期间需要挑战的请求：503 并提示稍后重试，届时通常已由其他实例接管
+fn draining_response(retry_after_secs: u64) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [
+            (header::RETRY_AFTER, retry_after_secs.max(1).to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        MSG_DRAINING,
+    )
+        .into_response()
+}
+
+/// 同步判定：开关 → 蜜罐 → 封禁 → 可疑路径 → 各类绕过 → test/dev 模式 → Cookie → skip_if_cookie → 规则 → 默认挑战
+fn decide(state: &AppState, req: &Request, banned: bool) -> GateDecision {
+    const PASS: GateDecision = GateDecision::PassThrough { verified: false };
+    let timing = req.extensions().get::<Arc<RequestTiming>>().map(Arc::as_ref);
+
+    if !state.pow_enabled.load(Ordering::Relaxed) {
+        tracing::debug!("pow disabled (admin kill-switch)");
+        return PASS;
+    }
     if state.config.pow.difficulty == 0 {
         tracing::debug!("pow disabled (difficulty=0)");
-        return next.run(req).await;
+        return PASS;
+    }
+
+    if let Some(ttl) = state.rules.load().check_honeypot(req.uri()) {
+        return GateDecision::Honeypot { ttl };
+    }
+
+    if banned {
+        let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+        tracing::info!(client_ip = %client_ip, "request blocked: ip temporarily banned");
+        return GateDecision::Banned;
+    }
+
+    // 先于所有绕过检查：`/.well-known/../admin` 之类不能借白名单前缀溜过去
+    let suspicious = RequestTiming::measure(timing, TimingStage::Rules, || state.rules.load().check_suspicious_path(req.uri()));
+    if let Some((action, pattern)) = suspicious {
+        let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+        tracing::warn!(
+            client_ip = %client_ip,
+            uri = %req.uri(),
+            pattern,
+            action = ?action,
+            "suspicious path detected"
+        );
+        if !matches!(action, RuleAction::Challenge) {
+            return GateDecision::Block;
+        }
+        // 已通过 PoW 的请求不再重复挑战，否则会陷入挑战循环
+        let verified = RequestTiming::measure(timing, TimingStage::Cookie, || {
+            extract_token(state, req.headers()).is_some_and(|cookie| verify_cookie(state, req, &cookie))
+        });
+        if verified {
+            return GateDecision::PassThrough { verified: true };
+        }
+        return GateDecision::Challenge {
+            difficulty: state.config.pow.base_difficulty(req.uri().path()).max(1),
+            worker_type: None,
+        };
     }
 
     if is_pow_path(req.uri().path()) {
         tracing::debug!("pow bypass for internal route");
-        return next.run(req).await;
+        return PASS;
     }
 
     if req.uri().path() == "/favicon.ico" {
         tracing::debug!("pow bypass for favicon.ico");
-        return next.run(req).await;
+        return PASS;
     }
 
-    if is_service_worker_request(&req) {
+    if is_service_worker_request(req) {
+        if state.config.pow.service_worker_mode == ServiceWorkerMode::Empty {
+            tracing::debug!("serving empty service worker script");
+            return GateDecision::EmptyServiceWorker;
+        }
         tracing::debug!("pow bypass for service worker request");
-        return next.run(req).await;
+        return PASS;
     }
 
-    if is_prefetch_request(&req) {
+    if is_prefetch_request(req) {
         tracing::info!(
             path = %req.uri().path(),
             sec_purpose = req.headers().get_str("sec-purpose").unwrap_or("-"),
@@ -56,77 +374,127 @@ pub async fn pow_gate(
             x_middleware_prefetch = req.headers().get_str("x-middleware-prefetch").unwrap_or("-"),
             "pow bypass for prefetch/speculation request"
         );
-        return next.run(req).await;
+        return PASS;
     }
 
     if state.rules.load().allow_wellknown && is_wellknown_path(req.uri().path()) {
         tracing::debug!(path = %req.uri().path(), "pow bypass for wellknown whitelist path");
-        return next.run(req).await;
+        return PASS;
     }
 
-    if state.config.pow.test_mode {
-        tracing::info!("pow test mode enabled: forcing challenge");
-        let resp = build_challenge_response(
-            &state,
-            req.headers(),
-            req.extensions(),
-            redirect_target(&req),
-            state.config.pow.difficulty,
-        ).await;
-        return maybe_gzip_challenge_response(req.headers(), resp).await;
+    let difficulty_override = debug_difficulty_override(state, req);
+    let base_difficulty = state.config.pow.base_difficulty(req.uri().path());
+
+    if state.config.pow.test_mode || state.config.pow.dev_mode {
+        tracing::info!(
+            test_mode = state.config.pow.test_mode,
+            dev_mode = state.config.pow.dev_mode,
+            "pow test/dev mode enabled: forcing challenge"
+        );
+        return GateDecision::Challenge {
+            difficulty: difficulty_override.unwrap_or(base_difficulty),
+            worker_type: None,
+        };
     }
 
-    if let Some(cookie) = extract_cookie(req.headers()) {
-        if verify_cookie(&state, &req, &cookie) {
+    // 子 span 只覆盖判定部分，放行后的上游调用不挂在它下面
+    let check_span = tracing::info_span!(
+        "pow_check",
+        cookie = tracing::field::Empty,
+        rule = tracing::field::Empty,
+        difficulty = tracing::field::Empty,
+    );
+    let _entered = check_span.enter();
+
+    let cookie_valid = RequestTiming::measure(timing, TimingStage::Cookie, || {
+        extract_token(state, req.headers()).map(|cookie| verify_cookie(state, req, &cookie))
+    });
+    check_span.record(
+        "cookie",
+        match cookie_valid {
+            Some(true) => "valid",
+            Some(false) => "invalid",
+            None => "absent",
+        },
+    );
+    match cookie_valid {
+        Some(true) => {
             tracing::debug!("pow cookie verified");
-            req.extensions_mut().insert(PowVerified);
-            return next.run(req).await;
+            return GateDecision::PassThrough { verified: true };
         }
-        tracing::debug!("pow cookie invalid");
+        Some(false) => tracing::debug!("pow cookie invalid"),
+        None => {}
+    }
+
+    if let Some(name) = find_skip_cookie(req.headers(), &state.config.pow.skip_if_cookie) {
+        tracing::debug!(cookie = %name, "pow bypass: upstream session cookie present");
+        return PASS;
     }
 
-    // 提前提取规则匹配所需的数据，为后续 async 规则匹配做准备
-    let (client_ip_str, ip_source) = resolve_request_ip(req.headers(), req.extensions());
+    let (client_ip_str, ip_source) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
     let client_ip = crate::crypto::parse_ip(&client_ip_str);
     let path = req.uri().path();
-    
-    if let Some(decision) = evaluate_rules(&state, path, req.headers(), client_ip) {
+    let base_difficulty = match &state.risk_model {
+        Some(model) => {
+            let score = model.score(req.uri(), req.headers(), client_ip);
+            let difficulty = model.difficulty(score, base_difficulty);
+            tracing::debug!(score, difficulty, "risk score");
+            difficulty
+        }
+        None => base_difficulty,
+    };
+
+    let decision = RequestTiming::measure(timing, TimingStage::Rules, || {
+        evaluate_rules(state, req.uri(), req.headers(), client_ip)
+    });
+    if let Some(decision) = decision {
+        check_span.record("rule", tracing::field::debug(&decision.action));
         return match decision.action {
             RuleAction::Allow => {
                 tracing::info!("rule decision: allow");
-                next.run(req).await
+                PASS
             }
             RuleAction::Block => {
                 tracing::info!("rule decision: block");
-                StatusCode::FORBIDDEN.into_response()
+                GateDecision::Block
             }
             RuleAction::Challenge => {
-                let base = state.config.pow.difficulty;
-                let effective = crate::rules::clamp_difficulty(base + decision.difficulty_delta);
-                tracing::info!(base, delta = decision.difficulty_delta, effective, "rule decision: challenge");
+                let base = base_difficulty;
+                let effective = difficulty_override
+                    .unwrap_or_else(|| decision.effective_difficulty(base));
+                check_span.record("difficulty", effective);
+                tracing::info!(
+                    base,
+                    delta = decision.difficulty_delta,
+                    absolute = ?decision.difficulty_absolute,
+                    effective,
+                    "rule decision: challenge"
+                );
                 if effective == 0 {
-                    next.run(req).await
+                    PASS
                 } else {
-                    let resp = build_challenge_response(
-                        &state,
-                        req.headers(),
-                        req.extensions(),
-                        redirect_target(&req),
-                        effective,
-                    )
-                    .await;
-                    return maybe_gzip_challenge_response(req.headers(), resp).await;
+                    GateDecision::Challenge {
+                        difficulty: effective,
+                        worker_type: decision.worker_type,
+                    }
                 }
             }
         };
     }
 
+    let difficulty = difficulty_override.unwrap_or(base_difficulty);
+    check_span.record("difficulty", difficulty);
+    if difficulty == 0 {
+        tracing::debug!(path = %path, "pow bypass: path_difficulty is 0");
+        return PASS;
+    }
+
     let user_agent = req.headers().get_string_or_default("User-Agent");
     let accept_language = req.headers().get_string_or_default("Accept-Language");
     let host = req.headers().get_string_or_default("Host");
 
     tracing::info!(
-        difficulty = state.config.pow.difficulty,
+        difficulty,
         client_ip = %client_ip_str,
         ip_source = %ip_source.get_string(),
         user_agent = %user_agent,
@@ -135,34 +503,69 @@ pub async fn pow_gate(
         host = %host,
         "pow challenge (default)"
     );
-    let resp = build_challenge_response(
-        &state,
-        req.headers(),
-        req.extensions(),
-        redirect_target(&req),
-        state.config.pow.difficulty,
-    )
-    .await;
-    maybe_gzip_challenge_response(req.headers(), resp).await
+    GateDecision::Challenge {
+        difficulty,
+        worker_type: None,
+    }
+}
+
+const DEBUG_TOKEN_HEADER: &str = "x-cowcat-debug-token";
+const DEBUG_DIFFICULTY_HEADER: &str = "x-cowcat-difficulty";
+
+/// 请求携带的 X-Cowcat-Debug-Token 是否与 pow.debug_token 一致（未配置时恒为 false）
+fn debug_token_matches(state: &AppState, headers: &HeaderMap) -> bool {
+    let expected = state.config.pow.debug_token.trim();
+    if expected.is_empty() {
+        return false;
+    }
+    let Some(token) = headers.get_str(DEBUG_TOKEN_HEADER) else {
+        return false;
+    };
+    crate::crypto::constant_time_eq(token.trim().as_bytes(), expected.as_bytes())
+}
+
+/// 仅当请求携带与 pow.debug_token 一致的令牌时，才允许用请求头覆盖本次挑战难度
+fn debug_difficulty_override(state: &AppState, req: &Request) -> Option<i32> {
+    let raw = req.headers().get_str(DEBUG_DIFFICULTY_HEADER)?;
+    if state.config.pow.debug_token.trim().is_empty() {
+        return None;
+    }
+    if !debug_token_matches(state, req.headers()) {
+        tracing::warn!(path = %req.uri().path(), "debug difficulty override rejected: bad token");
+        return None;
+    }
+    let requested = match raw.trim().parse::<i32>() {
+        Ok(value) => value,
+        Err(_) => {
+            tracing::warn!(value = %raw, "debug difficulty override rejected: not a number");
+            return None;
+        }
+    };
+    let difficulty = crate::rules::clamp_difficulty(requested);
+    let (client_ip, _) = resolve_request_ip(req.headers(), req.extensions(), &state.trusted_proxies);
+    tracing::warn!(
+        client_ip = %client_ip,
+        path = %req.uri().path(),
+        requested,
+        difficulty,
+        "debug difficulty override applied"
+    );
+    Some(difficulty)
 }
 
 fn evaluate_rules(
     state: &AppState,
-    path: &str,
+    uri: &Uri,
     headers: &HeaderMap,
     client_ip: Option<IpAddr>,
 ) -> Option<RuleDecision> {
-    state.rules.load().evaluate(path, headers, client_ip)
-}
-
-fn is_pow_path(path: &str) -> bool {
-    path.starts_with(POW_PREFIX)
+    state.rules.load().evaluate(uri, headers, client_ip)
 }
 
 const WELLKNOWN_PREFIX: &str = "/.well-known/";
 const WELLKNOWN_EXACT: &[&str] = &["/robots.txt", "/sitemap.xml", "/sitemap_index.xml", "/ads.txt", "/app-ads.txt"];
 
-fn is_wellknown_path(path: &str) -> bool {
+pub fn is_wellknown_path(path: &str) -> bool {
     if path.starts_with(WELLKNOWN_PREFIX) {
         return true;
     }
@@ -219,6 +622,20 @@ fn is_service_worker_request(req: &Request) -> bool {
     path.ends_with(".js") || path.ends_with(".mjs")
 }
 
+const EMPTY_SERVICE_WORKER: &str = "// cowcat-rs: empty service worker\n";
+
+fn empty_service_worker_response() -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("text/javascript; charset=utf-8")),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        EMPTY_SERVICE_WORKER,
+    )
+        .into_response()
+}
+
 fn redirect_target(req: &Request) -> &str {
     req.uri()
         .path_and_query()
@@ -236,9 +653,54 @@ fn extract_cookie(headers: &HeaderMap) -> Option<String> {
     None
 }
 
+/// 通行令牌：优先 Cookie；pow.accept_bearer_token 时依次回落到 X-Cowcat-Token 与 Authorization: Bearer
+fn extract_token(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    if let Some(cookie) = extract_cookie(headers) {
+        return Some(cookie);
+    }
+    if !state.config.pow.accept_bearer_token {
+        return None;
+    }
+    headers
+        .get_str(POW_TOKEN_HEADER)
+        .or_else(|| bearer_token(headers))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get_str(header::AUTHORIZATION)?.trim().strip_prefix("Bearer ")
+}
+
+/// 通行令牌不转发给上游；Authorization 只有正是 decide() 校验通过的那个令牌时才移除，业务自己的 Bearer 照常透传。
+/// 这里只比较不重复校验，密钥用量每个请求只计一次
+fn strip_token_headers(state: &AppState, headers: &mut HeaderMap) {
+    if !state.config.pow.accept_bearer_token {
+        return;
+    }
+    let verified = extract_token(state, headers);
+    headers.remove(POW_TOKEN_HEADER);
+    let ours = bearer_token(headers).is_some_and(|token| verified.as_deref() == Some(token.trim()));
+    if ours {
+        headers.remove(header::AUTHORIZATION);
+    }
+}
+
+/// 返回请求中出现的第一个 pow.skip_if_cookie 名称；只看是否存在，不校验其值
+fn find_skip_cookie(headers: &HeaderMap, names: &[String]) -> Option<String> {
+    if names.is_empty() {
+        return None;
+    }
+    let raw = headers.get(header::COOKIE)?.to_str().ok()?;
+    cookie::Cookie::split_parse(raw)
+        .flatten()
+        .find(|cookie| !cookie.value().is_empty() && names.iter().any(|name| name == cookie.name()))
+        .map(|cookie| cookie.name().to_string())
+}
+
 fn verify_cookie(state: &AppState, req: &Request, value: &str) -> bool {
     tracing::debug!("verifying pow cookie: {}", value);
-    let payload = match crate::crypto::verify_cookie(&state.server_secret, value) {
+    let payload = match crate::crypto::verify_cookie(&state.cookie_keys, value) {
         Some(payload) => payload,
         None => {
             tracing::debug!("pow cookie signature/expiry invalid");
@@ -259,7 +721,7 @@ fn verify_cookie(state: &AppState, req: &Request, value: &str) -> bool {
         return false;
     }
     if state.config.pow.ip_policy != IpPolicy::None {
-        let ip = crate::crypto::extract_client_ip(req.headers(), req.extensions(), state.config.pow.ip_policy);
+        let ip = crate::crypto::extract_client_ip(req.headers(), req.extensions(), state.config.pow.ip_policy, &state.trusted_proxies);
         let ip_hash = compute_ip_hash(&ip);
         if ip.is_empty() {
             tracing::debug!("pow cookie missing client ip under ip_policy");
@@ -274,10 +736,21 @@ fn verify_cookie(state: &AppState, req: &Request, value: &str) -> bool {
             return false;
         }
     }
+    if state.config.pow.bind_fingerprint {
+        let current = cookie_fingerprint(state, req.extensions());
+        if payload.fp.as_deref().unwrap_or_default() != current {
+            tracing::debug!(
+                payload_fp = ?payload.fp,
+                request_fp = %current,
+                "pow cookie tls fingerprint mismatch"
+            );
+            return false;
+        }
+    }
     true
 }
 
-async fn maybe_gzip_challenge_response(headers: &HeaderMap, response: Response) -> Response {
+async fn maybe_gzip_challenge_response(state: &AppState, headers: &HeaderMap, response: Response) -> Response {
     if !accepts_gzip(headers) {
         return response;
     }
@@ -308,6 +781,15 @@ async fn maybe_gzip_challenge_response(headers: &HeaderMap, response: Response)
         }
     };
 
+    state.metrics.challenge_compressed(CompressionEncoding::Gzip, bytes.len(), compressed.len());
+    tracing::debug!(
+        encoding = "gzip",
+        original = bytes.len(),
+        compressed = compressed.len(),
+        ratio = format_args!("{:.3}", compressed.len() as f64 / bytes.len().max(1) as f64),
+        "challenge response compressed"
+    );
+
     parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
     parts.headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
     parts.headers.insert(
@@ -355,3 +837,546 @@ fn accepts_gzip(headers: &HeaderMap) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::tls::TlsFingerprint;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn state(config: Config) -> Arc<AppState> {
+        Arc::new(AppState::new(config).await.unwrap())
+    }
+
+    async fn gate(config: Config) -> Router {
+        let state = state(config).await;
+        Router::new()
+            .fallback(|| async { "upstream" })
+            .layer(axum::middleware::from_fn_with_state(state, pow_gate))
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn request_with_headers(count: usize, value_len: usize) -> Request {
+        let mut builder = Request::builder().uri("/");
+        for idx in 0..count {
+            builder = builder.header(format!("x-filler-{idx}"), "v".repeat(value_len));
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn too_many_headers_is_431() {
+        let mut config = Config::default();
+        config.server.max_header_count = 10;
+        let resp = gate(config).await.oneshot(request_with_headers(11, 1)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn too_many_header_bytes_is_431() {
+        let mut config = Config::default();
+        config.server.max_header_bytes = 1024;
+        let resp = gate(config).await.oneshot(request_with_headers(2, 600)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn header_limits_are_off_by_default() {
+        let resp = gate(Config::default()).await.oneshot(request_with_headers(200, 200)).await.unwrap();
+        assert_ne!(resp.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    }
+
+    async fn body_text(resp: Response) -> String {
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn from_peer(uri: &str, peer: &str) -> Request {
+        let mut req = request(uri, &[]);
+        req.extensions_mut()
+            .insert(axum::extract::ConnectInfo(peer.parse::<std::net::SocketAddr>().unwrap()));
+        req
+    }
+
+    #[tokio::test]
+    async fn challenges_past_the_rate_limit_are_429_with_retry_after() {
+        let mut config = Config::default();
+        config.pow.challenge_rate_per_min = 2;
+        let app = gate(config).await;
+        for _ in 0..2 {
+            let resp = app.clone().oneshot(from_peer("/", "10.0.0.1:5000")).await.unwrap();
+            assert_ne!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_ne!(body_text(resp).await, "upstream");
+        }
+
+        let resp = app.clone().oneshot(from_peer("/", "10.0.0.1:5001")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+        assert!((1..=30).contains(&retry_after), "{retry_after}");
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store");
+
+        let other = app.oneshot(from_peer("/", "10.0.0.2:5000")).await.unwrap();
+        assert_ne!(other.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    fn service_worker_request() -> Request {
+        Request::builder()
+            .uri("/sw.js")
+            .header("sec-fetch-dest", "serviceworker")
+            .header("service-worker", "script")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn service_worker_is_proxied_in_proxy_mode() {
+        let mut config = Config::default();
+        config.pow.service_worker_mode = ServiceWorkerMode::Proxy;
+        let resp = gate(config).await.oneshot(service_worker_request()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_text(resp).await, "upstream");
+    }
+
+    #[tokio::test]
+    async fn service_worker_gets_empty_script_in_empty_mode() {
+        let mut config = Config::default();
+        config.pow.service_worker_mode = ServiceWorkerMode::Empty;
+        let resp = gate(config).await.oneshot(service_worker_request()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/javascript; charset=utf-8");
+        assert_eq!(body_text(resp).await, EMPTY_SERVICE_WORKER);
+    }
+
+    #[tokio::test]
+    async fn skip_if_cookie_passes_only_when_a_named_cookie_is_present() {
+        let mut config = Config::default();
+        config.pow.skip_if_cookie = vec!["sessionid".to_string(), "wordpress_logged_in".to_string()];
+        let state = state(config).await;
+        let pass = GateDecision::PassThrough { verified: false };
+
+        let present = request("/account", &[("cookie", "theme=dark; sessionid=abc123")]);
+        assert_eq!(decide(&state, &present, false), pass);
+        let second_name = request("/account", &[("cookie", "wordpress_logged_in=u1")]);
+        assert_eq!(decide(&state, &second_name, false), pass);
+
+        for cookie in ["theme=dark", "sessionid=", "xsessionid=abc"] {
+            let req = request("/account", &[("cookie", cookie)]);
+            assert!(matches!(decide(&state, &req, false), GateDecision::Challenge { .. }), "{cookie}");
+        }
+        let absent = request("/account", &[]);
+        assert!(matches!(decide(&state, &absent, false), GateDecision::Challenge { .. }));
+    }
+
+    /// 判定表用的配置：后面每一级都处于“会命中”的状态，用来验证前一级先生效
+    fn layered_config() -> Config {
+        let mut config = Config::default();
+        config.pow.skip_if_cookie = vec!["sessionid".to_string()];
+        config.pow.risk_score = Some(crate::config::RiskScoreConfig {
+            path: vec![crate::config::RiskPathWeight { prefix: "/login".to_string(), score: 10 }],
+            header: Vec::new(),
+            ip: Vec::new(),
+            curve: vec![crate::config::RiskStep { min_score: 10, difficulty: 7 }],
+        });
+        config.rules.enabled = true;
+        config.rules.block_suspicious_paths = true;
+        config.rules.honeypot_paths = vec!["/wp-admin.php".to_string()];
+        config.rules.rule = vec![
+            crate::config::RuleConfig {
+                name: Some("block-admin".to_string()),
+                action: RuleAction::Block,
+                path_prefix: Some("/admin".to_string()),
+                ..Default::default()
+            },
+            crate::config::RuleConfig {
+                name: Some("allow-public".to_string()),
+                action: RuleAction::Allow,
+                path_prefix: Some("/public".to_string()),
+                ..Default::default()
+            },
+            crate::config::RuleConfig {
+                name: Some("harder-login".to_string()),
+                action: RuleAction::Challenge,
+                difficulty_delta: Some(1),
+                path_prefix: Some("/login/strict".to_string()),
+                ..Default::default()
+            },
+        ];
+        config
+    }
+
+    fn valid_cookie(state: &AppState) -> String {
+        format!("{POW_COOKIE_NAME}={}", bare_token(state))
+    }
+
+    fn challenge(difficulty: i32) -> GateDecision {
+        GateDecision::Challenge { difficulty, worker_type: None }
+    }
+
+    const PASS: GateDecision = GateDecision::PassThrough { verified: false };
+
+    #[tokio::test]
+    async fn kill_switch_and_zero_difficulty_pass_everything() {
+        let state = state(layered_config()).await;
+        state.pow_enabled.store(false, Ordering::Relaxed);
+        assert_eq!(decide(&state, &request("/wp-admin.php", &[]), true), PASS);
+        assert_eq!(decide(&state, &request("/admin", &[]), false), PASS);
+
+        let mut config = layered_config();
+        config.pow.difficulty = 0;
+        let state = self::state(config).await;
+        assert_eq!(decide(&state, &request("/wp-admin.php", &[]), true), PASS);
+        assert_eq!(decide(&state, &request("/admin", &[]), false), PASS);
+    }
+
+    #[tokio::test]
+    async fn honeypot_then_ban_then_suspicious_path() {
+        let state = state(layered_config()).await;
+        assert!(matches!(decide(&state, &request("/wp-admin.php", &[]), true), GateDecision::Honeypot { .. }));
+        assert_eq!(decide(&state, &request("/files/../etc/passwd", &[]), true), GateDecision::Banned);
+        // 可疑路径先于白名单与 Cookie：带着有效 Cookie、借 well-known 前缀也不放行
+        let cookie = valid_cookie(&state);
+        let req = request("/.well-known/../admin", &[("cookie", &cookie), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &req, false), GateDecision::Block);
+    }
+
+    fn grace_cookie(resp: &Response) -> Option<String> {
+        resp.headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .find(|value| value.starts_with(GRACE_COOKIE_NAME))
+    }
+
+    #[tokio::test]
+    async fn first_request_passes_once_then_is_challenged() {
+        let mut config = Config::default();
+        config.pow.first_request_grace = Some(crate::config::FirstRequestGraceConfig { window_secs: 600 });
+        let app = gate(config).await;
+
+        let first = app.clone().oneshot(from_peer("/article", "10.0.0.5:5000")).await.unwrap();
+        let cookie = grace_cookie(&first).expect("grace cookie");
+        assert!(cookie.contains("Max-Age=600"), "{cookie}");
+        assert_eq!(body_text(first).await, "upstream");
+
+        // 带着宽限 Cookie 回来：照常挑战，不再续发
+        let mut second = from_peer("/article", "10.0.0.5:5001");
+        second.headers_mut().insert(header::COOKIE, format!("{GRACE_COOKIE_NAME}=1").parse().unwrap());
+        let second = app.clone().oneshot(second).await.unwrap();
+        assert!(grace_cookie(&second).is_none());
+        assert_ne!(body_text(second).await, "upstream");
+
+        // 丢掉 Cookie 也领不到第二次：同一 IP 窗口内只有一次
+        let dropped = app.clone().oneshot(from_peer("/article", "10.0.0.5:5002")).await.unwrap();
+        assert!(grace_cookie(&dropped).is_none());
+        assert_ne!(body_text(dropped).await, "upstream");
+
+        let other = app.oneshot(from_peer("/article", "10.0.0.6:5000")).await.unwrap();
+        assert!(grace_cookie(&other).is_some());
+        assert_eq!(body_text(other).await, "upstream");
+    }
+
+    #[tokio::test]
+    async fn draining_passes_sessions_and_turns_new_visitors_away() {
+        let mut config = Config::default();
+        config.server.drain_retry_after_secs = 45;
+        let state = state(config).await;
+        let app = Router::new()
+            .fallback(|| async { "upstream" })
+            .layer(axum::middleware::from_fn_with_state(state.clone(), pow_gate));
+        let with_cookie = || request("/", &[("cookie", &valid_cookie(&state)), ("user-agent", "test-agent")]);
+        assert!(!crate::drain::set_draining(&state, true, "test"));
+
+        let resp = app.clone().oneshot(with_cookie()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_text(resp).await, "upstream");
+
+        let resp = app.clone().oneshot(request("/", &[("user-agent", "test-agent")])).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "45");
+        assert_eq!(body_text(resp).await, MSG_DRAINING);
+        let metrics = state.metrics.render(true, true, None, None);
+        assert!(metrics.contains("cowcat_draining 1\n"), "{metrics}");
+        assert!(metrics.contains("cowcat_drain_rejected_total 1\n"), "{metrics}");
+
+        // 退出 drain 后重新下发挑战
+        assert!(crate::drain::set_draining(&state, false, "test"));
+        let resp = app.oneshot(request("/", &[("user-agent", "test-agent")])).await.unwrap();
+        assert_ne!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_ne!(body_text(resp).await, "upstream");
+    }
+
+    /// 封禁是空 body 的 403；挑战页的默认状态码同为 403，但带页面
+    async fn is_ban(resp: Response) -> bool {
+        resp.status() == StatusCode::FORBIDDEN && body_text(resp).await.is_empty()
+    }
+
+    #[tokio::test]
+    async fn honeypot_hit_bans_the_ip_for_later_requests() {
+        let mut config = Config::default();
+        config.rules.honeypot_paths = vec!["/trap".to_string()];
+        let state = state(config).await;
+        let app = Router::new()
+            .fallback(|| async { "upstream" })
+            .layer(axum::middleware::from_fn_with_state(state.clone(), pow_gate));
+
+        assert!(!is_ban(app.clone().oneshot(from_peer("/", "10.0.0.3:5000")).await.unwrap()).await);
+        let trap = app.clone().oneshot(from_peer("/trap", "10.0.0.3:5000")).await.unwrap();
+        assert_eq!(trap.status(), StatusCode::NOT_FOUND);
+
+        // 之后同一 IP 的任何请求都被拒，带着有效 Cookie 也一样
+        let mut with_cookie = from_peer("/", "10.0.0.3:5001");
+        with_cookie.headers_mut().insert(header::COOKIE, valid_cookie(&state).parse().unwrap());
+        with_cookie.headers_mut().insert(header::USER_AGENT, "test-agent".parse().unwrap());
+        for req in [from_peer("/", "10.0.0.3:5001"), from_peer("/public", "10.0.0.3:5002"), with_cookie] {
+            assert!(is_ban(app.clone().oneshot(req).await.unwrap()).await);
+        }
+        assert!(!is_ban(app.oneshot(from_peer("/", "10.0.0.4:5000")).await.unwrap()).await);
+        let metrics = state.metrics.render(true, false, None, state.failure_bans.as_deref());
+        assert!(metrics.contains("cowcat_banned_ips 1\n"), "{metrics}");
+    }
+
+    #[tokio::test]
+    async fn bypasses_come_before_test_mode() {
+        let mut config = layered_config();
+        config.pow.test_mode = true;
+        let state = state(config).await;
+        assert_eq!(decide(&state, &request("/__cowcatwaf/ok", &[]), false), PASS);
+        assert_eq!(decide(&state, &request("/favicon.ico", &[]), false), PASS);
+        assert_eq!(decide(&state, &request("/robots.txt", &[]), false), PASS);
+        // test 模式强制挑战，有效 Cookie、skip_if_cookie 与 allow 规则都不生效
+        let cookie = format!("{}; sessionid=abc", valid_cookie(&state));
+        let req = request("/public", &[("cookie", &cookie), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &req, false), challenge(3));
+    }
+
+    #[tokio::test]
+    async fn cookie_then_skip_cookie_then_rules() {
+        let state = state(layered_config()).await;
+        let cookie = valid_cookie(&state);
+        let verified = request("/admin", &[("cookie", &cookie), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &verified, false), GateDecision::PassThrough { verified: true });
+
+        // UA 不符的 Cookie 无效，继续往下判定：skip_if_cookie 先于 block 规则
+        let wrong_ua = request("/admin", &[("cookie", &format!("{cookie}; sessionid=abc")), ("user-agent", "other")]);
+        assert_eq!(decide(&state, &wrong_ua, false), PASS);
+        let no_session = request("/admin", &[("cookie", &cookie), ("user-agent", "other")]);
+        assert_eq!(decide(&state, &no_session, false), GateDecision::Block);
+    }
+
+    #[tokio::test]
+    async fn risk_sets_the_base_that_rules_and_default_build_on() {
+        let state = state(layered_config()).await;
+        assert_eq!(decide(&state, &request("/login", &[]), false), challenge(7));
+        assert_eq!(decide(&state, &request("/login/strict", &[]), false), challenge(8));
+        assert_eq!(decide(&state, &request("/public/page", &[]), false), PASS);
+        assert_eq!(decide(&state, &request("/admin/users", &[]), false), GateDecision::Block);
+        assert_eq!(decide(&state, &request("/anything", &[]), false), challenge(3));
+    }
+
+    #[tokio::test]
+    async fn absolute_rule_difficulty_replaces_the_risk_base() {
+        let mut config = layered_config();
+        config.rules.rule.insert(
+            0,
+            crate::config::RuleConfig {
+                name: Some("api".to_string()),
+                path_prefix: Some("/login/api".to_string()),
+                difficulty_absolute: Some(5),
+                difficulty_delta: Some(1),
+                ..crate::config::RuleConfig::default()
+            },
+        );
+        let state = state(config).await;
+        // /login 的风险基础难度为 7，绝对难度直接取代它，delta 不再叠加
+        assert_eq!(decide(&state, &request("/login/api", &[]), false), challenge(5));
+        assert_eq!(decide(&state, &request("/login", &[]), false), challenge(7));
+    }
+
+    fn bare_token(state: &AppState) -> String {
+        crate::crypto::generate_cookie(&state.cookie_keys, 12, "example.com", &compute_ua_hash("test-agent"), "", "", "1", 3600)
+    }
+
+    #[tokio::test]
+    async fn token_headers_are_ignored_unless_enabled() {
+        let state = state(Config::default()).await;
+        let token = bare_token(&state);
+        let bearer = format!("Bearer {token}");
+        for (name, value) in [(POW_TOKEN_HEADER, token.as_str()), ("authorization", bearer.as_str())] {
+            let req = request("/", &[(name, value), ("user-agent", "test-agent")]);
+            assert_eq!(decide(&state, &req, false), challenge(3), "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn token_header_or_bearer_passes_when_enabled() {
+        let mut config = Config::default();
+        config.pow.accept_bearer_token = true;
+        let state = state(config).await;
+        let token = bare_token(&state);
+        let verified = GateDecision::PassThrough { verified: true };
+
+        let req = request("/", &[(POW_TOKEN_HEADER, &token), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &req, false), verified);
+        let req = request("/", &[("authorization", &format!("Bearer {token}")), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &req, false), verified);
+
+        // 令牌同样绑定 UA；伪造或非本服务签发的 Bearer 不放行
+        let req = request("/", &[(POW_TOKEN_HEADER, &token), ("user-agent", "other")]);
+        assert_eq!(decide(&state, &req, false), challenge(3));
+        let req = request("/", &[("authorization", "Bearer app-session"), ("user-agent", "test-agent")]);
+        assert_eq!(decide(&state, &req, false), challenge(3));
+    }
+
+    #[tokio::test]
+    async fn only_our_token_headers_are_stripped_before_upstream() {
+        let mut config = Config::default();
+        config.pow.accept_bearer_token = true;
+        let state = state(config).await;
+        let token = bare_token(&state);
+        let app = Router::new()
+            .fallback(|headers: HeaderMap| async move {
+                let get = |name: &str| headers.get_str(name).unwrap_or("-").to_string();
+                format!("{}|{}", get(POW_TOKEN_HEADER), get("authorization"))
+            })
+            .layer(axum::middleware::from_fn_with_state(state.clone(), pow_gate));
+
+        let req = request("/", &[("authorization", &format!("Bearer {token}")), ("user-agent", "test-agent")]);
+        assert_eq!(body_text(app.clone().oneshot(req).await.unwrap()).await, "-|-");
+        // 剥离时不重复校验：密钥用量只计 decide() 那一次
+        assert_eq!(state.cookie_keys.usage()["v1"], 1);
+
+        // 业务自己的 Bearer 照常透传，通行令牌走 X-Cowcat-Token
+        let req = request(
+            "/",
+            &[(POW_TOKEN_HEADER, &token), ("authorization", "Bearer app-session"), ("user-agent", "test-agent")],
+        );
+        assert_eq!(body_text(app.oneshot(req).await.unwrap()).await, "-|Bearer app-session");
+    }
+
+    fn fingerprinted_request(cookie: &str, fingerprint: Option<&str>) -> Request {
+        let mut req = request("/", &[("cookie", cookie), ("user-agent", "test-agent")]);
+        if let Some(fingerprint) = fingerprint {
+            req.extensions_mut().insert(TlsFingerprint(fingerprint.to_string()));
+        }
+        req
+    }
+
+    fn cookie_with_fingerprint(state: &AppState, fingerprint: &str) -> String {
+        let token = crate::crypto::generate_cookie(&state.cookie_keys, 12, "example.com", &compute_ua_hash("test-agent"), "", fingerprint, "1", 3600);
+        format!("{POW_COOKIE_NAME}={token}")
+    }
+
+    #[tokio::test]
+    async fn bound_cookie_passes_only_on_the_same_fingerprint() {
+        let mut config = Config::default();
+        config.pow.bind_fingerprint = true;
+        let state = state(config).await;
+        let verified = GateDecision::PassThrough { verified: true };
+        let cookie = cookie_with_fingerprint(&state, "fp-chrome");
+
+        assert_eq!(decide(&state, &fingerprinted_request(&cookie, Some("fp-chrome")), false), verified);
+        assert_eq!(decide(&state, &fingerprinted_request(&cookie, Some("fp-curl")), false), challenge(3));
+        assert_eq!(decide(&state, &fingerprinted_request(&cookie, None), false), challenge(3));
+        // 开启前签发、不带指纹的 Cookie 需要重新挑战
+        let unbound = valid_cookie(&state);
+        assert_eq!(decide(&state, &fingerprinted_request(&unbound, Some("fp-chrome")), false), challenge(3));
+    }
+
+    #[tokio::test]
+    async fn fingerprint_is_ignored_when_binding_is_off() {
+        let state = state(Config::default()).await;
+        let verified = GateDecision::PassThrough { verified: true };
+        let cookie = cookie_with_fingerprint(&state, "fp-chrome");
+        assert_eq!(decide(&state, &fingerprinted_request(&cookie, Some("fp-curl")), false), verified);
+        assert_eq!(decide(&state, &fingerprinted_request(&valid_cookie(&state), Some("fp-curl")), false), verified);
+    }
+
+    async fn server_timing(config: Config, token: Option<&str>) -> Option<String> {
+        let headers: Vec<(&str, &str)> = token.map(|token| (DEBUG_TOKEN_HEADER, token)).into_iter().collect();
+        let resp = gate(config).await.oneshot(request("/", &headers)).await.unwrap();
+        resp.headers().get("server-timing").map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn server_timing_is_emitted_only_for_the_debug_token() {
+        let mut config = Config::default();
+        config.pow.debug_token = "debug-secret".to_string();
+
+        let value = server_timing(config.clone(), Some("debug-secret")).await.unwrap();
+        let names: Vec<&str> = value
+            .split(", ")
+            .map(|entry| {
+                let (name, dur) = entry.split_once(";dur=").unwrap();
+                let (whole, fraction) = dur.split_once('.').unwrap();
+                assert!(whole.parse::<u64>().is_ok() && fraction.len() == 3, "{entry}");
+                name
+            })
+            .collect();
+        // 挑战请求经过 Cookie 检查与挑战生成，不会到达上游
+        assert!(names.contains(&"cookie") && names.contains(&"challenge"), "{value}");
+        assert!(!names.contains(&"upstream"), "{value}");
+
+        assert_eq!(server_timing(config.clone(), Some("wrong")).await, None);
+        assert_eq!(server_timing(config, None).await, None);
+        assert_eq!(server_timing(Config::default(), Some("")).await, None);
+    }
+
+    fn override_request(uri: &str, token: Option<&str>, difficulty: &str) -> Request {
+        let mut headers = vec![(DEBUG_DIFFICULTY_HEADER, difficulty)];
+        headers.extend(token.map(|token| (DEBUG_TOKEN_HEADER, token)));
+        request(uri, &headers)
+    }
+
+    #[tokio::test]
+    async fn difficulty_override_needs_the_debug_token() {
+        let mut config = Config::default();
+        config.pow.debug_token = "debug-secret".to_string();
+        let state = state(config).await;
+        assert_eq!(decide(&state, &override_request("/", Some("debug-secret"), "7"), false), challenge(7));
+        assert_eq!(decide(&state, &override_request("/", None, "7"), false), challenge(3));
+        assert_eq!(decide(&state, &override_request("/", Some("wrong"), "7"), false), challenge(3));
+        assert_eq!(decide(&state, &override_request("/", Some("debug-secret"), "seven"), false), challenge(3));
+        assert_eq!(decide(&state, &override_request("/", Some("debug-secret"), ""), false), challenge(3));
+
+        // 未配置 debug_token 时一律不生效，空令牌也不匹配
+        let state = self::state(Config::default()).await;
+        assert_eq!(decide(&state, &override_request("/", Some(""), "7"), false), challenge(3));
+    }
+
+    #[tokio::test]
+    async fn difficulty_override_is_clamped() {
+        let mut config = Config::default();
+        config.pow.debug_token = "debug-secret".to_string();
+        let state = state(config).await;
+        assert_eq!(decide(&state, &override_request("/", Some("debug-secret"), " 99 "), false), challenge(10));
+        // 夹到 0 即放行
+        assert_eq!(decide(&state, &override_request("/", Some("debug-secret"), "-4"), false), PASS);
+    }
+
+    #[tokio::test]
+    async fn difficulty_override_wins_over_rule_difficulty() {
+        let mut config = layered_config();
+        config.pow.debug_token = "debug-secret".to_string();
+        config.rules.rule.insert(
+            0,
+            crate::config::RuleConfig {
+                name: Some("api".to_string()),
+                path_prefix: Some("/login/api".to_string()),
+                difficulty_absolute: Some(5),
+                ..crate::config::RuleConfig::default()
+            },
+        );
+        let state = state(config).await;
+        assert_eq!(decide(&state, &override_request("/login/api", Some("debug-secret"), "2"), false), challenge(2));
+        assert_eq!(decide(&state, &override_request("/login/api", None, "2"), false), challenge(5));
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderValue};
+
+/// 计时阶段，输出顺序即 Server-Timing 中的顺序
+#[derive(Debug, Clone, Copy)]
+pub enum TimingStage {
+    Rules,
+    Cookie,
+    Challenge,
+    Upstream,
+}
+
+impl TimingStage {
+    const ALL: [TimingStage; 4] = [
+        TimingStage::Rules,
+        TimingStage::Cookie,
+        TimingStage::Challenge,
+        TimingStage::Upstream,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            TimingStage::Rules => "rules",
+            TimingStage::Cookie => "cookie",
+            TimingStage::Challenge => "challenge",
+            TimingStage::Upstream => "upstream",
+        }
+    }
+}
+
+/// 单个请求各阶段耗时，携带合法调试令牌时由 pow_gate 放进请求扩展，
+/// 下游（proxy_handler）取出后继续记录；同一阶段多次记录时累加
+#[derive(Debug, Default)]
+pub struct RequestTiming {
+    stages: Mutex<[Option<Duration>; TimingStage::ALL.len()]>,
+}
+
+impl RequestTiming {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn record(&self, stage: TimingStage, elapsed: Duration) {
+        if let Ok(mut stages) = self.stages.lock() {
+            let slot = &mut stages[stage as usize];
+            *slot = Some(slot.unwrap_or_default() + elapsed);
+        }
+    }
+
+    /// 执行 f 并把耗时计入 stage；timing 为 None（未开启调试）时只执行 f
+    pub fn measure<T>(timing: Option<&Self>, stage: TimingStage, f: impl FnOnce() -> T) -> T {
+        let Some(timing) = timing else {
+            return f();
+        };
+        let started = Instant::now();
+        let value = f();
+        timing.record(stage, started.elapsed());
+        value
+    }
+
+    /// `rules;dur=0.012, cookie;dur=0.034`，单位毫秒；未经过的阶段不输出
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        let stages = self.stages.lock().ok()?;
+        let entries: Vec<String> = TimingStage::ALL
+            .iter()
+            .filter_map(|stage| {
+                stages[*stage as usize]
+                    .map(|elapsed| format!("{};dur={:.3}", stage.name(), elapsed.as_secs_f64() * 1000.0))
+            })
+            .collect();
+        if entries.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&entries.join(", ")).ok()
+    }
+
+    /// 追加而非覆盖，上游自己的 Server-Timing 一并保留
+    pub fn append_to(&self, headers: &mut HeaderMap) {
+        if let Some(value) = self.header_value() {
+            headers.append("server-timing", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lists_recorded_stages_in_order_in_milliseconds() {
+        let timing = RequestTiming::new();
+        timing.record(TimingStage::Upstream, Duration::from_millis(40));
+        timing.record(TimingStage::Rules, Duration::from_micros(300));
+        timing.record(TimingStage::Upstream, Duration::from_micros(2500));
+        assert_eq!(timing.header_value().unwrap(), "rules;dur=0.300, upstream;dur=42.500");
+    }
+
+    #[test]
+    fn no_header_until_a_stage_is_recorded() {
+        assert!(RequestTiming::new().header_value().is_none());
+        let mut headers = HeaderMap::new();
+        RequestTiming::new().append_to(&mut headers);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn upstream_server_timing_is_kept() {
+        let timing = RequestTiming::new();
+        timing.record(TimingStage::Cookie, Duration::from_micros(12));
+        let mut headers = HeaderMap::new();
+        headers.insert("server-timing", HeaderValue::from_static("db;dur=7"));
+        timing.append_to(&mut headers);
+        let values: Vec<_> = headers.get_all("server-timing").iter().collect();
+        assert_eq!(values, ["db;dur=7", "cookie;dur=0.012"]);
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use ring::hmac;
+
 use crate::storage::Task;
 
 pub const FRAME_MAGIC0: u8 = b'C';
@@ -25,7 +27,13 @@ pub const TLV_IP_HASH: u8 = 0x08;
 pub const TLV_WORKERS: u8 = 0x09;
 pub const TLV_NONCE: u8 = 0x0a;
 pub const TLV_WORKER_TYPE: u8 = 0x0b;
+pub const TLV_CHAIN_LENGTH: u8 = 0x0c;
+pub const TLV_HASH_ALGO: u8 = 0x0d;
+/// pow.signed_tasks：任务的 HMAC-SHA256 标签，随任务下发，verify 请求原样带回
+pub const TLV_SIG: u8 = 0x0e;
 pub const TLV_ERROR: u8 = 0x0f;
+/// pow.bind_commitment：本次下发的承诺，verify 请求原样带回
+pub const TLV_COMMITMENT: u8 = 0x10;
 
 #[derive(Debug, Clone)]
 pub struct BinaryTaskRequest {
@@ -44,6 +52,14 @@ pub struct BinaryTaskResponse {
     pub ip_hash: String,
     pub workers: i32,
     pub worker_type: String,
+    /// 链式挑战长度；为 1 时不编码，旧版页面照常工作
+    pub chain_length: u8,
+    /// pow.hash_algo；默认的 sha256 不编码，旧版页面照常工作
+    pub hash_algo: String,
+    /// pow.signed_tasks 开启时的任务签名
+    pub sig: Option<Vec<u8>>,
+    /// pow.bind_commitment 开启时的下发承诺
+    pub commitment: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -51,11 +67,17 @@ pub struct BinaryVerifyRequest {
     pub task_id: String,
     pub nonce: String,
     pub redirect: String,
+    /// 客户端带回的任务签名；旧版页面不携带
+    pub sig: Option<Vec<u8>>,
+    /// 客户端带回的下发承诺；旧版页面不携带
+    pub commitment: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BinaryVerifyResponse {
     pub redirect: String,
+    /// 可选：本次求解的 bits，供页面做统计；旧版 worker 会忽略该 TLV
+    pub bits: Option<u32>,
 }
 
 pub fn encode_frame(frame_type: u8, payload: Vec<u8>) -> Vec<u8> {
@@ -94,21 +116,111 @@ pub fn deobfuscate_frame(data: &mut [u8], key: &[u8]) {
 }
 
 pub fn encode_task_response(resp: BinaryTaskResponse) -> Vec<u8> {
-    let mut payload = Vec::new();
-    payload = append_tlv(payload, TLV_TASK_ID, resp.task_id.as_bytes());
-    payload = append_tlv(payload, TLV_SEED, resp.seed.as_bytes());
-    payload = append_tlv(payload, TLV_EXP, &(resp.exp as u64).to_be_bytes());
-    payload = append_tlv(payload, TLV_BITS, &(resp.bits as u16).to_be_bytes());
-    payload = append_tlv(payload, TLV_SCOPE, resp.scope.as_bytes());
-    payload = append_tlv(payload, TLV_UA_HASH, resp.ua_hash.as_bytes());
-    payload = append_tlv(payload, TLV_IP_HASH, resp.ip_hash.as_bytes());
+    let mut payload = encode_task_fields(
+        &resp.task_id,
+        &resp.seed,
+        resp.exp,
+        resp.bits,
+        &resp.scope,
+        &resp.ua_hash,
+        &resp.ip_hash,
+        resp.chain_length,
+    );
     payload = append_tlv(payload, TLV_WORKERS, &[resp.workers as u8]);
     if !resp.worker_type.is_empty() {
         payload = append_tlv(payload, TLV_WORKER_TYPE, resp.worker_type.as_bytes());
     }
+    if !resp.hash_algo.is_empty() && resp.hash_algo != "sha256" {
+        payload = append_tlv(payload, TLV_HASH_ALGO, resp.hash_algo.as_bytes());
+    }
+    if let Some(sig) = &resp.sig {
+        payload = append_tlv(payload, TLV_SIG, sig);
+    }
+    if let Some(commitment) = &resp.commitment {
+        payload = append_tlv(payload, TLV_COMMITMENT, commitment);
+    }
+    payload
+}
+
+/// 由任务本身决定的 TLV，即签名覆盖的部分；workers / worker_type / hash_algo 只影响求解方式，
+/// 各下发入口取值不同，不参与签名，校验时只凭 task store 里的任务即可重算
+#[allow(clippy::too_many_arguments)]
+fn encode_task_fields(
+    task_id: &str,
+    seed: &str,
+    exp: i64,
+    bits: i32,
+    scope: &str,
+    ua_hash: &str,
+    ip_hash: &str,
+    chain_length: u8,
+) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload = append_tlv(payload, TLV_TASK_ID, task_id.as_bytes());
+    payload = append_tlv(payload, TLV_SEED, seed.as_bytes());
+    payload = append_tlv(payload, TLV_EXP, &(exp as u64).to_be_bytes());
+    payload = append_tlv(payload, TLV_BITS, &(bits as u16).to_be_bytes());
+    payload = append_tlv(payload, TLV_SCOPE, scope.as_bytes());
+    payload = append_tlv(payload, TLV_UA_HASH, ua_hash.as_bytes());
+    payload = append_tlv(payload, TLV_IP_HASH, ip_hash.as_bytes());
+    if chain_length > 1 {
+        payload = append_tlv(payload, TLV_CHAIN_LENGTH, &[chain_length]);
+    }
     payload
 }
 
+fn task_chain_length(task: &Task) -> u8 {
+    task.chain_length.min(u8::MAX as u32) as u8
+}
+
+/// 任务签名：HMAC-SHA256(key, 任务字段 TLV)。
+/// 校验时按 task store 里保存的任务重算，帧中的任务字段本身从不被信任，客户端改了也只会解错题；
+/// 签名证明 verify 带回的是本服务为这个 task_id 下发的原帧。nonce 是求解结果、redirect 由服务端清理，
+/// 两者在下发时还不存在，不在签名范围内；同一 nonce 无法挪到别的任务上，因为任务按 task_id 一次性消费、签名绑定 task_id
+pub fn sign_task(key: &hmac::Key, task: &Task) -> Vec<u8> {
+    let message = encode_task_fields(
+        &task.task_id.0,
+        &task.seed.0,
+        task.exp,
+        task.bits as i32,
+        &task.scope.0,
+        &task.ua_hash.0,
+        &task.ip_hash.0,
+        task_chain_length(task),
+    );
+    hmac::sign(key, &message).as_ref().to_vec()
+}
+
+/// 下发承诺：HMAC-SHA256(key, task_id | 随机数)；任务没有随机数（未开启或验证码任务）时为 None
+pub fn task_commitment(key: &hmac::Key, task: &Task) -> Option<Vec<u8>> {
+    let nonce = task.commitment_nonce.as_deref()?;
+    let message = format!("{}|{}", task.task_id.0, nonce);
+    Some(hmac::sign(key, message.as_bytes()).as_ref().to_vec())
+}
+
+pub fn verify_task_commitment(key: &hmac::Key, task: &Task, commitment: &[u8]) -> bool {
+    let Some(nonce) = task.commitment_nonce.as_deref() else {
+        return false;
+    };
+    let message = format!("{}|{}", task.task_id.0, nonce);
+    hmac::verify(key, message.as_bytes(), commitment).is_ok()
+}
+
+/// 按 task store 中的任务重算签名并做常量时间比较
+pub fn verify_task_signature(key: &hmac::Key, task: &Task, sig: &[u8]) -> bool {
+    let message = encode_task_fields(
+        &task.task_id.0,
+        &task.seed.0,
+        task.exp,
+        task.bits as i32,
+        &task.scope.0,
+        &task.ua_hash.0,
+        &task.ip_hash.0,
+        task_chain_length(task),
+    );
+    hmac::verify(key, &message, sig).is_ok()
+}
+
 pub fn decode_task_request(payload: &[u8]) -> anyhow::Result<BinaryTaskRequest> {
     let fields = parse_tlv(payload)?;
     let redirect = fields
@@ -132,18 +244,42 @@ pub fn decode_verify_request(payload: &[u8]) -> anyhow::Result<BinaryVerifyReque
         .get(&TLV_REDIRECT)
         .map(|v| String::from_utf8_lossy(v).to_string())
         .unwrap_or_default();
+    let sig = fields.get(&TLV_SIG).map(|v| v.to_vec());
+    let commitment = fields.get(&TLV_COMMITMENT).map(|v| v.to_vec());
     if task_id.is_empty() || nonce.is_empty() {
         anyhow::bail!("missing fields");
     }
+    if !nonce.split(',').all(is_canonical_nonce) {
+        anyhow::bail!("non-canonical nonce");
+    }
     Ok(BinaryVerifyRequest {
         task_id,
         nonce,
         redirect,
+        sig,
+        commitment,
     })
 }
 
+/// nonce 定义为 u32 的规范十进制形式（与 wasm 的 write_u32_decimal、JS worker 的 String(n >>> 0) 一致）：
+/// 只含数字、无前导零（"0" 除外）、不超过 u32::MAX。`007` 与 `7` 哈希结果不同，
+/// 非规范形式一律在解码阶段拒绝，而不是在哈希比对时静默失败
+fn is_canonical_nonce(nonce: &str) -> bool {
+    if nonce.is_empty() || nonce.len() > 10 || !nonce.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    if nonce.len() > 1 && nonce.starts_with('0') {
+        return false;
+    }
+    nonce.parse::<u32>().is_ok()
+}
+
 pub fn encode_verify_response(resp: BinaryVerifyResponse) -> Vec<u8> {
-    append_tlv(Vec::new(), TLV_REDIRECT, resp.redirect.as_bytes())
+    let mut payload = append_tlv(Vec::new(), TLV_REDIRECT, resp.redirect.as_bytes());
+    if let Some(bits) = resp.bits {
+        payload = append_tlv(payload, TLV_BITS, &(bits as u16).to_be_bytes());
+    }
+    payload
 }
 
 pub fn encode_error_frame(message: &str) -> Vec<u8> {
@@ -155,6 +291,9 @@ pub fn encode_task_response_frame(
     task: &Task,
     workers: i32,
     worker_type: &str,
+    hash_algo: &str,
+    sign_key: Option<&hmac::Key>,
+    commitment_key: Option<&hmac::Key>,
 ) -> anyhow::Result<Vec<u8>> {
     let resp = BinaryTaskResponse {
         task_id: task.task_id.0.to_string(),
@@ -166,6 +305,10 @@ pub fn encode_task_response_frame(
         ip_hash: task.ip_hash.0.clone(),
         workers,
         worker_type: worker_type.to_string(),
+        chain_length: task_chain_length(task),
+        hash_algo: hash_algo.to_string(),
+        sig: sign_key.map(|key| sign_task(key, task)),
+        commitment: commitment_key.and_then(|key| task_commitment(key, task)),
     };
     let payload = encode_task_response(resp);
     let mut frame = encode_frame(FRAME_TYPE_TASK_RESPONSE, payload);
@@ -183,7 +326,7 @@ fn append_tlv(mut buf: Vec<u8>, t: u8, v: &[u8]) -> Vec<u8> {
     buf
 }
 
-fn parse_tlv<'a>(payload: &'a [u8]) -> anyhow::Result<HashMap<u8, &'a [u8]>> {
+fn parse_tlv(payload: &[u8]) -> anyhow::Result<HashMap<u8, &[u8]>> {
     let mut fields = HashMap::new();
     let mut idx = 0usize;
     while idx < payload.len() {
@@ -201,3 +344,109 @@ fn parse_tlv<'a>(payload: &'a [u8]) -> anyhow::Result<HashMap<u8, &'a [u8]>> {
     }
     Ok(fields)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify_response_fields(resp: BinaryVerifyResponse) -> HashMap<u8, Vec<u8>> {
+        let frame = encode_frame(FRAME_TYPE_VERIFY_RESPONSE, encode_verify_response(resp));
+        let (frame_type, payload) = decode_frame(&frame).unwrap();
+        assert_eq!(frame_type, FRAME_TYPE_VERIFY_RESPONSE);
+        parse_tlv(payload)
+            .unwrap()
+            .into_iter()
+            .map(|(t, v)| (t, v.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn verify_response_round_trips_without_bits() {
+        let fields = verify_response_fields(BinaryVerifyResponse { redirect: "/home?a=1".to_string(), bits: None });
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[&TLV_REDIRECT], b"/home?a=1");
+    }
+
+    #[test]
+    fn verify_response_round_trips_with_bits() {
+        let fields = verify_response_fields(BinaryVerifyResponse { redirect: "/".to_string(), bits: Some(20) });
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[&TLV_REDIRECT], b"/");
+        assert_eq!(fields[&TLV_BITS], 20u16.to_be_bytes());
+    }
+
+    fn verify_payload(nonce: &str) -> Vec<u8> {
+        let payload = append_tlv(Vec::new(), TLV_TASK_ID, b"task-1");
+        let payload = append_tlv(payload, TLV_NONCE, nonce.as_bytes());
+        append_tlv(payload, TLV_REDIRECT, b"/")
+    }
+
+    #[test]
+    fn canonical_nonces_are_accepted() {
+        for nonce in ["0", "7", "4294967295", "12,0,99"] {
+            let req = decode_verify_request(&verify_payload(nonce)).unwrap();
+            assert_eq!(req.nonce, nonce);
+        }
+    }
+
+    #[test]
+    fn non_canonical_nonces_are_rejected() {
+        for nonce in ["007", "00", "+7", "-1", " 7", "7 ", "0x1f", "4294967296", "99999999999", "12345678901234567890", "7,007", "7,", "٣"] {
+            let err = decode_verify_request(&verify_payload(nonce));
+            assert!(err.is_err(), "{nonce:?} accepted");
+        }
+    }
+
+    fn signed_task() -> Task {
+        use crate::storage::{IpHash, Scope, Seed, TaskId, UaHash};
+        Task {
+            task_id: TaskId::from("task-1"),
+            seed: Seed("seed".to_string()),
+            bits: 12,
+            exp: 1_700_000_300,
+            scope: Scope("example.com".to_string()),
+            ua_hash: UaHash("ua".to_string()),
+            ip_hash: IpHash(String::new()),
+            issued_at_ms: 0,
+            chain_length: 1,
+            captcha_answer: None,
+            commitment_nonce: None,
+        }
+    }
+
+    #[test]
+    fn task_signature_round_trips() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let task = signed_task();
+        let sig = sign_task(&key, &task);
+        assert_eq!(sig.len(), 32);
+        assert!(verify_task_signature(&key, &task, &sig));
+    }
+
+    #[test]
+    fn task_signature_detects_tampering() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let task = signed_task();
+        let sig = sign_task(&key, &task);
+
+        let mut flipped = sig.clone();
+        flipped[0] ^= 1;
+        assert!(!verify_task_signature(&key, &task, &flipped));
+        assert!(!verify_task_signature(&key, &task, &sig[..31]));
+        assert!(!verify_task_signature(&key, &task, &[]));
+        let other_key = hmac::Key::new(hmac::HMAC_SHA256, b"other");
+        assert!(!verify_task_signature(&other_key, &task, &sig));
+
+        // 签名覆盖每个下发字段：任一字段不同都对不上
+        let variants = [
+            Task { bits: 11, ..signed_task() },
+            Task { exp: task.exp + 1, ..signed_task() },
+            Task { seed: crate::storage::Seed("seed2".to_string()), ..signed_task() },
+            Task { task_id: crate::storage::TaskId::from("task-2"), ..signed_task() },
+            Task { chain_length: 2, ..signed_task() },
+        ];
+        for variant in variants {
+            assert!(!verify_task_signature(&key, &variant, &sig), "{variant:?}");
+        }
+    }
+}
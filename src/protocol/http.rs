@@ -44,12 +44,9 @@ impl HeaderMapExt for HeaderMap {
     where
         N: header::AsHeaderName,
     {
+        // 只取第一个地址，去掉方括号与端口后按规范形式返回；无法解析时视为没有该头
         let value = self.get_str(name)?;
-        let first = value.split(',').next()?.trim();
-        if first.is_empty() {
-            None
-        } else {
-            Some(first.to_string())
-        }
+        let first = value.split(',').next()?;
+        crate::ip_source::ip::parse_forwarded_ip(first).map(|ip| ip.to_string())
     }
 }
@@ -0,0 +1,153 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+/// 上游请求失败的分类，决定返回给客户端的状态码以及日志中的 reason 标签
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyError {
+    /// 上游主机名解析失败
+    Dns,
+    /// 上游拒绝连接（端口未监听等）
+    ConnectionRefused,
+    /// 其他连接阶段错误（网络不可达、连接被重置等）
+    Connect,
+    /// 连接或等待响应超时
+    Timeout,
+    /// 连接建立后的协议/读取错误
+    Upstream,
+}
+
+impl ProxyError {
+    /// 全部分类，按声明顺序；指标按此顺序输出
+    pub const ALL: [ProxyError; 5] = [
+        ProxyError::Dns,
+        ProxyError::ConnectionRefused,
+        ProxyError::Connect,
+        ProxyError::Timeout,
+        ProxyError::Upstream,
+    ];
+
+    pub fn from_client_error(err: &hyper_util::client::legacy::Error) -> Self {
+        if let Some(io_err) = find_io_error(err) {
+            match io_err.kind() {
+                io::ErrorKind::ConnectionRefused => return ProxyError::ConnectionRefused,
+                io::ErrorKind::TimedOut => return ProxyError::Timeout,
+                _ => {}
+            }
+        }
+        if err.is_connect() {
+            if error_chain_contains(err, "dns error") {
+                return ProxyError::Dns;
+            }
+            if error_chain_contains(err, "timed out") {
+                return ProxyError::Timeout;
+            }
+            return ProxyError::Connect;
+        }
+        ProxyError::Upstream
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProxyError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::Dns
+            | ProxyError::ConnectionRefused
+            | ProxyError::Connect
+            | ProxyError::Upstream => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ProxyError::Dns => "dns",
+            ProxyError::ConnectionRefused => "connection_refused",
+            ProxyError::Connect => "connect",
+            ProxyError::Timeout => "timeout",
+            ProxyError::Upstream => "upstream",
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> Response {
+        self.status().into_response()
+    }
+}
+
+fn find_io_error<'a>(err: &'a (dyn StdError + 'static)) -> Option<&'a io::Error> {
+    let mut current: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(e) = current {
+        if let Some(io_err) = e.downcast_ref::<io::Error>() {
+            return Some(io_err);
+        }
+        current = e.source();
+    }
+    None
+}
+
+fn error_chain_contains(err: &(dyn StdError + 'static), needle: &str) -> bool {
+    let mut current: Option<&(dyn StdError + 'static)> = Some(err);
+    while let Some(e) = current {
+        if e.to_string().to_ascii_lowercase().contains(needle) {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+
+    async fn classify(uri: &str) -> ProxyError {
+        let client: Client<_, Body> = Client::builder(TokioExecutor::new()).build_http();
+        let err = client.get(uri.parse().unwrap()).await.unwrap_err();
+        ProxyError::from_client_error(&err)
+    }
+
+    #[tokio::test]
+    async fn closed_port_is_connection_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert_eq!(classify(&format!("http://{addr}/")).await, ProxyError::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn unresolvable_host_is_dns() {
+        assert_eq!(classify("http://cowcat-upstream.invalid/").await, ProxyError::Dns);
+    }
+
+    #[tokio::test]
+    async fn connection_closed_before_response_is_upstream() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            drop(stream);
+        });
+        assert_eq!(classify(&format!("http://{addr}/")).await, ProxyError::Upstream);
+    }
+
+    #[test]
+    fn statuses_and_reasons() {
+        let mapped: Vec<_> = ProxyError::ALL.iter().map(|err| (err.reason(), err.status().as_u16())).collect();
+        assert_eq!(
+            mapped,
+            [("dns", 502), ("connection_refused", 502), ("connect", 502), ("timeout", 504), ("upstream", 502)]
+        );
+    }
+}
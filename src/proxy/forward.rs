@@ -2,35 +2,270 @@ use std::sync::Arc;
 
 use axum::body::Body;
 use axum::extract::State;
-use axum::http::{header, HeaderMap, Request, Response, StatusCode, Uri};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, Version};
 use axum::response::IntoResponse;
-use crate::handlers::pow::POW_PREFIX;
-use crate::middleware::pow::PowVerified;
+use http_body_util::BodyExt;
+use hyper::body::{Body as _, Incoming};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use crate::config::ResponseHeadersConfig;
+use crate::handlers::pow::is_pow_path;
+use crate::proxy::error::ProxyError;
+use crate::proxy::micro_cache::MicroCache;
+use crate::middleware::pow::{PowVerified, RequestId};
+use crate::middleware::timing::{RequestTiming, TimingStage};
 use crate::state::{AppState, HostProxyTarget, ProxyTarget};
 
 pub async fn proxy_handler(
     State(state): State<Arc<AppState>>,
     mut req: Request<Body>,
 ) -> impl IntoResponse {
-    if req.uri().path().starts_with(POW_PREFIX) {
+    if is_pow_path(req.uri().path()) {
         return StatusCode::NOT_FOUND.into_response();
     }
 
-    let target = resolve_proxy_target(&state, &req);
-    *req.uri_mut() = build_target_uri(&target.uri, req.uri());
-    rewrite_headers(req.headers_mut(), target);
+    let Some((targets, start)) = resolve_proxy_targets(&state, &req) else {
+        tracing::debug!(host = ?req.headers().get(header::HOST), "unknown host rejected (proxy.strict_host)");
+        return StatusCode::MISDIRECTED_REQUEST.into_response();
+    };
+    let micro_cache = state.micro_cache.as_deref();
+    // 缓存键固定取第一个候选，轮询不会把同一资源拆成多个条目
+    let cache_key = micro_cache.and_then(|cache| cache.key_for(&req, &targets[0].uri));
+    // 领头回源期间持有，响应头表明不可缓存、或已写入缓存后立即释放，等待者随即并发回源或命中
+    let mut flight = None;
+    if let (Some(cache), Some(key)) = (micro_cache, &cache_key) {
+        if let Some(hit) = cache.get(key).await {
+            tracing::debug!("micro cache hit");
+            state.metrics.micro_cache_lookup(true);
+            return state.response_headers.applied(hit.to_response());
+        }
+        let pending = cache.begin_flight(key).await;
+        if let Some(hit) = cache.get_after_flight(key).await {
+            tracing::debug!("micro cache hit after coalesced fetch");
+            state.metrics.micro_cache_lookup(true);
+            return state.response_headers.applied(hit.to_response());
+        }
+        state.metrics.micro_cache_lookup(false);
+        flight = Some(pending);
+    }
 
-    match state.proxy_client.request(req).await {
-        Ok(resp) => {
+
+    let client_version = req.version();
+    // 上游连接只说 HTTP/1.x；TLS 协商出 h2 的入站请求按 HTTP/1.1 转发，Host 由 rewrite_headers 补上
+    if matches!(client_version, Version::HTTP_2 | Version::HTTP_3) {
+        *req.version_mut() = Version::HTTP_11;
+    }
+    propagate_request_id(&mut req);
+
+    let timing = req.extensions().get::<Arc<RequestTiming>>().cloned();
+    let started = Instant::now();
+    // 连接失败时请求没有到达上游，可以换下一个重发；但请求体已交给连接消费，只有确定为空时才能重发
+    let attempts = if req.body().size_hint().exact() == Some(0) { targets.len() } else { 1 };
+    let mut attempt = 0;
+    let (result, span) = loop {
+        let target = &targets[(start + attempt) % targets.len()];
+        let retry = (attempt + 1 < attempts).then(|| replay_request(&req));
+        let (result, span) = send_upstream(&state, target, req).await;
+        if let (Some(Err(err)), Some(next)) = (&result, retry) {
+            if err.is_connect() {
+                let proxy_err = ProxyError::from_client_error(err);
+                span.record("status", proxy_err.status().as_u16());
+                state.metrics.proxy_error(proxy_err);
+                tracing::warn!(
+                    error = %err,
+                    reason = proxy_err.reason(),
+                    upstream_host = %target.host_value.to_str().unwrap_or_default(),
+                    "upstream unreachable, trying next target"
+                );
+                req = next;
+                attempt += 1;
+                continue;
+            }
+        }
+        break (result, span);
+    };
+    let request_timeout_ms = state.config.proxy.request_timeout_ms;
+    if let Some(timing) = &timing {
+        timing.record(TimingStage::Upstream, started.elapsed());
+    }
+    match result {
+        Some(Ok(mut resp)) => {
+            normalize_upstream_response(&mut resp, client_version);
             let status = resp.status();
+            span.record("status", status.as_u16());
+            state.metrics.proxy_forwarded();
             tracing::debug!(status = %status, "proxy response");
+            if let (Some(cache), Some(key)) = (micro_cache, cache_key) {
+                if let Some(ttl) = cache.cacheable_ttl(status, resp.headers()) {
+                    let resp = store_in_micro_cache(cache, key, ttl, resp).await;
+                    drop(flight);
+                    return state.response_headers.applied(resp);
+                }
+            }
+            drop(flight);
             let (parts, body) = resp.into_parts();
-            Response::from_parts(parts, Body::new(body))
+            state.response_headers.applied(Response::from_parts(parts, Body::new(body)))
+        }
+        Some(Err(err)) => {
+            let proxy_err = ProxyError::from_client_error(&err);
+            span.record("status", proxy_err.status().as_u16());
+            state.metrics.proxy_error(proxy_err);
+            tracing::warn!(
+                error = %err,
+                reason = proxy_err.reason(),
+                status = %proxy_err.status(),
+                "proxy request failed"
+            );
+            proxy_err.into_response()
+        }
+        None => {
+            let proxy_err = ProxyError::Timeout;
+            span.record("status", proxy_err.status().as_u16());
+            state.metrics.proxy_error(proxy_err);
+            tracing::warn!(
+                timeout_ms = request_timeout_ms,
+                reason = proxy_err.reason(),
+                status = %proxy_err.status(),
+                "proxy request timed out"
+            );
+            proxy_err.into_response()
         }
+    }
+}
+
+/// 向一个上游发出请求；结果为 None 表示 request_timeout_ms 内没有等到响应头
+async fn send_upstream(
+    state: &AppState,
+    target: &ProxyTarget,
+    mut req: Request<Body>,
+) -> (Option<Result<Response<Incoming>, hyper_util::client::legacy::Error>>, tracing::Span) {
+    *req.uri_mut() = build_target_uri(&target.uri, req.uri());
+    rewrite_headers(req.headers_mut(), target, state.forwarded_port.as_ref());
+
+    let span = tracing::info_span!(
+        "upstream",
+        upstream_host = %target.host_value.to_str().unwrap_or_default(),
+        status = tracing::field::Empty,
+    );
+    span.in_scope(|| crate::telemetry::inject_trace_context(req.headers_mut()));
+
+    let request = state.proxy_client.request(req).instrument(span.clone());
+    let request_timeout_ms = state.config.proxy.request_timeout_ms;
+    let result = if request_timeout_ms == 0 {
+        Some(request.await)
+    } else {
+        tokio::time::timeout(Duration::from_millis(request_timeout_ms), request).await.ok()
+    };
+    (result, span)
+}
+
+/// 复制一个尚未改写、请求体为空的请求，连接失败后发往下一个上游；扩展不需要带上
+fn replay_request(req: &Request<Body>) -> Request<Body> {
+    let mut replay = Request::new(Body::empty());
+    *replay.method_mut() = req.method().clone();
+    *replay.uri_mut() = req.uri().clone();
+    *replay.version_mut() = req.version();
+    *replay.headers_mut() = req.headers().clone();
+    replay
+}
+
+async fn store_in_micro_cache(
+    cache: &MicroCache,
+    key: String,
+    ttl: Duration,
+    resp: Response<Incoming>,
+) -> Response<Body> {
+    let (parts, body) = resp.into_parts();
+    let bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
         Err(err) => {
-            tracing::debug!(error = %err, "proxy request failed");
-            StatusCode::BAD_GATEWAY.into_response()
+            tracing::warn!(error = %err, "failed to read upstream body for micro cache");
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+    };
+    cache
+        .insert(key, parts.status, parts.headers.clone(), bytes.clone(), ttl)
+        .await;
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// 逐跳头，只对上游这一跳有意义，不能原样转给客户端
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// 上游的协议版本与连接语义不外泄：响应版本改为客户端请求的版本（上游说 HTTP/1.0 时客户端仍拿到 1.1），
+/// 并移除逐跳头及 Connection 中列出的头，分块/长度与 keep-alive 由本服务与客户端的连接自行决定。
+/// 101 保留 Upgrade/Connection
+fn normalize_upstream_response<B>(resp: &mut Response<B>, client_version: Version) {
+    *resp.version_mut() = client_version;
+    if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+        return;
+    }
+    let headers = resp.headers_mut();
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(*name);
+    }
+}
+
+/// proxy.response_headers 解析后的形式：先删除再添加
+pub struct ResponseHeaderEdits {
+    add: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+}
+
+impl ResponseHeaderEdits {
+    pub fn from_config(cfg: &ResponseHeadersConfig) -> anyhow::Result<Self> {
+        let add = cfg
+            .add
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.trim().as_bytes())
+                    .map_err(|_| anyhow::anyhow!("invalid header name in proxy.response_headers.add: {name}"))?;
+                if HOP_BY_HOP_HEADERS.contains(&name.as_str()) {
+                    anyhow::bail!("proxy.response_headers.add cannot set hop-by-hop header {name}");
+                }
+                let value = HeaderValue::from_str(value.trim())
+                    .map_err(|_| anyhow::anyhow!("invalid value for {name} in proxy.response_headers.add"))?;
+                Ok((name, value))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let remove = cfg
+            .remove
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.trim().as_bytes())
+                    .map_err(|_| anyhow::anyhow!("invalid header name in proxy.response_headers.remove: {name}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { add, remove })
+    }
+
+    pub fn applied(&self, mut resp: Response<Body>) -> Response<Body> {
+        let headers = resp.headers_mut();
+        for name in &self.remove {
+            headers.remove(name);
         }
+        for (name, value) in &self.add {
+            headers.insert(name.clone(), value.clone());
+        }
+        resp
     }
 }
 
@@ -41,7 +276,7 @@ pub fn build_target_uri(target: &Uri, original: &Uri) -> Uri {
     Uri::from_parts(parts).unwrap_or_else(|_| target.clone())
 }
 
-pub fn rewrite_headers(headers: &mut HeaderMap, target: &ProxyTarget) {
+pub fn rewrite_headers(headers: &mut HeaderMap, target: &ProxyTarget, forwarded_port: Option<&header::HeaderValue>) {
     headers.insert(header::HOST, target.host_value.clone());
     headers
         .entry(header::HeaderName::from_static("x-forwarded-host"))
@@ -49,24 +284,51 @@ pub fn rewrite_headers(headers: &mut HeaderMap, target: &ProxyTarget) {
     headers
         .entry(header::HeaderName::from_static("x-forwarded-proto"))
         .or_insert_with(|| target.x_forwarded_proto.clone());
+    if let Some(port) = forwarded_port {
+        headers
+            .entry(header::HeaderName::from_static("x-forwarded-port"))
+            .or_insert_with(|| port.clone());
+    }
 }
 
-fn resolve_proxy_target<'a>(state: &'a AppState, req: &Request<Body>) -> &'a ProxyTarget {
-    if req.extensions().get::<PowVerified>().is_none() {
-        return &state.proxy_target;
-    }
-    let host = match req.headers().get(header::HOST).and_then(|v| v.to_str().ok()) {
-        Some(value) => value,
-        None => return &state.proxy_target,
+/// 把 pow_gate 生成的请求 ID 传给上游，便于跨服务关联日志
+fn propagate_request_id(req: &mut Request<Body>) {
+    let Some(id) = req.extensions().get::<RequestId>() else {
+        return;
     };
-    let normalized = normalize_host(host);
-    if normalized.is_empty() {
-        return &state.proxy_target;
+    if let Ok(value) = header::HeaderValue::from_str(&id.0) {
+        req.headers_mut()
+            .entry(header::HeaderName::from_static("x-request-id"))
+            .or_insert(value);
+    }
+}
+
+/// 选择上游，返回候选列表与本次的起点：host_rule 命中时只有一个，否则为默认上游池的轮询位置。
+/// proxy.strict_host 下 Host 未知时返回 None，由调用方拒绝
+fn resolve_proxy_targets<'a>(state: &'a AppState, req: &Request<Body>) -> Option<(&'a [ProxyTarget], usize)> {
+    let normalized = request_host(req).map(normalize_host).unwrap_or_default();
+    if state.config.proxy.strict_host && !is_known_host(state, &normalized) {
+        return None;
     }
-    match find_host_target(&state.proxy_host_targets, &normalized) {
-        Some(target) => target,
-        None => &state.proxy_target,
+    if req.extensions().get::<PowVerified>().is_some() && !normalized.is_empty() {
+        if let Some(target) = find_host_target(&state.proxy_host_targets, &normalized) {
+            return Some((std::slice::from_ref(target), 0));
+        }
     }
+    Some((state.upstreams.targets(), state.upstreams.next_start()))
+}
+
+/// HTTP/1 取 Host 头；HTTP/2 请求可能只有 :authority，此时取 URI 中的 authority
+fn request_host(req: &Request<Body>) -> Option<&str> {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+}
+
+fn is_known_host(state: &AppState, host: &str) -> bool {
+    !host.is_empty()
+        && (state.allowed_hosts.contains(host) || find_host_target(&state.proxy_host_targets, host).is_some())
 }
 
 fn find_host_target<'a>(
@@ -89,3 +351,387 @@ fn normalize_host(raw: &str) -> String {
     }
     trimmed.to_ascii_lowercase()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    /// 在本地随机端口上启动上游，返回 http://addr
+    async fn stub_upstream(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    /// 把指定请求头原样放进响应体的上游
+    async fn echo_header_upstream(name: &'static str) -> String {
+        stub_upstream(axum::Router::new().fallback(move |headers: HeaderMap| async move {
+            headers.get(name).map(|v| v.to_str().unwrap().to_string()).unwrap_or_else(|| "-".to_string())
+        }))
+        .await
+    }
+
+    async fn proxy(config: Config, req: Request<Body>) -> Response<Body> {
+        let state = Arc::new(AppState::new(config).await.unwrap());
+        proxy_handler(State(state), req).await.into_response()
+    }
+
+    async fn body_text(resp: Response<Body>) -> String {
+        String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn forwarded_port_carries_the_listen_port() {
+        let mut config = Config::default();
+        config.proxy.target = echo_header_upstream("x-forwarded-port").await;
+        config.server.listen = "0.0.0.0:8443".to_string();
+        config.proxy.emit_forwarded_port = true;
+        assert_eq!(body_text(proxy(config.clone(), get("/")).await).await, "8443");
+
+        config.proxy.emit_forwarded_port = false;
+        assert_eq!(body_text(proxy(config, get("/")).await).await, "-");
+    }
+
+    async fn named_upstream(name: &'static str) -> String {
+        stub_upstream(axum::Router::new().fallback(move || async move { name })).await
+    }
+
+    fn with_host(host: &str) -> Request<Body> {
+        Request::builder().uri("/").header(header::HOST, host).body(Body::empty()).unwrap()
+    }
+
+    async fn strict_host_config() -> Config {
+        let mut config = Config::default();
+        config.proxy.target = named_upstream("default").await;
+        config.proxy.strict_host = true;
+        config.proxy.allowed_hosts = vec!["a.example".to_string()];
+        config.proxy.host_rule = vec![crate::config::ProxyHostRule {
+            host: "b.example".to_string(),
+            target: named_upstream("b").await,
+            page: None,
+        }];
+        config
+    }
+
+    #[tokio::test]
+    async fn strict_host_passes_known_hosts() {
+        let state = Arc::new(AppState::new(strict_host_config().await).await.unwrap());
+        for host in ["a.example", "A.Example:8080", "b.example"] {
+            let resp = proxy_handler(State(state.clone()), with_host(host)).await.into_response();
+            assert_eq!(resp.status(), StatusCode::OK, "{host}");
+            assert_eq!(body_text(resp).await, "default", "{host}");
+        }
+        let mut verified = with_host("b.example");
+        verified.extensions_mut().insert(PowVerified);
+        assert_eq!(body_text(proxy_handler(State(state), verified).await.into_response()).await, "b");
+    }
+
+    #[tokio::test]
+    async fn strict_host_rejects_unknown_and_missing_hosts() {
+        let state = Arc::new(AppState::new(strict_host_config().await).await.unwrap());
+        for req in [with_host("evil.example"), with_host("a.example.evil"), get("/")] {
+            let resp = proxy_handler(State(state.clone()), req).await.into_response();
+            assert_eq!(resp.status(), StatusCode::MISDIRECTED_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_host_falls_back_to_the_default_upstream_when_not_strict() {
+        let mut config = strict_host_config().await;
+        config.proxy.strict_host = false;
+        assert_eq!(body_text(proxy(config, with_host("evil.example")).await).await, "default");
+    }
+
+    /// 说 HTTP/1.0 的上游：不带 Content-Length，靠关闭连接结束响应体，并附带逐跳头
+    async fn http10_upstream() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let response = "HTTP/1.0 200 OK\r\n\
+                        Content-Type: text/plain\r\n\
+                        Connection: close, X-Hop\r\n\
+                        X-Hop: secret\r\n\
+                        Keep-Alive: timeout=5\r\n\
+                        X-End-To-End: kept\r\n\
+                        \r\n\
+                        hello from 1.0";
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                    stream.shutdown().await.unwrap();
+                });
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn http10_upstream_is_answered_in_the_client_version() {
+        let mut config = Config::default();
+        config.proxy.target = http10_upstream().await;
+        for version in [Version::HTTP_11, Version::HTTP_2] {
+            let req = Request::builder().uri("/").version(version).body(Body::empty()).unwrap();
+            let resp = proxy(config.clone(), req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.version(), version);
+            for hop in ["connection", "x-hop", "keep-alive"] {
+                assert!(resp.headers().get(hop).is_none(), "{hop} leaked");
+            }
+            assert_eq!(resp.headers()["x-end-to-end"], "kept");
+            assert_eq!(body_text(resp).await, "hello from 1.0");
+        }
+    }
+
+    #[tokio::test]
+    async fn http10_upstream_gives_a_reusable_http11_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let mut config = Config::default();
+        config.proxy.target = http10_upstream().await;
+        let state = Arc::new(AppState::new(config).await.unwrap());
+        let app = axum::Router::new().fallback(proxy_handler).with_state(state);
+        let proxy_addr = stub_upstream(app).await.trim_start_matches("http://").to_string();
+
+        let mut client = tokio::net::TcpStream::connect(&proxy_addr).await.unwrap();
+        for _ in 0..2 {
+            client.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").await.unwrap();
+            let mut response = Vec::new();
+            let mut buf = [0u8; 1024];
+            // 分块编码以 0\r\n\r\n 结束；同一连接还能发第二个请求，说明没有被上游的 close 带着关闭
+            while !response.ends_with(b"0\r\n\r\n") {
+                let n = client.read(&mut buf).await.unwrap();
+                assert!(n > 0, "connection closed: {}", String::from_utf8_lossy(&response));
+                response.extend_from_slice(&buf[..n]);
+            }
+            let text = String::from_utf8(response).unwrap().to_ascii_lowercase();
+            assert!(text.starts_with("http/1.1 200 ok\r\n"), "{text}");
+            assert!(text.contains("transfer-encoding: chunked\r\n"), "{text}");
+            assert!(!text.contains("connection:") && !text.contains("x-hop") && !text.contains("keep-alive"), "{text}");
+            assert!(text.contains("hello from 1.0"), "{text}");
+        }
+    }
+
+    /// 等 delay 之后才回应的上游
+    async fn slow_upstream(delay: Duration) -> String {
+        stub_upstream(axum::Router::new().fallback(move || async move {
+            tokio::time::sleep(delay).await;
+            "slow"
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn slow_upstream_is_504_after_request_timeout() {
+        let mut config = Config::default();
+        config.proxy.target = slow_upstream(Duration::from_secs(5)).await;
+        config.proxy.request_timeout_ms = 100;
+        let started = Instant::now();
+        let resp = proxy(config, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn upstream_answering_within_the_timeout_is_forwarded() {
+        let mut config = Config::default();
+        config.proxy.target = slow_upstream(Duration::from_millis(50)).await;
+        config.proxy.request_timeout_ms = 2000;
+        let resp = proxy(config.clone(), get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_text(resp).await, "slow");
+
+        // 0 为不限制
+        config.proxy.request_timeout_ms = 0;
+        assert_eq!(proxy(config, get("/")).await.status(), StatusCode::OK);
+    }
+
+    /// 带 Server、X-Powered-By 与自有 X-Frame-Options 的上游
+    async fn leaky_upstream() -> String {
+        stub_upstream(axum::Router::new().fallback(|| async {
+            (
+                [
+                    (header::SERVER, "nginx/1.2.3"),
+                    (HeaderName::from_static("x-powered-by"), "PHP/8.1"),
+                    (HeaderName::from_static("x-frame-options"), "ALLOWALL"),
+                    (header::CONTENT_TYPE, "text/plain"),
+                ],
+                "page",
+            )
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn response_headers_are_added_and_removed_on_proxied_responses() {
+        let mut config = Config::default();
+        config.proxy.target = leaky_upstream().await;
+        config.proxy.response_headers.add = [
+            ("Strict-Transport-Security", "max-age=31536000"),
+            ("X-Frame-Options", "DENY"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        config.proxy.response_headers.remove = vec!["server".to_string(), "X-Powered-By".to_string()];
+
+        let resp = proxy(config, get("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert!(headers.get(header::SERVER).is_none());
+        assert!(headers.get("x-powered-by").is_none());
+        assert_eq!(headers["strict-transport-security"], "max-age=31536000");
+        // add 覆盖上游的同名头，而不是追加第二个
+        assert_eq!(headers.get_all("x-frame-options").iter().collect::<Vec<_>>(), ["DENY"]);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(body_text(resp).await, "page");
+    }
+
+    #[tokio::test]
+    async fn upstream_headers_are_untouched_by_default() {
+        let mut config = Config::default();
+        config.proxy.target = leaky_upstream().await;
+        let resp = proxy(config, get("/")).await;
+        assert_eq!(resp.headers()[header::SERVER], "nginx/1.2.3");
+        assert_eq!(resp.headers()["x-powered-by"], "PHP/8.1");
+        assert_eq!(resp.headers()["x-frame-options"], "ALLOWALL");
+    }
+
+    #[test]
+    fn hop_by_hop_and_invalid_header_edits_are_rejected() {
+        for (name, value) in [("Connection", "close"), ("bad header", "v"), ("X-Ok", "line\nbreak")] {
+            let cfg = ResponseHeadersConfig {
+                add: [(name.to_string(), value.to_string())].into_iter().collect(),
+                remove: Vec::new(),
+            };
+            assert!(ResponseHeaderEdits::from_config(&cfg).is_err(), "{name}");
+        }
+    }
+
+    /// 已关闭端口的地址：连接立即被拒绝
+    fn closed_target() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        format!("http://{addr}")
+    }
+
+    async fn pool_app(targets: Vec<String>) -> axum::Router {
+        let mut config = Config::default();
+        config.proxy.targets = targets;
+        let state = Arc::new(AppState::new(config).await.unwrap());
+        axum::Router::new().fallback(proxy_handler).with_state(state)
+    }
+
+    async fn send(app: &axum::Router, req: Request<Body>) -> (StatusCode, String) {
+        use tower::ServiceExt;
+        let resp = app.clone().oneshot(req).await.unwrap();
+        (resp.status(), body_text(resp).await)
+    }
+
+    #[tokio::test]
+    async fn targets_are_used_round_robin() {
+        let app = pool_app(vec![named_upstream("a").await, named_upstream("b").await, named_upstream("c").await]).await;
+        let mut seen = Vec::new();
+        for _ in 0..6 {
+            let (status, body) = send(&app, get("/")).await;
+            assert_eq!(status, StatusCode::OK);
+            seen.push(body);
+        }
+        assert_eq!(seen, ["a", "b", "c", "a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_target_fails_over_to_the_next() {
+        let app = pool_app(vec![closed_target(), named_upstream("b").await, closed_target()]).await;
+        for _ in 0..6 {
+            assert_eq!(send(&app, get("/")).await, (StatusCode::OK, "b".to_string()));
+        }
+
+        let down = pool_app(vec![closed_target(), closed_target()]).await;
+        assert_eq!(send(&down, get("/")).await.0, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn request_with_a_body_is_not_retried() {
+        let app = pool_app(vec![closed_target(), named_upstream("b").await]).await;
+        let post = || Request::builder().method("POST").uri("/").body(Body::from("payload")).unwrap();
+        // 轮询起点先落在不可达的上游：请求体已交出，不能重发
+        assert_eq!(send(&app, post()).await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&app, post()).await, (StatusCode::OK, "b".to_string()));
+    }
+
+    /// 每个请求先等 100ms 再回应、并计数的上游；cache_control 为 None 时响应不可缓存
+    async fn slow_counting_upstream(cache_control: Option<&'static str>) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = count.clone();
+        let url = stub_upstream(axum::Router::new().fallback(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let cache_control = cache_control.unwrap_or("no-store");
+                ([(header::CACHE_CONTROL, cache_control)], "body")
+            }
+        }))
+        .await;
+        (url, count)
+    }
+
+    async fn concurrent_gets(state: &Arc<AppState>, n: usize) -> Duration {
+        let started = Instant::now();
+        let requests: Vec<_> = (0..n)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move { body_text(proxy_handler(State(state), get("/hot")).await.into_response()).await })
+            })
+            .collect();
+        for request in requests {
+            assert_eq!(request.await.unwrap(), "body");
+        }
+        started.elapsed()
+    }
+
+    #[tokio::test]
+    async fn micro_cache_coalesces_cacheable_misses() {
+        let (target, count) = slow_counting_upstream(Some("public, max-age=60")).await;
+        let mut config = Config::default();
+        config.proxy.target = target;
+        config.proxy.micro_cache.enabled = true;
+        let state = Arc::new(AppState::new(config).await.unwrap());
+
+        concurrent_gets(&state, 5).await;
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let metrics = state.metrics.render(true, false, None, None);
+        assert!(metrics.contains("cowcat_micro_cache_lookups_total{result=\"hit\"} 4\n"), "{metrics}");
+        assert!(metrics.contains("cowcat_micro_cache_lookups_total{result=\"miss\"} 1\n"), "{metrics}");
+    }
+
+    #[tokio::test]
+    async fn micro_cache_does_not_serialize_uncacheable_responses() {
+        let (target, count) = slow_counting_upstream(None).await;
+        let mut config = Config::default();
+        config.proxy.target = target;
+        config.proxy.micro_cache.enabled = true;
+        let state = Arc::new(AppState::new(config).await.unwrap());
+
+        // 领头者拿到 no-store 后放行，其余 4 个并发回源，而不是一个接一个等 100ms
+        let elapsed = concurrent_gets(&state, 5).await;
+        assert_eq!(count.load(std::sync::atomic::Ordering::SeqCst), 5);
+        assert!(elapsed < Duration::from_millis(400), "{elapsed:?}");
+    }
+}
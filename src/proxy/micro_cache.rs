@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Method, Request, Response, StatusCode, Uri};
+use bytes::Bytes;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tokio::time::interval;
+
+use crate::config::MicroCacheConfig;
+use crate::handlers::pow::POW_COOKIE_NAME;
+
+const CACHE_CLEANUP_INTERVAL: u64 = 60;
+/// 单个响应体上限，超过的响应直接流式转发，不进缓存
+const MAX_ENTRY_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    expires_at: Instant,
+}
+
+impl CachedResponse {
+    pub fn to_response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// 洪峰期间对相同的可缓存 GET 做短 TTL 缓存，并以单飞（single-flight）合并并发回源
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, CachedResponse>,
+    used_bytes: usize,
+}
+
+impl Entries {
+    fn evict_expired(&mut self, now: Instant) {
+        let mut freed = 0;
+        self.map.retain(|_, entry| {
+            let keep = entry.expires_at > now;
+            if !keep {
+                freed += entry.body.len();
+            }
+            keep
+        });
+        self.used_bytes -= freed;
+    }
+}
+
+pub struct MicroCache {
+    entries: RwLock<Entries>,
+    flights: StdMutex<HashMap<String, Arc<Mutex<()>>>>,
+    ttl: Duration,
+    max_bytes: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// 领头者持有期间同 key 的其他请求排队等待，结束后由它们重新查缓存；
+/// 等到的请求即便仍未命中也不再排队，各自并发回源
+pub struct Flight<'a> {
+    cache: &'a MicroCache,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.guard.take();
+        // 没有其他等待者时回收该 key 的单飞锁
+        if let Ok(mut flights) = self.cache.flights.lock() {
+            if flights.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+                flights.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl MicroCache {
+    pub fn new(cfg: &MicroCacheConfig) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            entries: RwLock::new(Entries::default()),
+            flights: StdMutex::new(HashMap::new()),
+            ttl: Duration::from_secs(cfg.ttl_secs.max(1)),
+            max_bytes: cfg.max_bytes,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        });
+        Self::spawn_cleanup(cache.clone());
+        cache
+    }
+
+    /// 只缓存匿名的 GET：携带 Authorization 或业务 Cookie 的请求可能拿到私有内容
+    pub fn key_for(&self, req: &Request<Body>, upstream: &Uri) -> Option<String> {
+        if req.method() != Method::GET {
+            return None;
+        }
+        let headers = req.headers();
+        if headers.contains_key(header::AUTHORIZATION) || has_foreign_cookie(headers) {
+            return None;
+        }
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok()).unwrap_or("");
+        let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let encoding = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        Some(format!("{upstream}\n{host}\n{path}\n{encoding}"))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let hit = self.fresh(key).await;
+        if hit.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    /// 单飞等待结束后的复查：命中说明搭上了别人的回源，未命中已在 get 中计过一次
+    pub async fn get_after_flight(&self, key: &str) -> Option<CachedResponse> {
+        let hit = self.fresh(key).await;
+        if hit.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.misses.fetch_sub(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    async fn fresh(&self, key: &str) -> Option<CachedResponse> {
+        let entries = self.entries.read().await;
+        entries
+            .map
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .cloned()
+    }
+
+    /// 没有进行中的回源时成为领头者并持锁；否则等领头者结束（写入缓存或放弃）后返回不持锁的 Flight
+    pub async fn begin_flight(&self, key: &str) -> Flight<'_> {
+        let lock = match self.flights.lock() {
+            Ok(mut flights) => flights.entry(key.to_string()).or_default().clone(),
+            Err(_) => Arc::new(Mutex::new(())),
+        };
+        let guard = match lock.clone().try_lock_owned() {
+            Ok(guard) => Some(guard),
+            Err(_) => {
+                drop(lock.lock_owned().await);
+                None
+            }
+        };
+        Flight {
+            cache: self,
+            key: key.to_string(),
+            guard,
+        }
+    }
+
+    /// 上游响应可缓存时返回 TTL：仅 200、无 Set-Cookie、未声明 no-store/private/no-cache、
+    /// Vary 只涉及 Accept-Encoding，且 Content-Length 已知并不超过单条上限
+    pub fn cacheable_ttl(&self, status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+        if status != StatusCode::OK || headers.contains_key(header::SET_COOKIE) {
+            return None;
+        }
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())?;
+        if length > MAX_ENTRY_BYTES || length as usize > self.max_bytes {
+            return None;
+        }
+        for value in headers.get_all(header::VARY) {
+            let value = value.to_str().ok()?;
+            if value
+                .split(',')
+                .map(str::trim)
+                .any(|v| !v.is_empty() && !v.eq_ignore_ascii_case("accept-encoding"))
+            {
+                return None;
+            }
+        }
+        let mut ttl = self.ttl;
+        for value in headers.get_all(header::CACHE_CONTROL) {
+            for directive in value.to_str().ok()?.split(',') {
+                let directive = directive.trim().to_ascii_lowercase();
+                if directive == "no-store" || directive == "private" || directive == "no-cache" {
+                    return None;
+                }
+                if let Some(age) = directive
+                    .strip_prefix("s-maxage=")
+                    .or_else(|| directive.strip_prefix("max-age="))
+                {
+                    let age = age.parse::<u64>().ok()?;
+                    if age == 0 {
+                        return None;
+                    }
+                    ttl = ttl.min(Duration::from_secs(age));
+                }
+            }
+        }
+        Some(ttl)
+    }
+
+    pub async fn insert(&self, key: String, status: StatusCode, headers: HeaderMap, body: Bytes, ttl: Duration) {
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        if let Some(old) = entries.map.remove(&key) {
+            entries.used_bytes -= old.body.len();
+        }
+        if entries.used_bytes + body.len() > self.max_bytes {
+            entries.evict_expired(now);
+            if entries.used_bytes + body.len() > self.max_bytes {
+                tracing::debug!(used = entries.used_bytes, max_bytes = self.max_bytes, "micro cache full, not caching");
+                return;
+            }
+        }
+        entries.used_bytes += body.len();
+        entries.map.insert(
+            key,
+            CachedResponse {
+                status,
+                headers,
+                body,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    fn spawn_cleanup(cache: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(CACHE_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                cache.cleanup().await;
+            }
+        });
+    }
+
+    async fn cleanup(&self) {
+        let now = Instant::now();
+        let remaining = {
+            let mut entries = self.entries.write().await;
+            entries.evict_expired(now);
+            entries.map.len()
+        };
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        if hits + misses > 0 {
+            tracing::info!(
+                hits,
+                misses,
+                hit_rate = hits as f64 / (hits + misses) as f64,
+                entries = remaining,
+                "micro cache stats"
+            );
+        }
+    }
+}
+
+fn has_foreign_cookie(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|raw| cookie::Cookie::split_parse(raw).flatten())
+        .any(|cookie| cookie.name() != POW_COOKIE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn cache(max_bytes: usize) -> Arc<MicroCache> {
+        MicroCache::new(&MicroCacheConfig { enabled: true, ttl_secs: 60, max_bytes })
+    }
+
+    fn cacheable_headers(len: usize) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+        headers
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).header(header::HOST, "a.example").body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn stored_response_is_a_hit() {
+        let cache = cache(1024);
+        let upstream: Uri = "http://127.0.0.1:1".parse().unwrap();
+        let key = cache.key_for(&get("/page?a=1"), &upstream).unwrap();
+        assert!(cache.get(&key).await.is_none());
+        cache.insert(key.clone(), StatusCode::OK, cacheable_headers(5), Bytes::from_static(b"hello"), Duration::from_secs(60)).await;
+        let hit = cache.get(&key).await.unwrap();
+        assert_eq!(hit.body, "hello");
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.misses.load(Ordering::Relaxed), 1);
+
+        // 查询串、Host 不同即为不同的键；带 Authorization 或业务 Cookie 的请求不缓存
+        let other = cache.key_for(&get("/page?a=2"), &upstream).unwrap();
+        assert!(cache.get(&other).await.is_none());
+        let mut authorized = get("/page?a=1");
+        authorized.headers_mut().insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer x"));
+        assert!(cache.key_for(&authorized, &upstream).is_none());
+        let mut session = get("/page?a=1");
+        session.headers_mut().insert(header::COOKIE, HeaderValue::from_static("sid=1"));
+        assert!(cache.key_for(&session, &upstream).is_none());
+    }
+
+    #[test]
+    fn private_or_personalised_responses_bypass_the_cache() {
+        let cache = MicroCache {
+            entries: RwLock::new(Entries::default()),
+            flights: StdMutex::new(HashMap::new()),
+            ttl: Duration::from_secs(60),
+            max_bytes: 1024,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        };
+        assert_eq!(cache.cacheable_ttl(StatusCode::OK, &cacheable_headers(5)), Some(Duration::from_secs(60)));
+        for (name, value) in [
+            (header::CACHE_CONTROL, "no-store"),
+            (header::CACHE_CONTROL, "public, private"),
+            (header::CACHE_CONTROL, "no-cache"),
+            (header::CACHE_CONTROL, "max-age=0"),
+            (header::SET_COOKIE, "sid=1"),
+            (header::VARY, "Accept-Encoding, Cookie"),
+        ] {
+            let mut headers = cacheable_headers(5);
+            headers.insert(name.clone(), HeaderValue::from_static(value));
+            assert_eq!(cache.cacheable_ttl(StatusCode::OK, &headers), None, "{name}: {value}");
+        }
+        let mut headers = cacheable_headers(5);
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=5"));
+        assert_eq!(cache.cacheable_ttl(StatusCode::OK, &headers), Some(Duration::from_secs(5)));
+        assert_eq!(cache.cacheable_ttl(StatusCode::NOT_FOUND, &cacheable_headers(5)), None);
+        // 长度未知或超过上限不缓存
+        assert_eq!(cache.cacheable_ttl(StatusCode::OK, &HeaderMap::new()), None);
+        assert_eq!(cache.cacheable_ttl(StatusCode::OK, &cacheable_headers(1025)), None);
+    }
+
+    #[tokio::test]
+    async fn max_bytes_bounds_the_stored_bodies() {
+        let cache = cache(10);
+        let ttl = Duration::from_secs(60);
+        cache.insert("a".to_string(), StatusCode::OK, HeaderMap::new(), Bytes::from_static(b"123456"), ttl).await;
+        cache.insert("b".to_string(), StatusCode::OK, HeaderMap::new(), Bytes::from_static(b"123456"), ttl).await;
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("b").await.is_none());
+        assert_eq!(cache.entries.read().await.used_bytes, 6);
+
+        // 过期条目在空间不够时被腾出
+        cache.insert("c".to_string(), StatusCode::OK, HeaderMap::new(), Bytes::from_static(b"1234"), Duration::ZERO).await;
+        assert_eq!(cache.entries.read().await.used_bytes, 10);
+        cache.insert("d".to_string(), StatusCode::OK, HeaderMap::new(), Bytes::from_static(b"1234"), ttl).await;
+        assert!(cache.get("d").await.is_some());
+        assert_eq!(cache.entries.read().await.used_bytes, 10);
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_wait_for_the_leader() {
+        let cache = cache(1024);
+        let leader = cache.begin_flight("k").await;
+        assert!(leader.guard.is_some());
+
+        let waiter = {
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                let flight = cache.begin_flight("k").await;
+                let hit = cache.get_after_flight("k").await;
+                (flight.guard.is_some(), hit.map(|hit| hit.body))
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        cache.insert("k".to_string(), StatusCode::OK, HeaderMap::new(), Bytes::from_static(b"body"), Duration::from_secs(60)).await;
+        drop(leader);
+
+        let (held, hit) = waiter.await.unwrap();
+        assert!(!held);
+        assert_eq!(hit.as_deref(), Some(&b"body"[..]));
+        assert!(cache.flights.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiters_proceed_together_when_the_leader_does_not_cache() {
+        let cache = cache(1024);
+        let leader = cache.begin_flight("k").await;
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    let flight = cache.begin_flight("k").await;
+                    // 仍持有 Flight 时其他等待者也应能继续
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    flight.guard.is_some()
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let started = Instant::now();
+        drop(leader);
+        for waiter in waiters {
+            assert!(!waiter.await.unwrap());
+        }
+        assert!(started.elapsed() < Duration::from_millis(140), "{:?}", started.elapsed());
+    }
+}
@@ -1 +1,3 @@
+pub mod error;
 pub mod forward;
+pub mod micro_cache;
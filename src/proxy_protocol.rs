@@ -0,0 +1,234 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// v2 头部的 12 字节签名
+const V2_SIGNATURE: [u8; 12] = [0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a];
+/// v1 头部（含结尾 CRLF）的最大长度，见协议文档 2.1 节
+const V1_MAX_LEN: usize = 107;
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// 两个版本都至少有这么多字节，可以先整块读出再分辨版本
+const PREFIX_LEN: usize = V2_SIGNATURE.len();
+
+const V2_VERSION: u8 = 0x20;
+const V2_CMD_LOCAL: u8 = 0x00;
+const V2_CMD_PROXY: u8 = 0x01;
+const V2_FAMILY_INET: u8 = 0x10;
+const V2_FAMILY_INET6: u8 = 0x20;
+
+/// 从连接开头读取并解析 PROXY protocol v1（文本）或 v2（二进制）头部，恰好消费头部本身的字节。
+/// 返回负载均衡器转述的客户端地址；v1 UNKNOWN、v2 LOCAL（负载均衡器自身的健康检查）
+/// 以及非 TCP/IP 地址族返回 None，由调用方沿用套接字对端。头部缺失或格式错误返回 InvalidData
+pub async fn read_header<R>(reader: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut prefix = [0u8; PREFIX_LEN];
+    reader.read_exact(&mut prefix).await?;
+    if prefix == V2_SIGNATURE {
+        return read_v2(reader).await;
+    }
+    if prefix.starts_with(V1_PREFIX) {
+        let mut line = prefix.to_vec();
+        let limit = (V1_MAX_LEN - PREFIX_LEN) as u64;
+        reader.take(limit).read_until(b'\n', &mut line).await?;
+        return parse_v1(&line);
+    }
+    Err(invalid("missing proxy protocol header"))
+}
+
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = line
+        .strip_suffix(b"\r\n")
+        .ok_or_else(|| invalid("proxy protocol v1 header is not terminated by CRLF"))?;
+    let line = std::str::from_utf8(line).map_err(|_| invalid("proxy protocol v1 header is not ascii"))?;
+    let mut parts = line.split(' ');
+    parts.next(); // "PROXY"
+    let family = parts.next().unwrap_or_default();
+    if family == "UNKNOWN" {
+        // 其余字段可有可无，接收方必须忽略
+        return Ok(None);
+    }
+    let fields: Vec<&str> = parts.collect();
+    let [src, dst, src_port, dst_port] = fields[..] else {
+        return Err(invalid("proxy protocol v1 header has a wrong number of fields"));
+    };
+    // 目的地址与端口不使用，但同样校验，格式错误的头部整体拒绝
+    let src: IpAddr = match family {
+        "TCP4" => {
+            parse_v1_addr::<Ipv4Addr>(dst)?;
+            parse_v1_addr::<Ipv4Addr>(src)?.into()
+        }
+        "TCP6" => {
+            parse_v1_addr::<Ipv6Addr>(dst)?;
+            parse_v1_addr::<Ipv6Addr>(src)?.into()
+        }
+        _ => return Err(invalid("proxy protocol v1 header has an unknown protocol")),
+    };
+    parse_v1_port(dst_port)?;
+    Ok(Some(SocketAddr::new(src, parse_v1_port(src_port)?)))
+}
+
+fn parse_v1_addr<T: std::str::FromStr>(value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| invalid("proxy protocol v1 header has an invalid address"))
+}
+
+fn parse_v1_port(value: &str) -> io::Result<u16> {
+    // 协议要求十进制且不带前导零
+    if value.is_empty() || (value.len() > 1 && value.starts_with('0')) || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid("proxy protocol v1 header has an invalid port"));
+    }
+    value.parse().map_err(|_| invalid("proxy protocol v1 header has an invalid port"))
+}
+
+async fn read_v2<R>(reader: &mut R) -> io::Result<Option<SocketAddr>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut head = [0u8; 4];
+    reader.read_exact(&mut head).await?;
+    let [version_command, family, len_hi, len_lo] = head;
+    let mut payload = vec![0u8; u16::from_be_bytes([len_hi, len_lo]) as usize];
+    reader.read_exact(&mut payload).await?;
+    parse_v2(version_command, family, &payload)
+}
+
+fn parse_v2(version_command: u8, family: u8, payload: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if version_command & 0xf0 != V2_VERSION {
+        return Err(invalid("unsupported proxy protocol v2 version"));
+    }
+    match version_command & 0x0f {
+        V2_CMD_LOCAL => return Ok(None),
+        V2_CMD_PROXY => {}
+        _ => return Err(invalid("unsupported proxy protocol v2 command")),
+    }
+    // 地址块之后可能跟着 TLV，这里只取地址
+    match family & 0xf0 {
+        V2_FAMILY_INET => {
+            let addr: &[u8; 12] = payload
+                .get(..12)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| invalid("proxy protocol v2 ipv4 address block is truncated"))?;
+            let ip = Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
+            let port = u16::from_be_bytes([addr[8], addr[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        V2_FAMILY_INET6 => {
+            let addr: &[u8; 36] = payload
+                .get(..36)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| invalid("proxy protocol v2 ipv6 address block is truncated"))?;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addr[..16]);
+            let port = u16::from_be_bytes([addr[32], addr[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        // UNSPEC / AF_UNIX：没有可用的 IP，沿用套接字对端
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 解析 data 开头的头部，并返回头部之后剩下的字节
+    async fn parse(data: &[u8]) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let mut reader = tokio::io::BufReader::new(data);
+        let result = read_header(&mut reader).await;
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        (result, rest)
+    }
+
+    fn v2_header(version_command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[version_command, family]);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(payload);
+        header
+    }
+
+    #[tokio::test]
+    async fn v1_header_yields_the_client_address_and_nothing_more() {
+        let (addr, rest) = parse(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n").await;
+        assert_eq!(addr.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let (addr, _) = parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::1]:56324".parse().unwrap()));
+        let (addr, rest) = parse(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\nrest").await;
+        assert_eq!(addr.unwrap(), None);
+        assert_eq!(rest, b"rest");
+    }
+
+    #[tokio::test]
+    async fn malformed_v1_headers_are_rejected() {
+        let longest = format!("PROXY TCP4 1.1.1.1 2.2.2.2 1 2{}\r\n", " ".repeat(V1_MAX_LEN));
+        let cases: [&[u8]; 10] = [
+            b"GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443 9\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 056324 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 65536 443\r\n",
+            b"PROXY TCP4 2001:db8::1 198.51.100.2 56324 443\r\n",
+            b"PROXY TCP6 192.0.2.1 2001:db8::2 56324 443\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.2 56324 443\r\n",
+            longest.as_bytes(),
+        ];
+        for data in cases {
+            let (addr, _) = parse(data).await;
+            assert!(addr.is_err(), "{:?} accepted", String::from_utf8_lossy(data));
+        }
+    }
+
+    #[tokio::test]
+    async fn v2_header_yields_the_client_address_and_skips_tlvs() {
+        let mut inet = vec![192, 0, 2, 1, 198, 51, 100, 2];
+        inet.extend_from_slice(&56324u16.to_be_bytes());
+        inet.extend_from_slice(&443u16.to_be_bytes());
+        inet.extend_from_slice(&[0x04, 0x00, 0x01, 0xff]); // 一个 NOOP TLV
+        let mut data = v2_header(0x21, 0x11, &inet);
+        data.extend_from_slice(b"GET /");
+        let (addr, rest) = parse(&data).await;
+        assert_eq!(addr.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"GET /");
+
+        let mut inet6 = Vec::new();
+        inet6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        inet6.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        inet6.extend_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+        let (addr, _) = parse(&v2_header(0x21, 0x21, &inet6)).await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        // LOCAL（健康检查）与 AF_UNIX 沿用套接字对端
+        let (addr, rest) = parse(&[v2_header(0x20, 0x00, &[]), b"x".to_vec()].concat()).await;
+        assert_eq!(addr.unwrap(), None);
+        assert_eq!(rest, b"x");
+        assert_eq!(parse(&v2_header(0x21, 0x31, &[0u8; 216])).await.0.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn malformed_v2_headers_are_rejected() {
+        let inet = [192, 0, 2, 1, 198, 51, 100, 2, 0, 80, 1, 187];
+        let cases = [
+            v2_header(0x11, 0x11, &inet),
+            v2_header(0x22, 0x11, &inet),
+            v2_header(0x21, 0x11, &inet[..8]),
+            v2_header(0x21, 0x21, &inet),
+        ];
+        for data in cases {
+            assert!(parse(&data).await.0.is_err(), "{data:02x?} accepted");
+        }
+        // 声明的长度超过实际数据
+        let mut truncated = v2_header(0x21, 0x11, &inet);
+        truncated.truncate(truncated.len() - 4);
+        assert!(parse(&truncated).await.0.is_err());
+    }
+}
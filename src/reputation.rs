@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::{Method, Request, Uri};
+use http_body_util::{BodyExt, Limited};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::Deserialize;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+
+use crate::config::ReputationConfig;
+use crate::metrics::{Metrics, ReputationLookup};
+use crate::rules::RuleAction;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+/// 查询服务的响应只需要一个分数，超出即视为异常
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct ReputationResponse {
+    score: f64,
+}
+
+struct CacheEntry {
+    /// None 表示查询失败，按无分数处理
+    score: Option<f64>,
+    expires_at: Instant,
+}
+
+/// rules.reputation：按 IP 查询外部信誉分并缓存；查询失败或超时一律放行（fail-open），
+/// 失败结果短暂缓存，查询服务故障时不会让每个请求都等满超时。
+/// 同一 IP 并发未命中时只查询一次（单飞），其余请求等结果写入缓存后直接取用
+pub struct ReputationClient {
+    client: Client<HttpConnector, Body>,
+    endpoint: String,
+    threshold: f64,
+    block: bool,
+    difficulty_delta: i32,
+    timeout: Duration,
+    cache_ttl: Duration,
+    error_ttl: Duration,
+    max_entries: usize,
+    cache: RwLock<HashMap<String, CacheEntry>>,
+    flights: StdMutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+/// 领头者查询期间同 IP 的其他请求排队等待；等到后仍未命中（如 error_ttl 为 0）则各自查询，不再排队
+struct Flight<'a> {
+    reputation: &'a ReputationClient,
+    client_ip: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.guard.take();
+        // 没有其他等待者时回收该 IP 的单飞锁
+        if let Ok(mut flights) = self.reputation.flights.lock() {
+            if flights.get(&self.client_ip).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+                flights.remove(&self.client_ip);
+            }
+        }
+    }
+}
+
+/// 信誉分达到阈值时对本次挑战的处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReputationVerdict {
+    Block,
+    /// 在原难度上增加的位数
+    Raise(i32),
+}
+
+impl ReputationClient {
+    pub fn new(cfg: &ReputationConfig) -> Arc<Self> {
+        let reputation = Arc::new(Self {
+            client: Client::builder(TokioExecutor::new()).build(HttpConnector::new()),
+            endpoint: cfg.endpoint.trim().to_string(),
+            threshold: cfg.threshold,
+            block: matches!(cfg.action, RuleAction::Block),
+            difficulty_delta: cfg.difficulty_delta,
+            timeout: Duration::from_millis(cfg.timeout_ms.max(1)),
+            cache_ttl: Duration::from_secs(cfg.cache_ttl_secs.max(1)),
+            error_ttl: Duration::from_secs(cfg.error_ttl_secs),
+            max_entries: cfg.cache_max_entries,
+            cache: RwLock::new(HashMap::new()),
+            flights: StdMutex::new(HashMap::new()),
+        });
+        Self::spawn_cleanup(reputation.clone());
+        tracing::info!(
+            endpoint = %reputation.endpoint,
+            threshold = reputation.threshold,
+            action = ?cfg.action,
+            "ip reputation lookup enabled"
+        );
+        reputation
+    }
+
+    /// 查询（或取缓存）client_ip 的信誉分，达到阈值时返回处理方式
+    pub async fn check(&self, client_ip: &str, metrics: &Metrics) -> Option<ReputationVerdict> {
+        let score = self.score(client_ip, metrics).await?;
+        if score < self.threshold {
+            return None;
+        }
+        metrics.reputation_flagged(self.block);
+        if self.block {
+            Some(ReputationVerdict::Block)
+        } else {
+            Some(ReputationVerdict::Raise(self.difficulty_delta))
+        }
+    }
+
+    /// 信誉分是否达到阈值，不计入 flagged 计数；供验证码入口判断是否免票放行
+    pub async fn is_flagged(&self, client_ip: &str, metrics: &Metrics) -> bool {
+        self.score(client_ip, metrics).await.is_some_and(|score| score >= self.threshold)
+    }
+
+    async fn score(&self, client_ip: &str, metrics: &Metrics) -> Option<f64> {
+        if client_ip.is_empty() {
+            return None;
+        }
+        if let Some(score) = self.cached(client_ip).await {
+            metrics.reputation_lookup(ReputationLookup::Hit);
+            return score;
+        }
+        let _flight = self.begin_flight(client_ip).await;
+        if let Some(score) = self.cached(client_ip).await {
+            metrics.reputation_lookup(ReputationLookup::Hit);
+            return score;
+        }
+        metrics.reputation_lookup(ReputationLookup::Miss);
+        let score = match self.fetch(client_ip).await {
+            Ok(score) => {
+                tracing::debug!(client_ip = %client_ip, score, "ip reputation fetched");
+                Some(score)
+            }
+            Err(err) => {
+                metrics.reputation_lookup(ReputationLookup::Error);
+                tracing::warn!(client_ip = %client_ip, error = %err, "ip reputation lookup failed, failing open");
+                None
+            }
+        };
+        let ttl = if score.is_some() { self.cache_ttl } else { self.error_ttl };
+        if !ttl.is_zero() {
+            self.store(client_ip, score, ttl).await;
+        }
+        score
+    }
+
+    /// 未过期的缓存结果；外层 None 表示未命中，内层 None 表示缓存的是查询失败
+    async fn cached(&self, client_ip: &str) -> Option<Option<f64>> {
+        let cache = self.cache.read().await;
+        cache
+            .get(client_ip)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.score)
+    }
+
+    /// 没有进行中的查询时成为领头者并持锁；否则等领头者结束后返回不持锁的 Flight
+    async fn begin_flight(&self, client_ip: &str) -> Flight<'_> {
+        let lock = match self.flights.lock() {
+            Ok(mut flights) => flights.entry(client_ip.to_string()).or_default().clone(),
+            Err(_) => Arc::new(Mutex::new(())),
+        };
+        let guard = match lock.clone().try_lock_owned() {
+            Ok(guard) => Some(guard),
+            Err(_) => {
+                drop(lock.lock_owned().await);
+                None
+            }
+        };
+        Flight {
+            reputation: self,
+            client_ip: client_ip.to_string(),
+            guard,
+        }
+    }
+
+    async fn fetch(&self, client_ip: &str) -> anyhow::Result<f64> {
+        let uri: Uri = self.lookup_url(client_ip).parse()?;
+        let req = Request::builder().method(Method::GET).uri(uri).body(Body::empty())?;
+        tokio::time::timeout(self.timeout, async {
+            let resp = self.client.request(req).await?;
+            if !resp.status().is_success() {
+                anyhow::bail!("unexpected status {}", resp.status());
+            }
+            let body = Limited::new(resp.into_body(), MAX_RESPONSE_BYTES)
+                .collect()
+                .await
+                .map_err(|err| anyhow::anyhow!("failed to read body: {err}"))?
+                .to_bytes();
+            let parsed: ReputationResponse = serde_json::from_slice(&body)?;
+            if !parsed.score.is_finite() {
+                anyhow::bail!("score is not a finite number");
+            }
+            Ok(parsed.score)
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}ms", self.timeout.as_millis()))?
+    }
+
+    fn lookup_url(&self, client_ip: &str) -> String {
+        if self.endpoint.contains("{ip}") {
+            return self.endpoint.replace("{ip}", client_ip);
+        }
+        let separator = if self.endpoint.contains('?') { '&' } else { '?' };
+        format!("{}{separator}ip={client_ip}", self.endpoint)
+    }
+
+    async fn store(&self, client_ip: &str, score: Option<f64>, ttl: Duration) {
+        let mut cache = self.cache.write().await;
+        // 过期条目由后台任务定期清理，满时不跟踪新 IP（与 FailureBans 一致）
+        if !cache.contains_key(client_ip) && cache.len() >= self.max_entries {
+            tracing::debug!("ip reputation cache full, not caching new ip");
+            return;
+        }
+        cache.insert(client_ip.to_string(), CacheEntry { score, expires_at: Instant::now() + ttl });
+    }
+
+    fn spawn_cleanup(reputation: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let now = Instant::now();
+                reputation.cache.write().await.retain(|_, entry| entry.expires_at > now);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract::{Path, Query};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+
+    /// 分数取 IP 的最后一段，便于按阈值构造用例
+    fn score_body(ip: &str) -> String {
+        let score: f64 = ip.rsplit('.').next().and_then(|part| part.parse().ok()).unwrap_or(0.0);
+        format!("{{\"score\": {score}}}")
+    }
+
+    /// 本地桩查询服务：/score/{ip} 与 /score?ip= 返回分数（后者稍慢，让并发请求重叠），
+    /// /slow 超过客户端超时，/error 返回 500；另返回收到的请求数
+    async fn stub_service() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let app = axum::Router::new()
+            .route("/score/{ip}", get(|Path(ip): Path<String>| async move { score_body(&ip) }))
+            .route(
+                "/score",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    score_body(&query["ip"])
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    score_body("99")
+                }),
+            )
+            .route("/error", get(|| async { (StatusCode::INTERNAL_SERVER_ERROR, score_body("99")) }))
+            .layer(axum::middleware::from_fn(move |req: axum::extract::Request, next: axum::middleware::Next| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                next.run(req)
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), calls)
+    }
+
+    fn client(endpoint: String, action: RuleAction, error_ttl_secs: u64) -> Arc<ReputationClient> {
+        ReputationClient::new(&ReputationConfig {
+            endpoint,
+            threshold: 50.0,
+            action,
+            difficulty_delta: 2,
+            timeout_ms: 200,
+            error_ttl_secs,
+            ..ReputationConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn verdict_follows_the_threshold_and_is_cached() {
+        let (base, calls) = stub_service().await;
+        let metrics = Metrics::default();
+        let block = client(format!("{base}/score/{{ip}}"), RuleAction::Block, 30);
+        assert_eq!(block.check("10.0.0.49", &metrics).await, None);
+        assert_eq!(block.check("10.0.0.50", &metrics).await, Some(ReputationVerdict::Block));
+        assert_eq!(block.check("10.0.0.50", &metrics).await, Some(ReputationVerdict::Block));
+        assert_eq!(block.check("", &metrics).await, None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let challenge = client(format!("{base}/score/{{ip}}"), RuleAction::Challenge, 30);
+        assert_eq!(challenge.check("10.0.0.80", &metrics).await, Some(ReputationVerdict::Raise(2)));
+        assert!(challenge.is_flagged("10.0.0.80", &metrics).await);
+        assert!(!challenge.is_flagged("10.0.0.1", &metrics).await);
+
+        let out = metrics.render(true, false, None, None);
+        assert!(out.contains("cowcat_reputation_lookups_total{result=\"hit\"} 2"), "{out}");
+        assert!(out.contains("cowcat_reputation_lookups_total{result=\"miss\"} 4"), "{out}");
+        assert!(out.contains("cowcat_reputation_flagged_total{action=\"block\"} 2"), "{out}");
+        assert!(out.contains("cowcat_reputation_flagged_total{action=\"challenge\"} 1"), "{out}");
+    }
+
+    #[tokio::test]
+    async fn lookup_url_templates_or_appends_the_ip() {
+        let (base, _) = stub_service().await;
+        let metrics = Metrics::default();
+        let templated = client(format!("{base}/score/{{ip}}"), RuleAction::Block, 30);
+        assert_eq!(templated.lookup_url("1.2.3.4"), format!("{base}/score/1.2.3.4"));
+        let appended = client(format!("{base}/score"), RuleAction::Block, 30);
+        assert_eq!(appended.lookup_url("1.2.3.4"), format!("{base}/score?ip=1.2.3.4"));
+        assert_eq!(appended.check("10.0.0.60", &metrics).await, Some(ReputationVerdict::Block));
+        let with_query = client(format!("{base}/score?key=k"), RuleAction::Block, 30);
+        assert_eq!(with_query.lookup_url("1.2.3.4"), format!("{base}/score?key=k&ip=1.2.3.4"));
+        assert_eq!(with_query.check("10.0.0.60", &metrics).await, Some(ReputationVerdict::Block));
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_fail_open_and_are_cached_for_error_ttl() {
+        let (base, calls) = stub_service().await;
+        let metrics = Metrics::default();
+        for path in ["slow", "error"] {
+            let reputation = client(format!("{base}/{path}"), RuleAction::Block, 30);
+            let before = calls.load(Ordering::SeqCst);
+            assert_eq!(reputation.check("10.0.0.99", &metrics).await, None);
+            assert_eq!(reputation.check("10.0.0.99", &metrics).await, None);
+            assert_eq!(calls.load(Ordering::SeqCst), before + 1, "{path}");
+        }
+        assert!(metrics.render(true, false, None, None).contains("cowcat_reputation_lookups_total{result=\"error\"} 2"));
+
+        // error_ttl 为 0：失败不缓存，每次都重新查询
+        let uncached = client(format!("{base}/error"), RuleAction::Block, 0);
+        let before = calls.load(Ordering::SeqCst);
+        assert_eq!(uncached.check("10.0.0.99", &metrics).await, None);
+        assert_eq!(uncached.check("10.0.0.99", &metrics).await, None);
+        assert_eq!(calls.load(Ordering::SeqCst), before + 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_for_one_ip_share_a_lookup() {
+        let (base, calls) = stub_service().await;
+        let metrics = Arc::new(Metrics::default());
+        let reputation = client(format!("{base}/score"), RuleAction::Block, 30);
+        let checks: Vec<_> = (0..10)
+            .map(|_| {
+                let (reputation, metrics) = (reputation.clone(), metrics.clone());
+                tokio::spawn(async move { reputation.check("10.0.0.70", &metrics).await })
+            })
+            .collect();
+        for check in checks {
+            assert_eq!(check.await.unwrap(), Some(ReputationVerdict::Block));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(reputation.flights.lock().unwrap().is_empty());
+        assert!(metrics.render(true, false, None, None).contains("cowcat_reputation_lookups_total{result=\"miss\"} 1"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, Uri};
+use ipnet::IpNet;
+use percent_encoding::percent_decode_str;
+
+use crate::config::RiskScoreConfig;
+use crate::rules::{clamp_difficulty, parse_ip_nets, to_header_predicate, HeaderPredicate};
+
+/// pow.risk_score：路径、请求头、IP 各自贡献分数，累加后按 curve 映射为基础难度。
+/// 只取代 path_difficulty / pow.difficulty 得出的基础难度，规则的 difficulty_delta / difficulty_absolute
+/// 仍在其上生效；未配置时即退化为原来的“基础难度 + delta”模型
+#[derive(Debug)]
+pub struct RiskModel {
+    paths: Vec<(String, u32)>,
+    headers: Vec<(HeaderPredicate, u32)>,
+    ips: Vec<(Vec<IpNet>, u32)>,
+    /// 按 min_score 升序
+    curve: Vec<(u32, i32)>,
+}
+
+impl RiskModel {
+    pub fn from_config(cfg: &RiskScoreConfig) -> anyhow::Result<Self> {
+        if cfg.curve.is_empty() {
+            anyhow::bail!("pow.risk_score.curve needs at least one step");
+        }
+        let paths = cfg
+            .path
+            .iter()
+            .map(|entry| {
+                if !entry.prefix.starts_with('/') {
+                    anyhow::bail!("pow.risk_score.path prefix must start with '/': {}", entry.prefix);
+                }
+                Ok((entry.prefix.clone(), entry.score))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let headers = cfg
+            .header
+            .iter()
+            .map(|entry| Ok((to_header_predicate(&entry.matcher)?, entry.score)))
+            .collect::<anyhow::Result<_>>()
+            .map_err(|err: anyhow::Error| anyhow::anyhow!("pow.risk_score.header: {err}"))?;
+        let ips = cfg
+            .ip
+            .iter()
+            .map(|entry| Ok((parse_ip_nets(&entry.cidr)?, entry.score)))
+            .collect::<anyhow::Result<_>>()
+            .map_err(|err: anyhow::Error| anyhow::anyhow!("pow.risk_score.ip: {err}"))?;
+        let mut curve: Vec<(u32, i32)> = cfg.curve.iter().map(|step| (step.min_score, step.difficulty)).collect();
+        curve.sort_by_key(|(min_score, _)| *min_score);
+        if curve.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            anyhow::bail!("pow.risk_score.curve has duplicate min_score");
+        }
+        Ok(Self { paths, headers, ips, curve })
+    }
+
+    /// 所有命中项的分数之和；路径与 path_prefix 规则一样匹配解码一次后的 path
+    pub fn score(&self, uri: &Uri, headers: &HeaderMap, client_ip: Option<IpAddr>) -> u32 {
+        let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+        let path_score = self
+            .paths
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map(|(_, score)| *score);
+        let header_score = self
+            .headers
+            .iter()
+            .filter(|(predicate, _)| predicate.is_match(headers))
+            .map(|(_, score)| *score);
+        let ip_score = self
+            .ips
+            .iter()
+            .filter(|(nets, _)| client_ip.is_some_and(|ip| nets.iter().any(|net| net.contains(&ip))))
+            .map(|(_, score)| *score);
+        path_score
+            .chain(header_score)
+            .chain(ip_score)
+            .fold(0u32, u32::saturating_add)
+    }
+
+    /// min_score 不超过 score 的最高一档；低于所有档位时沿用 fallback（常规基础难度）
+    pub fn difficulty(&self, score: u32, fallback: i32) -> i32 {
+        self.curve
+            .iter()
+            .rev()
+            .find(|(min_score, _)| *min_score <= score)
+            .map(|(_, difficulty)| clamp_difficulty(*difficulty))
+            .unwrap_or(fallback)
+    }
+}
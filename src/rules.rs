@@ -1,12 +1,17 @@
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, Uri};
 use ipnet::IpNet;
-use serde::Deserialize;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::IpAddr;
+use std::time::Duration;
+use time::{OffsetDateTime, UtcOffset};
 
-use crate::config::{HeaderMatch, RulesConfig};
+use crate::config::{is_valid_worker_type, HeaderMatch, OnInvalid, RuleConfig, RulesConfig};
 use crate::protocol::http::HeaderMapExt;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum RuleAction {
     Allow,
@@ -18,6 +23,9 @@ pub enum RuleAction {
 pub struct RulesEngine {
     enabled: bool,
     pub allow_wellknown: bool,
+    suspicious_path_action: Option<RuleAction>,
+    honeypot_paths: HashSet<String>,
+    honeypot_ban: Duration,
     default_action: RuleAction,
     rules: Vec<Rule>,
 }
@@ -27,19 +35,34 @@ struct Rule {
     name: Option<String>,
     action: RuleAction,
     difficulty_delta: i32,
+    difficulty_absolute: Option<i32>,
+    worker_type: Option<String>,
     matcher: Matcher,
 }
 
 #[derive(Debug, Clone)]
 struct Matcher {
-    path_prefix: Option<String>,
+    path_prefix: Option<String>,  // 匹配解码一次后的 path
     path_exact: Option<String>,
+    path_regex: Option<Regex>,  // 与 path_prefix 一样匹配解码一次后的 path，未锚定
+    path_raw_prefix: Option<String>,  // 匹配原始 path-and-query，不解码
+    path_raw_contains: Option<String>,  // 预规范化为小写
     header: Option<HeaderPredicate>,
     ip_nets: Vec<IpNet>,
+    languages: Vec<String>,  // 预规范化为小写
+    active_hours: Option<ActiveHours>,
 }
 
+/// 一天内的生效时段，以分钟计；start > end 表示跨午夜
 #[derive(Debug, Clone)]
-struct HeaderPredicate {
+struct ActiveHours {
+    start: u16,
+    end: u16,
+    offset: UtcOffset,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HeaderPredicate {
     name: String,
     equals: Option<String>,  // 预规范化为小写
     contains: Option<String>,  // 预规范化为小写
@@ -47,15 +70,28 @@ struct HeaderPredicate {
 
 #[derive(Debug, Clone)]
 pub struct RuleDecision {
+    /// 命中的规则名；走 default_action 时为 None
+    pub rule: Option<String>,
     pub action: RuleAction,
     pub difficulty_delta: i32,
+    /// 设置时直接取代基础难度，difficulty_delta 不再生效
+    pub difficulty_absolute: Option<i32>,
+    pub worker_type: Option<String>,
+}
+
+impl RuleDecision {
+    /// Challenge 时的实际难度：difficulty_absolute 优先，否则为 base + difficulty_delta
+    pub fn effective_difficulty(&self, base: i32) -> i32 {
+        clamp_difficulty(self.difficulty_absolute.unwrap_or(base + self.difficulty_delta))
+    }
 }
 
 impl RulesEngine {
     pub fn from_config(cfg: &RulesConfig) -> anyhow::Result<Self> {
         let mut rules = Vec::new();
         let mut skipped = 0usize;
-        for rule_cfg in &cfg.rule {
+        let mut invalid = 0usize;
+        for (index, rule_cfg) in cfg.rule.iter().enumerate() {
             if !rule_cfg.enabled {
                 skipped += 1;
                 tracing::info!(
@@ -64,67 +100,172 @@ impl RulesEngine {
                 );
                 continue;
             }
-            let ip_nets = parse_ip_nets(rule_cfg.ip_cidr.as_deref().unwrap_or_default())?;
-            let header = rule_cfg.header.as_ref().map(to_header_predicate).transpose()?;
-            let matcher = Matcher {
-                path_prefix: rule_cfg.path_prefix.clone(),
-                path_exact: rule_cfg.path_exact.clone(),
-                header,
-                ip_nets,
-            };
-            let rule = Rule {
-                name: rule_cfg.name.clone(),
-                action: rule_cfg.action.clone(),
-                difficulty_delta: rule_cfg.difficulty_delta.unwrap_or(0),
-                matcher,
-            };
-            rules.push(rule);
+            match build_rule(rule_cfg) {
+                Ok(rule) => rules.push(rule),
+                Err(err) if cfg.on_invalid == OnInvalid::Skip => {
+                    invalid += 1;
+                    tracing::error!(
+                        index,
+                        rule = rule_cfg.name.as_deref().unwrap_or("unnamed"),
+                        error = %err,
+                        "invalid rule skipped (on_invalid = skip)"
+                    );
+                }
+                Err(err) => {
+                    anyhow::bail!(
+                        "rule #{index} ({}): {err}",
+                        rule_cfg.name.as_deref().unwrap_or("unnamed")
+                    );
+                }
+            }
         }
         if skipped > 0 {
             tracing::info!(skipped, "disabled rules skipped");
         }
+        if invalid > 0 {
+            tracing::warn!(invalid, loaded = rules.len(), "invalid rules skipped, the remaining rules are active");
+        }
+        if cfg.block_suspicious_paths && matches!(cfg.suspicious_path_action, RuleAction::Allow) {
+            anyhow::bail!("suspicious_path_action must be block or challenge");
+        }
         Ok(Self {
             enabled: cfg.enabled,
             allow_wellknown: cfg.allow_wellknown,
+            suspicious_path_action: cfg
+                .block_suspicious_paths
+                .then(|| cfg.suspicious_path_action.clone()),
+            honeypot_paths: cfg
+                .honeypot_paths
+                .iter()
+                .map(|path| path.trim().to_string())
+                .filter(|path| !path.is_empty())
+                .collect(),
+            honeypot_ban: Duration::from_secs(cfg.honeypot_ban_secs.max(1)),
             default_action: cfg.default_action.clone(),
             rules,
         })
     }
 
+    /// `Uri::path()` 保留请求行里的百分号编码，axum 不做解码或 `..` 折叠；
+    /// 这里解码一次供 path_prefix/path_exact 使用，raw 系列直接看原始 path-and-query
     pub fn evaluate(
         &self,
-        path: &str,
+        uri: &Uri,
         headers: &HeaderMap,
         client_ip: Option<IpAddr>,
+    ) -> Option<RuleDecision> {
+        self.evaluate_at(uri, headers, client_ip, OffsetDateTime::now_utc())
+    }
+
+    /// 同 evaluate，但 active_hours 按给定时刻判断
+    pub fn evaluate_at(
+        &self,
+        uri: &Uri,
+        headers: &HeaderMap,
+        client_ip: Option<IpAddr>,
+        now: OffsetDateTime,
     ) -> Option<RuleDecision> {
         if !self.enabled {
             return None;
         }
+        let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+        let raw = uri.path_and_query().map(|p| p.as_str()).unwrap_or_else(|| uri.path());
+        let target = RequestPath { decoded: &path, raw };
         for rule in &self.rules {
-            if rule.matcher.is_match(path, headers, client_ip) {
+            if rule.matcher.is_match(&target, headers, client_ip, now) {
                 tracing::info!(rule = rule.name.as_deref().unwrap_or("unnamed"), "rule matched");
                 return Some(RuleDecision {
+                    rule: Some(rule.name.clone().unwrap_or_else(|| "unnamed".to_string())),
                     action: rule.action.clone(),
                     difficulty_delta: rule.difficulty_delta,
+                    difficulty_absolute: rule.difficulty_absolute,
+                    worker_type: rule.worker_type.clone(),
                 });
             }
         }
         Some(RuleDecision {
+            rule: None,
             action: self.default_action.clone(),
             difficulty_delta: 0,
+            difficulty_absolute: None,
+            worker_type: None,
         })
     }
+
+    /// 命中 honeypot_paths 时返回封禁时长；与 path_exact 一样比较解码一次后的 path，不受 enabled 影响
+    pub fn check_honeypot(&self, uri: &Uri) -> Option<Duration> {
+        if self.honeypot_paths.is_empty() {
+            return None;
+        }
+        let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+        self.honeypot_paths.contains(path.as_ref()).then_some(self.honeypot_ban)
+    }
+
+    /// block_suspicious_paths 开启且命中时返回动作和命中的模式名
+    pub fn check_suspicious_path(&self, uri: &Uri) -> Option<(RuleAction, &'static str)> {
+        let action = self.suspicious_path_action.as_ref()?;
+        let raw = uri.path_and_query().map(|p| p.as_str()).unwrap_or_else(|| uri.path());
+        suspicious_path_pattern(raw).map(|pattern| (action.clone(), pattern))
+    }
+}
+
+/// 在原始、解码一次、解码两次三种形态上查找攻击特征，覆盖单层和双层编码的绕过
+fn suspicious_path_pattern(raw: &str) -> Option<&'static str> {
+    let lower = raw.to_ascii_lowercase();
+    // %c0/%c1 开头的字节在合法 UTF-8 中不会出现，%e0%80 / %f0%80 是三、四字节的过长形式
+    if ["%c0", "%c1", "%e0%80", "%f0%80"].iter().any(|p| lower.contains(p)) {
+        return Some("overlong utf-8 encoding");
+    }
+    let once = percent_decode_str(&lower).decode_utf8_lossy();
+    let twice = percent_decode_str(&once).decode_utf8_lossy();
+    if [lower.as_str(), &once, &twice].iter().any(|form| form.contains('\0')) {
+        return Some("null byte");
+    }
+    if [lower.as_str(), &once, &twice].iter().any(|form| has_dot_dot_segment(form)) {
+        return Some("path traversal");
+    }
+    None
+}
+
+/// 以 `/`、`\`、`?`、`&`、`=` 切分后存在恰为 ".." 的段，查询参数里的 `file=../../etc/passwd` 也算
+fn has_dot_dot_segment(value: &str) -> bool {
+    value.split(['/', '\\', '?', '&', '=']).any(|segment| segment == "..")
+}
+
+struct RequestPath<'a> {
+    decoded: &'a str,
+    raw: &'a str,
 }
 
 impl Matcher {
-    fn is_match(&self, path: &str, headers: &HeaderMap, client_ip: Option<IpAddr>) -> bool {
+    fn is_match(&self, path: &RequestPath<'_>, headers: &HeaderMap, client_ip: Option<IpAddr>, now: OffsetDateTime) -> bool {
+        if let Some(hours) = &self.active_hours {
+            if !hours.contains(now) {
+                return false;
+            }
+        }
         if let Some(prefix) = &self.path_prefix {
-            if !path.starts_with(prefix) {
+            if !path.decoded.starts_with(prefix) {
                 return false;
             }
         }
         if let Some(exact) = &self.path_exact {
-            if path != exact {
+            if path.decoded != exact {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.path_regex {
+            if !regex.is_match(path.decoded) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.path_raw_prefix {
+            if !path.raw.starts_with(prefix) {
+                return false;
+            }
+        }
+        if let Some(needle) = &self.path_raw_contains {
+            if !path.raw.to_ascii_lowercase().contains(needle) {
                 return false;
             }
         }
@@ -141,19 +282,32 @@ impl Matcher {
                 return false;
             }
         }
-        if self.path_prefix.is_none()
-            && self.path_exact.is_none()
-            && self.header.is_none()
-            && self.ip_nets.is_empty()
-        {
-            return true;
+        if !self.languages.is_empty() {
+            let Some(tag) = preferred_language(headers) else {
+                return false;
+            };
+            if !self.languages.iter().any(|range| language_matches(range, &tag)) {
+                return false;
+            }
         }
         true
     }
 }
 
+impl ActiveHours {
+    fn contains(&self, now: OffsetDateTime) -> bool {
+        let local = now.to_offset(self.offset);
+        let minute = local.hour() as u16 * 60 + local.minute() as u16;
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
 impl HeaderPredicate {
-    fn is_match(&self, headers: &HeaderMap) -> bool {
+    pub(crate) fn is_match(&self, headers: &HeaderMap) -> bool {
         let Some(value) = headers.get_str(self.name.as_str()) else {
             return false;
         };
@@ -168,7 +322,107 @@ impl HeaderPredicate {
     }
 }
 
-fn parse_ip_nets(values: &[String]) -> anyhow::Result<Vec<IpNet>> {
+fn build_rule(rule_cfg: &RuleConfig) -> anyhow::Result<Rule> {
+    let ip_nets = parse_ip_nets(rule_cfg.ip_cidr.as_deref().unwrap_or_default())?;
+    let header = rule_cfg.header.as_ref().map(to_header_predicate).transpose()?;
+    let languages = parse_languages(rule_cfg.accept_language.as_deref().unwrap_or_default())?;
+    let active_hours = match &rule_cfg.active_hours {
+        Some(window) => Some(parse_active_hours(window, rule_cfg.timezone.as_deref())?),
+        None if rule_cfg.timezone.is_some() => anyhow::bail!("timezone requires active_hours"),
+        None => None,
+    };
+    let worker_type = rule_cfg
+        .worker_type
+        .as_ref()
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty());
+    if let Some(value) = &worker_type {
+        if !is_valid_worker_type(value) {
+            anyhow::bail!("worker_type must be wasm or native");
+        }
+    }
+    if rule_cfg.difficulty_absolute.is_some() && rule_cfg.difficulty_delta.is_some() {
+        tracing::warn!(
+            rule = rule_cfg.name.as_deref().unwrap_or("unnamed"),
+            "both difficulty_absolute and difficulty_delta set, difficulty_delta is ignored"
+        );
+    }
+    let path_regex = rule_cfg
+        .path_regex
+        .as_deref()
+        .map(|pattern| Regex::new(pattern).map_err(|err| anyhow::anyhow!("invalid path_regex {pattern:?}: {err}")))
+        .transpose()?;
+    let matcher = Matcher {
+        path_prefix: rule_cfg.path_prefix.clone(),
+        path_exact: rule_cfg.path_exact.clone(),
+        path_regex,
+        path_raw_prefix: rule_cfg.path_raw_prefix.clone(),
+        path_raw_contains: rule_cfg.path_raw_contains.as_ref().map(|s| s.to_ascii_lowercase()),
+        header,
+        ip_nets,
+        languages,
+        active_hours,
+    };
+    Ok(Rule {
+        name: rule_cfg.name.clone(),
+        action: rule_cfg.action.clone(),
+        difficulty_delta: rule_cfg.difficulty_delta.unwrap_or(0),
+        difficulty_absolute: rule_cfg.difficulty_absolute,
+        worker_type,
+        matcher,
+    })
+}
+
+fn parse_active_hours(window: &str, timezone: Option<&str>) -> anyhow::Result<ActiveHours> {
+    let (start, end) = window
+        .split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("invalid active_hours {window:?}: expected \"HH:MM-HH:MM\""))?;
+    let start = parse_clock(start).ok_or_else(|| anyhow::anyhow!("invalid active_hours start in {window:?}"))?;
+    let end = parse_clock(end).ok_or_else(|| anyhow::anyhow!("invalid active_hours end in {window:?}"))?;
+    if start == end {
+        anyhow::bail!("active_hours {window:?} is empty");
+    }
+    let offset = match timezone {
+        Some(raw) => parse_utc_offset(raw).ok_or_else(|| {
+            anyhow::anyhow!("invalid timezone {raw:?}: expected UTC or a fixed offset like +08:00")
+        })?,
+        None => UtcOffset::UTC,
+    };
+    Ok(ActiveHours { start, end, offset })
+}
+
+/// "HH:MM" 转为当天分钟数；允许 "24:00" 表示一天结束
+fn parse_clock(raw: &str) -> Option<u16> {
+    let (hour, minute) = raw.trim().split_once(':')?;
+    let hour: u16 = hour.parse().ok()?;
+    let minute: u16 = minute.parse().ok()?;
+    if minute >= 60 || hour > 24 || (hour == 24 && minute != 0) {
+        return None;
+    }
+    Some(hour * 60 + minute)
+}
+
+/// 只支持固定偏移：time 不带时区数据库，夏令时地区需自行按季节调整
+fn parse_utc_offset(raw: &str) -> Option<UtcOffset> {
+    let raw = raw.trim();
+    if raw.eq_ignore_ascii_case("utc") || raw == "Z" {
+        return Some(UtcOffset::UTC);
+    }
+    let (sign, rest) = match raw.as_bytes().first()? {
+        b'+' => (1i8, &raw[1..]),
+        b'-' => (-1i8, &raw[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i8 = hours.parse().ok()?;
+    let minutes: i8 = minutes.parse().ok()?;
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return None;
+    }
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+pub(crate) fn parse_ip_nets(values: &[String]) -> anyhow::Result<Vec<IpNet>> {
     let mut nets = Vec::new();
     for raw in values {
         let net: IpNet = raw
@@ -179,7 +433,51 @@ fn parse_ip_nets(values: &[String]) -> anyhow::Result<Vec<IpNet>> {
     Ok(nets)
 }
 
-fn to_header_predicate(match_cfg: &HeaderMatch) -> anyhow::Result<HeaderPredicate> {
+fn parse_languages(values: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut languages = Vec::new();
+    for raw in values {
+        let tag = raw.trim().to_ascii_lowercase();
+        if tag.is_empty() || tag == "*" {
+            anyhow::bail!("invalid accept_language tag: {raw:?}");
+        }
+        languages.push(tag);
+    }
+    Ok(languages)
+}
+
+/// Accept-Language 中 q 值最高的语言标签（同分取靠前者），小写；只看首选语言，
+/// 避免在列表末尾附带一个低权重语言就能命中规则
+fn preferred_language(headers: &HeaderMap) -> Option<String> {
+    let raw = headers.get_str("accept-language")?;
+    let mut best: Option<(&str, f32)> = None;
+    for part in raw.split(',') {
+        let mut iter = part.trim().split(';');
+        let tag = iter.next().unwrap_or("").trim();
+        if tag.is_empty() || tag == "*" {
+            continue;
+        }
+        let mut q = 1.0f32;
+        for param in iter {
+            if let Some(value) = param.trim().strip_prefix("q=") {
+                q = value.trim().parse().unwrap_or(0.0);
+            }
+        }
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((tag, q));
+        }
+    }
+    best.map(|(tag, _)| tag.to_ascii_lowercase())
+}
+
+/// 按子标签边界做前缀匹配：`en` 命中 `en`、`en-us`，但不命中 `eng`
+fn language_matches(range: &str, tag: &str) -> bool {
+    tag == range
+        || tag
+            .strip_prefix(range)
+            .is_some_and(|rest| rest.starts_with('-'))
+}
+
+pub(crate) fn to_header_predicate(match_cfg: &HeaderMatch) -> anyhow::Result<HeaderPredicate> {
     let name = match_cfg.name.trim();
     if name.is_empty() {
         anyhow::bail!("header.name must be set");
@@ -197,3 +495,293 @@ fn to_header_predicate(match_cfg: &HeaderMatch) -> anyhow::Result<HeaderPredicat
 pub fn clamp_difficulty(value: i32) -> i32 {
     value.clamp(0, 10)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn engine(rules: Vec<RuleConfig>) -> RulesEngine {
+        RulesEngine::from_config(&RulesConfig { enabled: true, rule: rules, ..RulesConfig::default() }).unwrap()
+    }
+
+    /// 命中的规则名，走 default_action 时为 None
+    fn matched(engine: &RulesEngine, uri: &str, headers: &HeaderMap) -> Option<String> {
+        engine.evaluate(&uri.parse().unwrap(), headers, None).unwrap().rule
+    }
+
+    fn with_language(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("accept-language", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn accept_language_matches_on_primary_subtag() {
+        let engine = engine(vec![RuleConfig {
+            name: Some("english".to_string()),
+            action: RuleAction::Allow,
+            accept_language: Some(vec!["EN".to_string()]),
+            ..RuleConfig::default()
+        }]);
+        assert_eq!(matched(&engine, "/", &with_language("en")).as_deref(), Some("english"));
+        assert_eq!(matched(&engine, "/", &with_language("en-US,en;q=0.9")).as_deref(), Some("english"));
+        assert_eq!(matched(&engine, "/", &with_language("zh-CN,zh;q=0.9,en;q=0.8")), None);
+        assert_eq!(matched(&engine, "/", &with_language("eng")), None);
+        assert_eq!(matched(&engine, "/", &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn accept_language_range_can_be_a_full_tag() {
+        let engine = engine(vec![RuleConfig {
+            name: Some("mainland".to_string()),
+            accept_language: Some(vec!["zh-cn".to_string()]),
+            ..RuleConfig::default()
+        }]);
+        assert_eq!(matched(&engine, "/", &with_language("zh-CN")).as_deref(), Some("mainland"));
+        assert_eq!(matched(&engine, "/", &with_language("zh-TW")), None);
+        assert_eq!(matched(&engine, "/", &with_language("zh")), None);
+    }
+
+    fn named(name: &str, cfg: RuleConfig) -> RuleConfig {
+        RuleConfig { name: Some(name.to_string()), ..cfg }
+    }
+
+    #[test]
+    fn decoded_prefix_sees_through_one_layer_of_encoding() {
+        let engine = engine(vec![named("admin", RuleConfig { path_prefix: Some("/admin".to_string()), ..RuleConfig::default() })]);
+        let none = HeaderMap::new();
+        assert_eq!(matched(&engine, "/admin/users", &none).as_deref(), Some("admin"));
+        assert_eq!(matched(&engine, "/%61dmin/users", &none).as_deref(), Some("admin"));
+        // 只解码一次：双重编码解出的是 "/%61dmin"，不再命中
+        assert_eq!(matched(&engine, "/%2561dmin/users", &none), None);
+    }
+
+    #[test]
+    fn raw_matchers_see_the_encoding_itself() {
+        let engine = engine(vec![
+            named("raw-admin", RuleConfig { path_raw_prefix: Some("/admin".to_string()), ..RuleConfig::default() }),
+            named("double-encoded", RuleConfig { path_raw_contains: Some("%25".to_string()), ..RuleConfig::default() }),
+        ]);
+        let none = HeaderMap::new();
+        assert_eq!(matched(&engine, "/admin", &none).as_deref(), Some("raw-admin"));
+        assert_eq!(matched(&engine, "/%61dmin", &none), None);
+        assert_eq!(matched(&engine, "/%2561dmin", &none).as_deref(), Some("double-encoded"));
+        assert_eq!(matched(&engine, "/search?q=%2e%2E%252f", &none).as_deref(), Some("double-encoded"));
+    }
+
+    fn suspicious(uri: &str) -> Option<&'static str> {
+        let engine = RulesEngine::from_config(&RulesConfig { block_suspicious_paths: true, ..RulesConfig::default() }).unwrap();
+        engine.check_suspicious_path(&uri.parse().unwrap()).map(|(_, pattern)| pattern)
+    }
+
+    #[test]
+    fn suspicious_paths_are_detected() {
+        assert_eq!(suspicious("/../../etc/passwd"), Some("path traversal"));
+        assert_eq!(suspicious("/foo%00.php"), Some("null byte"));
+        assert_eq!(suspicious("/static/%2e%2e/%2e%2e/etc/passwd"), Some("path traversal"));
+        assert_eq!(suspicious("/download?file=%252e%252e%252fsecret"), Some("path traversal"));
+        assert_eq!(suspicious("/foo%2500.php"), Some("null byte"));
+        assert_eq!(suspicious("/%c0%ae%c0%ae/etc/passwd"), Some("overlong utf-8 encoding"));
+    }
+
+    #[test]
+    fn ordinary_paths_are_not_suspicious() {
+        assert_eq!(suspicious("/"), None);
+        assert_eq!(suspicious("/docs/v1..v2/changes"), None);
+        assert_eq!(suspicious("/search?q=..."), None);
+        assert_eq!(suspicious("/%E4%BD%A0%E5%A5%BD"), None);
+    }
+
+    #[test]
+    fn suspicious_check_is_off_by_default() {
+        let engine = RulesEngine::from_config(&RulesConfig::default()).unwrap();
+        assert!(engine.check_suspicious_path(&"/../../etc/passwd".parse().unwrap()).is_none());
+    }
+
+    fn one_bad_rule_among_many(on_invalid: OnInvalid) -> anyhow::Result<RulesEngine> {
+        RulesEngine::from_config(&RulesConfig {
+            enabled: true,
+            on_invalid,
+            rule: vec![
+                named("first", RuleConfig { path_prefix: Some("/a".to_string()), ..RuleConfig::default() }),
+                named(
+                    "bad-cidr",
+                    RuleConfig {
+                        path_prefix: Some("/c".to_string()),
+                        ip_cidr: Some(vec!["10.0.0.0/33".to_string()]),
+                        ..RuleConfig::default()
+                    },
+                ),
+                named("last", RuleConfig { path_prefix: Some("/b".to_string()), ..RuleConfig::default() }),
+            ],
+            ..RulesConfig::default()
+        })
+    }
+
+    #[test]
+    fn invalid_rule_fails_the_whole_set_by_default() {
+        let err = one_bad_rule_among_many(OnInvalid::default()).err().unwrap().to_string();
+        assert!(err.starts_with("rule #1 (bad-cidr):"), "{err}");
+    }
+
+    #[test]
+    fn invalid_rule_is_dropped_under_skip() {
+        let engine = one_bad_rule_among_many(OnInvalid::Skip).unwrap();
+        let none = HeaderMap::new();
+        assert_eq!(matched(&engine, "/a", &none).as_deref(), Some("first"));
+        assert_eq!(matched(&engine, "/b", &none).as_deref(), Some("last"));
+        assert_eq!(matched(&engine, "/c", &none), None);
+    }
+
+    /// 2024-01-01 当天的 UTC 时刻
+    fn utc(hour: i64, minute: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_704_067_200 + hour * 3600 + minute * 60).unwrap()
+    }
+
+    fn scheduled(window: &str, timezone: Option<&str>) -> RulesEngine {
+        engine(vec![named(
+            "scheduled",
+            RuleConfig {
+                active_hours: Some(window.to_string()),
+                timezone: timezone.map(str::to_string),
+                ..RuleConfig::default()
+            },
+        )])
+    }
+
+    fn active_at(engine: &RulesEngine, now: OffsetDateTime) -> bool {
+        engine.evaluate_at(&"/".parse().unwrap(), &HeaderMap::new(), None, now).unwrap().rule.is_some()
+    }
+
+    #[test]
+    fn active_hours_is_half_open() {
+        let engine = scheduled("09:00-17:00", None);
+        assert!(!active_at(&engine, utc(8, 59)));
+        assert!(active_at(&engine, utc(9, 0)));
+        assert!(active_at(&engine, utc(16, 59)));
+        assert!(!active_at(&engine, utc(17, 0)));
+    }
+
+    #[test]
+    fn active_hours_can_span_midnight() {
+        let engine = scheduled("22:00-06:00", None);
+        assert!(active_at(&engine, utc(23, 30)));
+        assert!(active_at(&engine, utc(0, 0)));
+        assert!(active_at(&engine, utc(5, 59)));
+        assert!(!active_at(&engine, utc(6, 0)));
+        assert!(!active_at(&engine, utc(12, 0)));
+        assert!(!active_at(&engine, utc(21, 59)));
+    }
+
+    #[test]
+    fn active_hours_follow_the_timezone() {
+        // +08:00 的 22:00-06:00 即 UTC 14:00-22:00
+        let engine = scheduled("22:00-06:00", Some("+08:00"));
+        assert!(!active_at(&engine, utc(13, 59)));
+        assert!(active_at(&engine, utc(14, 0)));
+        assert!(active_at(&engine, utc(21, 59)));
+        assert!(!active_at(&engine, utc(23, 30)));
+    }
+
+    #[test]
+    fn invalid_schedule_is_a_config_error() {
+        let build = |window: Option<&str>, timezone: Option<&str>| {
+            RulesEngine::from_config(&RulesConfig {
+                enabled: true,
+                rule: vec![RuleConfig {
+                    active_hours: window.map(str::to_string),
+                    timezone: timezone.map(str::to_string),
+                    ..RuleConfig::default()
+                }],
+                ..RulesConfig::default()
+            })
+        };
+        assert!(build(Some("22:00-06:00"), Some("Asia/Shanghai")).is_err());
+        assert!(build(Some("22:00-06:00"), Some("+15:00")).is_err());
+        assert!(build(Some("25:00-06:00"), None).is_err());
+        assert!(build(Some("08:00-08:00"), None).is_err());
+        assert!(build(Some("08:00"), None).is_err());
+        assert!(build(None, Some("UTC")).is_err());
+        assert!(build(Some("00:00-24:00"), Some("-05:30")).is_ok());
+    }
+
+    fn difficulty_at(engine: &RulesEngine, uri: &str, base: i32) -> i32 {
+        engine.evaluate(&uri.parse().unwrap(), &HeaderMap::new(), None).unwrap().effective_difficulty(base)
+    }
+
+    fn difficulty_rules() -> RulesEngine {
+        engine(vec![
+            named("absolute", RuleConfig { path_prefix: Some("/api".to_string()), difficulty_absolute: Some(5), ..RuleConfig::default() }),
+            named("delta", RuleConfig { path_prefix: Some("/slow".to_string()), difficulty_delta: Some(2), ..RuleConfig::default() }),
+            named(
+                "both",
+                RuleConfig {
+                    path_prefix: Some("/both".to_string()),
+                    difficulty_delta: Some(2),
+                    difficulty_absolute: Some(6),
+                    ..RuleConfig::default()
+                },
+            ),
+            named("too-hard", RuleConfig { path_prefix: Some("/max".to_string()), difficulty_absolute: Some(99), ..RuleConfig::default() }),
+        ])
+    }
+
+    #[test]
+    fn absolute_difficulty_ignores_the_base() {
+        let engine = difficulty_rules();
+        assert_eq!(difficulty_at(&engine, "/api/users", 3), 5);
+        assert_eq!(difficulty_at(&engine, "/api/users", 9), 5);
+        assert_eq!(difficulty_at(&engine, "/max", 3), 10);
+    }
+
+    #[test]
+    fn delta_difficulty_builds_on_the_base() {
+        let engine = difficulty_rules();
+        assert_eq!(difficulty_at(&engine, "/slow", 3), 5);
+        assert_eq!(difficulty_at(&engine, "/slow", 9), 10);
+        // 未命中规则时走 default_action，难度即基础难度
+        assert_eq!(difficulty_at(&engine, "/other", 3), 3);
+    }
+
+    #[test]
+    fn absolute_wins_over_delta() {
+        let engine = difficulty_rules();
+        assert_eq!(difficulty_at(&engine, "/both", 3), 6);
+        assert_eq!(difficulty_at(&engine, "/both", 8), 6);
+    }
+
+    fn regex_rule(pattern: &str) -> RuleConfig {
+        named("regex", RuleConfig { path_regex: Some(pattern.to_string()), ..RuleConfig::default() })
+    }
+
+    #[test]
+    fn path_regex_is_unanchored_on_the_decoded_path() {
+        let engine = engine(vec![regex_rule(r"/api/v\d+/")]);
+        let none = HeaderMap::new();
+        assert_eq!(matched(&engine, "/api/v2/users", &none).as_deref(), Some("regex"));
+        assert_eq!(matched(&engine, "/proxy/api/v10/x", &none).as_deref(), Some("regex"));
+        assert_eq!(matched(&engine, "/api/%762/users", &none).as_deref(), Some("regex"));
+        assert_eq!(matched(&engine, "/api/vx/users", &none), None);
+        // 只看 path，不看查询串
+        assert_eq!(matched(&engine, "/search?next=/api/v1/", &none), None);
+    }
+
+    #[test]
+    fn anchored_path_regex_needs_a_full_match() {
+        let engine = engine(vec![regex_rule(r"^/item/[0-9]+$")]);
+        let none = HeaderMap::new();
+        assert_eq!(matched(&engine, "/item/42", &none).as_deref(), Some("regex"));
+        assert_eq!(matched(&engine, "/item/42/edit", &none), None);
+        assert_eq!(matched(&engine, "/shop/item/42", &none), None);
+    }
+
+    #[test]
+    fn invalid_path_regex_fails_from_config() {
+        let err = RulesEngine::from_config(&RulesConfig { enabled: true, rule: vec![regex_rule("/api/(v1")], ..RulesConfig::default() })
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("invalid path_regex"), "{err}");
+    }
+}
@@ -4,14 +4,14 @@ use std::time::{Duration, Instant};
 
 use notify::{EventKind, RecursiveMode, Watcher};
 
-use crate::config::{Config, RulesConfig};
+use crate::config::{primary_config_path, read_merged_toml, Config, RulesConfig};
 use crate::rules::RulesEngine;
 use crate::state::AppState;
 
 const RELOAD_COOLDOWN: Duration = Duration::from_secs(2);
 
-pub fn start_rules_watcher(state: Arc<AppState>, config_path: String) {
-    let (watch_path, is_external) = resolve_watch_path(&state.config, &config_path);
+pub fn start_rules_watcher(state: Arc<AppState>, config_paths: Vec<String>) {
+    let (watch_path, is_external) = resolve_watch_path(&state.config, &config_paths);
     let watch_dir = watch_path
         .parent()
         .unwrap_or(Path::new("."))
@@ -83,7 +83,7 @@ pub fn start_rules_watcher(state: Arc<AppState>, config_path: String) {
                     while rx.try_recv().is_ok() {}
 
                     last_reload = Instant::now();
-                    reload_rules(&state, &watch_path, is_external);
+                    reload_rules(&state, &watch_path, is_external, &config_paths);
                 }
                 Ok(Err(e)) => {
                     tracing::warn!(error = %e, "file watcher error");
@@ -97,25 +97,27 @@ pub fn start_rules_watcher(state: Arc<AppState>, config_path: String) {
     });
 }
 
-fn resolve_watch_path(config: &Config, config_path: &str) -> (PathBuf, bool) {
+/// 多配置文件时只监听最后一个（覆盖层），重载时仍按完整顺序合并
+fn resolve_watch_path(config: &Config, config_paths: &[String]) -> (PathBuf, bool) {
     if let Some(ref rules_file) = config.rules.rules_file {
         if !rules_file.trim().is_empty() {
-            let config_dir = Path::new(config_path)
+            let config_dir = Path::new(primary_config_path(config_paths))
                 .parent()
                 .unwrap_or(Path::new("."));
             return (config_dir.join(rules_file), true);
         }
     }
-    (PathBuf::from(config_path), false)
+    let last = config_paths.last().map(String::as_str).unwrap_or("config.toml");
+    (PathBuf::from(last), false)
 }
 
-fn reload_rules(state: &AppState, path: &Path, is_external: bool) {
+fn reload_rules(state: &AppState, path: &Path, is_external: bool, config_paths: &[String]) {
     tracing::info!(path = %path.display(), "reloading rules");
 
     let rules_config = if is_external {
         load_external_rules(path)
     } else {
-        load_inline_rules(path)
+        load_inline_rules(config_paths)
     };
 
     match rules_config {
@@ -154,10 +156,12 @@ fn load_external_rules(path: &Path) -> anyhow::Result<RulesConfig> {
     Ok(cfg)
 }
 
-fn load_inline_rules(path: &Path) -> anyhow::Result<RulesConfig> {
-    let raw = std::fs::read_to_string(path)
-        .map_err(|e| anyhow::anyhow!("read {}: {}", path.display(), e))?;
-    let cfg: Config = toml::from_str(&raw)
-        .map_err(|e| anyhow::anyhow!("parse {}: {}", path.display(), e))?;
+fn load_inline_rules(config_paths: &[String]) -> anyhow::Result<RulesConfig> {
+    let Some(merged) = read_merged_toml(config_paths)? else {
+        return Ok(RulesConfig::default());
+    };
+    let cfg: Config = merged
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("parse {}: {}", config_paths.join(", "), e))?;
     Ok(cfg.rules)
 }
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+use time::OffsetDateTime;
+
+use crate::config::Config;
+use crate::crypto;
+use crate::rules::clamp_difficulty;
+use crate::storage::{IpHash, Scope, Seed, Task, TaskId, UaHash};
+
+/// 超过该难度的自检耗时不可控，直接跳过
+const MAX_SELF_TEST_DIFFICULTY: i32 = 6;
+const SELF_TEST_BUDGET: Duration = Duration::from_secs(10);
+
+/// 启动自检：生成一个任务，在进程内求解，再交给 verify_pow 校验。
+/// 用于尽早发现服务端与 worker 的哈希原像格式不一致
+pub async fn run(config: &Config) -> anyhow::Result<()> {
+    let difficulty = clamp_difficulty(config.pow.difficulty);
+    if difficulty > MAX_SELF_TEST_DIFFICULTY {
+        tracing::warn!(
+            difficulty,
+            max = MAX_SELF_TEST_DIFFICULTY,
+            "pow self-test skipped: difficulty too high to grind at startup"
+        );
+        return Ok(());
+    }
+
+    let task = Task {
+        task_id: TaskId::from(crypto::generate_random_id()?),
+        seed: Seed(crypto::generate_random_seed()?),
+        bits: (difficulty * 4) as u32,
+        exp: OffsetDateTime::now_utc().unix_timestamp() + 120,
+        scope: Scope("self-test".to_string()),
+        ua_hash: UaHash(crypto::compute_ua_hash("cowcat-rs self-test")),
+        ip_hash: IpHash(String::new()),
+        issued_at_ms: 0,
+        chain_length: 1,
+        captcha_answer: None,
+        commitment_nonce: None,
+    };
+
+    let started = Instant::now();
+    let deadline = started + SELF_TEST_BUDGET;
+    let solve_task = task.clone();
+    let scheme = config.pow.scheme.clone();
+    let algo = config.pow.hash_algo();
+    let outcome =
+        tokio::task::spawn_blocking(move || crypto::solve_pow(&scheme, algo, &solve_task, deadline)).await?;
+    let elapsed = started.elapsed();
+
+    let attempts = outcome.attempts;
+    let Some(nonce) = outcome.nonce else {
+        tracing::warn!(
+            difficulty,
+            budget_ms = SELF_TEST_BUDGET.as_millis() as u64,
+            "pow self-test timed out before finding a nonce, continuing startup"
+        );
+        return Ok(());
+    };
+
+    if !crypto::verify_pow(&config.pow.scheme, algo, &task, &nonce) {
+        let preimage = crypto::pow_preimage(&config.pow.scheme, &task, &nonce);
+        anyhow::bail!(
+            "pow self-test failed: verify_pow rejected in-process solution nonce={nonce} preimage={}",
+            String::from_utf8_lossy(&preimage)
+        );
+    }
+
+    tracing::info!(
+        difficulty,
+        hash_algo = algo.name(),
+        bits = task.bits,
+        attempts,
+        elapsed_ms = elapsed.as_millis() as u64,
+        "pow self-test passed (single core solve time, use as a calibration hint)"
+    );
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::net::TcpListener;
+use tower::Service;
+
+use crate::config::ServerConfig;
+use crate::proxy_protocol;
+use crate::tls::{TlsAcceptor, TlsFingerprint};
+
+/// 等价于 `axum::serve(..).into_make_service_with_connect_info::<SocketAddr>()`，
+/// 但可以设置入站连接的 keep-alive 与请求头读取超时：axum::serve 不暴露 hyper 的这些参数。
+/// 超时作用在连接层，早于 header 数量限制、pow_gate 等任何中间件，
+/// 逐字节发送请求头的慢速连接在进入应用之前就会被断开。
+/// proxy_protocol 为 true 时每个连接先读取 PROXY protocol 头部，以其中的客户端地址作为 ConnectInfo；
+/// tls 存在时随后完成 TLS 握手，连接指纹写入每个请求的扩展。两者同样受请求头读取超时约束，失败即关闭连接
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    cfg: &ServerConfig,
+    proxy_protocol: bool,
+    tls: Option<TlsAcceptor>,
+) -> std::io::Result<()> {
+    let header_timeout = (cfg.header_read_timeout_ms > 0).then(|| Duration::from_millis(cfg.header_read_timeout_ms));
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .keep_alive(cfg.http1_keepalive)
+        .header_read_timeout(header_timeout)
+        .timer(TokioTimer::new());
+
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                // 与 axum::serve 一致：accept 失败（如 fd 耗尽）时短暂退避后继续
+                tracing::debug!(error = %err, "accept failed");
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        let builder = builder.clone();
+        let tls = tls.clone();
+        if !proxy_protocol {
+            tokio::spawn(handshake_and_serve(builder, app, stream, remote, tls, header_timeout));
+            continue;
+        }
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            match with_timeout(header_timeout, proxy_protocol::read_header(&mut stream)).await {
+                Ok(client) => handshake_and_serve(builder, app, stream, client.unwrap_or(remote), tls, header_timeout).await,
+                // 头部缺失或格式错误：不交给 hyper，直接关闭连接
+                Err(err) => tracing::debug!(error = %err, remote = %remote, "proxy protocol header rejected"),
+            }
+        });
+    }
+}
+
+async fn handshake_and_serve<S>(
+    builder: Builder<TokioExecutor>,
+    app: Router,
+    stream: S,
+    remote: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    handshake_timeout: Option<Duration>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let Some(tls) = tls else {
+        return serve_connection(builder, app, stream, remote, None).await;
+    };
+    match with_timeout(handshake_timeout, tls.accept(stream)).await {
+        Ok((stream, fingerprint)) => serve_connection(builder, app, stream, remote, Some(fingerprint)).await,
+        Err(err) => tracing::debug!(error = %err, remote = %remote, "tls handshake failed"),
+    }
+}
+
+async fn with_timeout<T>(limit: Option<Duration>, fut: impl Future<Output = std::io::Result<T>>) -> std::io::Result<T> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, fut)
+            .await
+            .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out"))),
+        None => fut.await,
+    }
+}
+
+/// remote 为写入 ConnectInfo 的客户端地址：开启 PROXY protocol 时是头部转述的地址
+async fn serve_connection<S>(
+    builder: Builder<TokioExecutor>,
+    app: Router,
+    stream: S,
+    remote: SocketAddr,
+    fingerprint: Option<TlsFingerprint>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+        req.extensions_mut().insert(ConnectInfo::<SocketAddr>(remote));
+        if let Some(fingerprint) = &fingerprint {
+            req.extensions_mut().insert(fingerprint.clone());
+        }
+        app.clone().call(req.map(axum::body::Body::new))
+    });
+    if let Err(err) = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), service)
+        .await
+    {
+        tracing::debug!(error = %err, remote = %remote, "connection closed with error");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 把 ConnectInfo 里的客户端地址写进响应体的应用
+    fn echo_peer_app() -> Router {
+        Router::new().fallback(|ConnectInfo(peer): ConnectInfo<SocketAddr>| async move { peer.to_string() })
+    }
+
+    async fn start(proxy_protocol: bool, tls: Option<TlsAcceptor>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let cfg = ServerConfig { header_read_timeout_ms: 2000, ..crate::config::Config::default().server };
+        tokio::spawn(async move { serve(listener, echo_peer_app(), &cfg, proxy_protocol, tls).await });
+        addr
+    }
+
+    /// 发出 prefix 加一个 Connection: close 的请求，读到对端关闭为止
+    async fn exchange(addr: SocketAddr, prefix: &[u8]) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(prefix).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap_or_default();
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol_address_becomes_the_connect_info() {
+        let addr = start(true, None).await;
+        let v1 = exchange(addr, b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n").await;
+        assert!(v1.starts_with("HTTP/1.1 200"), "{v1}");
+        assert!(v1.ends_with("192.0.2.1:56324"), "{v1}");
+
+        let mut v2 = vec![0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a, 0x21, 0x11, 0x00, 0x0c];
+        v2.extend_from_slice(&[203, 0, 113, 9, 198, 51, 100, 2, 0x1f, 0x90, 0x01, 0xbb]);
+        let v2 = exchange(addr, &v2).await;
+        assert!(v2.ends_with("203.0.113.9:8080"), "{v2}");
+
+        // UNKNOWN 沿用套接字对端
+        let unknown = exchange(addr, b"PROXY UNKNOWN\r\n").await;
+        assert!(unknown.contains("\r\n\r\n127.0.0.1:"), "{unknown}");
+    }
+
+    #[tokio::test]
+    async fn missing_or_malformed_proxy_header_closes_the_connection() {
+        let addr = start(true, None).await;
+        for prefix in [&b""[..], b"PROXY TCP4 192.0.2.1 nope 1 2\r\n", b"PROXY TCP4 192.0.2.1 198.51.100.2 1 2\n"] {
+            assert_eq!(exchange(addr, prefix).await, "", "{:?}", String::from_utf8_lossy(prefix));
+        }
+        // 未开启时请求照常处理，对端就是套接字地址
+        let plain = exchange(start(false, None).await, b"").await;
+        assert!(plain.contains("\r\n\r\n127.0.0.1:"), "{plain}");
+    }
+
+    fn fixture(name: &str) -> String {
+        format!("{}/testdata/{name}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn fixture_acceptor() -> TlsAcceptor {
+        crate::tls::load_acceptor(&crate::config::TlsConfig {
+            cert_path: fixture("localhost.crt"),
+            key_path: fixture("localhost.key"),
+        })
+        .unwrap()
+    }
+
+    /// 只信任 testdata 自签证书的客户端；suites 为空时使用 ring 的默认套件
+    fn tls_client(suites: &[rustls::SupportedCipherSuite]) -> tokio_rustls::TlsConnector {
+        use rustls::pki_types::pem::PemObject;
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls::pki_types::CertificateDer::pem_file_iter(fixture("localhost.crt")).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut provider = rustls::crypto::ring::default_provider();
+        if !suites.is_empty() {
+            provider.cipher_suites = suites.to_vec();
+        }
+        let mut config = rustls::ClientConfig::builder_with_provider(std::sync::Arc::new(provider))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        tokio_rustls::TlsConnector::from(std::sync::Arc::new(config))
+    }
+
+    /// 先写入 prefix（PROXY 头部）再经 TLS 发一个请求，返回应用看到的 (客户端地址, 连接指纹)
+    async fn over_tls(addr: SocketAddr, client: &tokio_rustls::TlsConnector, prefix: &[u8]) -> (String, String) {
+        let mut tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tcp.write_all(prefix).await.unwrap();
+        let name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+        let mut stream = client.connect(name, tcp).await.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        // 对端不发 close_notify 就断开时 rustls 报 UnexpectedEof，此前读到的内容仍然完整
+        let _ = stream.read_to_end(&mut response).await;
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let (peer, fingerprint) = body.split_once(' ').unwrap();
+        (peer.to_string(), fingerprint.to_string())
+    }
+
+    async fn start_tls(proxy_protocol: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().fallback(
+            |ConnectInfo(peer): ConnectInfo<SocketAddr>, axum::Extension(fp): axum::Extension<TlsFingerprint>| async move {
+                format!("{peer} {}", fp.0)
+            },
+        );
+        let cfg = ServerConfig { header_read_timeout_ms: 2000, ..crate::config::Config::default().server };
+        let tls = fixture_acceptor();
+        tokio::spawn(async move { serve(listener, app, &cfg, proxy_protocol, Some(tls)).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn tls_handshake_with_the_fixture_cert_carries_a_stable_fingerprint() {
+        let addr = start_tls(false).await;
+        // 同一客户端的两个连接（第二个会恢复会话）指纹相同
+        let client = tls_client(&[]);
+        let (peer, first) = over_tls(addr, &client, b"").await;
+        assert!(peer.starts_with("127.0.0.1:"), "{peer}");
+        assert_eq!(first.len(), 32, "{first}");
+        assert_eq!(over_tls(addr, &client, b"").await.1, first);
+
+        // 能力列表不同的客户端得到不同的指纹
+        let narrow = tls_client(&[rustls::crypto::ring::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256]);
+        assert_ne!(over_tls(addr, &narrow, b"").await.1, first);
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol_header_precedes_the_tls_handshake() {
+        let addr = start_tls(true).await;
+        let client = tls_client(&[]);
+        let (peer, fingerprint) = over_tls(addr, &client, b"PROXY TCP6 2001:db8::7 2001:db8::1 40000 443\r\n").await;
+        assert_eq!(peer, "[2001:db8::7]:40000");
+        assert_eq!(fingerprint, over_tls(addr, &client, b"PROXY UNKNOWN\r\n").await.1);
+    }
+
+    #[tokio::test]
+    async fn plaintext_on_a_tls_listener_gets_no_http_response() {
+        let addr = start(false, Some(fixture_acceptor())).await;
+        // 握手失败时对端至多收到一个 TLS alert，然后连接关闭
+        let response = exchange(addr, b"").await;
+        assert!(!response.contains("HTTP/"), "{response:?}");
+    }
+}
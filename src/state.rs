@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -6,13 +8,22 @@ use axum::http::{HeaderMap, HeaderValue, StatusCode, Uri};
 use bytes::Bytes;
 use ring::rand::{SecureRandom, SystemRandom};
 
-use crate::config::{Config, ProxyHostRule};
+use crate::challenge_webhook::ChallengeWebhook;
+use crate::config::{Config, PowConfig, ProxyConfig, ProxyHostRule, StorageBackend};
+use crate::crypto::{CookieKeyring, PowVerifier, Verifier};
+use crate::ip_source::ip::TrustedProxies;
+use crate::metrics::Metrics;
+use crate::reputation::ReputationClient;
+use crate::risk::RiskModel;
 use crate::rules::RulesEngine;
+use crate::static_files::TemplateAssets;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 
-use crate::storage::TaskStore;
+use crate::proxy::forward::ResponseHeaderEdits;
+use crate::proxy::micro_cache::MicroCache;
+use crate::storage::{CaptchaTickets, ChallengeRateLimiter, FailureBans, MemoryTaskStore, RateLimiter, RedisTaskStore, TaskPool, TaskStoreBackend, VerifyRateLimiter};
 
 #[derive(Clone)]
 pub struct ProxyTarget {
@@ -26,6 +37,28 @@ pub struct ProxyTarget {
     pub x_forwarded_proto: HeaderValue,
 }
 
+/// 默认上游：proxy.targets 中的多个等价实例（未配置时只有 proxy.target 一个），按轮询选择
+pub struct UpstreamPool {
+    targets: Vec<ProxyTarget>,
+    next: AtomicUsize,
+}
+
+impl UpstreamPool {
+    pub fn targets(&self) -> &[ProxyTarget] {
+        &self.targets
+    }
+
+    /// 本次请求的轮询起点，每次调用前进一位
+    pub fn next_start(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % self.targets.len()
+    }
+
+    /// 轮询取下一个上游，用于不做故障转移的内部请求
+    pub fn pick(&self) -> &ProxyTarget {
+        &self.targets[self.next_start()]
+    }
+}
+
 #[derive(Clone)]
 pub struct HostProxyTarget {
     pub host: String,
@@ -38,57 +71,265 @@ pub struct FaviconCache {
     pub headers: HeaderMap,
     pub body: Bytes,
     pub cached_at: Instant,
+    /// 按状态码决定：2xx 为 1 小时，其余为 favicon.error_ttl_secs
+    pub ttl: Duration,
 }
 
 impl FaviconCache {
     pub fn is_valid(&self) -> bool {
-        self.cached_at.elapsed() < Duration::from_secs(3600) // 1 hour
+        self.cached_at.elapsed() < self.ttl
+    }
+
+    pub fn etag(&self) -> Option<&HeaderValue> {
+        self.headers.get(axum::http::header::ETAG)
+    }
+
+    pub fn last_modified(&self) -> Option<&HeaderValue> {
+        self.headers.get(axum::http::header::LAST_MODIFIED)
     }
+
+    /// 只有 2xx 条目过期后向上游发条件请求重新验证
+    pub fn has_validators(&self) -> bool {
+        self.status.is_success() && (self.etag().is_some() || self.last_modified().is_some())
+    }
+}
+
+/// /healthz 最近一次探测上游的结果：Ok 为上游状态码，Err 为失败原因
+pub struct UpstreamHealth {
+    pub checked_at: Instant,
+    pub result: Result<u16, String>,
 }
 
 pub struct AppState {
     pub config: Config,
     pub rules: ArcSwap<RulesEngine>,
-    pub task_store: Arc<TaskStore>,
-    pub server_secret: String,
-    pub template: String,
-    pub cowcat_image1: String,
-    pub cowcat_image2: String,
+    pub risk_model: Option<RiskModel>,
+    /// rules.reputation：外部 IP 信誉查询及其缓存
+    pub reputation: Option<Arc<ReputationClient>>,
+    /// 任务存储，按 [storage] backend 选择实现
+    pub task_store: Arc<dyn TaskStoreBackend>,
+    /// pow_verify 使用的挑战校验器，默认是 PoW
+    pub verifier: Box<dyn Verifier>,
+    /// pow.signed_tasks：任务签名密钥，由 server secret 派生，与 Cookie 签名密钥分开
+    pub task_sign_key: Option<ring::hmac::Key>,
+    /// pow.bind_commitment：下发承诺的签名密钥，同样由 server secret 派生
+    pub commitment_key: Option<ring::hmac::Key>,
+    /// Cookie 签名密钥：salt 派生的 primary 加上 pow.cookie_keys
+    pub cookie_keys: CookieKeyring,
+    /// 默认挑战页；pow.page.remote_refresh_secs 下由后台任务整体替换
+    pub template_assets: ArcSwap<TemplateAssets>,
+    pub host_template_assets: HashMap<String, Arc<TemplateAssets>>,
+    /// test_mode 下 /ok-page 的确认页模板，未开启 test_mode 时不加载
+    pub ok_page: Option<String>,
     pub proxy_client: Client<HttpConnector, axum::body::Body>,
     pub favicon_cache: Arc<tokio::sync::RwLock<Option<FaviconCache>>>,
-    pub proxy_target: ProxyTarget,
+    /// 持锁探测，同一时刻的多个探针只触发一次上游请求
+    pub upstream_health: tokio::sync::Mutex<Option<UpstreamHealth>>,
+    pub upstreams: UpstreamPool,
     pub proxy_host_targets: Vec<HostProxyTarget>,
+    /// proxy.allowed_hosts 规范化后的集合，仅 proxy.strict_host 时使用
+    pub allowed_hosts: HashSet<String>,
+    /// proxy.emit_forwarded_port 开启时预先算好的 X-Forwarded-Port 值
+    pub forwarded_port: Option<HeaderValue>,
+    pub response_headers: ResponseHeaderEdits,
+    /// pow.trusted_proxies 解析后的网段，决定是否采信转发头中的客户端 IP
+    pub trusted_proxies: TrustedProxies,
+    pub challenge_webhook: Option<Arc<ChallengeWebhook>>,
+    /// 运行时总开关，由管理接口切换；false 时所有流量直接放行
+    pub pow_enabled: AtomicBool,
+    /// drain 模式，由管理接口或 SIGUSR1 切换；true 时不再下发新挑战
+    pub draining: AtomicBool,
+    pub failure_bans: Option<Arc<FailureBans>>,
+    pub task_pool: Option<Arc<TaskPool>>,
+    /// pow.wasm_fallback 的按 IP 限流
+    pub fallback_limiter: Option<Arc<RateLimiter>>,
+    /// pow.first_request_grace：每个 IP 每个窗口只放行一次
+    pub grace_limiter: Option<Arc<RateLimiter>>,
+    /// pow.captcha_fallback 的按 IP 限流
+    pub captcha_limiter: Option<Arc<RateLimiter>>,
+    /// pow.captcha_fallback：/verify 失败后换取验证码的票据
+    pub captcha_tickets: Option<Arc<CaptchaTickets>>,
+    /// 验证码页模板，未开启 pow.captcha_fallback 时不加载
+    pub captcha_page: Option<String>,
+    pub verify_limiter: Option<VerifyRateLimiter>,
+    /// pow.challenge_rate_per_min 大于 0 时的按 IP 挑战下发限流
+    pub challenge_limiter: Option<Arc<ChallengeRateLimiter>>,
+    pub micro_cache: Option<Arc<MicroCache>>,
+    pub metrics: Metrics,
 }
 
 impl AppState {
     pub async fn new(config: Config) -> anyhow::Result<Self> {
         let rules = RulesEngine::from_config(&config.rules)?;
-        let task_store = TaskStore::new();
+        let risk_model = config.pow.risk_score.as_ref().map(RiskModel::from_config).transpose()?;
+        let reputation = config.rules.reputation.as_ref().map(ReputationClient::new);
+        let task_store: Arc<dyn TaskStoreBackend> = match config.storage.backend {
+            StorageBackend::Memory => MemoryTaskStore::new(),
+            StorageBackend::Redis => Arc::new(RedisTaskStore::connect(&config.storage).await?),
+        };
+        let verifier: Box<dyn Verifier> = Box::new(PowVerifier::new(config.pow.scheme.clone(), config.pow.hash_algo()));
+        // 蜜罐封禁复用同一张表：只配置了 honeypot_paths 时也要建表
+        let failure_bans = (config.pow.failure_ban.is_some() || !config.rules.honeypot_paths.is_empty())
+            .then(|| FailureBans::new(config.pow.failure_ban.as_ref()));
+        let task_pool = config.pow.task_pool.as_ref().map(TaskPool::new);
+        let fallback_limiter = config
+            .pow
+            .wasm_fallback
+            .as_ref()
+            .map(|cfg| RateLimiter::new(cfg.max_per_ip, Duration::from_secs(cfg.window_secs)));
+        let grace_limiter = config
+            .pow
+            .first_request_grace
+            .as_ref()
+            .map(|cfg| RateLimiter::new(1, Duration::from_secs(cfg.window_secs)));
+        let captcha_limiter = config
+            .pow
+            .captcha_fallback
+            .as_ref()
+            .map(|cfg| RateLimiter::new(cfg.max_per_ip, Duration::from_secs(cfg.window_secs)));
+        let captcha_tickets = config.pow.captcha_fallback.is_some().then(CaptchaTickets::new);
+        let verify_limiter = config.pow.verify_rate_limit.as_ref().map(VerifyRateLimiter::new);
+        let challenge_limiter = (config.pow.challenge_rate_per_min > 0)
+            .then(|| ChallengeRateLimiter::new(config.pow.challenge_rate_per_min));
+        let micro_cache = config
+            .proxy
+            .micro_cache
+            .enabled
+            .then(|| MicroCache::new(&config.proxy.micro_cache));
         let server_secret = build_server_secret(&config.pow.salt)?;
         tracing::debug!("server secret: {}", server_secret);
-        let (template, cowcat_image1, cowcat_image2) = crate::static_files::load_template_assets()?;
+        let task_sign_key = config.pow.signed_tasks.then(|| derive_hmac_key(&server_secret, b"cowcat task signature"));
+        let commitment_key = config.pow.bind_commitment.then(|| derive_hmac_key(&server_secret, b"cowcat challenge commitment"));
+        let cookie_keys = build_cookie_keyring(&config.pow, server_secret);
+        let template_assets = crate::static_files::load_template_assets(&config.pow).await?;
+        let host_template_assets = build_host_templates(&config, &template_assets)?;
+        let ok_page = if config.pow.test_mode {
+            Some(crate::static_files::load_ok_page(&config.pow, &template_assets)?)
+        } else {
+            None
+        };
+        let captcha_page = if config.pow.captcha_fallback.is_some() {
+            Some(crate::static_files::load_captcha_page(&config.pow)?)
+        } else {
+            None
+        };
+
+        let mut connector = HttpConnector::new();
+        if config.proxy.connect_timeout_ms > 0 {
+            connector.set_connect_timeout(Some(Duration::from_millis(config.proxy.connect_timeout_ms)));
+        }
+        let proxy_client = Client::builder(TokioExecutor::new()).build(connector);
 
-        let proxy_client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+        let mut upstream_targets = build_upstream_targets(&config.proxy)?;
+        let mut proxy_host_targets = build_host_targets(&config.proxy.host_rule)?;
+        if config.server.tls.is_some() {
+            // 自行终结 TLS 时客户端一侧总是 https，与上游地址的 scheme 无关
+            let https = HeaderValue::from_static("https");
+            for target in &mut upstream_targets {
+                target.x_forwarded_proto = https.clone();
+            }
+            for entry in &mut proxy_host_targets {
+                entry.target.x_forwarded_proto = https.clone();
+            }
+        }
+        let allowed_hosts = config
+            .proxy
+            .allowed_hosts
+            .iter()
+            .map(|host| normalize_host(host))
+            .filter(|host| !host.is_empty())
+            .collect();
+        let response_headers = ResponseHeaderEdits::from_config(&config.proxy.response_headers)?;
+        let trusted_proxies = TrustedProxies::from_config(&config.pow.trusted_proxies)?;
+        let forwarded_port = if config.proxy.emit_forwarded_port {
+            Some(listen_port_value(&config.server.listen)?)
+        } else {
+            None
+        };
 
-        let proxy_target = parse_proxy_target(&config.proxy.target)?;
-        let proxy_host_targets = build_host_targets(&config.proxy.host_rule)?;
+        let webhook_endpoint = config.pow.challenge_webhook.trim();
+        let challenge_webhook = if webhook_endpoint.is_empty() {
+            None
+        } else {
+            Some(ChallengeWebhook::start(webhook_endpoint)?)
+        };
 
         Ok(Self {
             config,
             rules: ArcSwap::new(Arc::new(rules)),
+            risk_model,
+            reputation,
             task_store,
-            server_secret,
-            template,
-            cowcat_image1,
-            cowcat_image2,
+            verifier,
+            task_sign_key,
+            commitment_key,
+            cookie_keys,
+            template_assets: ArcSwap::from_pointee(template_assets),
+            host_template_assets,
+            ok_page,
             proxy_client,
             favicon_cache: Arc::new(tokio::sync::RwLock::new(None)),
-            proxy_target,
+            upstream_health: tokio::sync::Mutex::new(None),
+            upstreams: UpstreamPool { targets: upstream_targets, next: AtomicUsize::new(0) },
             proxy_host_targets,
+            allowed_hosts,
+            forwarded_port,
+            response_headers,
+            trusted_proxies,
+            challenge_webhook,
+            pow_enabled: AtomicBool::new(true),
+            draining: AtomicBool::new(false),
+            failure_bans,
+            task_pool,
+            fallback_limiter,
+            grace_limiter,
+            captcha_limiter,
+            captcha_tickets,
+            captcha_page,
+            verify_limiter,
+            challenge_limiter,
+            micro_cache,
+            metrics: Metrics::default(),
         })
     }
 }
 
+impl AppState {
+    /// 按请求 Host 选择挑战页资源，未配置的 host 使用默认页
+    pub fn template_for(&self, headers: &HeaderMap) -> Arc<TemplateAssets> {
+        if self.host_template_assets.is_empty() {
+            return self.template_assets.load_full();
+        }
+        let host = headers
+            .get(axum::http::header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .map(normalize_host)
+            .unwrap_or_default();
+        self.host_template_assets
+            .get(&host)
+            .cloned()
+            .unwrap_or_else(|| self.template_assets.load_full())
+    }
+}
+
+fn build_host_templates(
+    config: &Config,
+    default: &TemplateAssets,
+) -> anyhow::Result<HashMap<String, Arc<TemplateAssets>>> {
+    let mut templates = HashMap::new();
+    for rule in &config.proxy.host_rule {
+        let Some(page) = &rule.page else {
+            continue;
+        };
+        let host = normalize_host(&rule.host);
+        let assets = crate::static_files::load_host_template_assets(default, &config.pow, page)
+            .map_err(|err| anyhow::anyhow!("invalid challenge page for host {host}: {err}"))?;
+        tracing::info!(host = %host, "loaded host-specific challenge page");
+        templates.insert(host, Arc::new(assets));
+    }
+    Ok(templates)
+}
+
 fn build_server_secret(salt: &str) -> anyhow::Result<String> {
     let trimmed = salt.trim();
     if !trimmed.is_empty() {
@@ -103,6 +344,26 @@ fn build_server_secret(salt: &str) -> anyhow::Result<String> {
     Ok(pad_secret(&encoded, 32))
 }
 
+/// 以 server secret 为密钥对固定标签做一次 HMAC，得到各用途专用的子密钥
+fn derive_hmac_key(server_secret: &str, label: &[u8]) -> ring::hmac::Key {
+    let root = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, server_secret.as_bytes());
+    let derived = ring::hmac::sign(&root, label);
+    ring::hmac::Key::new(ring::hmac::HMAC_SHA256, derived.as_ref())
+}
+
+fn build_cookie_keyring(pow: &PowConfig, primary: String) -> CookieKeyring {
+    let keys = pow
+        .cookie_keys
+        .iter()
+        .map(|key| (key.kid.trim().to_string(), pad_secret(key.secret.trim(), 32)))
+        .collect();
+    let active_kid = pow.active_cookie_kid.trim();
+    if !active_kid.is_empty() {
+        tracing::info!(kid = %active_kid, keys = pow.cookie_keys.len(), "issuing v2 cookies");
+    }
+    CookieKeyring::new(primary, keys, (!active_kid.is_empty()).then(|| active_kid.to_string()))
+}
+
 fn pad_secret(value: &str, min_len: usize) -> String {
     if value.len() >= min_len {
         return value.to_string();
@@ -140,6 +401,29 @@ fn parse_proxy_target(target: &str) -> anyhow::Result<ProxyTarget> {
     })
 }
 
+fn build_upstream_targets(cfg: &ProxyConfig) -> anyhow::Result<Vec<ProxyTarget>> {
+    if cfg.targets.is_empty() {
+        return Ok(vec![parse_proxy_target(&cfg.target)?]);
+    }
+    let targets = cfg
+        .targets
+        .iter()
+        .map(|target| {
+            parse_proxy_target(target.trim())
+                .map_err(|err| anyhow::anyhow!("invalid proxy.targets entry {target}: {err}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    tracing::info!(count = targets.len(), "round-robin upstream pool enabled");
+    Ok(targets)
+}
+
+fn listen_port_value(listen: &str) -> anyhow::Result<HeaderValue> {
+    let addr: std::net::SocketAddr = listen
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid listen address: {err}"))?;
+    Ok(HeaderValue::from(addr.port()))
+}
+
 fn normalize_host(raw: &str) -> String {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
@@ -164,3 +448,39 @@ fn build_host_targets(rules: &[ProxyHostRule]) -> anyhow::Result<Vec<HostProxyTa
     }
     Ok(targets)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    /// 不 accept 的监听端口，塞满 backlog 后新的 SYN 被丢弃，connect 一直挂起，效果等同不可路由地址
+    async fn blackhole() -> (std::net::SocketAddr, Vec<tokio::net::TcpStream>, tokio::net::TcpListener) {
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(1).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut filled = Vec::new();
+        while let Ok(Ok(stream)) =
+            tokio::time::timeout(Duration::from_millis(100), tokio::net::TcpStream::connect(addr)).await
+        {
+            filled.push(stream);
+        }
+        (addr, filled, listener)
+    }
+
+    #[tokio::test]
+    async fn connect_timeout_fails_fast() {
+        let (addr, _filled, _listener) = blackhole().await;
+        let mut config = Config::default();
+        config.proxy.target = format!("http://{addr}");
+        config.proxy.connect_timeout_ms = 200;
+        let state = AppState::new(config).await.unwrap();
+
+        let started = std::time::Instant::now();
+        let req = axum::http::Request::builder().uri(format!("http://{addr}/")).body(Body::empty()).unwrap();
+        let err = state.proxy_client.request(req).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+        assert_eq!(crate::proxy::error::ProxyError::from_client_error(&err), crate::proxy::error::ProxyError::Timeout);
+    }
+}
@@ -1,31 +1,400 @@
 use std::path::{Component, Path};
+use std::sync::Arc;
+use std::time::Duration;
 
+use axum::body::Body;
+use axum::http::{Method, Request, Uri};
 use bytes::Bytes;
+use http_body_util::{BodyExt, Limited};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use rust_embed::RustEmbed;
 use base64::Engine;
 
+use crate::config::{HostPageConfig, PowConfig};
+use crate::handlers::pow::POW_PREFIX;
+use crate::state::AppState;
+
 #[derive(RustEmbed)]
 #[folder = "static/"]
 struct EmbeddedAssets;
 
-pub fn load_template_assets() -> anyhow::Result<(String, String, String)> {
+/// 挑战页运行所需的全部内嵌资源，缺失任意一个都无法正常完成验证
+const REQUIRED_ASSETS: &[&str] = &[
+    "catpaw.html",
+    "assets/cowcat1.webp",
+    "assets/cowcat2.webp",
+    "assets/catpaw.min.js",
+    "assets/catpaw.worker.min.js",
+    "assets/catpaw.wasm",
+];
+
+/// 渲染挑战页所需的模板与图片（图片已 base64 编码）
+#[derive(Debug, Clone)]
+pub struct TemplateAssets {
+    /// 加载时切分好的挑战页，图片已填入字面量
+    pub segments: Vec<TemplateSegment>,
+    /// 所有字面量的总长度，渲染时据此预分配
+    pub literal_len: usize,
+    pub cowcat_image1: String,
+    pub cowcat_image2: String,
+}
+
+/// 挑战页模板的一段：字面量，或每次请求才确定的占位符
+#[derive(Debug, Clone)]
+pub enum TemplateSegment {
+    Literal(String),
+    TaskData,
+    RedirectUrl,
+}
+
+impl TemplateAssets {
+    fn new(template: &str, cowcat_image1: String, cowcat_image2: String) -> Self {
+        let segments = compile_template(template, &cowcat_image1, &cowcat_image2);
+        let literal_len = segments
+            .iter()
+            .map(|segment| match segment {
+                TemplateSegment::Literal(text) => text.len(),
+                _ => 0,
+            })
+            .sum();
+        Self {
+            segments,
+            literal_len,
+            cowcat_image1,
+            cowcat_image2,
+        }
+    }
+}
+
+/// 单次扫描模板切分出占位符；图片每页固定，直接并入相邻字面量。
+/// 未知的 "{{ " 原样保留，填入的值不再被当作模板解析。这与旧的逐个 String::replace 只有一处不同：
+/// 旧实现先填 redirect 再替换图片，redirect 里的 "{{ CowcatImage1 }}" 会被展开成整张图片，现在原样输出
+fn compile_template(template: &str, cowcat_image1: &str, cowcat_image2: &str) -> Vec<TemplateSegment> {
+    let mut segments = Vec::new();
+    let mut literal = String::with_capacity(template.len() + cowcat_image1.len() + cowcat_image2.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{ ") {
+        literal.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let (name, segment) = if tail.starts_with("{{ TaskData }}") {
+            ("{{ TaskData }}", Some(TemplateSegment::TaskData))
+        } else if tail.starts_with("{{ RedirectURL }}") {
+            ("{{ RedirectURL }}", Some(TemplateSegment::RedirectUrl))
+        } else if tail.starts_with("{{ CowcatImage1 }}") {
+            literal.push_str(cowcat_image1);
+            ("{{ CowcatImage1 }}", None)
+        } else if tail.starts_with("{{ CowcatImage2 }}") {
+            literal.push_str(cowcat_image2);
+            ("{{ CowcatImage2 }}", None)
+        } else {
+            literal.push_str("{{ ");
+            ("{{ ", None)
+        };
+        if let Some(segment) = segment {
+            segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
+            segments.push(segment);
+        }
+        rest = &tail[name.len()..];
+    }
+    literal.push_str(rest);
+    segments.push(TemplateSegment::Literal(literal));
+    segments.retain(|segment| !matches!(segment, TemplateSegment::Literal(text) if text.is_empty()));
+    segments
+}
+
+/// 自定义模板必须保留的占位符，缺失任意一个都会导致挑战页无法工作
+const REQUIRED_PLACEHOLDERS: &[&str] = &["{{ TaskData }}", "{{ RedirectURL }}"];
+/// pow.page.remote_url 响应体上限
+const MAX_REMOTE_TEMPLATE_BYTES: usize = 1024 * 1024;
+
+/// 默认挑战页；配置了 pow.page.remote_url 时优先使用拉取到的 HTML，拉取或校验失败回落到内嵌页面
+pub async fn load_template_assets(pow: &PowConfig) -> anyhow::Result<TemplateAssets> {
+    let remote = if pow.page.remote_url.trim().is_empty() {
+        None
+    } else {
+        match fetch_remote_template(pow).await {
+            Ok(raw) => {
+                tracing::info!(url = %pow.page.remote_url.trim(), bytes = raw.len(), "loaded remote challenge page");
+                Some(raw)
+            }
+            Err(err) => {
+                tracing::warn!(url = %pow.page.remote_url.trim(), error = %err, "remote challenge page unavailable, using embedded page");
+                None
+            }
+        }
+    };
+    build_template_assets(pow, remote.as_deref())
+}
+
+fn build_template_assets(pow: &PowConfig, remote: Option<&str>) -> anyhow::Result<TemplateAssets> {
+    check_required_assets()?;
+
     let cowcat1 = EmbeddedAssets::get("assets/cowcat1.webp")
         .ok_or_else(|| anyhow::anyhow!("missing assets/cowcat1.webp"))?;
     let cowcat2 = EmbeddedAssets::get("assets/cowcat2.webp")
         .ok_or_else(|| anyhow::anyhow!("missing assets/cowcat2.webp"))?;
-    let css_raw = EmbeddedAssets::get("assets/catpaw.min.css")
-        .or_else(|| EmbeddedAssets::get("catpaw.css"))
-        .ok_or_else(|| anyhow::anyhow!("missing catpaw css"))?;
     let template_raw = EmbeddedAssets::get("catpaw.html")
         .ok_or_else(|| anyhow::anyhow!("missing catpaw.html"))?;
 
     let img1 = base64::engine::general_purpose::STANDARD.encode(cowcat1.data);
     let img2 = base64::engine::general_purpose::STANDARD.encode(cowcat2.data);
-    let template = normalize_template(std::str::from_utf8(&template_raw.data)?);
+    let raw = match remote {
+        Some(raw) => raw,
+        None => std::str::from_utf8(&template_raw.data)?,
+    };
+    let template = build_template(raw, &embedded_css()?, pow);
+
+    Ok(TemplateAssets::new(&template, img1, img2))
+}
+
+/// GET pow.page.remote_url：仅接受 2xx、不超过 1 MiB 的 UTF-8 响应，并通过 validate_custom_template
+async fn fetch_remote_template(pow: &PowConfig) -> anyhow::Result<String> {
+    let uri: Uri = pow.page.remote_url.trim().parse()?;
+    let client: Client<HttpConnector, Body> = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+    let req = Request::builder().method(Method::GET).uri(uri).body(Body::empty())?;
+    let timeout = Duration::from_millis(pow.page.remote_timeout_ms.max(1));
+    let raw = tokio::time::timeout(timeout, async {
+        let resp = client.request(req).await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("unexpected status {}", resp.status());
+        }
+        let body = Limited::new(resp.into_body(), MAX_REMOTE_TEMPLATE_BYTES)
+            .collect()
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to read body: {err}"))?
+            .to_bytes();
+        Ok(String::from_utf8(body.to_vec())?)
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("timed out after {}ms", timeout.as_millis()))??;
+    validate_custom_template(&raw)?;
+    Ok(raw)
+}
+
+/// pow.page.remote_refresh_secs：定期重新拉取默认挑战页，失败时保留当前页面
+pub fn start_remote_page_refresh(state: Arc<AppState>) {
+    let page = &state.config.pow.page;
+    if page.remote_url.trim().is_empty() || page.remote_refresh_secs == 0 {
+        return;
+    }
+    let period = Duration::from_secs(page.remote_refresh_secs);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let pow = &state.config.pow;
+            let raw = match fetch_remote_template(pow).await {
+                Ok(raw) => raw,
+                Err(err) => {
+                    tracing::warn!(url = %pow.page.remote_url.trim(), error = %err, "remote challenge page refresh failed, keeping current page");
+                    continue;
+                }
+            };
+            match build_template_assets(pow, Some(&raw)) {
+                Ok(assets) => {
+                    state.template_assets.store(Arc::new(assets));
+                    tracing::info!(url = %pow.page.remote_url.trim(), bytes = raw.len(), "remote challenge page refreshed");
+                }
+                Err(err) => tracing::warn!(error = %err, "failed to build refreshed challenge page"),
+            }
+        }
+    });
+}
+
+/// 切分之前的内嵌挑战页模板，测试里用来对照旧的逐个 replace 渲染
+#[cfg(test)]
+pub(crate) fn embedded_challenge_template(pow: &PowConfig) -> anyhow::Result<String> {
+    let raw = EmbeddedAssets::get("catpaw.html").ok_or_else(|| anyhow::anyhow!("missing catpaw.html"))?;
+    Ok(build_template(std::str::from_utf8(&raw.data)?, &embedded_css()?, pow))
+}
+
+/// 按 host_rule.page 覆盖默认挑战页：未指定的部分沿用默认资源
+pub fn load_host_template_assets(
+    default: &TemplateAssets,
+    pow: &PowConfig,
+    host_page: &HostPageConfig,
+) -> anyhow::Result<TemplateAssets> {
+    let css = match &host_page.css {
+        Some(path) => read_text_file(path)?,
+        None => embedded_css()?,
+    };
+    let template = match &host_page.html {
+        Some(path) => {
+            let raw = read_text_file(path)?;
+            validate_custom_template(&raw).map_err(|err| anyhow::anyhow!("{path}: {err}"))?;
+            build_template(&raw, &css, pow)
+        }
+        None => {
+            let raw = EmbeddedAssets::get("catpaw.html")
+                .ok_or_else(|| anyhow::anyhow!("missing catpaw.html"))?;
+            build_template(std::str::from_utf8(&raw.data)?, &css, pow)
+        }
+    };
+    let cowcat_image1 = match &host_page.image1 {
+        Some(path) => read_image_base64(path)?,
+        None => default.cowcat_image1.clone(),
+    };
+    let cowcat_image2 = match &host_page.image2 {
+        Some(path) => read_image_base64(path)?,
+        None => default.cowcat_image2.clone(),
+    };
+    Ok(TemplateAssets::new(&template, cowcat_image1, cowcat_image2))
+}
+
+/// test_mode 下验证通过后的确认页，复用挑战页的样式、主题与图片
+pub fn load_ok_page(pow: &PowConfig, assets: &TemplateAssets) -> anyhow::Result<String> {
+    let raw = EmbeddedAssets::get("ok.html").ok_or_else(|| anyhow::anyhow!("missing ok.html"))?;
+    let template = build_template(std::str::from_utf8(&raw.data)?, &embedded_css()?, pow);
+    Ok(template.replace("{{ CowcatImage2 }}", &assets.cowcat_image2))
+}
+
+/// 填入本次求解的难度与耗时；两者都来自已解析的数字，无需转义
+pub fn render_ok_page(template: &str, bits: Option<u32>, elapsed: Option<&str>) -> String {
+    template
+        .replace("{{ Bits }}", &bits.map(|bits| bits.to_string()).unwrap_or_else(|| "-".to_string()))
+        .replace("{{ Elapsed }}", elapsed.unwrap_or("-"))
+}
+
+/// pow.captcha_fallback 的验证码页，与确认页一样复用挑战页的样式与主题
+pub fn load_captcha_page(pow: &PowConfig) -> anyhow::Result<String> {
+    let raw = EmbeddedAssets::get("captcha.html").ok_or_else(|| anyhow::anyhow!("missing captcha.html"))?;
+    Ok(build_template(std::str::from_utf8(&raw.data)?, &embedded_css()?, pow))
+}
+
+/// SVG 由服务端生成、task_id 为十六进制，原样填入；redirect 与错误信息来自请求，需转义
+pub fn render_captcha_page(template: &str, svg: &str, task_id: &str, redirect: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|msg| format!(r#"<div class="e" role="alert"><div class="em">{}</div></div>"#, escape_html(msg)))
+        .unwrap_or_default();
+    template
+        .replace("{{ CaptchaError }}", &error)
+        .replace("{{ CaptchaImage }}", svg)
+        .replace("{{ TaskId }}", task_id)
+        .replace("{{ RedirectURL }}", &escape_html(redirect))
+}
+
+fn escape_html(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// 校验自定义模板是否包含挑战流程必需的占位符
+pub fn validate_custom_template(raw: &str) -> anyhow::Result<()> {
+    let normalized = normalize_template(raw);
+    let missing: Vec<&str> = REQUIRED_PLACEHOLDERS
+        .iter()
+        .copied()
+        .filter(|placeholder| !normalized.contains(placeholder))
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("custom template missing placeholder(s): {}", missing.join(", "));
+    }
+    Ok(())
+}
+
+fn build_template(raw: &str, css: &str, pow: &PowConfig) -> String {
+    let template = normalize_template(raw);
     let template = minify_template_lines(&template);
-    let template = template.replace("{{ CatpawCSS }}", std::str::from_utf8(&css_raw.data)?);
+    let template = template.replace("{{ CatpawCSS }}", css);
+    template
+        .replace("{{ CoreScript }}", &core_script(pow))
+        .replace("{{ Theme }}", pow.page.theme.as_str())
+        .replace("{{ AssetBase }}", &asset_base(pow))
+}
+
+/// 页面脚本：默认为外部 script 标签（CDN 加载失败时回落到本机）；
+/// pow.page.inline_all 时内联脚本本体、worker 源码与 wasm data URL
+fn core_script(pow: &PowConfig) -> String {
+    const EXTERNAL: &str = "<script data-cfasync=\"false\" src=\"{{ AssetBase }}/catpaw.min.js\" data-asset-base=\"{{ AssetBase }}\" \
+        onerror=\"if(this.dataset.assetBase!=='/__cowcatwaf/assets'){var s=document.createElement('script');s.src='/__cowcatwaf/assets/catpaw.min.js';document.body.appendChild(s);}\"></script>";
+    if !pow.page.inline_all {
+        return EXTERNAL.to_string();
+    }
+    let (Some(core), Some(worker), Some(wasm)) = (
+        embedded_text("assets/catpaw.min.js"),
+        embedded_text("assets/catpaw.worker.min.js"),
+        EmbeddedAssets::get("assets/catpaw.wasm"),
+    ) else {
+        // check_required_assets 已保证存在，这里只是兜底
+        return EXTERNAL.to_string();
+    };
+    let wasm = base64::engine::general_purpose::STANDARD.encode(wasm.data);
+    format!(
+        "<script type=\"text/plain\" id=\"catpaw-worker-src\">{}</script>\
+         <script data-cfasync=\"false\" data-wasm=\"data:application/wasm;base64,{wasm}\">{}</script>",
+        escape_inline_script(&worker),
+        escape_inline_script(&core)
+    )
+}
+
+fn embedded_text(path: &str) -> Option<String> {
+    let file = EmbeddedAssets::get(path)?;
+    String::from_utf8(file.data.into_owned()).ok()
+}
+
+/// 内联脚本中出现的 `</script` 会提前结束标签；JS 字符串与正则里 `<\/` 与 `</` 等价
+fn escape_inline_script(js: &str) -> String {
+    js.replace("</script", "<\\/script")
+}
+
+/// 挑战页脚本与 wasm 的基础 URL：配置了 pow.asset_base_url 时指向 CDN，否则为本机 /assets
+pub fn asset_base(pow: &PowConfig) -> String {
+    let base = pow.asset_base_url.trim().trim_end_matches('/');
+    if base.is_empty() {
+        format!("{POW_PREFIX}/assets")
+    } else {
+        base.to_string()
+    }
+}
+
+fn embedded_css() -> anyhow::Result<String> {
+    let css_raw = EmbeddedAssets::get("assets/catpaw.min.css")
+        .or_else(|| EmbeddedAssets::get("catpaw.css"))
+        .ok_or_else(|| anyhow::anyhow!("missing catpaw css"))?;
+    Ok(std::str::from_utf8(&css_raw.data)?.to_string())
+}
+
+fn read_text_file(path: &str) -> anyhow::Result<String> {
+    std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("failed to read {path}: {err}"))
+}
 
-    Ok((template, img1, img2))
+fn read_image_base64(path: &str) -> anyhow::Result<String> {
+    let data = std::fs::read(path).map_err(|err| anyhow::anyhow!("failed to read {path}: {err}"))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+fn check_required_assets() -> anyhow::Result<()> {
+    check_assets(|path| EmbeddedAssets::get(path).is_some())
+}
+
+/// 一次性列出所有缺失的内嵌资源，方便打包时一次修好，而不是逐个报错；
+/// exists 查询内嵌目录，测试中换成空目录
+fn check_assets(exists: impl Fn(&str) -> bool) -> anyhow::Result<()> {
+    let mut missing: Vec<&str> = REQUIRED_ASSETS
+        .iter()
+        .copied()
+        .filter(|path| !exists(path))
+        .collect();
+    if !exists("assets/catpaw.min.css") && !exists("catpaw.css") {
+        missing.push("assets/catpaw.min.css (or catpaw.css)");
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    for path in &missing {
+        tracing::error!(asset = %path, "required embedded asset missing");
+    }
+    anyhow::bail!(
+        "missing {} embedded asset(s): {} (rebuild static/assets via build.rs, `make jsminify` and `./wasm/build.sh`)",
+        missing.len(),
+        missing.join(", ")
+    )
 }
 
 pub fn get_asset(path: &str) -> Option<Bytes> {
@@ -38,12 +407,61 @@ pub fn get_asset(path: &str) -> Option<Bytes> {
     })
 }
 
+/// 内嵌资源的内容哈希 ETag（弱校验器：压缩层会按 Accept-Encoding 改写响应体）
+pub fn get_asset_etag(path: &str) -> Option<String> {
+    let normalized = sanitize_path(path)?;
+    let file = EmbeddedAssets::get(&normalized)?;
+    Some(format!("W/\"{}\"", hex::encode(&file.metadata.sha256_hash()[..16])))
+}
+
+/// 单个磁盘资源的最大字节数，防止误配置目录时把大文件读入内存
+const MAX_DISK_ASSET_BYTES: u64 = 8 * 1024 * 1024;
+
+/// 从 pow.page.assets_dir 读取额外静态文件（内嵌资源优先，未命中时才回落到磁盘）。
+/// 解析符号链接后的真实路径必须仍在 assets_dir 内
+pub async fn get_disk_asset(dir: &str, path: &str) -> Option<Bytes> {
+    let normalized = sanitize_path(path)?;
+    let root = tokio::fs::canonicalize(dir).await.ok()?;
+    let full_path = tokio::fs::canonicalize(root.join(normalized)).await.ok()?;
+    if !full_path.starts_with(&root) {
+        tracing::warn!(path = %full_path.display(), "disk asset resolves outside assets_dir, refusing to serve");
+        return None;
+    }
+    let metadata = tokio::fs::metadata(&full_path).await.ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    if metadata.len() > MAX_DISK_ASSET_BYTES {
+        tracing::warn!(
+            path = %full_path.display(),
+            size = metadata.len(),
+            "disk asset exceeds size limit, refusing to serve"
+        );
+        return None;
+    }
+    match tokio::fs::read(&full_path).await {
+        Ok(data) => Some(Bytes::from(data)),
+        Err(err) => {
+            tracing::warn!(path = %full_path.display(), error = %err, "failed to read disk asset");
+            None
+        }
+    }
+}
+
 fn normalize_template(raw: &str) -> String {
     raw.replace("{{.TaskData}}", "{{ TaskData }}")
         .replace("{{.RedirectURL}}", "{{ RedirectURL }}")
         .replace("{{.CowcatImage1}}", "{{ CowcatImage1 }}")
         .replace("{{.CowcatImage2}}", "{{ CowcatImage2 }}")
         .replace("{{.CatpawCSS}}", "{{ CatpawCSS }}")
+        .replace("{{.Theme}}", "{{ Theme }}")
+        .replace("{{.AssetBase}}", "{{ AssetBase }}")
+        .replace("{{.CoreScript}}", "{{ CoreScript }}")
+        .replace("{{.Bits}}", "{{ Bits }}")
+        .replace("{{.Elapsed}}", "{{ Elapsed }}")
+        .replace("{{.CaptchaImage}}", "{{ CaptchaImage }}")
+        .replace("{{.CaptchaError}}", "{{ CaptchaError }}")
+        .replace("{{.TaskId}}", "{{ TaskId }}")
 }
 
 fn minify_template_lines(raw: &str) -> String {
@@ -76,3 +494,130 @@ fn sanitize_path(path: &str) -> Option<String> {
         Some(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_embed_lists_every_missing_asset() {
+        let err = check_assets(|_| false).unwrap_err().to_string();
+        assert!(err.starts_with("missing 7 embedded asset(s)"), "{err}");
+        for path in REQUIRED_ASSETS {
+            assert!(err.contains(path), "{path} not reported: {err}");
+        }
+        assert!(err.contains("assets/catpaw.min.css (or catpaw.css)"));
+    }
+
+    #[test]
+    fn unminified_css_satisfies_the_css_requirement() {
+        let err = check_assets(|path| path != "assets/catpaw.min.css" && path != "assets/catpaw.wasm")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("missing 1 embedded asset(s): assets/catpaw.wasm"), "{err}");
+    }
+
+    #[test]
+    fn bundled_assets_are_complete() {
+        assert!(check_required_assets().is_ok());
+    }
+
+    fn literal_text(assets: &TemplateAssets) -> String {
+        assets
+            .segments
+            .iter()
+            .filter_map(|segment| match segment {
+                TemplateSegment::Literal(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 本地桩服务：/valid 为合格模板，/invalid 缺 {{ RedirectURL }}，其余 404
+    async fn template_server() -> String {
+        use axum::routing::get;
+        let app = axum::Router::new()
+            .route("/valid", get(|| async { "<html>remote-brand {{ TaskData }} {{ RedirectURL }}</html>" }))
+            .route("/invalid", get(|| async { "<html>remote-brand {{ TaskData }}</html>" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn remote_pow(url: String) -> PowConfig {
+        let mut pow = PowConfig::default();
+        pow.page.remote_url = url;
+        pow.page.remote_timeout_ms = 2000;
+        pow
+    }
+
+    #[tokio::test]
+    async fn remote_template_is_used_when_valid() {
+        let base = template_server().await;
+        let assets = load_template_assets(&remote_pow(format!("{base}/valid"))).await.unwrap();
+        assert!(literal_text(&assets).contains("remote-brand"));
+        assert!(assets.segments.iter().any(|segment| matches!(segment, TemplateSegment::TaskData)));
+        assert!(assets.segments.iter().any(|segment| matches!(segment, TemplateSegment::RedirectUrl)));
+    }
+
+    #[tokio::test]
+    async fn invalid_or_missing_remote_template_falls_back_to_the_embedded_page() {
+        let base = template_server().await;
+        let embedded = literal_text(&load_template_assets(&PowConfig::default()).await.unwrap());
+        for path in ["/invalid", "/missing"] {
+            let assets = load_template_assets(&remote_pow(format!("{base}{path}"))).await.unwrap();
+            assert_eq!(literal_text(&assets), embedded, "{path}");
+        }
+        assert!(fetch_remote_template(&remote_pow(format!("{base}/invalid")))
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("{{ RedirectURL }}"));
+    }
+
+    fn assets_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("cowcat-assets-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fonts")).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn disk_asset_is_read_from_assets_dir() {
+        let dir = assets_dir("read");
+        std::fs::write(dir.join("fonts/brand.woff2"), b"font-bytes").unwrap();
+        let bytes = get_disk_asset(dir.to_str().unwrap(), "/fonts/brand.woff2").await;
+        assert_eq!(bytes.as_deref(), Some(&b"font-bytes"[..]));
+    }
+
+    #[tokio::test]
+    async fn missing_or_escaping_disk_asset_is_none() {
+        let dir = assets_dir("missing");
+        assert!(get_disk_asset(dir.to_str().unwrap(), "fonts/absent.woff2").await.is_none());
+        assert!(get_disk_asset(dir.to_str().unwrap(), "fonts").await.is_none());
+        assert!(get_disk_asset(dir.to_str().unwrap(), "../../etc/passwd").await.is_none());
+
+        // 符号链接按真实路径判断：指向目录外的拒绝，指向目录内的照常读取
+        #[cfg(unix)]
+        {
+            let outside = std::env::temp_dir().join(format!("cowcat-assets-test-{}-secret", std::process::id()));
+            std::fs::write(&outside, b"secret").unwrap();
+            std::fs::write(dir.join("fonts/brand.woff2"), b"font-bytes").unwrap();
+            for (link, target) in [("fonts/escape.woff2", outside), ("alias.woff2", dir.join("fonts/brand.woff2"))] {
+                let _ = std::fs::remove_file(dir.join(link));
+                std::os::unix::fs::symlink(target, dir.join(link)).unwrap();
+            }
+            assert!(get_disk_asset(dir.to_str().unwrap(), "fonts/escape.woff2").await.is_none());
+            let alias = get_disk_asset(dir.to_str().unwrap(), "alias.woff2").await;
+            assert_eq!(alias.unwrap(), Bytes::from_static(b"font-bytes"));
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_disk_asset_is_refused() {
+        let dir = assets_dir("oversized");
+        let file = std::fs::File::create(dir.join("huge.bin")).unwrap();
+        file.set_len(MAX_DISK_ASSET_BYTES + 1).unwrap();
+        assert!(get_disk_asset(dir.to_str().unwrap(), "huge.bin").await.is_none());
+    }
+}
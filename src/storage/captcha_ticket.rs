@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+const TICKET_CLEANUP_INTERVAL: u64 = 60;
+/// 票据的有效期：足够用户看完失败提示再点验证码入口
+const TICKET_TTL: Duration = Duration::from_secs(600);
+/// 条目上限，满时不再登记新票据（对应的客户端只能重新挑战）
+const MAX_TICKETS: usize = 100_000;
+
+/// pow.captcha_fallback 的入场票据：/verify 消耗掉但没通过的 PoW 任务按 task_id 登记，
+/// 同一 IP 凭该 task_id 可换一道验证码，用一次即作废。仅在本进程内有效
+pub struct CaptchaTickets {
+    inner: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl CaptchaTickets {
+    pub fn new() -> Arc<Self> {
+        let tickets = Arc::new(Self {
+            inner: Mutex::new(HashMap::new()),
+        });
+        Self::spawn_cleanup(tickets.clone());
+        tickets
+    }
+
+    /// 登记一张票据，绑定到提交失败验证的 IP
+    pub async fn grant(&self, task_id: &str, ip: &str) {
+        let mut guard = self.inner.lock().await;
+        if guard.len() >= MAX_TICKETS {
+            tracing::debug!("captcha ticket table full, not granting");
+            return;
+        }
+        guard.insert(task_id.to_string(), (ip.to_string(), Instant::now() + TICKET_TTL));
+    }
+
+    /// 兑换票据：task_id 存在、未过期且 IP 一致时移除并返回 true
+    pub async fn redeem(&self, task_id: &str, ip: &str) -> bool {
+        let mut guard = self.inner.lock().await;
+        match guard.get(task_id) {
+            Some((owner, expires_at)) if owner == ip && *expires_at > Instant::now() => {
+                guard.remove(task_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn spawn_cleanup(tickets: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(TICKET_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                let now = Instant::now();
+                tickets.inner.lock().await.retain(|_, (_, expires_at)| *expires_at > now);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ticket_is_single_use_and_bound_to_ip() {
+        let tickets = CaptchaTickets::new();
+        tickets.grant("task-1", "203.0.113.7").await;
+        assert!(!tickets.redeem("task-1", "198.51.100.1").await);
+        assert!(!tickets.redeem("task-2", "203.0.113.7").await);
+        assert!(tickets.redeem("task-1", "203.0.113.7").await);
+        assert!(!tickets.redeem("task-1", "203.0.113.7").await);
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use tokio::time::interval;
+
+use crate::config::FailureBanConfig;
+
+const BAN_CLEANUP_INTERVAL: u64 = 60;
+/// 封禁时长按 2^strikes 递增的上限
+const MAX_BAN_TTL: Duration = Duration::from_secs(24 * 3600);
+/// 条目上限，满时不再跟踪新 IP（已跟踪的 IP 不受影响）
+const MAX_TRACKED_IPS: usize = 100_000;
+/// 每个请求都要查封禁表，按 IP 分片减少锁竞争
+const BAN_SHARDS: usize = 32;
+
+#[derive(Debug, Default)]
+struct FailureEntry {
+    consecutive: u32,
+    strikes: u32,
+    banned_until: Option<Instant>,
+    last_seen: Option<Instant>,
+}
+
+/// 连续提交无效 PoW 的 IP 自动封禁，封禁时长随次数指数增长；
+/// 蜜罐等直接判定的封禁也记在这里，共用同一张表与 is_banned 检查
+pub struct FailureBans {
+    shards: Box<[StdMutex<HashMap<String, FailureEntry>>]>,
+    hasher: RandomState,
+    /// None 表示未配置 pow.failure_ban：只接受 ban() 的直接封禁，不按失败次数自动封禁
+    threshold: Option<u32>,
+    base_ttl: Duration,
+    /// 带 banned_until 的条目数；到期的封禁在下一次清理时才扣除
+    banned: AtomicUsize,
+}
+
+impl FailureBans {
+    pub fn new(cfg: Option<&FailureBanConfig>) -> Arc<Self> {
+        let defaults = FailureBanConfig::default();
+        let bans = Arc::new(Self {
+            shards: (0..BAN_SHARDS).map(|_| StdMutex::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+            threshold: cfg.map(|cfg| cfg.threshold.max(1)),
+            base_ttl: Duration::from_secs(cfg.unwrap_or(&defaults).base_ttl_secs.max(1)),
+            banned: AtomicUsize::new(0),
+        });
+        Self::spawn_cleanup(bans.clone());
+        bans
+    }
+
+    pub fn is_banned(&self, ip: &str) -> bool {
+        if ip.is_empty() {
+            return false;
+        }
+        let Ok(shard) = self.shard(ip).lock() else {
+            return false;
+        };
+        shard
+            .get(ip)
+            .and_then(|entry| entry.banned_until)
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// 当前封禁中的 IP 数（近似：到期后最多滞后一个清理周期）
+    pub fn banned_count(&self) -> usize {
+        self.banned.load(Ordering::Relaxed)
+    }
+
+    /// 记录一次失败；达到阈值时封禁并返回本次封禁时长
+    pub fn record_failure(&self, ip: &str) -> Option<Duration> {
+        let threshold = self.threshold?;
+        if ip.is_empty() {
+            return None;
+        }
+        let mut shard = self.shard(ip).lock().ok()?;
+        if !shard.contains_key(ip) && shard.len() >= MAX_TRACKED_IPS / BAN_SHARDS {
+            tracing::debug!("failure ban shard full, not tracking new ip");
+            return None;
+        }
+        let now = Instant::now();
+        let entry = shard.entry(ip.to_string()).or_default();
+        entry.last_seen = Some(now);
+        entry.consecutive += 1;
+        if entry.consecutive < threshold {
+            return None;
+        }
+        let ttl = self
+            .base_ttl
+            .saturating_mul(2u32.saturating_pow(entry.strikes))
+            .min(MAX_BAN_TTL);
+        entry.consecutive = 0;
+        entry.strikes = entry.strikes.saturating_add(1);
+        if entry.banned_until.replace(now + ttl).is_none() {
+            self.banned.fetch_add(1, Ordering::Relaxed);
+        }
+        let strikes = entry.strikes;
+        drop(shard);
+        tracing::warn!(
+            client_ip = %ip,
+            strikes,
+            ttl_secs = ttl.as_secs(),
+            banned = self.banned_count(),
+            "ip banned after repeated invalid proofs"
+        );
+        Some(ttl)
+    }
+
+    /// 直接封禁 ttl；已有更长的封禁时保留原到期时间。不计入 strikes，不影响自动封禁的升级
+    pub fn ban(&self, ip: &str, ttl: Duration) {
+        if ip.is_empty() {
+            return;
+        }
+        let Ok(mut shard) = self.shard(ip).lock() else {
+            return;
+        };
+        if !shard.contains_key(ip) && shard.len() >= MAX_TRACKED_IPS / BAN_SHARDS {
+            tracing::debug!("failure ban shard full, not tracking new ip");
+            return;
+        }
+        let now = Instant::now();
+        let entry = shard.entry(ip.to_string()).or_default();
+        entry.last_seen = Some(now);
+        let until = now + ttl.min(MAX_BAN_TTL);
+        match entry.banned_until {
+            None => {
+                entry.banned_until = Some(until);
+                self.banned.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(current) if current < until => entry.banned_until = Some(until),
+            Some(_) => {}
+        }
+    }
+
+    /// 验证成功后清零连续失败计数（历史封禁次数保留，用于后续升级）
+    pub fn record_success(&self, ip: &str) {
+        if ip.is_empty() {
+            return;
+        }
+        let Ok(mut shard) = self.shard(ip).lock() else {
+            return;
+        };
+        if let Some(entry) = shard.get_mut(ip) {
+            entry.consecutive = 0;
+        }
+    }
+
+    fn shard(&self, ip: &str) -> &StdMutex<HashMap<String, FailureEntry>> {
+        let index = self.hasher.hash_one(ip) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    fn spawn_cleanup(bans: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(tokio::time::Duration::from_secs(BAN_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                bans.cleanup();
+            }
+        });
+    }
+
+    fn cleanup(&self) {
+        let now = Instant::now();
+        // 封禁到期的条目记录日志；长时间无活动且未封禁的条目直接丢弃
+        let idle_limit = MAX_BAN_TTL;
+        for shard in self.shards.iter() {
+            let Ok(mut shard) = shard.lock() else {
+                continue;
+            };
+            shard.retain(|ip, entry| {
+                if let Some(until) = entry.banned_until {
+                    if until <= now {
+                        tracing::info!(client_ip = %ip, strikes = entry.strikes, "ip ban expired");
+                        entry.banned_until = None;
+                        self.banned.fetch_sub(1, Ordering::Relaxed);
+                    } else {
+                        return true;
+                    }
+                }
+                entry
+                    .last_seen
+                    .is_some_and(|seen| now.duration_since(seen) < idle_limit)
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bans(threshold: u32, base_ttl_secs: u64) -> Arc<FailureBans> {
+        FailureBans::new(Some(&FailureBanConfig { threshold, base_ttl_secs }))
+    }
+
+    #[tokio::test]
+    async fn ban_starts_at_the_threshold() {
+        let bans = bans(3, 60);
+        assert_eq!(bans.record_failure("1.2.3.4"), None);
+        assert_eq!(bans.record_failure("1.2.3.4"), None);
+        assert!(!bans.is_banned("1.2.3.4"));
+        assert_eq!(bans.record_failure("1.2.3.4"), Some(Duration::from_secs(60)));
+        assert!(bans.is_banned("1.2.3.4"));
+        assert!(!bans.is_banned("5.6.7.8"));
+        assert_eq!(bans.banned_count(), 1);
+
+        // 空 IP 不跟踪；未配置 failure_ban 时只接受直接封禁
+        assert_eq!(bans.record_failure(""), None);
+        let manual = FailureBans::new(None);
+        for _ in 0..10 {
+            assert_eq!(manual.record_failure("1.2.3.4"), None);
+        }
+        assert!(!manual.is_banned("1.2.3.4"));
+        manual.ban("1.2.3.4", Duration::from_secs(30));
+        assert!(manual.is_banned("1.2.3.4"));
+        assert_eq!(manual.banned_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn ban_ttl_doubles_per_strike_up_to_the_cap() {
+        let bans = bans(1, 3600);
+        let ttls: Vec<_> = (0..7).map(|_| bans.record_failure("1.2.3.4").unwrap().as_secs()).collect();
+        assert_eq!(ttls, [3600, 7200, 14400, 28800, 57600, 86400, 86400]);
+        // 同一 IP 重复封禁只计一次
+        assert_eq!(bans.banned_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn success_resets_the_consecutive_failures() {
+        let bans = bans(3, 60);
+        bans.record_failure("1.2.3.4");
+        bans.record_failure("1.2.3.4");
+        bans.record_success("1.2.3.4");
+        assert_eq!(bans.record_failure("1.2.3.4"), None);
+        assert_eq!(bans.record_failure("1.2.3.4"), None);
+        assert!(!bans.is_banned("1.2.3.4"));
+        assert_eq!(bans.record_failure("1.2.3.4"), Some(Duration::from_secs(60)));
+        // 成功不清除已有的封禁，也不清除 strikes：下一次封禁时长翻倍
+        bans.record_success("1.2.3.4");
+        assert!(bans.is_banned("1.2.3.4"));
+        for _ in 0..2 {
+            bans.record_failure("1.2.3.4");
+        }
+        assert_eq!(bans.record_failure("1.2.3.4"), Some(Duration::from_secs(120)));
+    }
+
+    #[tokio::test]
+    async fn expired_bans_leave_the_count_on_cleanup() {
+        let bans = bans(1, 60);
+        bans.ban("1.2.3.4", Duration::ZERO);
+        bans.record_failure("5.6.7.8");
+        assert_eq!(bans.banned_count(), 2);
+        assert!(!bans.is_banned("1.2.3.4"));
+        bans.cleanup();
+        assert_eq!(bans.banned_count(), 1);
+        assert!(bans.is_banned("5.6.7.8"));
+    }
+}
@@ -1,3 +1,17 @@
+mod captcha_ticket;
+mod failure_ban;
+mod rate_limit;
+mod redis_store;
+mod task_pool;
 mod task_store;
 
-pub use task_store::{ConsumeError, IpHash, Scope, Seed, Task, TaskId, TaskStore, UaHash};
+pub use task_store::{
+    ConsumeError, IpHash, MemoryTaskStore, Scope, Seed, Task, TaskId, TaskStoreBackend, UaHash,
+};
+#[cfg(test)]
+pub use task_store::TaskValidator;
+pub use captcha_ticket::CaptchaTickets;
+pub use failure_ban::FailureBans;
+pub use redis_store::RedisTaskStore;
+pub use rate_limit::{ChallengeRateLimiter, RateLimiter, VerifyRateLimiter};
+pub use task_pool::TaskPool;
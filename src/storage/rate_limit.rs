@@ -0,0 +1,298 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+use crate::config::VerifyRateLimitConfig;
+
+const LIMIT_CLEANUP_INTERVAL: u64 = 60;
+/// 条目上限，满时新 IP 直接放行而不跟踪（已跟踪的 IP 照常计数）
+const MAX_TRACKED_IPS: usize = 100_000;
+
+/// 按 IP 的固定窗口计数：每个窗口内最多放行 limit 次
+pub struct RateLimiter {
+    inner: Mutex<HashMap<String, (Instant, u32)>>,
+    limit: u32,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Arc<Self> {
+        let limiter = Arc::new(Self {
+            inner: Mutex::new(HashMap::new()),
+            limit: limit.max(1),
+            window: window.max(Duration::from_secs(1)),
+        });
+        Self::spawn_cleanup(limiter.clone());
+        limiter
+    }
+
+    /// 计入一次并返回是否仍在额度内；拿不到 IP 时不限流
+    pub async fn try_acquire(&self, ip: &str) -> bool {
+        if ip.is_empty() {
+            return true;
+        }
+        let now = Instant::now();
+        let mut guard = self.inner.lock().await;
+        if !guard.contains_key(ip) && guard.len() >= MAX_TRACKED_IPS {
+            tracing::debug!("rate limit table full, not tracking new ip");
+            return true;
+        }
+        let entry = guard.entry(ip.to_string()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= self.window {
+            *entry = (now, 0);
+        }
+        if entry.1 >= self.limit {
+            return false;
+        }
+        entry.1 += 1;
+        true
+    }
+
+    fn spawn_cleanup(limiter: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(LIMIT_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                limiter.cleanup().await;
+            }
+        });
+    }
+
+    async fn cleanup(&self) {
+        let now = Instant::now();
+        let mut guard = self.inner.lock().await;
+        guard.retain(|_, (started, _)| now.duration_since(*started) < self.window);
+    }
+}
+
+/// 全局固定窗口计数：所有请求共用一个额度
+pub struct GlobalRateLimiter {
+    inner: StdMutex<(Instant, u32)>,
+    limit: u32,
+    window: Duration,
+}
+
+impl GlobalRateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            inner: StdMutex::new((Instant::now(), 0)),
+            limit: limit.max(1),
+            window: window.max(Duration::from_secs(1)),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let now = Instant::now();
+        let Ok(mut guard) = self.inner.lock() else {
+            return true;
+        };
+        if now.duration_since(guard.0) >= self.window {
+            *guard = (now, 0);
+        }
+        if guard.1 >= self.limit {
+            return false;
+        }
+        guard.1 += 1;
+        true
+    }
+}
+
+/// 令牌桶分片数；按 IP 哈希分散到各自的锁上，挑战洪峰下不争同一把锁
+const BUCKET_SHARDS: usize = 32;
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// pow.challenge_rate_per_min：按 IP 的令牌桶，容量与每分钟补充量相同
+pub struct ChallengeRateLimiter {
+    shards: Box<[StdMutex<HashMap<String, TokenBucket>>]>,
+    hasher: RandomState,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl ChallengeRateLimiter {
+    pub fn new(rate_per_min: u32) -> Arc<Self> {
+        let capacity = rate_per_min.max(1) as f64;
+        let limiter = Arc::new(Self {
+            shards: (0..BUCKET_SHARDS).map(|_| StdMutex::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+            capacity,
+            refill_per_sec: capacity / 60.0,
+        });
+        Self::spawn_cleanup(limiter.clone());
+        limiter
+    }
+
+    /// 取走一个令牌；桶空时返回距下一个令牌可用的等待时间。拿不到 IP 时不限流
+    pub fn try_acquire(&self, ip: &str) -> Result<(), Duration> {
+        if ip.is_empty() {
+            return Ok(());
+        }
+        let Ok(mut shard) = self.shard(ip).lock() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if !shard.contains_key(ip) && shard.len() >= MAX_TRACKED_IPS / BUCKET_SHARDS {
+            tracing::debug!("challenge rate limit shard full, not tracking new ip");
+            return Ok(());
+        }
+        let bucket = shard.entry(ip.to_string()).or_insert(TokenBucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.refill_per_sec))
+    }
+
+    fn shard(&self, ip: &str) -> &StdMutex<HashMap<String, TokenBucket>> {
+        let index = self.hasher.hash_one(ip) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    fn spawn_cleanup(limiter: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(LIMIT_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                limiter.cleanup();
+            }
+        });
+    }
+
+    /// 已补满的桶与新建无异，直接丢弃
+    fn cleanup(&self) {
+        let now = Instant::now();
+        for shard in self.shards.iter() {
+            if let Ok(mut shard) = shard.lock() {
+                shard.retain(|_, bucket| {
+                    let refilled = now.duration_since(bucket.updated).as_secs_f64() * self.refill_per_sec;
+                    bucket.tokens + refilled < self.capacity
+                });
+            }
+        }
+    }
+}
+
+/// /verify 被拒绝的原因
+#[derive(Debug, Clone, Copy)]
+pub enum VerifyLimit {
+    PerIp,
+    Global,
+}
+
+/// pow.verify_rate_limit：在解析请求、触碰任务存储的锁之前拦下 verify 洪峰
+pub struct VerifyRateLimiter {
+    per_ip: Option<Arc<RateLimiter>>,
+    global: Option<GlobalRateLimiter>,
+    rejected_per_ip: AtomicU64,
+    rejected_global: AtomicU64,
+}
+
+impl VerifyRateLimiter {
+    pub fn new(cfg: &VerifyRateLimitConfig) -> Self {
+        let window = Duration::from_secs(cfg.window_secs);
+        Self {
+            per_ip: (cfg.per_ip > 0).then(|| RateLimiter::new(cfg.per_ip, window)),
+            global: (cfg.global > 0).then(|| GlobalRateLimiter::new(cfg.global, window)),
+            rejected_per_ip: AtomicU64::new(0),
+            rejected_global: AtomicU64::new(0),
+        }
+    }
+
+    /// 先按 IP 再按全局计数；被单 IP 限流拦下的请求不占用全局额度
+    pub async fn check(&self, ip: &str) -> Result<(), VerifyLimit> {
+        if let Some(per_ip) = &self.per_ip {
+            if !per_ip.try_acquire(ip).await {
+                self.rejected_per_ip.fetch_add(1, Ordering::Relaxed);
+                return Err(VerifyLimit::PerIp);
+            }
+        }
+        if let Some(global) = &self.global {
+            if !global.try_acquire() {
+                self.rejected_global.fetch_add(1, Ordering::Relaxed);
+                return Err(VerifyLimit::Global);
+            }
+        }
+        Ok(())
+    }
+
+    /// 自启动以来被拒绝的次数：(按 IP, 全局)
+    pub fn rejected(&self) -> (u64, u64) {
+        (
+            self.rejected_per_ip.load(Ordering::Relaxed),
+            self.rejected_global.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn challenge_limiter_allows_a_burst_then_asks_to_wait() {
+        let limiter = ChallengeRateLimiter::new(3);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire("1.2.3.4").is_ok());
+        }
+        let wait = limiter.try_acquire("1.2.3.4").unwrap_err();
+        // 每分钟补 3 个：下一个令牌约 20 秒后可用
+        assert!(wait > Duration::from_secs(19) && wait <= Duration::from_secs(20), "{wait:?}");
+
+        assert!(limiter.try_acquire("5.6.7.8").is_ok());
+        for _ in 0..10 {
+            assert!(limiter.try_acquire("").is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn ip_limiter_counts_each_ip_separately() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.try_acquire("1.2.3.4").await);
+        assert!(limiter.try_acquire("1.2.3.4").await);
+        assert!(!limiter.try_acquire("1.2.3.4").await);
+        assert!(limiter.try_acquire("5.6.7.8").await);
+        for _ in 0..10 {
+            assert!(limiter.try_acquire("").await);
+        }
+    }
+
+    #[test]
+    fn global_limiter_stops_at_the_limit() {
+        let limiter = GlobalRateLimiter::new(3, Duration::from_secs(60));
+        assert_eq!((0..5).map(|_| limiter.try_acquire()).collect::<Vec<_>>(), [true, true, true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn per_ip_rejections_leave_the_global_quota_alone() {
+        let limiter = VerifyRateLimiter::new(&VerifyRateLimitConfig { per_ip: 1, global: 2, window_secs: 60 });
+        assert!(limiter.check("1.2.3.4").await.is_ok());
+        for _ in 0..5 {
+            assert!(matches!(limiter.check("1.2.3.4").await, Err(VerifyLimit::PerIp)));
+        }
+        assert!(limiter.check("5.6.7.8").await.is_ok());
+        assert!(matches!(limiter.check("9.9.9.9").await, Err(VerifyLimit::Global)));
+        assert_eq!(limiter.rejected(), (5, 1));
+
+        // per_ip 为 0 时只按全局计数
+        let global_only = VerifyRateLimiter::new(&VerifyRateLimitConfig { per_ip: 0, global: 1, window_secs: 60 });
+        assert!(global_only.check("1.2.3.4").await.is_ok());
+        assert!(matches!(global_only.check("5.6.7.8").await, Err(VerifyLimit::Global)));
+        assert_eq!(global_only.rejected(), (0, 1));
+    }
+}
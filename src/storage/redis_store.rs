@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use time::OffsetDateTime;
+
+use super::task_store::{ConsumeError, Task, TaskStoreBackend, TaskValidator};
+use crate::config::StorageConfig;
+
+/// 单次 SCAN 的建议条数，只用于 len / clear 这类运维操作
+const SCAN_BATCH: usize = 1000;
+/// 启动时连接 Redis 最多等待的超时轮数（每轮 redis_timeout_ms）
+const STARTUP_CONNECT_ATTEMPTS: u32 = 3;
+
+/// Redis 存储：每个任务一个 key（key_prefix + task_id），值为 JSON，过期时间即任务的 exp。
+/// 重启不丢任务，多个实例共享同一个 Redis 时任意实例都能完成 verify
+pub struct RedisTaskStore {
+    conn: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RedisTaskStore {
+    /// 启动时即建立连接，Redis 不可达时直接报错而不是等到第一个请求
+    pub async fn connect(cfg: &StorageConfig) -> anyhow::Result<Self> {
+        let client = redis::Client::open(cfg.redis_url.as_str())
+            .map_err(|err| anyhow::anyhow!("invalid storage.redis_url: {err}"))?;
+        let timeout = Duration::from_millis(cfg.redis_timeout_ms.max(1));
+        let manager_cfg = ConnectionManagerConfig::new()
+            .set_connection_timeout(timeout)
+            .set_response_timeout(timeout);
+        // ConnectionManager 连接失败时会自行退避重试，启动阶段只等有限的几轮
+        let conn = tokio::time::timeout(timeout * STARTUP_CONNECT_ATTEMPTS, ConnectionManager::new_with_config(client, manager_cfg))
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out connecting to redis task store"))?
+            .map_err(|err| anyhow::anyhow!("failed to connect to redis task store: {err}"))?;
+        tracing::info!(key_prefix = %cfg.redis_key_prefix, "redis task store connected");
+        Ok(Self {
+            conn,
+            key_prefix: cfg.redis_key_prefix.clone(),
+        })
+    }
+
+    fn key(&self, task_id: &str) -> String {
+        format!("{}{}", self.key_prefix, task_id)
+    }
+
+    async fn scan_keys(&self) -> redis::RedisResult<Vec<String>> {
+        let mut conn = self.conn.clone();
+        let pattern = format!("{}*", self.key_prefix);
+        let mut cursor: u64 = 0;
+        let mut keys = Vec::new();
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+}
+
+#[async_trait]
+impl TaskStoreBackend for RedisTaskStore {
+    async fn insert(&self, task: Task) -> anyhow::Result<()> {
+        let value = serde_json::to_string(&task)?;
+        let mut conn = self.conn.clone();
+        // EXAT 直接用任务的绝对过期时间，与内存存储的 exp 检查一致
+        redis::cmd("SET")
+            .arg(self.key(&task.task_id.0))
+            .arg(value)
+            .arg("EXAT")
+            .arg(task.exp)
+            .query_async::<()>(&mut conn)
+            .await
+            .map_err(|err| anyhow::anyhow!("redis SET failed: {err}"))
+    }
+
+    /// GETDEL 一条命令完成取出与删除（Redis >= 6.2），并发 verify 只有一个能拿到任务
+    async fn consume_if(&self, task_id: &str, validate: TaskValidator<'_>) -> Result<Task, ConsumeError> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = redis::cmd("GETDEL")
+            .arg(self.key(task_id))
+            .query_async(&mut conn)
+            .await
+            .map_err(|err| {
+                tracing::error!(error = %err, "redis GETDEL failed");
+                ConsumeError::Unavailable
+            })?;
+        let value = value.ok_or(ConsumeError::NotFound)?;
+        let task: Task = serde_json::from_str(&value).map_err(|err| {
+            tracing::warn!(error = %err, "undecodable task in redis");
+            ConsumeError::NotFound
+        })?;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        if task.exp < now {
+            return Err(ConsumeError::Expired);
+        }
+        validate(&task)?;
+        Ok(task)
+    }
+
+    async fn len(&self) -> usize {
+        match self.scan_keys().await {
+            Ok(keys) => keys.len(),
+            Err(err) => {
+                tracing::warn!(error = %err, "redis SCAN failed");
+                0
+            }
+        }
+    }
+
+    async fn clear(&self) {
+        let keys = match self.scan_keys().await {
+            Ok(keys) => keys,
+            Err(err) => {
+                tracing::warn!(error = %err, "redis SCAN failed");
+                return;
+            }
+        };
+        let mut conn = self.conn.clone();
+        for chunk in keys.chunks(SCAN_BATCH) {
+            if let Err(err) = redis::cmd("DEL").arg(chunk).query_async::<()>(&mut conn).await {
+                tracing::warn!(error = %err, "redis DEL failed");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{IpHash, Scope, Seed, TaskId, UaHash};
+
+    /// 需要真实的 Redis（>= 6.2）：设置 COWCAT_TEST_REDIS_URL 后运行，未设置时跳过。
+    /// key 前缀带进程号与测试名，不会碰到同一 Redis 上的其他数据
+    async fn store(name: &str) -> Option<RedisTaskStore> {
+        let Ok(url) = std::env::var("COWCAT_TEST_REDIS_URL") else {
+            eprintln!("COWCAT_TEST_REDIS_URL not set, skipping {name}");
+            return None;
+        };
+        let cfg = StorageConfig {
+            redis_url: url,
+            redis_key_prefix: format!("cowcat-test:{}:{name}:", std::process::id()),
+            ..StorageConfig::default()
+        };
+        let store = RedisTaskStore::connect(&cfg).await.unwrap();
+        store.clear().await;
+        Some(store)
+    }
+
+    fn task(id: &str) -> Task {
+        Task {
+            task_id: TaskId::from(id),
+            seed: Seed("seed".to_string()),
+            bits: 12,
+            exp: OffsetDateTime::now_utc().unix_timestamp() + 120,
+            scope: Scope("example.com".to_string()),
+            ua_hash: UaHash("ua".to_string()),
+            ip_hash: IpHash(String::new()),
+            issued_at_ms: 0,
+            chain_length: 1,
+            captcha_answer: None,
+            commitment_nonce: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_consume_has_exactly_one_winner() {
+        let Some(store) = store("concurrent").await else { return };
+        store.insert(task("abcdef0123")).await.unwrap();
+
+        let store = std::sync::Arc::new(store);
+        let attempts: Vec<_> = (0..16)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))).await })
+            })
+            .collect();
+        let mut won = 0;
+        for attempt in attempts {
+            match attempt.await.unwrap() {
+                Ok(task) => {
+                    won += 1;
+                    assert_eq!(&*task.task_id.0, "abcdef0123");
+                }
+                Err(err) => assert!(matches!(err, ConsumeError::NotFound), "{err:?}"),
+            }
+        }
+        assert_eq!(won, 1);
+        assert_eq!(store.len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_validation_still_consumes() {
+        let Some(store) = store("validation").await else { return };
+        store.insert(task("abcdef0123")).await.unwrap();
+
+        let rejected = store
+            .consume_if("abcdef0123", Box::new(|_: &Task| Err(ConsumeError::ValidationFailed("bad"))))
+            .await;
+        assert!(matches!(rejected, Err(ConsumeError::ValidationFailed("bad"))));
+        let replay = store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))).await;
+        assert!(matches!(replay, Err(ConsumeError::NotFound)));
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{interval, Duration};
+
+use crate::config::TaskPoolConfig;
+
+/// 预生成的 (task_id, seed) 随机对；exp / scope / 哈希等在下发时再填，保证每个任务都是新鲜的
+pub struct TaskPool {
+    inner: Mutex<VecDeque<(String, String)>>,
+    capacity: usize,
+}
+
+impl TaskPool {
+    pub fn new(cfg: &TaskPoolConfig) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            inner: Mutex::new(VecDeque::with_capacity(cfg.size)),
+            capacity: cfg.size.max(1),
+        });
+        Self::spawn_refill(pool.clone(), Duration::from_millis(cfg.refill_interval_ms.max(1)));
+        pool
+    }
+
+    /// 取出一对预生成的随机值；池空时返回 None，由调用方现场生成
+    pub fn pop(&self) -> Option<(String, String)> {
+        self.inner.lock().ok()?.pop_front()
+    }
+
+    fn spawn_refill(pool: Arc<Self>, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = interval(every);
+            loop {
+                ticker.tick().await;
+                pool.refill();
+            }
+        });
+    }
+
+    /// RNG 调用放在锁外，只在入队时短暂持锁
+    fn refill(&self) {
+        let missing = match self.inner.lock() {
+            Ok(guard) => self.capacity.saturating_sub(guard.len()),
+            Err(_) => return,
+        };
+        if missing == 0 {
+            return;
+        }
+        let mut fresh = Vec::with_capacity(missing);
+        for _ in 0..missing {
+            match (crate::crypto::generate_random_id(), crate::crypto::generate_random_seed()) {
+                (Ok(id), Ok(seed)) => fresh.push((id, seed)),
+                _ => {
+                    tracing::warn!("task pool refill failed to generate randomness");
+                    break;
+                }
+            }
+        }
+        if let Ok(mut guard) = self.inner.lock() {
+            let room = self.capacity.saturating_sub(guard.len());
+            guard.extend(fresh.into_iter().take(room));
+            tracing::debug!(size = guard.len(), "task pool refilled");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::AppState;
+    use axum::http::{Extensions, HeaderMap};
+    use time::OffsetDateTime;
+
+    #[tokio::test]
+    async fn build_task_takes_pooled_ids_and_stamps_them_at_issuance() {
+        // 直接构造，不启动补充任务：池里只有这一对
+        let pool = Arc::new(TaskPool {
+            inner: Mutex::new(VecDeque::from([("pooled-id".to_string(), "pooled-seed".to_string())])),
+            capacity: 1,
+        });
+        let mut state = AppState::new(Config::default()).await.unwrap();
+        state.task_pool = Some(pool.clone());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let before = OffsetDateTime::now_utc();
+        let pooled = crate::handlers::pow::build_task(&state, &HeaderMap::new(), &Extensions::new(), 3).unwrap();
+        assert_eq!(&*pooled.task_id.0, "pooled-id");
+        assert_eq!(pooled.seed.0, "pooled-seed");
+        // exp 与 issued_at_ms 按下发时刻计算，而不是入池时刻
+        assert!(pooled.issued_at_ms >= (before.unix_timestamp_nanos() / 1_000_000) as i64);
+        assert!(pooled.exp >= before.unix_timestamp() + 120);
+        assert!(pool.pop().is_none());
+
+        // 池空时现场生成
+        let fresh = crate::handlers::pow::build_task(&state, &HeaderMap::new(), &Extensions::new(), 3).unwrap();
+        assert_ne!(&*fresh.task_id.0, "pooled-id");
+        assert!(!fresh.task_id.0.is_empty());
+        assert_ne!(fresh.seed.0, "pooled-seed");
+        assert!(fresh.exp >= pooled.exp);
+    }
+}
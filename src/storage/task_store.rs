@@ -2,19 +2,25 @@ use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tokio::time::{interval, Duration};
 use time::OffsetDateTime;
 
 const TASK_CLEANUP_INTERVAL: u64 = 300;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct TaskId(pub Arc<str>);
 
 impl TaskId {
+    /// 前 6 个字符，按字符边界截取，非 ASCII 的 id 也不会 panic
     pub fn short_id(&self) -> &str {
-        debug_assert!(self.0.is_empty() || self.0.is_ascii());
-        &self.0[..6.min(self.0.len())]
+        match self.0.char_indices().nth(6) {
+            Some((end, _)) => &self.0[..end],
+            None => &self.0,
+        }
     }
 }
 
@@ -42,7 +48,8 @@ impl Borrow<str> for TaskId {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Seed(pub String);
 
 impl fmt::Display for Seed {
@@ -57,7 +64,8 @@ impl From<String> for Seed {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct UaHash(pub String);
 
 impl fmt::Display for UaHash {
@@ -72,7 +80,8 @@ impl From<String> for UaHash {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct IpHash(pub String);
 
 impl fmt::Display for IpHash {
@@ -87,7 +96,8 @@ impl From<String> for IpHash {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Scope(pub String);
 
 impl fmt::Display for Scope {
@@ -107,9 +117,12 @@ pub enum ConsumeError {
     NotFound,
     Expired,
     ValidationFailed(&'static str),
+    /// 存储后端不可用（如 Redis 连接失败），任务是否还在未知
+    Unavailable,
 }
 
-#[derive(Debug, Clone)]
+/// 可序列化以便放进进程外存储；新类型字段都按内部字符串透明编码
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
     pub task_id: TaskId,
     pub seed: Seed,
@@ -118,14 +131,46 @@ pub struct Task {
     pub scope: Scope,
     pub ua_hash: UaHash,
     pub ip_hash: IpHash,
+    /// 任务下发时间（unix 毫秒），用于估算客户端求解耗时
+    pub issued_at_ms: i64,
+    /// 链式挑战长度，1 为普通单挑战
+    pub chain_length: u32,
+    /// 验证码任务的期望答案；有值的任务只能经 /captcha 消费，/verify 一律拒绝
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captcha_answer: Option<String>,
+    /// pow.bind_commitment：本次下发的随机数，只存服务端；客户端拿到的是它与 task_id 的 HMAC 承诺
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitment_nonce: Option<String>,
 }
 
+/// consume_if 在取出任务后、返回前执行的校验
+pub type TaskValidator<'a> = Box<dyn FnOnce(&Task) -> Result<(), ConsumeError> + Send + 'a>;
+
+/// 任务存储后端，AppState 只依赖这个 trait，具体实现由 [storage] backend 选择。
+/// 实现必须保证 consume_if 的“取出即删除”是原子的：同一 task_id 最多被消费一次（防重放）
+#[async_trait]
+pub trait TaskStoreBackend: Send + Sync {
+    /// 插入新任务；失败时调用方不应把任务下发给客户端
+    async fn insert(&self, task: Task) -> anyhow::Result<()>;
+
+    /// 消费任务：取出并移除，然后检查过期并调用 validate；无论结果如何任务都已被消耗
+    async fn consume_if(&self, task_id: &str, validate: TaskValidator<'_>) -> Result<Task, ConsumeError>;
+
+    /// 当前保存的任务数（含尚未清理的过期任务）
+    async fn len(&self) -> usize;
+
+    /// 丢弃全部任务（例如轮换 salt 后让已下发的任务全部失效）
+    #[allow(dead_code)]
+    async fn clear(&self);
+}
+
+/// 默认的进程内存储
 #[derive(Clone)]
-pub struct TaskStore {
+pub struct MemoryTaskStore {
     inner: Arc<Mutex<HashMap<Arc<str>, Task>>>,
 }
 
-impl TaskStore {
+impl MemoryTaskStore {
     pub fn new() -> Arc<Self> {
         let store = Arc::new(Self {
             inner: Arc::new(Mutex::new(HashMap::new())),
@@ -134,18 +179,40 @@ impl TaskStore {
         store
     }
 
-    /// 插入新任务
-    pub async fn insert(&self, task: Task) {
+    fn spawn_cleanup(store: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(TASK_CLEANUP_INTERVAL));
+            loop {
+                ticker.tick().await;
+                store.cleanup().await;
+            }
+        });
+    }
+
+    async fn cleanup(&self) {
+        let mut guard = self.inner.lock().await;
+        if guard.is_empty() {
+            tracing::debug!("no tasks to cleanup");
+            return;
+        }
+        tracing::debug!("cleaning up tasks start");
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        guard.retain(|_, task| task.exp >= now);
+        tracing::info!("cleaning up tasks done: {} remaining", guard.len());
+    }
+}
+
+#[async_trait]
+impl TaskStoreBackend for MemoryTaskStore {
+    async fn insert(&self, task: Task) -> anyhow::Result<()> {
         let mut guard = self.inner.lock().await;
         guard.insert(task.task_id.0.clone(), task);
+        Ok(())
     }
 
-    /// 消费任务：取出并移除，然后验证
-    /// 无论验证成功与否，任务都被消耗（防重放）
-    pub async fn consume_if<F>(&self, task_id: &str, validate: F) -> Result<Task, ConsumeError>
-    where
-        F: FnOnce(&Task) -> Result<(), ConsumeError>,
-    {
+    /// 取出与移除在同一把锁内完成：同一 task_id 的并发 verify 只有一个能拿到任务，
+    /// 其余一律返回 NotFound，不存在“先读后删”的竞态窗口
+    async fn consume_if(&self, task_id: &str, validate: TaskValidator<'_>) -> Result<Task, ConsumeError> {
         let mut guard = self.inner.lock().await;
 
         // 先移除任务（任务被消耗）
@@ -163,25 +230,117 @@ impl TaskStore {
         Ok(task)
     }
 
-    fn spawn_cleanup(store: Arc<Self>) {
-        tokio::spawn(async move {
-            let mut ticker = interval(Duration::from_secs(TASK_CLEANUP_INTERVAL));
-            loop {
-                ticker.tick().await;
-                store.cleanup().await;
-            }
-        });
+    async fn len(&self) -> usize {
+        self.inner.lock().await.len()
     }
 
-    async fn cleanup(&self) {
-        let mut guard = self.inner.lock().await;
-        if guard.len() == 0 {
-            tracing::debug!("no tasks to cleanup");
-            return;
+    async fn clear(&self) {
+        self.inner.lock().await.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, exp: i64) -> Task {
+        Task {
+            task_id: TaskId::from(id),
+            seed: Seed("seed".to_string()),
+            bits: 12,
+            exp,
+            scope: Scope("example.com".to_string()),
+            ua_hash: UaHash("ua".to_string()),
+            ip_hash: IpHash(String::new()),
+            issued_at_ms: 0,
+            chain_length: 1,
+            captcha_answer: None,
+            commitment_nonce: None,
         }
-        tracing::debug!("cleaning up tasks start");
-        let now = OffsetDateTime::now_utc().unix_timestamp();
-        guard.retain(|_, task| task.exp >= now);
-        tracing::info!("cleaning up tasks done: {} remaining", guard.len());
+    }
+
+    fn in_two_minutes() -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp() + 120
+    }
+
+    #[tokio::test]
+    async fn concurrent_consume_has_exactly_one_winner() {
+        let store = MemoryTaskStore::new();
+        store.insert(task("abcdef0123", in_two_minutes())).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))),
+            store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))),
+        );
+
+        let results = [first, second];
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert_eq!(
+            results.iter().filter(|r| matches!(r, Err(ConsumeError::NotFound))).count(),
+            1
+        );
+        assert_eq!(store.len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_validation_still_consumes() {
+        let store = MemoryTaskStore::new();
+        store.insert(task("abcdef0123", in_two_minutes())).await.unwrap();
+
+        let rejected = store
+            .consume_if("abcdef0123", Box::new(|_: &Task| Err(ConsumeError::ValidationFailed("bad"))))
+            .await;
+        assert!(matches!(rejected, Err(ConsumeError::ValidationFailed("bad"))));
+        let replay = store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))).await;
+        assert!(matches!(replay, Err(ConsumeError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn expired_task_is_rejected() {
+        let store = MemoryTaskStore::new();
+        store.insert(task("abcdef0123", 0)).await.unwrap();
+        let result = store.consume_if("abcdef0123", Box::new(|_: &Task| Ok(()))).await;
+        assert!(matches!(result, Err(ConsumeError::Expired)));
+    }
+
+    #[test]
+    fn short_id_slices_on_char_boundaries() {
+        assert_eq!(TaskId::from("abcdef0123").short_id(), "abcdef");
+        assert_eq!(TaskId::from("abc").short_id(), "abc");
+        assert_eq!(TaskId::from("").short_id(), "");
+        // 第 6 个字节落在多字节字符中间
+        assert_eq!(TaskId::from("abcde任务id").short_id(), "abcde任");
+        assert_eq!(TaskId::from("猫爪挑战任务编号").short_id(), "猫爪挑战任务");
+    }
+
+    #[test]
+    fn task_serde_round_trip_is_compact() {
+        let mut original = task("abcdef0123", 1_700_000_000);
+        original.commitment_nonce = Some("n0nce".to_string());
+        let json = serde_json::to_string(&original).unwrap();
+        // newtype 透明序列化为内部字符串，未设置的可选字段不出现
+        assert!(json.contains(r#""task_id":"abcdef0123""#), "{json}");
+        assert!(json.contains(r#""seed":"seed""#), "{json}");
+        assert!(!json.contains("captcha_answer"), "{json}");
+
+        let decoded: Task = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.task_id, original.task_id);
+        assert_eq!(decoded.seed.0, original.seed.0);
+        assert_eq!(decoded.bits, original.bits);
+        assert_eq!(decoded.exp, original.exp);
+        assert_eq!(decoded.scope.0, original.scope.0);
+        assert_eq!(decoded.ua_hash, original.ua_hash);
+        assert_eq!(decoded.ip_hash, original.ip_hash);
+        assert_eq!(decoded.chain_length, original.chain_length);
+        assert_eq!(decoded.captcha_answer, None);
+        assert_eq!(decoded.commitment_nonce.as_deref(), Some("n0nce"));
+    }
+
+    #[test]
+    fn task_without_optional_fields_still_decodes() {
+        let json = r#"{"task_id":"t1","seed":"s","bits":8,"exp":1,"scope":"x","ua_hash":"u","ip_hash":"","issued_at_ms":0,"chain_length":1}"#;
+        let decoded: Task = serde_json::from_str(json).unwrap();
+        assert_eq!(decoded.task_id.short_id(), "t1");
+        assert!(decoded.captcha_answer.is_none() && decoded.commitment_nonce.is_none());
     }
 }
@@ -0,0 +1,100 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Tracer};
+use opentelemetry_sdk::Resource;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
+
+use crate::config::OtelConfig;
+
+type OtelLayer = Option<OpenTelemetryLayer<Registry, Tracer>>;
+
+/// 日志订阅器的句柄：OTLP 导出层在配置加载后才能确定，先以空层占位，再热替换
+pub struct Telemetry {
+    otel: reload::Handle<OtelLayer, Registry>,
+    provider: Option<SdkTracerProvider>,
+}
+
+/// 初始化 JSON 日志；OTLP 层此时为空，见 [`Telemetry::enable_otel`]
+pub fn init() -> Telemetry {
+    let (otel, handle) = reload::Layer::new(None);
+    tracing_subscriber::registry()
+        .with(otel)
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with(tracing_subscriber::fmt::layer().json())
+        .init();
+    Telemetry {
+        otel: handle,
+        provider: None,
+    }
+}
+
+impl Telemetry {
+    /// 按 server.otel 启用 OTLP(gRPC) span 导出；未配置时保持 no-op
+    pub fn enable_otel(&mut self, config: Option<&OtelConfig>) -> anyhow::Result<()> {
+        let Some(config) = config else {
+            return Ok(());
+        };
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.endpoint.clone())
+            .build()
+            .map_err(|err| anyhow::anyhow!("failed to build otlp exporter: {err}"))?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(config.service_name.clone())
+                    .build(),
+            )
+            .build();
+        let tracer = provider.tracer("cowcat-rs");
+        self.otel
+            .reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+            .map_err(|err| anyhow::anyhow!("failed to install otlp layer: {err}"))?;
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        self.provider = Some(provider);
+        tracing::info!(endpoint = %config.endpoint, "opentelemetry trace export enabled");
+        Ok(())
+    }
+
+    /// 进程退出前刷出尚未导出的 span
+    pub fn shutdown(&self) {
+        if let Some(provider) = &self.provider {
+            if let Err(err) = provider.shutdown() {
+                tracing::warn!(error = %err, "failed to flush opentelemetry spans");
+            }
+        }
+    }
+}
+
+/// 把当前 span 的 W3C traceparent 写入上游请求头；未启用 OTLP 时全局 propagator 为 no-op
+pub fn inject_trace_context(headers: &mut HeaderMap) {
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers));
+    });
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Uri};
+use time::OffsetDateTime;
+
+use crate::config::Config;
+use crate::middleware::pow::is_wellknown_path;
+use crate::risk::RiskModel;
+use crate::rules::{RuleAction, RulesEngine};
+
+/// 用合成请求跑一遍规则引擎并打印结果，便于离线排查规则为什么命中/没命中。
+/// 只覆盖规则相关的判定（可疑路径、well-known 白名单、规则、难度），不涉及 Cookie 与封禁
+pub fn run(config: &Config, path: &str, raw_headers: &[String], ip: Option<&str>, at: Option<i64>) -> anyhow::Result<()> {
+    let uri: Uri = path
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid --path {path}: {err}"))?;
+    let headers = parse_headers(raw_headers)?;
+    let client_ip = ip
+        .map(|raw| {
+            raw.trim()
+                .parse::<IpAddr>()
+                .map_err(|err| anyhow::anyhow!("invalid --ip {raw}: {err}"))
+        })
+        .transpose()?;
+    let now = match at {
+        Some(ts) => OffsetDateTime::from_unix_timestamp(ts).map_err(|err| anyhow::anyhow!("invalid --at {ts}: {err}"))?,
+        None => OffsetDateTime::now_utc(),
+    };
+    let engine = RulesEngine::from_config(&config.rules)?;
+
+    println!("request:   {uri}");
+    for (name, value) in &headers {
+        println!("header:    {}: {}", name, value.to_str().unwrap_or("<binary>"));
+    }
+    println!("client ip: {}", client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_string()));
+    println!("time:      {} (unix {})", now, now.unix_timestamp());
+    println!();
+
+    if let Some((action, pattern)) = engine.check_suspicious_path(&uri) {
+        println!("suspicious path: {pattern} -> {}", action_name(&action));
+        return Ok(());
+    }
+    if engine.allow_wellknown && is_wellknown_path(uri.path()) {
+        println!("well-known path: bypass (allow_wellknown = true)");
+        return Ok(());
+    }
+
+    let mut base = config.pow.base_difficulty(uri.path());
+    if let Some(risk) = &config.pow.risk_score {
+        let model = RiskModel::from_config(risk)?;
+        let score = model.score(&uri, &headers, client_ip);
+        base = model.difficulty(score, base);
+        println!("risk score: {score} -> base difficulty {base}");
+    }
+    let Some(decision) = engine.evaluate_at(&uri, &headers, client_ip, now) else {
+        println!("rules disabled: default challenge, difficulty {base}");
+        return Ok(());
+    };
+    match &decision.rule {
+        Some(name) => println!("matched rule: {name}"),
+        None => println!("matched rule: none (default_action)"),
+    }
+    println!("action:       {}", action_name(&decision.action));
+    if matches!(decision.action, RuleAction::Challenge) {
+        let effective = decision.effective_difficulty(base);
+        let source = match decision.difficulty_absolute {
+            Some(absolute) => format!("absolute {absolute}, base {base} ignored"),
+            None => format!("base {base}, delta {:+}", decision.difficulty_delta),
+        };
+        println!(
+            "difficulty:   {effective} ({source}){}",
+            if effective == 0 { " -> pass through" } else { "" }
+        );
+        println!(
+            "worker_type:  {}",
+            decision.worker_type.as_deref().unwrap_or(&config.pow.worker_type)
+        );
+    }
+    Ok(())
+}
+
+/// `--header "Name: value"`，可重复；同名头按出现顺序追加
+fn parse_headers(raw_headers: &[String]) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for raw in raw_headers {
+        let (name, value) = raw
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("invalid --header {raw:?}: expected \"Name: value\""))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|err| anyhow::anyhow!("invalid header name in {raw:?}: {err}"))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|err| anyhow::anyhow!("invalid header value in {raw:?}: {err}"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+fn action_name(action: &RuleAction) -> &'static str {
+    match action {
+        RuleAction::Allow => "allow",
+        RuleAction::Block => "block",
+        RuleAction::Challenge => "challenge",
+    }
+}
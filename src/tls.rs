@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{Acceptor, ClientHello};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::LazyConfigAcceptor;
+
+use crate::config::TlsConfig;
+
+/// server.tls 的握手入口。先读 ClientHello 再用同一份 ServerConfig 完成握手，
+/// 顺带得到连接指纹，供 pow.bind_fingerprint 使用
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<rustls::ServerConfig>,
+}
+
+impl TlsAcceptor {
+    pub async fn accept<IO>(&self, stream: IO) -> std::io::Result<(TlsStream<IO>, TlsFingerprint)>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let start = LazyConfigAcceptor::new(Acceptor::default(), stream).await?;
+        let offered = OfferedParams::from_client_hello(&start.client_hello());
+        let stream = start.into_stream(self.config.clone()).await?;
+        let negotiated = stream.get_ref().1.negotiated_cipher_suite().map(|suite| u16::from(suite.suite()));
+        Ok((stream, offered.fingerprint(negotiated.unwrap_or_default())))
+    }
+}
+
+/// 粗粒度的 TLS 连接指纹（十六进制），由握手时写入请求扩展。
+/// 同一 TLS 栈（浏览器及其版本）跨连接、重连与会话恢复保持不变，不同 TLS 栈之间通常不同
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsFingerprint(pub String);
+
+/// ClientHello 中参与指纹的部分。与 JA3 一样只取客户端的能力列表：不含 SNI、随机数与会话 id，
+/// 也不看扩展顺序（Chrome 每个连接都会打乱）；GREASE 值每个连接随机，计算前剔除
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OfferedParams {
+    pub cipher_suites: Vec<u16>,
+    pub named_groups: Vec<u16>,
+    pub signature_schemes: Vec<u16>,
+    pub alpn: Vec<Vec<u8>>,
+}
+
+impl OfferedParams {
+    fn from_client_hello(hello: &ClientHello<'_>) -> Self {
+        Self {
+            cipher_suites: hello.cipher_suites().iter().map(|&suite| u16::from(suite)).collect(),
+            named_groups: hello
+                .named_groups()
+                .map(|groups| groups.iter().map(|&group| u16::from(group)).collect())
+                .unwrap_or_default(),
+            signature_schemes: hello.signature_schemes().iter().map(|&scheme| u16::from(scheme)).collect(),
+            alpn: hello
+                .alpn()
+                .map(|protocols| protocols.map(<[u8]>::to_vec).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// negotiated 为协商出的 cipher suite
+    pub(crate) fn fingerprint(&self, negotiated: u16) -> TlsFingerprint {
+        let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+        for list in [&self.cipher_suites, &self.named_groups, &self.signature_schemes] {
+            for &value in list.iter().filter(|&&value| !is_grease(value)) {
+                ctx.update(&value.to_be_bytes());
+            }
+            ctx.update(b"|");
+        }
+        for protocol in &self.alpn {
+            ctx.update(&(protocol.len() as u16).to_be_bytes());
+            ctx.update(protocol);
+        }
+        ctx.update(b"|");
+        ctx.update(&negotiated.to_be_bytes());
+        TlsFingerprint(hex::encode(&ctx.finish().as_ref()[..16]))
+    }
+}
+
+/// RFC 8701 保留的 GREASE 值：0x0a0a、0x1a1a … 0xfafa
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// 读取 server.tls 的 PEM 证书链与私钥并构建 TLS acceptor；启动时调用，任何问题都直接报错退出。
+/// 只使用 ring 提供的算法，不依赖进程级默认 CryptoProvider
+pub fn load_acceptor(cfg: &TlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&cfg.cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| anyhow::anyhow!("failed to load server.tls.cert_path {}: {err}", cfg.cert_path))?;
+    if certs.is_empty() {
+        anyhow::bail!("server.tls.cert_path {} contains no certificate", cfg.cert_path);
+    }
+    let key = PrivateKeyDer::from_pem_file(&cfg.key_path).map_err(|err| match err {
+        pem::Error::NoItemsFound => anyhow::anyhow!("server.tls.key_path {} contains no private key", cfg.key_path),
+        err => anyhow::anyhow!("failed to load server.tls.key_path {}: {err}", cfg.key_path),
+    })?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| anyhow::anyhow!("failed to configure tls: {err}"))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| anyhow::anyhow!("server.tls certificate and key are not usable together: {err}"))?;
+    // 与 hyper 的 auto builder 一致：同时提供 HTTP/2 与 HTTP/1.1
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    tracing::info!(cert_path = %cfg.cert_path, "tls termination enabled");
+    Ok(TlsAcceptor { config: Arc::new(config) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chrome_like(grease: u16) -> OfferedParams {
+        OfferedParams {
+            cipher_suites: vec![grease, 0x1301, 0x1302, 0x1303, 0xc02b],
+            named_groups: vec![grease, 0x001d, 0x0017],
+            signature_schemes: vec![0x0403, 0x0804],
+            alpn: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+
+    #[test]
+    fn grease_values_are_recognised() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+    }
+
+    #[test]
+    fn fingerprint_ignores_per_connection_grease() {
+        assert_eq!(chrome_like(0x0a0a).fingerprint(0x1301), chrome_like(0x7a7a).fingerprint(0x1301));
+        assert_eq!(chrome_like(0x0a0a).fingerprint(0x1301).0.len(), 32);
+    }
+
+    #[test]
+    fn fingerprint_separates_different_clients() {
+        let base = chrome_like(0x0a0a);
+        let fingerprint = base.fingerprint(0x1301);
+        assert_ne!(base.fingerprint(0x1302), fingerprint);
+
+        let reordered = OfferedParams { cipher_suites: vec![0x1302, 0x1301, 0x1303, 0xc02b], ..base.clone() };
+        assert_ne!(reordered.fingerprint(0x1301), fingerprint);
+        let http1_only = OfferedParams { alpn: vec![b"http/1.1".to_vec()], ..base.clone() };
+        assert_ne!(http1_only.fingerprint(0x1301), fingerprint);
+        let fewer_groups = OfferedParams { named_groups: vec![0x001d], ..base };
+        assert_ne!(fewer_groups.fingerprint(0x1301), fingerprint);
+    }
+
+    fn tls_config(cert: &str, key: &str) -> TlsConfig {
+        let fixture = |name: &str| format!("{}/testdata/{name}", env!("CARGO_MANIFEST_DIR"));
+        TlsConfig { cert_path: fixture(cert), key_path: fixture(key) }
+    }
+
+    #[test]
+    fn fixture_cert_and_key_load() {
+        assert!(load_acceptor(&tls_config("localhost.crt", "localhost.key")).is_ok());
+    }
+
+    #[test]
+    fn unusable_cert_or_key_fails_with_the_offending_path() {
+        let cases = [
+            (tls_config("absent.crt", "localhost.key"), "failed to load server.tls.cert_path"),
+            (tls_config("localhost.key", "localhost.key"), "contains no certificate"),
+            (tls_config("localhost.crt", "localhost.crt"), "contains no private key"),
+            (tls_config("localhost.crt", "absent.key"), "failed to load server.tls.key_path"),
+        ];
+        for (cfg, expected) in cases {
+            let err = load_acceptor(&cfg).err().unwrap().to_string();
+            assert!(err.contains(expected), "{err}");
+        }
+    }
+}
@@ -1 +1 @@
-"use strict";let wasmInstancePromise;async function loadWasm(){return wasmInstancePromise||(wasmInstancePromise=(async()=>{const n="/__cowcatwaf/assets/catpaw.wasm",e={};if(typeof WebAssembly.instantiateStreaming=="function")try{const{instance:o}=await WebAssembly.instantiateStreaming(fetch(n),e);return o}catch{const i=await(await fetch(n)).arrayBuffer(),{instance:f}=await WebAssembly.instantiate(i,e);return f}const c=await(await fetch(n)).arrayBuffer(),{instance:s}=await WebAssembly.instantiate(c,e);return s})(),wasmInstancePromise)}function clampU32(n){return!Number.isFinite(n)||n<0?0:n>>>0}function hasLeadingZeroBits(n,e){if(e===0)return!0;let t=e;for(const c of n){if(t<=0)return!0;const s=Math.clz32(c)-24;if(s>=t)return!0;if(s!==8)return!1;t-=8}return t<=0}async function powSearchNative(n,e,t,c,s){if(typeof crypto>"u"||!crypto.subtle||typeof crypto.subtle.digest!="function")throw new Error("WebCrypto unavailable");const o=new TextEncoder,u=1e3;let i=0,f=t;const r=s===0?Number.MAX_SAFE_INTEGER:s;for(;i<r;){const g=r-i,a=Math.min(u,g);for(let l=0;l<a;l++){const b=o.encode(n+String(f)),w=await crypto.subtle.digest("SHA-256",b);if(hasLeadingZeroBits(new Uint8Array(w),e))return{found:!0,nonce:f>>>0};f=f+c>>>0}i+=a,self.postMessage({type:"progress",hashes:a})}return{found:!1}}self.onmessage=async n=>{try{const e=n.data||{},t=e.prefix,c=clampU32(e.bits),s=clampU32(e.start),o=clampU32(e.step),u=clampU32(e.max_iters),i=String(e.worker_type||"").trim().toLowerCase();if(typeof t!="string"||t.length===0)throw new Error("Invalid prefix");if(o===0)throw new Error("Invalid step");if(i==="native"){const p=await powSearchNative(t,c,s,o,u);if(p.found){self.postMessage({nonce:String(p.nonce)});return}throw new Error("Nonce not found")}const r=(await loadWasm()).exports;if(!r||typeof r.pow_search!="function"||typeof r.alloc!="function"||typeof r.dealloc!="function")throw new Error("Invalid WASM exports");const a=new TextEncoder().encode(t),l=r.alloc(a.length);new Uint8Array(r.memory.buffer,l,a.length).set(a);const w=1e5;let m=0,h=s,d=!1,A=0;for(;m<u&&!d;){const p=u-m,y=Math.min(w,p),S=r.pow_search(l,a.length,c,h,o,y);if(m+=y,self.postMessage({type:"progress",hashes:y}),S>>>0!==4294967295){d=!0,A=S;break}h=h+o*y>>>0}if(r.dealloc(l,a.length),d)self.postMessage({nonce:String(A>>>0)});else throw new Error("Nonce not found")}catch(e){const t={error:e&&e.message?e.message:String(e),errorType:e&&e.name?e.name:"UnknownError",errorStack:e&&e.stack?e.stack:"",workerInfo:{hasTextEncoder:typeof TextEncoder<"u",hasWebAssembly:typeof WebAssembly<"u",hasWebAssemblyInstantiateStreaming:typeof WebAssembly<"u"&&typeof WebAssembly.instantiateStreaming=="function",userAgent:typeof navigator<"u"?navigator.userAgent:"unknown"}};self.postMessage(t)}};
+"use strict";let wasmInstancePromise;async function loadWasm(w){return wasmInstancePromise||(wasmInstancePromise=(async()=>{const n=w||"/__cowcatwaf/assets/catpaw.wasm",e={};if(typeof WebAssembly.instantiateStreaming=="function")try{const{instance:o}=await WebAssembly.instantiateStreaming(fetch(n),e);return o}catch{const i=await(await fetch(n)).arrayBuffer(),{instance:f}=await WebAssembly.instantiate(i,e);return f}const c=await(await fetch(n)).arrayBuffer(),{instance:s}=await WebAssembly.instantiate(c,e);return s})(),wasmInstancePromise)}function clampU32(n){return!Number.isFinite(n)||n<0?0:n>>>0}function hasLeadingZeroBits(n,e){if(e===0)return!0;let t=e;for(const c of n){if(t<=0)return!0;const s=Math.clz32(c)-24;if(s>=t)return!0;if(s!==8)return!1;t-=8}return t<=0}async function powSearchNative(n,e,t,c,s){if(typeof crypto>"u"||!crypto.subtle||typeof crypto.subtle.digest!="function")throw new Error("WebCrypto unavailable");const o=new TextEncoder,u=1e3;let i=0,f=t;const r=s===0?Number.MAX_SAFE_INTEGER:s;for(;i<r;){const g=r-i,a=Math.min(u,g);for(let l=0;l<a;l++){const b=o.encode(n+String(f)),w=await crypto.subtle.digest("SHA-256",b);if(hasLeadingZeroBits(new Uint8Array(w),e))return{found:!0,nonce:f>>>0};f=f+c>>>0}i+=a,self.postMessage({type:"progress",hashes:a})}return{found:!1}}const KECCAK_RC=[1,0,32898,0,32906,2147483648,2147516416,2147483648,32907,0,2147483649,0,2147516545,2147483648,32777,2147483648,138,0,136,0,2147516425,0,2147483658,0,2147516555,0,139,2147483648,32905,2147483648,32771,2147483648,32770,2147483648,128,2147483648,32778,0,2147483658,2147483648,2147516545,2147483648,32896,2147483648,2147483649,0,2147516424,2147483648],KECCAK_ROT=[0,1,62,28,27,36,44,6,55,20,3,10,43,25,39,41,45,15,21,8,18,2,61,56,14],SHA3_256_RATE=136;function keccakF(n){const e=new Uint32Array(10),t=new Uint32Array(50);for(let c=0;c<24;c++){for(let s=0;s<5;s++)e[2*s]=n[2*s]^n[2*s+10]^n[2*s+20]^n[2*s+30]^n[2*s+40],e[2*s+1]=n[2*s+1]^n[2*s+11]^n[2*s+21]^n[2*s+31]^n[2*s+41];for(let s=0;s<5;s++){const o=2*((s+4)%5),u=2*((s+1)%5),i=e[o]^(e[u]<<1|e[u+1]>>>31),f=e[o+1]^(e[u+1]<<1|e[u]>>>31);for(let r=0;r<25;r+=5)n[2*(s+r)]^=i,n[2*(s+r)+1]^=f}for(let s=0;s<5;s++)for(let o=0;o<5;o++){const u=s+5*o,i=n[2*u],f=n[2*u+1],r=KECCAK_ROT[u],g=2*(o+5*((2*s+3*o)%5));r===0?(t[g]=i,t[g+1]=f):r<32?(t[g]=i<<r|f>>>32-r,t[g+1]=f<<r|i>>>32-r):r===32?(t[g]=f,t[g+1]=i):(t[g]=f<<r-32|i>>>64-r,t[g+1]=i<<r-32|f>>>64-r)}for(let s=0;s<25;s+=5)for(let o=0;o<5;o++){const u=2*(o+s),i=2*((o+1)%5+s),f=2*((o+2)%5+s);n[u]=t[u]^~t[i]&t[f],n[u+1]=t[u+1]^~t[i+1]&t[f+1]}n[0]^=KECCAK_RC[2*c],n[1]^=KECCAK_RC[2*c+1]}}function sha3_256(n){const e=new Uint32Array(50),t=Math.floor(n.length/SHA3_256_RATE)+1,c=new Uint8Array(t*SHA3_256_RATE);c.set(n),c[n.length]^=6,c[c.length-1]^=128;for(let o=0;o<c.length;o+=SHA3_256_RATE){for(let u=0;u<SHA3_256_RATE/4;u++){const i=o+4*u;e[u]^=c[i]|c[i+1]<<8|c[i+2]<<16|c[i+3]<<24}keccakF(e)}const s=new Uint8Array(32);for(let o=0;o<32;o++)s[o]=e[o>>2]>>>8*(o&3)&255;return s}function powSearchSha3(n,e,t,c,s){const o=new TextEncoder,u=1e3;let i=0,f=t;const r=s===0?Number.MAX_SAFE_INTEGER:s;for(;i<r;){const g=Math.min(u,r-i);for(let a=0;a<g;a++){const l=sha3_256(o.encode(n+String(f)));if(hasLeadingZeroBits(l,e))return{found:!0,nonce:f>>>0};f=f+c>>>0}i+=g,self.postMessage({type:"progress",hashes:g})}return{found:!1}}self.onmessage=async n=>{try{const e=n.data||{},t=e.prefix,c=clampU32(e.bits),s=clampU32(e.start),o=clampU32(e.step),u=clampU32(e.max_iters),i=String(e.worker_type||"").trim().toLowerCase(),H=String(e.hash_algo||"sha256").trim().toLowerCase();if(typeof t!="string"||t.length===0)throw new Error("Invalid prefix");if(o===0)throw new Error("Invalid step");if(H==="sha3-256"){const p=powSearchSha3(t,c,s,o,u);if(p.found){self.postMessage({nonce:String(p.nonce)});return}throw new Error("Nonce not found")}if(i==="native"){const p=await powSearchNative(t,c,s,o,u);if(p.found){self.postMessage({nonce:String(p.nonce)});return}throw new Error("Nonce not found")}const r=(await loadWasm(e.wasm_url)).exports;if(!r||typeof r.pow_search!="function"||typeof r.alloc!="function"||typeof r.dealloc!="function")throw new Error("Invalid WASM exports");const a=new TextEncoder().encode(t),l=r.alloc(a.length);new Uint8Array(r.memory.buffer,l,a.length).set(a);const w=1e5;let m=0,h=s,d=!1,A=0;for(;m<u&&!d;){const p=u-m,y=Math.min(w,p),S=r.pow_search(l,a.length,c,h,o,y);if(m+=y,self.postMessage({type:"progress",hashes:y}),S>>>0!==4294967295){d=!0,A=S;break}h=h+o*y>>>0}if(r.dealloc(l,a.length),d)self.postMessage({nonce:String(A>>>0)});else throw new Error("Nonce not found")}catch(e){const t={error:e&&e.message?e.message:String(e),errorType:e&&e.name?e.name:"UnknownError",errorStack:e&&e.stack?e.stack:"",workerInfo:{hasTextEncoder:typeof TextEncoder<"u",hasWebAssembly:typeof WebAssembly<"u",hasWebAssemblyInstantiateStreaming:typeof WebAssembly<"u"&&typeof WebAssembly.instantiateStreaming=="function",userAgent:typeof navigator<"u"?navigator.userAgent:"unknown"}};self.postMessage(t)}};
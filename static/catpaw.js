@@ -13,7 +13,8 @@
         hashRate: 0,
         totalHashes: 0,
         manualRedirect: false,  // 是否手动跳转模式
-        pendingRedirectUrl: null  // 待跳转的URL
+        pendingRedirectUrl: null,  // 待跳转的URL
+        solvedBits: null  // verify 响应带回的 bits（pow.verify_response_bits），未开启时为 null
     };
 
     // ===== DOM Element References =====
@@ -91,6 +92,12 @@
                 elements.errorDetails.style.display = 'block';
             }
         }
+
+        // pow.captcha_fallback 开启时页面带有验证码入口，PoW 失败后展示
+        const captchaEntry = document.getElementById('cowcat-captcha');
+        if (captchaEntry) {
+            captchaEntry.hidden = false;
+        }
     }
 
     function updateHashStats(rate, total) {
@@ -255,8 +262,23 @@
 
     // ===== WASM Codec =====
 
+    // 页面脚本可能由 pow.asset_base_url 指向的 CDN 提供；worker 必须同源，仍从本机加载
+    const ORIGIN_ASSET_BASE = '/__cowcatwaf/assets';
+    const assetBase = (document.currentScript && document.currentScript.dataset.assetBase) || ORIGIN_ASSET_BASE;
+    // pow.page.inline_all 时 wasm 以 data URL 内联在 script 标签上，不再单独请求
+    const inlineWasmUrl = (document.currentScript && document.currentScript.dataset.wasm) || '';
+
     const wasmCodecPromise = (async () => {
-        const resp = await fetch('/__cowcatwaf/assets/catpaw.wasm');
+        let resp;
+        try {
+            resp = await fetch(inlineWasmUrl || assetBase + '/catpaw.wasm');
+        } catch (e) {
+            resp = null;
+        }
+        // CDN 不可用时回落到本机资源
+        if ((!resp || !resp.ok) && assetBase !== ORIGIN_ASSET_BASE) {
+            resp = await fetch(ORIGIN_ASSET_BASE + '/catpaw.wasm');
+        }
         const bytes = await resp.arrayBuffer();
         const { instance } = await WebAssembly.instantiate(bytes, {});
         return instance.exports;
@@ -384,8 +406,8 @@
         return t === 'native' ? 'native' : 'wasm';
     }
 
-    function extractWorkerType(frameBytes) {
-        if (!frameBytes || frameBytes.length < 8) return '';
+    function extractTlv(frameBytes, type) {
+        if (!frameBytes || frameBytes.length < 8) return null;
         const keyBytes = new TextEncoder().encode('cowcatwaflibwafcatcow');
         const data = new Uint8Array(frameBytes);
         const deobfuscated = data.slice();
@@ -394,7 +416,7 @@
         }
 
         if (deobfuscated[0] !== 0x43 || deobfuscated[1] !== 0x57 || deobfuscated[2] !== 0x01 || deobfuscated[3] !== 0x02) {
-            return '';
+            return null;
         }
 
         const payloadLen = (
@@ -404,7 +426,7 @@
             deobfuscated[7]
         ) >>> 0;
         if (payloadLen !== deobfuscated.length - 8) {
-            return '';
+            return null;
         }
 
         const payload = deobfuscated.subarray(8);
@@ -412,13 +434,59 @@
             const t = payload[i];
             const len = (payload[i + 1] << 8) | payload[i + 2];
             i += 3;
-            if (i + len > payload.length) return '';
-            if (t === 0x0b) {
-                return new TextDecoder().decode(payload.subarray(i, i + len));
+            if (i + len > payload.length) return null;
+            if (t === type) {
+                return payload.subarray(i, i + len);
             }
             i += len;
         }
-        return '';
+        return null;
+    }
+
+    function extractWorkerType(frameBytes) {
+        const value = extractTlv(frameBytes, 0x0b);
+        return value ? new TextDecoder().decode(value) : '';
+    }
+
+    // 链式挑战长度（TLV 0x0c，单字节），缺省为 1
+    function extractChainLength(frameBytes) {
+        const value = extractTlv(frameBytes, 0x0c);
+        return value && value.length > 0 ? Math.max(1, value[0]) : 1;
+    }
+
+    // 哈希算法（TLV 0x0d），缺省为 sha256
+    function extractHashAlgo(frameBytes) {
+        const value = extractTlv(frameBytes, 0x0d);
+        return value ? new TextDecoder().decode(value).trim().toLowerCase() : 'sha256';
+    }
+
+    // 任务签名（TLV 0x0e，pow.signed_tasks）与下发承诺（TLV 0x10，pow.bind_commitment），verify 时原样带回
+    function extractEchoTlv(frameBytes, type) {
+        const value = extractTlv(frameBytes, type);
+        return value ? value.slice() : null;
+    }
+
+    // 在已混淆的帧末尾追加一个 TLV：解混淆、追加、改写 payload 长度后重新混淆
+    function appendFrameTlv(frameBytes, type, value) {
+        const keyBytes = new TextEncoder().encode('cowcatwaflibwafcatcow');
+        const out = new Uint8Array(frameBytes.length + 3 + value.length);
+        out.set(frameBytes);
+        for (let i = 0; i < frameBytes.length; i++) {
+            out[i] ^= keyBytes[i % keyBytes.length];
+        }
+        out[frameBytes.length] = type;
+        out[frameBytes.length + 1] = (value.length >> 8) & 0xff;
+        out[frameBytes.length + 2] = value.length & 0xff;
+        out.set(value, frameBytes.length + 3);
+        const payloadLen = out.length - 8;
+        out[4] = (payloadLen >>> 24) & 0xff;
+        out[5] = (payloadLen >>> 16) & 0xff;
+        out[6] = (payloadLen >>> 8) & 0xff;
+        out[7] = payloadLen & 0xff;
+        for (let i = 0; i < out.length; i++) {
+            out[i] ^= keyBytes[i % keyBytes.length];
+        }
+        return out;
     }
 
     function computePowProgress(attempts, reportAs) {
@@ -462,6 +530,15 @@
 
     // ===== Challenge Solving =====
 
+    // 页面内联了 worker 源码（pow.page.inline_all）时用 Blob URL 启动，否则从本机加载
+    function workerScriptUrl(timeStamp) {
+        const inline = document.getElementById('catpaw-worker-src');
+        if (inline && typeof Blob !== 'undefined' && typeof URL !== 'undefined' && URL.createObjectURL) {
+            return URL.createObjectURL(new Blob([inline.textContent], { type: 'text/javascript' }));
+        }
+        return '/__cowcatwaf/assets/catpaw.worker.min.js?v=' + timeStamp;
+    }
+
     async function solveChallenge(task) {
         const numWorkers = workerCount(3, 1);
         const prefix = makePrefix(task);
@@ -500,10 +577,11 @@
 
         const noncePromise = new Promise(function(resolve, reject) {
             const timeStampNow = Date.now();
+            const workerUrl = workerScriptUrl(timeStampNow);
             for (let i = 0; i < numWorkers; i++) {
                 let w;
                 try {
-                    w = new Worker('/__cowcatwaf/assets/catpaw.worker.min.js?v=' + timeStampNow);
+                    w = new Worker(workerUrl);
                 } catch (err) {
                     const creationError = {
                         workerId: i,
@@ -601,6 +679,8 @@
                     step: numWorkers,
                     max_iters: maxItersPerWorker,
                     worker_type: workerType,
+                    hash_algo: task.hash_algo || 'sha256',
+                    wasm_url: inlineWasmUrl || undefined,
                 });
             }
         });
@@ -620,7 +700,9 @@
         updateStatus('正在验证解决方案...');
 
         try {
-            if (typeof crypto !== 'undefined' && crypto.subtle && typeof crypto.subtle.digest === 'function') {
+            if (task.hash_algo === 'sha3-256') {
+                console.log('PoW verification proceeding (sha3-256, hash logging skipped)');
+            } else if (typeof crypto !== 'undefined' && crypto.subtle && typeof crypto.subtle.digest === 'function') {
                 try {
                     const msg = 'v1|' + task.seed + '|' + task.exp + '|' + task.bits + '|' + task.scope + '|' + task.ua_hash + '|' + nonce;
                     const msgBuffer = new TextEncoder().encode(msg);
@@ -634,7 +716,13 @@
             } else {
                 console.log('PoW verification proceeding (crypto.subtle not available for hash logging)');
             }
-            const payload = await encodeVerifyRequest(taskId, nonce, redirect);
+            let payload = await encodeVerifyRequest(taskId, nonce, redirect);
+            if (task.sig) {
+                payload = appendFrameTlv(payload, 0x0e, task.sig);
+            }
+            if (task.commitment) {
+                payload = appendFrameTlv(payload, 0x10, task.commitment);
+            }
             // 计算从开始计算到发送验证的时间（毫秒）
             let computeTimeParam = '';
             if (challengeStartTime) {
@@ -655,6 +743,11 @@
                 throw new Error(result.error || '验证失败');
             }
             updateProgress(100, '验证成功！');
+            // 供页面做统计：window.addEventListener('cowcat:verified', e => e.detail.bits)
+            state.solvedBits = typeof result.bits === 'number' ? result.bits : null;
+            window.dispatchEvent(new CustomEvent('cowcat:verified', {
+                detail: { redirect: result.redirect, bits: state.solvedBits }
+            }));
 
             // 切换图片：隐藏图片1，显示图片2
             if (elements.visualImage1) {
@@ -700,6 +793,37 @@
         }
     }
 
+    // 链式挑战：下一环的 seed 为 上一环 seed + '.' + nonce，只能顺序求解
+    async function solveTask(task, taskBytes) {
+        const chainLength = extractChainLength(taskBytes);
+        task.hash_algo = extractHashAlgo(taskBytes);
+        task.sig = extractEchoTlv(taskBytes, 0x0e);
+        task.commitment = extractEchoTlv(taskBytes, 0x10);
+        const nonces = [];
+        let step = task;
+        for (let i = 0; i < chainLength; i++) {
+            const stepNonce = await solveChallenge(step);
+            nonces.push(stepNonce);
+            step = Object.assign({}, step, { seed: step.seed + '.' + stepNonce });
+        }
+        return { task: task, nonces: nonces };
+    }
+
+    // 服务端未开启或已限流时返回 null，由调用方沿用原始错误
+    async function requestFallbackTask() {
+        try {
+            const response = await fetch('/__cowcatwaf/fallback', { method: 'POST' });
+            if (!response.ok) return null;
+            const bytes = new Uint8Array(await response.arrayBuffer());
+            const task = await decodeTaskResponse(bytes);
+            if (task.error) return null;
+            task.worker_type = normalizeWorkerType(extractWorkerType(bytes) || 'native');
+            return { task: task, bytes: bytes };
+        } catch (err) {
+            return null;
+        }
+    }
+
     // ===== Main Function =====
 
     async function main() {
@@ -761,8 +885,17 @@
 
             // 重置挑战开始时间
             challengeStartTime = Date.now();
-            const nonce = await solveChallenge(task);
-            await verifyAndRedirect(task.task_id, nonce, redirect, task);
+            let solved;
+            try {
+                solved = await solveTask(task, taskBytes);
+            } catch (solveErr) {
+                // wasm worker 不可用时向服务端申请 native worker 的降级任务（需开启 pow.wasm_fallback）
+                const fallback = normalizeWorkerType(task.worker_type) === 'wasm' ? await requestFallbackTask() : null;
+                if (!fallback) throw solveErr;
+                updateStatus('正在切换兼容模式...');
+                solved = await solveTask(fallback.task, fallback.bytes);
+            }
+            await verifyAndRedirect(solved.task.task_id, solved.nonces.join(','), redirect, solved.task);
 
         } catch (err) {
             console.error('Error:', err);
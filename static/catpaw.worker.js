@@ -2,11 +2,12 @@
 
 let wasmInstancePromise;
 
-async function loadWasm() {
+// wasmUrl 为 pow.page.inline_all 时页面传入的 data URL，未传时从本机加载
+async function loadWasm(wasmUrl) {
     if (wasmInstancePromise) return wasmInstancePromise;
 
     wasmInstancePromise = (async () => {
-        const url = '/__cowcatwaf/assets/catpaw.wasm';
+        const url = wasmUrl || '/__cowcatwaf/assets/catpaw.wasm';
         const imports = {};
 
         if (typeof WebAssembly.instantiateStreaming === 'function') {
@@ -82,6 +83,119 @@ async function powSearchNative(prefix, bits, start, step, maxIters) {
     return { found: false };
 }
 
+// SHA3-256（pow.hash_algo = sha3-256）；WebCrypto 不支持 SHA-3，这里用 32 位高低半字模拟 64 位 lane
+const KECCAK_RC = [
+    0x00000001, 0x00000000, 0x00008082, 0x00000000, 0x0000808a, 0x80000000, 0x80008000, 0x80000000,
+    0x0000808b, 0x00000000, 0x80000001, 0x00000000, 0x80008081, 0x80000000, 0x00008009, 0x80000000,
+    0x0000008a, 0x00000000, 0x00000088, 0x00000000, 0x80008009, 0x00000000, 0x8000000a, 0x00000000,
+    0x8000808b, 0x00000000, 0x0000008b, 0x80000000, 0x00008089, 0x80000000, 0x00008003, 0x80000000,
+    0x00008002, 0x80000000, 0x00000080, 0x80000000, 0x0000800a, 0x00000000, 0x8000000a, 0x80000000,
+    0x80008081, 0x80000000, 0x00008080, 0x80000000, 0x80000001, 0x00000000, 0x80008008, 0x80000000,
+];
+const KECCAK_ROT = [0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14];
+const SHA3_256_RATE = 136;
+
+function keccakF(s) {
+    const c = new Uint32Array(10);
+    const b = new Uint32Array(50);
+    for (let round = 0; round < 24; round++) {
+        for (let x = 0; x < 5; x++) {
+            c[2 * x] = s[2 * x] ^ s[2 * x + 10] ^ s[2 * x + 20] ^ s[2 * x + 30] ^ s[2 * x + 40];
+            c[2 * x + 1] = s[2 * x + 1] ^ s[2 * x + 11] ^ s[2 * x + 21] ^ s[2 * x + 31] ^ s[2 * x + 41];
+        }
+        for (let x = 0; x < 5; x++) {
+            const p = 2 * ((x + 4) % 5);
+            const n = 2 * ((x + 1) % 5);
+            const dLo = c[p] ^ ((c[n] << 1) | (c[n + 1] >>> 31));
+            const dHi = c[p + 1] ^ ((c[n + 1] << 1) | (c[n] >>> 31));
+            for (let y = 0; y < 25; y += 5) {
+                s[2 * (x + y)] ^= dLo;
+                s[2 * (x + y) + 1] ^= dHi;
+            }
+        }
+        for (let x = 0; x < 5; x++) {
+            for (let y = 0; y < 5; y++) {
+                const i = x + 5 * y;
+                const lo = s[2 * i];
+                const hi = s[2 * i + 1];
+                const r = KECCAK_ROT[i];
+                const j = 2 * (y + 5 * ((2 * x + 3 * y) % 5));
+                if (r === 0) {
+                    b[j] = lo;
+                    b[j + 1] = hi;
+                } else if (r < 32) {
+                    b[j] = (lo << r) | (hi >>> (32 - r));
+                    b[j + 1] = (hi << r) | (lo >>> (32 - r));
+                } else if (r === 32) {
+                    b[j] = hi;
+                    b[j + 1] = lo;
+                } else {
+                    b[j] = (hi << (r - 32)) | (lo >>> (64 - r));
+                    b[j + 1] = (lo << (r - 32)) | (hi >>> (64 - r));
+                }
+            }
+        }
+        for (let y = 0; y < 25; y += 5) {
+            for (let x = 0; x < 5; x++) {
+                const i = 2 * (x + y);
+                const i1 = 2 * ((x + 1) % 5 + y);
+                const i2 = 2 * ((x + 2) % 5 + y);
+                s[i] = b[i] ^ (~b[i1] & b[i2]);
+                s[i + 1] = b[i + 1] ^ (~b[i1 + 1] & b[i2 + 1]);
+            }
+        }
+        s[0] ^= KECCAK_RC[2 * round];
+        s[1] ^= KECCAK_RC[2 * round + 1];
+    }
+}
+
+function sha3_256(bytes) {
+    const s = new Uint32Array(50);
+    const blocks = Math.floor(bytes.length / SHA3_256_RATE) + 1;
+    const padded = new Uint8Array(blocks * SHA3_256_RATE);
+    padded.set(bytes);
+    padded[bytes.length] ^= 0x06;
+    padded[padded.length - 1] ^= 0x80;
+    for (let off = 0; off < padded.length; off += SHA3_256_RATE) {
+        for (let i = 0; i < SHA3_256_RATE / 4; i++) {
+            const k = off + 4 * i;
+            s[i] ^= padded[k] | (padded[k + 1] << 8) | (padded[k + 2] << 16) | (padded[k + 3] << 24);
+        }
+        keccakF(s);
+    }
+    const out = new Uint8Array(32);
+    for (let i = 0; i < 32; i++) {
+        out[i] = (s[i >> 2] >>> (8 * (i & 3))) & 0xff;
+    }
+    return out;
+}
+
+function powSearchSha3(prefix, bits, start, step, maxIters) {
+    const encoder = new TextEncoder();
+    const batchSize = 1000;
+    let totalHashes = 0;
+    let currentNonce = start;
+    const limit = maxIters === 0 ? Number.MAX_SAFE_INTEGER : maxIters;
+
+    while (totalHashes < limit) {
+        const currentBatch = Math.min(batchSize, limit - totalHashes);
+        for (let i = 0; i < currentBatch; i++) {
+            const hash = sha3_256(encoder.encode(prefix + String(currentNonce)));
+            if (hasLeadingZeroBits(hash, bits)) {
+                return { found: true, nonce: currentNonce >>> 0 };
+            }
+            currentNonce = (currentNonce + step) >>> 0;
+        }
+        totalHashes += currentBatch;
+        self.postMessage({
+            type: 'progress',
+            hashes: currentBatch
+        });
+    }
+
+    return { found: false };
+}
+
 self.onmessage = async (event) => {
     try {
         const data = event.data || {};
@@ -91,6 +205,7 @@ self.onmessage = async (event) => {
         const step = clampU32(data.step);
         const maxIters = clampU32(data.max_iters);
         const workerType = String(data.worker_type || '').trim().toLowerCase();
+        const hashAlgo = String(data.hash_algo || 'sha256').trim().toLowerCase();
 
         if (typeof prefix !== 'string' || prefix.length === 0) {
             throw new Error('Invalid prefix');
@@ -99,6 +214,16 @@ self.onmessage = async (event) => {
             throw new Error('Invalid step');
         }
 
+        // 内置 wasm 只实现了 SHA-256，sha3-256 任务无论 worker_type 都由 JS 求解
+        if (hashAlgo === 'sha3-256') {
+            const result = powSearchSha3(prefix, bits, start, step, maxIters);
+            if (result.found) {
+                self.postMessage({ nonce: String(result.nonce) });
+                return;
+            }
+            throw new Error('Nonce not found');
+        }
+
         if (workerType === 'native') {
             const result = await powSearchNative(prefix, bits, start, step, maxIters);
             if (result.found) {
@@ -108,7 +233,7 @@ self.onmessage = async (event) => {
             throw new Error('Nonce not found');
         }
 
-        const instance = await loadWasm();
+        const instance = await loadWasm(data.wasm_url);
         const exports = instance.exports;
 
         if (!exports || typeof exports.pow_search !== 'function' || typeof exports.alloc !== 'function' || typeof exports.dealloc !== 'function') {
@@ -340,7 +340,10 @@ fn decode_verify_response_json(frame: &[u8]) -> String {
 		None => return error_json("missing redirect"),
 	};
 
-	format!("{{\"redirect\":\"{}\"}}", json_escape(&redirect))
+	match field_u16(&fields, TLV_BITS) {
+		Some(bits) => format!("{{\"redirect\":\"{}\",\"bits\":{}}}", json_escape(&redirect), bits),
+		None => format!("{{\"redirect\":\"{}\"}}", json_escape(&redirect)),
+	}
 }
 
 fn decode_error_json(payload: &[u8]) -> String {